@@ -1,5 +1,23 @@
+use crate::message::message_parser::ErrorCode;
 use crate::{Result, StreamId};
 
+/// Error codes for closing the whole session, as defined by the draft. These
+/// are the codes the parser reports, so a parse error closes the session as is.
+pub type SessionErrorCode = ErrorCode;
+
+/// Application error codes for abandoning a single stream with RESET_STREAM
+/// or STOP_SENDING.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StreamErrorCode {
+    InternalError = 0x0,
+    Cancelled = 0x1,
+    DeliveryTimeout = 0x2,
+    SessionClosed = 0x3,
+}
+
+/// Datagram payload size assumed until the backend can query the path MTU.
+const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1200;
+
 #[allow(clippy::upper_case_acronyms)]
 pub enum Connection {
     QUIC,
@@ -19,6 +37,10 @@ impl Connection {
     pub fn send_datagram(&mut self, _data: &[u8]) -> Result<usize> {
         Ok(0)
     }
+    /// Largest datagram that fits the current path, or None if datagrams are unsupported.
+    pub fn max_datagram_size(&self) -> Option<usize> {
+        Some(DEFAULT_MAX_DATAGRAM_SIZE)
+    }
     pub fn recv_datagram(&mut self, _data: &mut [u8]) -> Result<usize> {
         Ok(0)
     }
@@ -28,7 +50,47 @@ impl Connection {
     pub fn recv_stream_data(&mut self, _stream_id: StreamId, _data: &mut [u8]) -> Result<usize> {
         Ok(0)
     }
-    pub fn close_with_error(&mut self, _error_code: u64, _error_reason: &str) -> Result<()> {
+    /// Abandons sending on |stream_id|.
+    pub fn reset_stream(
+        &mut self,
+        _stream_id: StreamId,
+        _error_code: StreamErrorCode,
+    ) -> Result<()> {
+        Ok(())
+    }
+    /// Asks the peer to stop sending on |stream_id|.
+    pub fn stop_sending(
+        &mut self,
+        _stream_id: StreamId,
+        _error_code: StreamErrorCode,
+    ) -> Result<()> {
         Ok(())
     }
+    pub fn close_with_error(
+        &mut self,
+        _error_code: SessionErrorCode,
+        _error_reason: &str,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_error_code_values() {
+        for (code, value) in [
+            (SessionErrorCode::NoError, 0x0),
+            (SessionErrorCode::InternalError, 0x1),
+            (SessionErrorCode::Unauthorized, 0x2),
+            (SessionErrorCode::ProtocolViolation, 0x3),
+            (SessionErrorCode::DuplicateTrackAlias, 0x4),
+            (SessionErrorCode::ParameterLengthMismatch, 0x5),
+            (SessionErrorCode::GoawayTimeout, 0x10),
+        ] {
+            assert_eq!(code as u64, value, "{:?}", code);
+        }
+    }
 }
@@ -0,0 +1,419 @@
+//! Diagnostics for reading MoQT wire images.
+
+use crate::message::fetch::FetchType;
+use crate::message::message_parser::{MessageParser, MessageParserEvent};
+use crate::message::{FilterType, FullSequence, MessageType, Version};
+use crate::serde::parameters::ParameterKey;
+use crate::{Deserializer, Error, Result};
+use bytes::Buf;
+use std::fmt::{Debug, Write};
+
+/// Returns a human-readable dump of the control message at the start of
+/// |bytes|: one line per field with its byte offset, length, name and value.
+/// Decoding stops at the first field that cannot be read and the dump ends
+/// with the error, so a truncated or malformed image is annotated as far as
+/// it goes.
+pub fn annotate(bytes: &[u8], uses_web_transport: bool) -> String {
+    let mut annotator = Annotator::new(bytes);
+    match annotator.control_message() {
+        Ok(()) => {
+            // The walk only decodes fields; the parser also enforces the rules
+            // that span them, such as required parameters.
+            let mut parser = MessageParser::new_control(uses_web_transport);
+            parser.process_data(&mut &bytes[..], false);
+            while let Some(event) = parser.poll_event() {
+                if let MessageParserEvent::ParsingError(code, reason) = event {
+                    let _ = writeln!(annotator.out, "error: {:?}: {}", code, reason);
+                }
+            }
+            if annotator.offset < bytes.len() {
+                let _ = writeln!(
+                    annotator.out,
+                    "{:#06x} [{}] trailing bytes",
+                    annotator.offset,
+                    bytes.len() - annotator.offset
+                );
+            }
+        }
+        Err(err) => {
+            let _ = writeln!(
+                annotator.out,
+                "{:#06x} error: {}",
+                annotator.offset,
+                describe(&err)
+            );
+        }
+    }
+    annotator.out
+}
+
+/// Returns the offset and name of each varint field of the control message at
+/// the start of |bytes|, named as in annotate.
+pub(crate) fn varint_fields(bytes: &[u8]) -> Result<Vec<(usize, String)>> {
+    let mut annotator = Annotator::new(bytes);
+    annotator.control_message()?;
+    Ok(annotator.varints)
+}
+
+fn describe(err: &Error) -> String {
+    match err {
+        Error::ErrUnexpectedEnd | Error::ErrBufferTooShort => "truncated".to_string(),
+        Error::ErrParseError(_, reason) => reason.clone(),
+        err => err.to_string(),
+    }
+}
+
+struct Annotator<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    out: String,
+    varints: Vec<(usize, String)>,
+}
+
+impl<'a> Annotator<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            out: String::new(),
+            varints: vec![],
+        }
+    }
+
+    fn line(&mut self, len: usize, name: &str, value: impl AsRef<str>) {
+        let _ = writeln!(
+            self.out,
+            "{:#06x} [{}] {}: {}",
+            self.offset,
+            len,
+            name,
+            value.as_ref()
+        );
+        self.offset += len;
+    }
+
+    fn field<T: Deserializer + Debug>(&mut self, name: &str) -> Result<T> {
+        let mut r = &self.bytes[self.offset..];
+        let (value, len) = T::deserialize(&mut r)?;
+        self.line(len, name, format!("{:?}", value));
+        Ok(value)
+    }
+
+    fn varint<T: Deserializer + Debug>(&mut self, name: &str) -> Result<T> {
+        let offset = self.offset;
+        let value = self.field(name)?;
+        self.varints.push((offset, name.to_string()));
+        Ok(value)
+    }
+
+    fn control_message(&mut self) -> Result<()> {
+        let mut r = self.bytes;
+        let (value, len) = u64::deserialize(&mut r)?;
+        let message_type = MessageType::try_from(value)?;
+        self.varints.push((0, "type".to_string()));
+        self.line(
+            len,
+            "type",
+            format!("{:?} ({:#x})", message_type, message_type as u64),
+        );
+        match message_type {
+            MessageType::SubscribeUpdate => {
+                self.varint::<u64>("subscribe_id")?;
+                self.field::<FullSequence>("start_group_object")?;
+                self.field::<FullSequence>("end_group_object")?;
+                self.parameters()
+            }
+            MessageType::Subscribe => {
+                self.varint::<u64>("subscribe_id")?;
+                self.varint::<u64>("track_alias")?;
+                self.field::<String>("track_namespace")?;
+                self.field::<String>("track_name")?;
+                self.field::<FilterType>("filter_type")?;
+                self.parameters()
+            }
+            MessageType::SubscribeOk => {
+                self.varint::<u64>("subscribe_id")?;
+                self.varint::<u64>("expires")?;
+                if self.field::<bool>("content_exists")? {
+                    self.field::<FullSequence>("largest_group_object")?;
+                }
+                Ok(())
+            }
+            MessageType::SubscribeError => {
+                self.varint::<u64>("subscribe_id")?;
+                self.varint::<u64>("error_code")?;
+                self.field::<String>("reason_phrase")?;
+                self.varint::<u64>("track_alias")?;
+                Ok(())
+            }
+            MessageType::Announce => {
+                self.field::<String>("track_namespace")?;
+                self.parameters()
+            }
+            MessageType::AnnounceOk | MessageType::UnAnnounce | MessageType::AnnounceCancel => {
+                self.field::<String>("track_namespace")?;
+                Ok(())
+            }
+            MessageType::AnnounceError => {
+                self.field::<String>("track_namespace")?;
+                self.varint::<u64>("error_code")?;
+                self.field::<String>("reason_phrase")?;
+                Ok(())
+            }
+            MessageType::UnSubscribe => {
+                self.varint::<u64>("subscribe_id")?;
+                Ok(())
+            }
+            MessageType::SubscribeDone => {
+                self.varint::<u64>("subscribe_id")?;
+                self.varint::<u64>("status_code")?;
+                self.field::<String>("reason_phrase")?;
+                if self.field::<bool>("content_exists")? {
+                    self.field::<FullSequence>("final_group_object")?;
+                }
+                Ok(())
+            }
+            MessageType::TrackStatusRequest => {
+                self.field::<String>("track_namespace")?;
+                self.field::<String>("track_name")?;
+                Ok(())
+            }
+            MessageType::TrackStatus => {
+                self.field::<String>("track_namespace")?;
+                self.field::<String>("track_name")?;
+                self.varint::<u64>("status_code")?;
+                self.field::<FullSequence>("last_group_object")?;
+                Ok(())
+            }
+            MessageType::GoAway => {
+                self.field::<String>("new_session_uri")?;
+                Ok(())
+            }
+            MessageType::MaxRequestId | MessageType::RequestsBlocked => {
+                self.varint::<u64>("max_request_id")?;
+                Ok(())
+            }
+            MessageType::Fetch => {
+                self.varint::<u64>("request_id")?;
+                match self.field::<FetchType>("fetch_type")? {
+                    FetchType::Standalone => {
+                        self.field::<String>("track_namespace")?;
+                        self.field::<String>("track_name")?;
+                        self.field::<FullSequence>("start")?;
+                        self.field::<FullSequence>("end")?;
+                    }
+                    FetchType::RelativeJoining | FetchType::AbsoluteJoining => {
+                        self.varint::<u64>("joining_request_id")?;
+                        self.varint::<u64>("joining_start")?;
+                    }
+                }
+                self.parameters()
+            }
+            MessageType::FetchCancel => {
+                self.varint::<u64>("request_id")?;
+                Ok(())
+            }
+            MessageType::FetchOk => {
+                self.varint::<u64>("request_id")?;
+                self.field::<bool>("end_of_track")?;
+                self.field::<FullSequence>("end_location")?;
+                self.parameters()
+            }
+            MessageType::ClientSetup => {
+                let num_versions = self.varint::<usize>("num_supported_versions")?;
+                for i in 0..num_versions {
+                    self.field::<Version>(&format!("supported_versions[{}]", i))?;
+                }
+                self.parameters()
+            }
+            MessageType::ServerSetup => {
+                self.field::<Version>("selected_version")?;
+                self.parameters()
+            }
+            MessageType::ObjectStream
+            | MessageType::ObjectDatagram
+            | MessageType::StreamHeaderTrack
+            | MessageType::StreamHeaderGroup => Err(Error::ErrOther(format!(
+                "{:?} is not a control message",
+                message_type
+            ))),
+        }
+    }
+
+    fn parameters(&mut self) -> Result<()> {
+        let num_params = self.varint::<u64>("num_parameters")?;
+        for _ in 0..num_params {
+            let mut r = &self.bytes[self.offset..];
+            let (key, kl) = u64::deserialize(&mut r)?;
+            let (size, sl) = usize::deserialize(&mut r)?;
+            if r.remaining() < size {
+                return Err(Error::ErrBufferTooShort);
+            }
+            let value = &r[..size];
+            let name = if key == ParameterKey::Role as u64 {
+                "ROLE".to_string()
+            } else if key == ParameterKey::Path as u64 {
+                "PATH".to_string()
+            } else if key == ParameterKey::AuthorizationInfo as u64 {
+                "AUTHORIZATION_INFO".to_string()
+            } else if key == ParameterKey::PartialObjectDelivery as u64 {
+                "PARTIAL_OBJECT_DELIVERY".to_string()
+            } else if key == ParameterKey::DatagramFragmentation as u64 {
+                "DATAGRAM_FRAGMENTATION".to_string()
+            } else if key == ParameterKey::LengthPrefixedControl as u64 {
+                "LENGTH_PREFIXED_CONTROL".to_string()
+            } else {
+                format!("parameter {:#x}", key)
+            };
+            let value = match std::str::from_utf8(value) {
+                Ok(s) if !s.is_empty() && !s.chars().any(char::is_control) => format!("{:?}", s),
+                _ => format!("{:02x?}", value),
+            };
+            self.line(kl + sl + size, &name, value);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::fetch::{Fetch, FetchTarget, JoiningFetch, StandaloneFetch};
+    use crate::message::fetch_cancel::FetchCancel;
+    use crate::message::fetch_ok::FetchOk;
+    use crate::message::max_request_id::MaxRequestId;
+    use crate::message::message_test::create_test_message;
+    use crate::message::requests_blocked::RequestsBlocked;
+    use crate::message::subscribe::Subscribe;
+    use crate::message::{ControlMessage, FullTrackName};
+    use crate::Serializer;
+
+    fn subscribe_bytes() -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        let _ = ControlMessage::Subscribe(Subscribe {
+            subscribe_id: 1,
+            track_alias: 2,
+            track_namespace: "foo".to_string(),
+            track_name: "abcd".to_string(),
+            filter_type: FilterType::AbsoluteStart(FullSequence::new(4, 1)),
+            authorization_info: Some("bar".to_string()),
+        })
+        .serialize(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    #[test]
+    fn test_annotate_subscribe() -> Result<()> {
+        let annotation = annotate(&subscribe_bytes()?, false);
+        for label in [
+            "0x0000 [1] type: Subscribe (0x3)",
+            "0x0001 [1] subscribe_id: 1",
+            "0x0002 [1] track_alias: 2",
+            "0x0003 [4] track_namespace: \"foo\"",
+            "0x0007 [5] track_name: \"abcd\"",
+            "filter_type: AbsoluteStart(FullSequence { group_id: 4, object_id: 1 })",
+            "num_parameters: 1",
+            "AUTHORIZATION_INFO: \"bar\"",
+        ] {
+            assert!(
+                annotation.contains(label),
+                "missing {:?} in\n{}",
+                label,
+                annotation
+            );
+        }
+        assert!(!annotation.contains("error"), "{}", annotation);
+        Ok(())
+    }
+
+    fn assert_annotates_whole_message(bytes: &[u8], uses_web_transport: bool) {
+        let annotation = annotate(bytes, uses_web_transport);
+        assert!(
+            !annotation.contains("error:") && !annotation.contains("trailing bytes"),
+            "{}",
+            annotation
+        );
+    }
+
+    // The walk in Annotator::control_message restates every wire layout, so
+    // it is checked against the sample of each control message.
+    #[test]
+    fn test_annotate_reads_every_control_message() -> Result<()> {
+        for value in 0..=0xff {
+            let Ok(message_type) = MessageType::try_from(value) else {
+                continue;
+            };
+            if message_type.is_object_message() {
+                continue;
+            }
+            if matches!(
+                message_type,
+                MessageType::MaxRequestId
+                    | MessageType::Fetch
+                    | MessageType::FetchCancel
+                    | MessageType::FetchOk
+                    | MessageType::RequestsBlocked
+            ) {
+                // No sample in message_test; built below.
+                continue;
+            }
+            for uses_web_transport in [false, true] {
+                let message = create_test_message(message_type, uses_web_transport);
+                assert_annotates_whole_message(message.packet_sample(), uses_web_transport);
+            }
+        }
+
+        let joining = JoiningFetch {
+            joining_request_id: 3,
+            joining_start: 1,
+        };
+        for message in [
+            ControlMessage::MaxRequestId(MaxRequestId { max_request_id: 8 }),
+            ControlMessage::Fetch(Fetch {
+                request_id: 1,
+                target: FetchTarget::Standalone(StandaloneFetch {
+                    full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                    start: FullSequence::new(0, 0),
+                    end: FullSequence::new(4, 2),
+                }),
+                authorization_info: Some("baz".to_string()),
+            }),
+            ControlMessage::Fetch(Fetch {
+                request_id: 2,
+                target: FetchTarget::RelativeJoining(joining.clone()),
+                authorization_info: None,
+            }),
+            ControlMessage::Fetch(Fetch {
+                request_id: 2,
+                target: FetchTarget::AbsoluteJoining(joining),
+                authorization_info: None,
+            }),
+            ControlMessage::FetchCancel(FetchCancel { request_id: 1 }),
+            ControlMessage::FetchOk(FetchOk {
+                request_id: 1,
+                end_of_track: true,
+                end_location: FullSequence::new(4, 2),
+            }),
+            ControlMessage::RequestsBlocked(RequestsBlocked { max_request_id: 8 }),
+        ] {
+            let mut bytes = vec![];
+            let _ = message.serialize(&mut bytes)?;
+            assert_annotates_whole_message(&bytes, false);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_annotate_truncated_subscribe() -> Result<()> {
+        let bytes = subscribe_bytes()?;
+        let annotation = annotate(&bytes[..9], false);
+        assert!(annotation.contains("track_namespace: \"foo\""));
+        assert!(!annotation.contains("track_name:"));
+        assert!(
+            annotation.ends_with("0x0007 error: truncated\n"),
+            "{}",
+            annotation
+        );
+        Ok(())
+    }
+}
@@ -1,11 +1,10 @@
-use crate::connection::Connection;
+use crate::connection::{Connection, SessionErrorCode, StreamErrorCode};
 use crate::protocol::{
     Command, Config, EventIn, EventOut, ReadInput, SessionCore, StreamPurpose, WriteOutput,
 };
 use crate::{Result, StreamId};
 use bytes::{Bytes, BytesMut};
 use sansio::Protocol;
-use std::collections::VecDeque;
 use std::time::Instant;
 
 /// Minimal transport surface needed to drive a [`SessionCore`].
@@ -13,7 +12,38 @@ pub trait SessionTransport {
     fn open_bi_stream(&mut self, purpose: StreamPurpose) -> Result<StreamId>;
     fn send_stream(&mut self, stream_id: StreamId, bytes: BytesMut, fin: bool) -> Result<()>;
     fn send_datagram(&mut self, bytes: Bytes) -> Result<()>;
-    fn close(&mut self, code: u64, reason: String) -> Result<()>;
+
+    /// Like send_stream, but hands |bytes| back once the transport is done
+    /// with them, so the session can reuse the allocation for later writes.
+    /// Transports that keep the buffer return None, which is the default.
+    fn send_stream_and_reclaim(
+        &mut self,
+        stream_id: StreamId,
+        bytes: BytesMut,
+        fin: bool,
+    ) -> Result<Option<BytesMut>> {
+        self.send_stream(stream_id, bytes, fin)?;
+        Ok(None)
+    }
+
+    fn close(&mut self, code: SessionErrorCode, reason: String) -> Result<()>;
+
+    /// Abandons sending on |stream_id|. Transports that cannot reset a
+    /// stream ignore it, leaving the stream to finish on its own.
+    fn reset_stream(&mut self, _stream_id: StreamId, _error_code: StreamErrorCode) -> Result<()> {
+        Ok(())
+    }
+
+    /// Asks the peer to stop sending on |stream_id|. Ignored by default; data
+    /// that still arrives on the stream is discarded by the session.
+    fn stop_sending(&mut self, _stream_id: StreamId, _error_code: StreamErrorCode) -> Result<()> {
+        Ok(())
+    }
+
+    /// Largest datagram the transport can currently send, or None if unbounded.
+    fn max_datagram_size(&self) -> Option<usize> {
+        None
+    }
 }
 
 impl SessionTransport for Connection {
@@ -26,21 +56,43 @@ impl SessionTransport for Connection {
         Ok(())
     }
 
+    fn send_stream_and_reclaim(
+        &mut self,
+        stream_id: StreamId,
+        bytes: BytesMut,
+        _fin: bool,
+    ) -> Result<Option<BytesMut>> {
+        let _ = self.send_stream_data(stream_id, &bytes)?;
+        Ok(Some(bytes))
+    }
+
     fn send_datagram(&mut self, bytes: Bytes) -> Result<()> {
         let _ = self.send_datagram(&bytes)?;
         Ok(())
     }
 
-    fn close(&mut self, code: u64, reason: String) -> Result<()> {
+    fn reset_stream(&mut self, stream_id: StreamId, error_code: StreamErrorCode) -> Result<()> {
+        Connection::reset_stream(self, stream_id, error_code)
+    }
+
+    fn stop_sending(&mut self, stream_id: StreamId, error_code: StreamErrorCode) -> Result<()> {
+        Connection::stop_sending(self, stream_id, error_code)
+    }
+
+    fn close(&mut self, code: SessionErrorCode, reason: String) -> Result<()> {
         self.close_with_error(code, &reason)
     }
+
+    fn max_datagram_size(&self) -> Option<usize> {
+        // No datagram support means no datagram fits.
+        Some(Connection::max_datagram_size(self).unwrap_or(0))
+    }
 }
 
 /// Adapter that connects [`SessionCore`] to a concrete transport.
 pub struct SessionDriver<T> {
     protocol: SessionCore,
     transport: T,
-    events: VecDeque<EventOut>,
 }
 
 impl<T: SessionTransport> SessionDriver<T> {
@@ -48,7 +100,6 @@ impl<T: SessionTransport> SessionDriver<T> {
         Self {
             protocol: SessionCore::new(config),
             transport,
-            events: VecDeque::new(),
         }
     }
 
@@ -64,6 +115,14 @@ impl<T: SessionTransport> SessionDriver<T> {
         self.transport
     }
 
+    pub fn protocol(&self) -> &SessionCore {
+        &self.protocol
+    }
+
+    pub fn protocol_mut(&mut self) -> &mut SessionCore {
+        &mut self.protocol
+    }
+
     pub fn on_transport_connected(&mut self) -> Result<()> {
         self.protocol.handle_event(EventIn::TransportConnected)?;
         self.flush()
@@ -89,25 +148,48 @@ impl<T: SessionTransport> SessionDriver<T> {
         self.flush()
     }
 
+    pub fn on_stop_sending(&mut self, stream_id: StreamId, error_code: u64) -> Result<()> {
+        self.protocol.handle_event(EventIn::StopSendingReceived {
+            stream_id,
+            error_code,
+        })?;
+        self.flush()
+    }
+
     pub fn on_stream_data(&mut self, stream_id: StreamId, data: Bytes, fin: bool) -> Result<()> {
         self.protocol.handle_read(ReadInput::StreamData {
             stream_id,
             data,
             fin,
+            now: Instant::now(),
         })?;
         self.flush()
     }
 
     pub fn on_datagram(&mut self, bytes: Bytes) -> Result<()> {
-        self.protocol.handle_read(ReadInput::Datagram(bytes))?;
+        self.protocol.handle_read(ReadInput::Datagram {
+            bytes,
+            now: Instant::now(),
+        })?;
         self.flush()
     }
 
     pub fn handle_command(&mut self, command: Command) -> Result<()> {
+        self.protocol
+            .set_max_datagram_size(self.transport.max_datagram_size());
         self.protocol.handle_write(command)?;
         self.flush()
     }
 
+    /// Writes any queued control messages, then closes the transport with
+    /// |code| and |reason|. Later commands fail with an error.
+    pub fn close(&mut self, code: SessionErrorCode, reason: &str) -> Result<()> {
+        self.handle_command(Command::Close {
+            code,
+            reason: reason.to_string(),
+        })
+    }
+
     pub fn handle_timeout(&mut self, now: Instant) -> Result<()> {
         self.protocol.handle_timeout(now)?;
         self.flush()
@@ -117,42 +199,45 @@ impl<T: SessionTransport> SessionDriver<T> {
         self.protocol.poll_timeout()
     }
 
+    /// Events are left queued in the protocol until polled, so its
+    /// [`crate::ObjectEventQueue`] bound applies to consumers of the driver too.
     pub fn poll_event(&mut self) -> Option<EventOut> {
-        self.events.pop_front()
+        self.protocol.poll_event()
     }
 
     fn flush(&mut self) -> Result<()> {
-        loop {
-            let mut progressed = false;
-
-            while let Some(write) = self.protocol.poll_write() {
-                progressed = true;
-                match write {
-                    WriteOutput::OpenBiStream { purpose } => {
-                        let stream_id = self.transport.open_bi_stream(purpose)?;
-                        self.protocol.handle_event(EventIn::StreamOpened {
-                            stream_id,
-                            bidi: true,
-                            local: true,
-                        })?;
-                    }
-                    WriteOutput::SendStream {
+        while let Some(write) = self.protocol.poll_write() {
+            match write {
+                WriteOutput::OpenBiStream { purpose } => {
+                    let stream_id = self.transport.open_bi_stream(purpose)?;
+                    self.protocol.handle_event(EventIn::StreamOpened {
                         stream_id,
-                        bytes,
-                        fin,
-                    } => self.transport.send_stream(stream_id, bytes, fin)?,
-                    WriteOutput::SendDatagram(bytes) => self.transport.send_datagram(bytes)?,
-                    WriteOutput::Close { code, reason } => self.transport.close(code, reason)?,
+                        bidi: true,
+                        local: true,
+                    })?;
                 }
-            }
-
-            while let Some(event) = self.protocol.poll_event() {
-                progressed = true;
-                self.events.push_back(event);
-            }
-
-            if !progressed {
-                break;
+                WriteOutput::SendStream {
+                    stream_id,
+                    bytes,
+                    fin,
+                } => {
+                    if let Some(bytes) = self
+                        .transport
+                        .send_stream_and_reclaim(stream_id, bytes, fin)?
+                    {
+                        self.protocol.recycle_buffer(bytes);
+                    }
+                }
+                WriteOutput::SendDatagram(bytes) => self.transport.send_datagram(bytes)?,
+                WriteOutput::ResetStream {
+                    stream_id,
+                    error_code,
+                } => self.transport.reset_stream(stream_id, error_code)?,
+                WriteOutput::StopSending {
+                    stream_id,
+                    error_code,
+                } => self.transport.stop_sending(stream_id, error_code)?,
+                WriteOutput::Close { code, reason } => self.transport.close(code, reason)?,
             }
         }
 
@@ -164,11 +249,16 @@ impl<T: SessionTransport> SessionDriver<T> {
 mod test {
     use super::*;
     use crate::message::client_setup::ClientSetup;
+    use crate::message::max_request_id::MaxRequestId;
     use crate::message::message_framer::MessageFramer;
     use crate::message::message_parser::{MessageParser, MessageParserEvent};
     use crate::message::object::{ObjectForwardingPreference, ObjectStatus};
     use crate::message::subscribe::Subscribe;
+    use crate::message::unsubscribe::UnSubscribe;
     use crate::message::{ControlMessage, FilterType, FullSequence, Role, Version};
+    use crate::protocol::{
+        ObjectEventQueue, DEFAULT_BUFFER_POOL_SIZE, DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+    };
 
     #[derive(Default)]
     struct FakeTransport {
@@ -176,7 +266,12 @@ mod test {
         opened_streams: Vec<(StreamPurpose, StreamId)>,
         sent_streams: Vec<(StreamId, BytesMut, bool)>,
         sent_datagrams: Vec<Bytes>,
-        closes: Vec<(u64, String)>,
+        resets: Vec<(StreamId, StreamErrorCode)>,
+        closes: Vec<(SessionErrorCode, String)>,
+        max_datagram_size: Option<usize>,
+        // Hand stream buffers back, recording where each one lived.
+        reclaim_buffers: bool,
+        buffer_addresses: Vec<usize>,
     }
 
     impl FakeTransport {
@@ -201,15 +296,40 @@ mod test {
             Ok(())
         }
 
+        fn send_stream_and_reclaim(
+            &mut self,
+            stream_id: StreamId,
+            bytes: BytesMut,
+            fin: bool,
+        ) -> Result<Option<BytesMut>> {
+            if !self.reclaim_buffers {
+                self.send_stream(stream_id, bytes, fin)?;
+                return Ok(None);
+            }
+            self.buffer_addresses.push(bytes.as_ptr() as usize);
+            self.sent_streams
+                .push((stream_id, BytesMut::from(&bytes[..]), fin));
+            Ok(Some(bytes))
+        }
+
         fn send_datagram(&mut self, bytes: Bytes) -> Result<()> {
             self.sent_datagrams.push(bytes);
             Ok(())
         }
 
-        fn close(&mut self, code: u64, reason: String) -> Result<()> {
+        fn reset_stream(&mut self, stream_id: StreamId, error_code: StreamErrorCode) -> Result<()> {
+            self.resets.push((stream_id, error_code));
+            Ok(())
+        }
+
+        fn close(&mut self, code: SessionErrorCode, reason: String) -> Result<()> {
             self.closes.push((code, reason));
             Ok(())
         }
+
+        fn max_datagram_size(&self) -> Option<usize> {
+            self.max_datagram_size
+        }
     }
 
     fn client_config(use_web_transport: bool) -> Config {
@@ -219,6 +339,14 @@ mod test {
             use_web_transport,
             path: "/moq".to_string(),
             deliver_partial_objects: false,
+            additional_versions: vec![],
+            object_event_queue: ObjectEventQueue::Unbounded,
+            reorder_objects: false,
+            max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+            buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+            announce_retry: None,
         }
     }
 
@@ -229,6 +357,14 @@ mod test {
             use_web_transport,
             path: "/moq".to_string(),
             deliver_partial_objects: false,
+            additional_versions: vec![],
+            object_event_queue: ObjectEventQueue::Unbounded,
+            reorder_objects: false,
+            max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+            buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+            announce_retry: None,
         }
     }
 
@@ -262,6 +398,49 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn driver_reuses_reclaimed_stream_buffers() -> Result<()> {
+        let mut transport = FakeTransport::new(101);
+        transport.reclaim_buffers = true;
+        let mut driver = SessionDriver::new(server_config(false), transport);
+
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        driver.on_stream_data(5, client_setup_bytes.freeze(), false)?;
+        let _ = driver.poll_event();
+        for max_request_id in [200, 300] {
+            driver.handle_command(Command::MaxRequestId { max_request_id })?;
+        }
+
+        // SERVER_SETUP's buffer carries both MAX_REQUEST_IDs in turn.
+        let addresses = &driver.transport().buffer_addresses;
+        assert_eq!(addresses.len(), 3);
+        assert!(addresses.iter().all(|address| *address == addresses[0]));
+        let sent = &driver.transport().sent_streams;
+        for ((_, bytes, _), max_request_id) in sent[1..].iter().zip([200, 300]) {
+            let mut parser = MessageParser::new(false);
+            parser.process_data(&mut bytes.as_ref(), false);
+            assert_eq!(
+                parser.poll_event(),
+                Some(MessageParserEvent::ControlMessage(
+                    ControlMessage::MaxRequestId(MaxRequestId { max_request_id })
+                ))
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn server_driver_opens_data_stream_and_sends_track_object() -> Result<()> {
         let transport = FakeTransport::new(101);
@@ -274,6 +453,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -354,6 +536,77 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn driver_resets_data_streams_of_unsubscribed_track() -> Result<()> {
+        let transport = FakeTransport::new(101);
+        let mut driver = SessionDriver::new(server_config(false), transport);
+
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        driver.on_stream_data(5, client_setup_bytes.freeze(), false)?;
+        let _ = driver.poll_event();
+
+        driver.handle_command(Command::RegisterLocalTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            forwarding_preference: ObjectForwardingPreference::Track,
+            next_sequence: None,
+        })?;
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 7,
+                track_alias: 9,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type: FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+                authorization_info: None,
+            }),
+            &mut subscribe_bytes,
+        )?;
+        driver.on_stream_data(5, subscribe_bytes.freeze(), false)?;
+        let _ = driver.poll_event();
+        driver.handle_command(Command::SubscribeOk {
+            subscribe_id: 7,
+            expires: 0,
+            largest_group_object: None,
+        })?;
+        driver.handle_command(Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id: 0,
+            send_order: 0,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from_static(b"frame"),
+        })?;
+        assert!(driver.transport().resets.is_empty());
+
+        let mut unsubscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::UnSubscribe(UnSubscribe { subscribe_id: 7 }),
+            &mut unsubscribe_bytes,
+        )?;
+        driver.on_stream_data(5, unsubscribe_bytes.freeze(), false)?;
+
+        assert_eq!(
+            driver.transport().resets,
+            vec![(101, StreamErrorCode::Cancelled)]
+        );
+        Ok(())
+    }
+
     #[test]
     fn driver_surfaces_protocol_close_to_transport() -> Result<()> {
         let transport = FakeTransport::new(1);
@@ -374,10 +627,111 @@ mod test {
         assert_eq!(
             driver.transport().closes,
             vec![(
-                1,
+                SessionErrorCode::ProtocolViolation,
                 "received SUBSCRIBE_DONE before session setup".to_string()
             )]
         );
         Ok(())
     }
+
+    #[test]
+    fn driver_close_reaches_transport_and_rejects_later_commands() -> Result<()> {
+        let transport = FakeTransport::new(7);
+        let mut driver = SessionDriver::new(client_config(false), transport);
+        driver.on_transport_connected()?;
+
+        driver.close(SessionErrorCode::GoawayTimeout, "going away")?;
+
+        assert_eq!(
+            driver.transport().closes,
+            vec![(SessionErrorCode::GoawayTimeout, "going away".to_string())]
+        );
+        assert_eq!(
+            driver.handle_command(Command::RegisterLocalTrack {
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                forwarding_preference: ObjectForwardingPreference::Track,
+                next_sequence: None,
+            }),
+            Err(crate::Error::ErrOther("session closed".to_string()))
+        );
+        assert!(driver
+            .close(SessionErrorCode::GoawayTimeout, "going away")
+            .is_err());
+        assert_eq!(driver.transport().closes.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn driver_rejects_datagram_larger_than_transport_allows() -> Result<()> {
+        let mut transport = FakeTransport::new(101);
+        transport.max_datagram_size = Some(32);
+        let mut driver = SessionDriver::new(server_config(false), transport);
+
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        driver.on_stream_data(5, client_setup_bytes.freeze(), false)?;
+        let _ = driver.poll_event();
+
+        driver.handle_command(Command::RegisterLocalTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            forwarding_preference: ObjectForwardingPreference::Datagram,
+            next_sequence: None,
+        })?;
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 7,
+                track_alias: 9,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type: FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+                authorization_info: None,
+            }),
+            &mut subscribe_bytes,
+        )?;
+        driver.on_stream_data(5, subscribe_bytes.freeze(), false)?;
+        let _ = driver.poll_event();
+        driver.handle_command(Command::SubscribeOk {
+            subscribe_id: 7,
+            expires: 60,
+            largest_group_object: None,
+        })?;
+
+        let result = driver.handle_command(Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id: 0,
+            send_order: 0,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from(vec![0u8; 64]),
+        });
+        assert!(result.is_err());
+        assert!(driver.transport().sent_datagrams.is_empty());
+
+        driver.handle_command(Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id: 0,
+            send_order: 0,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from_static(b"frame"),
+        })?;
+        assert_eq!(driver.transport().sent_datagrams.len(), 1);
+        Ok(())
+    }
 }
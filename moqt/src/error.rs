@@ -31,6 +31,8 @@ pub enum Error {
     ErrUnsupportedVersion(u64),
     #[error("invalid role: {0}")]
     ErrInvalidRole(u64),
+    #[error("invalid subscribe done code: {0}")]
+    ErrInvalidSubscribeDoneCode(u64),
     #[error("invalid object type due to {0}")]
     ErrInvalidObjectType(String),
     #[error("track or group forward preference requires length")]
@@ -49,7 +51,36 @@ pub enum Error {
     ErrStreamNotExisted,
     #[error("stream closed")]
     ErrStreamClosed,
+    #[error("io error: {0}")]
+    ErrIo(String),
+    #[error("object event queue is full")]
+    ErrEventQueueFull,
 
     #[error("invalid string")]
     ErrInvalidString(#[from] FromUtf8Error),
 }
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::ErrIo(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_from_io_error() -> Result<()> {
+        let read = || -> Result<()> {
+            Err(std::io::Error::new(
+                ErrorKind::ConnectionReset,
+                "peer went away",
+            ))?;
+            Ok(())
+        };
+        assert_eq!(read(), Err(Error::ErrIo("peer went away".to_string())));
+        Ok(())
+    }
+}
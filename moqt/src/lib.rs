@@ -2,6 +2,7 @@
 #![allow(dead_code)]
 
 mod connection;
+pub mod debug;
 mod driver;
 mod error;
 mod message;
@@ -9,7 +10,7 @@ mod protocol;
 mod serde;
 mod session;
 
-pub use connection::Connection;
+pub use connection::{Connection, SessionErrorCode, StreamErrorCode};
 pub use driver::{SessionDriver, SessionTransport};
 pub use error::{Error, Result};
 pub use message::announce::Announce;
@@ -20,30 +21,46 @@ pub use message::client_setup::ClientSetup;
 pub use message::fetch::{Fetch, FetchTarget, FetchType, JoiningFetch, StandaloneFetch};
 pub use message::fetch_cancel::FetchCancel;
 pub use message::fetch_ok::FetchOk;
-pub use message::go_away::GoAway;
+pub use message::go_away::{GoAway, SessionUri};
 pub use message::max_request_id::MaxRequestId;
 pub use message::message_framer::MessageFramer;
-pub use message::message_parser::{ErrorCode, MessageParser, MessageParserEvent};
-pub use message::object::{ObjectForwardingPreference, ObjectHeader, ObjectStatus};
+pub use message::message_parser::{
+    ErrorCode, MessageObserver, MessageParser, MessageParserEvent, ParseErrorSeverity,
+    ParserCheckpoint, ParserStats, SniffResult,
+};
+pub use message::object::{
+    DatagramFragment, ObjectForwardingPreference, ObjectHeader, ObjectStatus,
+};
 pub use message::requests_blocked::RequestsBlocked;
 pub use message::server_setup::ServerSetup;
 pub use message::subscribe::Subscribe;
-pub use message::subscribe_done::SubscribeDone;
-pub use message::subscribe_error::SubscribeError;
+pub use message::subscribe_done::{SubscribeDone, SubscribeDoneCode};
+pub use message::subscribe_error::{SubscribeError, SubscribeErrorCode};
 pub use message::subscribe_ok::SubscribeOk;
 pub use message::subscribe_update::SubscribeUpdate;
-pub use message::track_status::TrackStatus;
+pub use message::track_status::{TrackStatus, TrackStatusCode};
 pub use message::track_status_request::TrackStatusRequest;
 pub use message::unannounce::UnAnnounce;
 pub use message::unsubscribe::UnSubscribe;
-pub use message::{ControlMessage, FilterType, FullSequence, FullTrackName, Role, Version};
+pub use message::{
+    ControlMessage, FilterType, FullSequence, FullTrackName, NamespaceLayout, Role, Version,
+};
 pub use protocol::{
-    Command, Config as ProtocolConfig, EventIn, EventOut, Perspective as ProtocolPerspective,
-    ReadInput, SessionCore, StreamPurpose, WriteOutput,
+    AnnounceRetryPolicy, Command, Config as ProtocolConfig, EventIn, EventOut, ObjectEventQueue,
+    Perspective as ProtocolPerspective, ReadInput, SessionCore, StreamPurpose, SubscribeAuthorizer,
+    WriteOutput, DEFAULT_BUFFER_POOL_SIZE, DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+};
+pub use serde::{
+    parameters::{ParameterKey, Parameters},
+    varint::VarInt,
+    Deserializer, Serializer,
 };
-pub use serde::{parameters::Parameters, varint::VarInt, Deserializer, Serializer};
 pub use session::config::{Config as SessionConfig, Perspective as SessionPerspective};
-pub use session::remote_track::RemoteTrackOnObjectFragment;
+pub use session::object_cache::CachedObject;
+pub use session::remote_track::{
+    RemoteTrack, RemoteTrackOnObjectFragment, DATAGRAM_REASSEMBLY_TIMEOUT,
+};
+pub use session::send_order::SendOrderPolicy;
 pub use session::Session;
 
 /// match between client and server perspective, since there may be a proxy
@@ -1,50 +1,33 @@
-use crate::message::message_parser::ErrorCode;
+use crate::message::validate_track_namespace;
 use crate::serde::parameters::ParameterKey;
-use crate::{Deserializer, Error, Parameters, Result, Serializer};
+use crate::{Deserializer, Parameters, Result, Serializer};
 use bytes::{Buf, BufMut};
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct Announce {
     pub track_namespace: String,
-    pub authorization_info: Option<String>,
+    pub parameters: Parameters,
+}
+
+impl Announce {
+    pub fn authorization_info(&self) -> Result<Option<String>> {
+        self.parameters.get(ParameterKey::AuthorizationInfo)
+    }
 }
 
 impl Deserializer for Announce {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (track_namespace, tnsl) = String::deserialize(r)?;
+        validate_track_namespace(&track_namespace, "ANNOUNCE")?;
 
-        let mut authorization_info: Option<String> = None;
-        let (num_params, mut pl) = u64::deserialize(r)?;
-        // Parse parameters
-        for _ in 0..num_params {
-            let (key, kl) = u64::deserialize(r)?;
-            pl += kl;
-            let (size, sl) = usize::deserialize(r)?;
-            pl += sl;
-
-            if r.remaining() < size {
-                return Err(Error::ErrBufferTooShort);
-            }
-
-            if key == ParameterKey::AuthorizationInfo as u64 {
-                if authorization_info.is_some() {
-                    return Err(Error::ErrParseError(
-                        ErrorCode::ProtocolViolation,
-                        "AUTHORIZATION_INFO parameter appears twice in ANNOUNCE".to_string(),
-                    ));
-                }
-                let mut buf = vec![0; size];
-                r.copy_to_slice(&mut buf);
-                pl += size;
-
-                authorization_info = Some(String::from_utf8(buf)?);
-            }
-        }
+        let (parameters, pl) = Parameters::deserialize_in_message(r, "ANNOUNCE")?;
+        // make sure authorization_info, if any, is a well-formed string
+        parameters.get::<String>(ParameterKey::AuthorizationInfo)?;
 
         Ok((
             Self {
                 track_namespace,
-                authorization_info,
+                parameters,
             },
             tnsl + pl,
         ))
@@ -54,18 +37,7 @@ impl Deserializer for Announce {
 impl Serializer for Announce {
     fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
         let mut l = self.track_namespace.serialize(w)?;
-
-        if let Some(authorization_info) = self.authorization_info.as_ref() {
-            let mut parameters = Parameters::new();
-            parameters.insert(
-                ParameterKey::AuthorizationInfo,
-                authorization_info.to_string(),
-            )?;
-            l += parameters.serialize(w)?;
-        } else {
-            l += 0u64.serialize(w)?;
-        }
-
+        l += self.parameters.serialize(w)?;
         Ok(l)
     }
 }
@@ -73,7 +45,9 @@ impl Serializer for Announce {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::message::message_parser::ErrorCode;
     use crate::message::ControlMessage;
+    use crate::Error;
     use std::io::Cursor;
 
     #[test]
@@ -84,9 +58,11 @@ mod test {
             0x02, 0x03, 0x62, 0x61, 0x72, // authorization_info = "bar"
         ];
 
+        let mut parameters = Parameters::new();
+        parameters.insert(ParameterKey::AuthorizationInfo, "bar".to_string())?;
         let expected_message = ControlMessage::Announce(Announce {
             track_namespace: "foo".to_string(),
-            authorization_info: Some("bar".to_string()),
+            parameters,
         });
 
         let mut cursor: Cursor<&[u8]> = Cursor::new(expected_packet.as_ref());
@@ -110,7 +86,7 @@ mod test {
 
         let expected_message = ControlMessage::Announce(Announce {
             track_namespace: "foo".to_string(),
-            authorization_info: None,
+            parameters: Parameters::new(),
         });
 
         let mut cursor: Cursor<&[u8]> = Cursor::new(expected_packet.as_ref());
@@ -124,4 +100,61 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_announce_keeps_other_parameters() -> Result<()> {
+        let packet: Vec<u8> = vec![
+            0x06, 0x03, 0x66, 0x6f, 0x6f, // track_namespace = "foo"
+            0x02, // 2 parameters
+            0x02, 0x03, 0x62, 0x61, 0x72, // authorization_info = "bar"
+            0x05, 0x01, 0x07, // unknown parameter 0x05
+        ];
+
+        let (actual_message, actual_len) = ControlMessage::deserialize(&mut packet.as_slice())?;
+        assert_eq!(packet.len(), actual_len);
+        let ControlMessage::Announce(announce) = &actual_message else {
+            panic!("expected ANNOUNCE");
+        };
+        assert_eq!(announce.authorization_info()?, Some("bar".to_string()));
+        assert_eq!(announce.parameters.0.get(&0x05), Some(&vec![0x07]));
+
+        let mut actual_packet = vec![];
+        let _ = actual_message.serialize(&mut actual_packet)?;
+        assert_eq!(packet, actual_packet);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_announce_duplicate_authorization_info() {
+        let packet: Vec<u8> = vec![
+            0x06, 0x03, 0x66, 0x6f, 0x6f, // track_namespace = "foo"
+            0x02, // 2 parameters
+            0x02, 0x03, 0x62, 0x61, 0x72, // authorization_info = "bar"
+            0x02, 0x03, 0x62, 0x61, 0x72, // authorization_info = "bar"
+        ];
+        assert_eq!(
+            ControlMessage::deserialize(&mut packet.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "AUTHORIZATION_INFO parameter appears twice in ANNOUNCE".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_announce_empty_namespace() -> Result<()> {
+        let packet: Vec<u8> = vec![
+            0x06, 0x00, // track_namespace = ""
+            0x00, // 0 parameters
+        ];
+        assert_eq!(
+            ControlMessage::deserialize(&mut packet.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "empty track_namespace in ANNOUNCE".to_string()
+            ))
+        );
+        Ok(())
+    }
 }
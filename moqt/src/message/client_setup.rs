@@ -1,5 +1,5 @@
 use crate::message::message_parser::ErrorCode;
-use crate::message::{Role, Version};
+use crate::message::{parse_bool_param, Role, Version};
 use crate::serde::parameters::ParameterKey;
 use crate::{Deserializer, Error, Parameters, Result, Serializer};
 use bytes::{Buf, BufMut};
@@ -10,6 +10,12 @@ pub struct ClientSetup {
     pub role: Option<Role>,
     pub path: Option<String>,
     pub uses_web_transport: bool,
+    /// Whether the client can handle objects delivered in fragments.
+    pub partial_object_delivery: bool,
+    /// Whether the client can reassemble objects split across datagrams.
+    pub datagram_fragmentation: bool,
+    /// Whether the client can read control messages preceded by their length.
+    pub length_prefixed_control: bool,
 }
 
 impl ClientSetup {
@@ -19,11 +25,43 @@ impl ClientSetup {
             ..Default::default()
         }
     }
+
+    /// Checks PATH against the transport the message arrived on: a raw QUIC
+    /// client must send it, a WebTransport client must not, since the URL
+    /// already carries the path.
+    pub fn validate_path(&self, uses_web_transport: bool) -> Result<()> {
+        if uses_web_transport && self.path.is_some() {
+            return Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "WebTransport connection is using PATH parameter in SETUP".to_string(),
+            ));
+        }
+        if !uses_web_transport && self.path.is_none() {
+            return Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "PATH SETUP parameter missing from Client message over QUIC".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates PATH for |uses_web_transport| and records the transport.
+    pub(crate) fn bind_transport(&mut self, uses_web_transport: bool) -> Result<()> {
+        self.validate_path(uses_web_transport)?;
+        self.uses_web_transport = uses_web_transport;
+        Ok(())
+    }
 }
 
 impl Deserializer for ClientSetup {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (number_supported_versions, mut tl) = usize::deserialize(r)?;
+        if number_supported_versions == 0 {
+            return Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "CLIENT_SETUP offers no versions".to_string(),
+            ));
+        }
         let mut supported_versions = Vec::with_capacity(number_supported_versions);
         for _ in 0..number_supported_versions {
             let (version, vl) = Version::deserialize(r)?;
@@ -36,6 +74,9 @@ impl Deserializer for ClientSetup {
 
         let mut role: Option<Role> = None;
         let mut path: Option<String> = None;
+        let mut partial_object_delivery: Option<bool> = None;
+        let mut datagram_fragmentation: Option<bool> = None;
+        let mut length_prefixed_control: Option<bool> = None;
 
         // Parse parameters
         for _ in 0..num_params {
@@ -83,6 +124,31 @@ impl Deserializer for ClientSetup {
                 tl += size;
 
                 path = Some(String::from_utf8(buf)?);
+            } else if key == ParameterKey::PartialObjectDelivery as u64 {
+                tl += parse_bool_param(
+                    "PARTIAL_OBJECT_DELIVERY",
+                    &mut partial_object_delivery,
+                    size,
+                    r,
+                )?;
+            } else if key == ParameterKey::DatagramFragmentation as u64 {
+                tl += parse_bool_param(
+                    "DATAGRAM_FRAGMENTATION",
+                    &mut datagram_fragmentation,
+                    size,
+                    r,
+                )?;
+            } else if key == ParameterKey::LengthPrefixedControl as u64 {
+                tl += parse_bool_param(
+                    "LENGTH_PREFIXED_CONTROL",
+                    &mut length_prefixed_control,
+                    size,
+                    r,
+                )?;
+            } else {
+                // Parameters this implementation doesn't know about are skipped.
+                r.advance(size);
+                tl += size;
             }
         }
 
@@ -99,6 +165,9 @@ impl Deserializer for ClientSetup {
                 role,
                 path,
                 uses_web_transport: false,
+                partial_object_delivery: partial_object_delivery.unwrap_or(false),
+                datagram_fragmentation: datagram_fragmentation.unwrap_or(false),
+                length_prefixed_control: length_prefixed_control.unwrap_or(false),
             },
             tl,
         ))
@@ -121,6 +190,15 @@ impl Serializer for ClientSetup {
                 parameters.insert(ParameterKey::Path, path.to_string())?;
             }
         }
+        if self.partial_object_delivery {
+            parameters.insert(ParameterKey::PartialObjectDelivery, true)?;
+        }
+        if self.datagram_fragmentation {
+            parameters.insert(ParameterKey::DatagramFragmentation, true)?;
+        }
+        if self.length_prefixed_control {
+            parameters.insert(ParameterKey::LengthPrefixedControl, true)?;
+        }
         l += parameters.serialize(w)?;
 
         Ok(l)
@@ -182,4 +260,108 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_client_setup_skips_unknown_parameter() -> Result<()> {
+        let packet: Vec<u8> = vec![
+            0x40, 0x40, // type
+            0x01, // versions
+            192, 0, 0, 0, 255, 0, 0, 4,    // Draft04
+            0x02, // 2 parameters
+            0x00, 0x01, 0x03, // role = PubSub
+            0x21, 0x02, 0xaa, 0xbb, // unknown parameter, skipped
+        ];
+
+        let (actual_message, actual_len) = ControlMessage::deserialize(&mut packet.as_slice())?;
+        assert_eq!(
+            actual_message,
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                ..Default::default()
+            })
+        );
+        assert_eq!(packet.len(), actual_len);
+
+        let mut actual_packet = vec![];
+        let _ = actual_message.serialize(&mut actual_packet)?;
+        assert_eq!(
+            actual_packet,
+            vec![
+                0x40, 0x40, 0x01, 192, 0, 0, 0, 255, 0, 0, 4,    // type and versions
+                0x01, // 1 parameter
+                0x00, 0x01, 0x03, // role = PubSub
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_setup_partial_object_delivery() -> Result<()> {
+        let packet: Vec<u8> = vec![
+            0x40, 0x40, // type
+            0x01, // versions
+            192, 0, 0, 0, 255, 0, 0, 4,    // Draft04
+            0x02, // 2 parameters
+            0x00, 0x01, 0x03, // role = PubSub
+            0x3e, 0x01, 0x01, // partial_object_delivery = true
+        ];
+
+        let (actual_message, actual_len) = ControlMessage::deserialize(&mut packet.as_slice())?;
+        assert_eq!(
+            actual_message,
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                partial_object_delivery: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(packet.len(), actual_len);
+
+        let mut actual_packet = vec![];
+        let _ = actual_message.serialize(&mut actual_packet)?;
+        assert_eq!(actual_packet, packet);
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_setup_validate_path() {
+        let mut client_setup = ClientSetup::new(false);
+        assert_eq!(
+            client_setup.validate_path(false),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "PATH SETUP parameter missing from Client message over QUIC".to_string(),
+            ))
+        );
+        assert_eq!(client_setup.validate_path(true), Ok(()));
+
+        client_setup.path = Some("/moq".to_string());
+        assert_eq!(client_setup.validate_path(false), Ok(()));
+        assert_eq!(
+            client_setup.validate_path(true),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "WebTransport connection is using PATH parameter in SETUP".to_string(),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_client_setup_without_versions() {
+        let packet: Vec<u8> = vec![
+            0x40, 0x40, // type
+            0x00, // versions
+            0x01, // 1 parameter
+            0x00, 0x01, 0x03, // role = PubSub
+        ];
+        assert_eq!(
+            ControlMessage::deserialize(&mut packet.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "CLIENT_SETUP offers no versions".to_string(),
+            ))
+        );
+    }
 }
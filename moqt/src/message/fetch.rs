@@ -1,5 +1,5 @@
 use crate::message::message_parser::ErrorCode;
-use crate::message::{FullSequence, FullTrackName};
+use crate::message::{FullSequence, FullTrackName, NamespaceLayout};
 use crate::serde::parameters::ParameterKey;
 use crate::{Deserializer, Error, Parameters, Result, Serializer};
 use bytes::{Buf, BufMut};
@@ -83,16 +83,19 @@ impl Fetch {
         }
         Ok(())
     }
-}
 
-impl Deserializer for Fetch {
-    fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
+    /// Reads a FETCH whose track namespace is in |layout|.
+    pub fn deserialize_with_layout<R: Buf>(
+        layout: NamespaceLayout,
+        r: &mut R,
+    ) -> Result<(Self, usize)> {
         let (request_id, request_len) = u64::deserialize(r)?;
         let (fetch_type, type_len) = FetchType::deserialize(r)?;
 
         let (target, body_len) = match fetch_type {
             FetchType::Standalone => {
-                let (full_track_name, name_len) = FullTrackName::deserialize(r)?;
+                let (full_track_name, name_len) =
+                    FullTrackName::deserialize_with_layout(layout, r)?;
                 let (start, start_len) = FullSequence::deserialize(r)?;
                 let (mut end, end_len) = FullSequence::deserialize(r)?;
                 if end.object_id == 0 {
@@ -170,16 +173,19 @@ impl Deserializer for Fetch {
             request_len + type_len + body_len + param_len,
         ))
     }
-}
 
-impl Serializer for Fetch {
-    fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
+    /// Writes the FETCH with its track namespace in |layout|.
+    pub fn serialize_with_layout<W: BufMut>(
+        &self,
+        layout: NamespaceLayout,
+        w: &mut W,
+    ) -> Result<usize> {
         let mut len = self.request_id.serialize(w)?;
         match &self.target {
             FetchTarget::Standalone(fetch) => {
                 Self::validate_range(fetch.start, fetch.end)?;
                 len += FetchType::Standalone.serialize(w)?;
-                len += fetch.full_track_name.serialize(w)?;
+                len += fetch.full_track_name.serialize_with_layout(layout, w)?;
                 len += fetch.start.serialize(w)?;
                 let mut end = fetch.end;
                 if end.object_id == u64::MAX {
@@ -201,17 +207,27 @@ impl Serializer for Fetch {
             }
         }
 
+        let mut parameters = Parameters::new();
         if let Some(authorization_info) = self.authorization_info.as_ref() {
-            let mut parameters = Parameters::new();
             parameters.insert(
                 ParameterKey::AuthorizationInfo,
                 authorization_info.to_string(),
             )?;
-            len += parameters.serialize(w)?;
-        } else {
-            len += 0u64.serialize(w)?;
         }
+        len += parameters.serialize(w)?;
 
         Ok(len)
     }
 }
+
+impl Deserializer for Fetch {
+    fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
+        Self::deserialize_with_layout(NamespaceLayout::String, r)
+    }
+}
+
+impl Serializer for Fetch {
+    fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
+        self.serialize_with_layout(NamespaceLayout::String, w)
+    }
+}
@@ -1,11 +1,81 @@
-use crate::{Deserializer, Result, Serializer};
+use crate::{Deserializer, Error, Result, Serializer};
 use bytes::{Buf, BufMut};
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct GoAway {
     pub new_session_uri: String,
 }
 
+impl GoAway {
+    /// Parses new_session_uri. An empty URI asks the client to reconnect to
+    /// the current one and yields None.
+    pub fn session_uri(&self) -> Result<Option<SessionUri>> {
+        if self.new_session_uri.is_empty() {
+            return Ok(None);
+        }
+        self.new_session_uri.parse().map(Some)
+    }
+}
+
+/// An absolute URI a client migrates to after GOAWAY, split as
+/// scheme://authority followed by the path, query and fragment.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SessionUri {
+    pub scheme: String,
+    pub authority: String,
+    pub path: String,
+}
+
+impl fmt::Display for SessionUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}{}", self.scheme, self.authority, self.path)
+    }
+}
+
+impl FromStr for SessionUri {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(Error::ErrOther(format!(
+                "session URI {:?} contains whitespace or control characters",
+                s
+            )));
+        }
+        let Some((scheme, rest)) = s.split_once("://") else {
+            return Err(Error::ErrOther(format!(
+                "session URI {:?} is not scheme://authority",
+                s
+            )));
+        };
+        // RFC 3986: scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )
+        let mut chars = scheme.chars();
+        if !chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            || !chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        {
+            return Err(Error::ErrOther(format!(
+                "session URI {:?} has an invalid scheme",
+                s
+            )));
+        }
+        let authority_len = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let (authority, path) = rest.split_at(authority_len);
+        if authority.is_empty() {
+            return Err(Error::ErrOther(format!(
+                "session URI {:?} has an empty authority",
+                s
+            )));
+        }
+        Ok(Self {
+            scheme: scheme.to_string(),
+            authority: authority.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
 impl Deserializer for GoAway {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (new_session_uri, nsul) = String::deserialize(r)?;
@@ -44,4 +114,36 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_session_uri() -> Result<()> {
+        let go_away = GoAway {
+            new_session_uri: String::new(),
+        };
+        assert_eq!(go_away.session_uri()?, None);
+
+        let go_away = GoAway {
+            new_session_uri: "https://relay.example.com:4443/moq?room=1".to_string(),
+        };
+        let uri = go_away.session_uri()?.unwrap();
+        assert_eq!(uri.scheme, "https");
+        assert_eq!(uri.authority, "relay.example.com:4443");
+        assert_eq!(uri.path, "/moq?room=1");
+        assert_eq!(uri.to_string(), go_away.new_session_uri);
+
+        for malformed in [
+            "relay.example.com",
+            "://relay.example.com",
+            "1moq://relay",
+            "moq:///path",
+            "moq://relay/a b",
+        ] {
+            assert!(
+                malformed.parse::<SessionUri>().is_err(),
+                "{:?} should not parse",
+                malformed
+            );
+        }
+        Ok(())
+    }
 }
@@ -1,7 +1,14 @@
-use crate::message::object::{ObjectForwardingPreference, ObjectHeader, ObjectStatus};
-use crate::message::{ControlMessage, MessageType};
-use crate::{Error, Result, Serializer};
-use bytes::{BufMut, Bytes};
+use crate::message::object::{
+    ObjectForwardingPreference, ObjectHeader, ObjectStatus, MAX_DATAGRAM_FRAGMENTS,
+    OBJECT_DATAGRAM_FRAGMENT_TYPE,
+};
+use crate::message::{ControlMessage, MessageType, NamespaceLayout};
+#[cfg(test)]
+use crate::Deserializer;
+use crate::{Error, Result, Serializer, VarInt};
+use bytes::{BufMut, Bytes, BytesMut};
+#[cfg(test)]
+use std::collections::HashMap;
 
 const FETCH_STREAM_TYPE: u64 = 0x05;
 const FETCH_HAS_OBJECT_ID: u64 = 0x04;
@@ -12,6 +19,24 @@ const FETCH_IS_DATAGRAM_LIKE: u64 = 0x40;
 
 pub struct MessageFramer;
 
+/// Framing options for interop testing against peers that expect specific
+/// encodings. Production framing always writes minimal-length varints.
+#[cfg(test)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MessageFramerTestingConfig {
+    forced_varint_lengths: HashMap<String, usize>,
+}
+
+#[cfg(test)]
+impl MessageFramerTestingConfig {
+    /// Writes every varint field called |field|, named as in debug::annotate,
+    /// in exactly |length| bytes.
+    pub(crate) fn with_forced_varint_length(mut self, field: &str, length: usize) -> Self {
+        self.forced_varint_lengths.insert(field.to_string(), length);
+        self
+    }
+}
+
 impl MessageFramer {
     pub fn serialize_control_message<W: BufMut>(
         control_message: ControlMessage,
@@ -20,6 +45,83 @@ impl MessageFramer {
         control_message.serialize(w)
     }
 
+    /// Like serialize_control_message, with the track namespace in |layout|.
+    pub fn serialize_control_message_with_layout<W: BufMut>(
+        control_message: &ControlMessage,
+        layout: NamespaceLayout,
+        w: &mut W,
+    ) -> Result<usize> {
+        control_message.serialize_with_layout(layout, w)
+    }
+
+    /// Writes |control_message| preceded by its length as a varint, for
+    /// control streams parsed with MessageParser::new_length_prefixed_control.
+    pub fn serialize_length_prefixed_control_message<W: BufMut>(
+        control_message: &ControlMessage,
+        w: &mut W,
+    ) -> Result<usize> {
+        Self::serialize_length_prefixed_control_message_with_layout(
+            control_message,
+            NamespaceLayout::String,
+            w,
+        )
+    }
+
+    /// Like serialize_length_prefixed_control_message, with the track
+    /// namespace in |layout|.
+    pub fn serialize_length_prefixed_control_message_with_layout<W: BufMut>(
+        control_message: &ControlMessage,
+        layout: NamespaceLayout,
+        w: &mut W,
+    ) -> Result<usize> {
+        let mut message = vec![];
+        control_message.serialize_with_layout(layout, &mut message)?;
+        let mut l = message.len().serialize(w)?;
+        l += Bytes::from(message).serialize(w)?;
+        Ok(l)
+    }
+
+    /// Serializes |control_message| into a newly allocated buffer.
+    pub fn to_vec(control_message: &ControlMessage) -> Result<Vec<u8>> {
+        let mut buffer = vec![];
+        control_message.serialize(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Like to_vec, but re-encodes the varint fields named in |config| at
+    /// their forced lengths.
+    #[cfg(test)]
+    pub(crate) fn to_vec_for_testing(
+        control_message: &ControlMessage,
+        config: &MessageFramerTestingConfig,
+    ) -> Result<Vec<u8>> {
+        let minimal = Self::to_vec(control_message)?;
+        let forced: Vec<(usize, usize)> = crate::debug::varint_fields(&minimal)?
+            .into_iter()
+            .filter_map(|(offset, name)| {
+                let length = config.forced_varint_lengths.get(&name)?;
+                Some((offset, *length))
+            })
+            .collect();
+        Self::force_varint_lengths(&minimal, &forced)
+    }
+
+    /// Copies |bytes|, re-encoding the varint starting at each offset in
+    /// |forced| in exactly the paired number of bytes. Offsets must ascend.
+    #[cfg(test)]
+    pub(crate) fn force_varint_lengths(bytes: &[u8], forced: &[(usize, usize)]) -> Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(bytes.len());
+        let mut offset = 0;
+        for &(field_offset, length) in forced {
+            buffer.extend_from_slice(&bytes[offset..field_offset]);
+            let (value, len) = VarInt::deserialize(&mut &bytes[field_offset..])?;
+            value.serialize_with_length(&mut buffer, length)?;
+            offset = field_offset + len;
+        }
+        buffer.extend_from_slice(&bytes[offset..]);
+        Ok(buffer)
+    }
+
     pub fn serialize_object_header<W: BufMut>(
         object_header: ObjectHeader,
         is_first_in_stream: bool,
@@ -32,6 +134,13 @@ impl MessageFramer {
         {
             return Err(Error::ErrInvalidObjectType("Track or Group forwarding preference requires knowing the object length in advance".to_string()));
         }
+        if object_header.object_payload_length.is_some()
+            && object_header.object_forwarding_preference == ObjectForwardingPreference::Object
+        {
+            return Err(Error::ErrInvalidObjectType(
+                "OBJECT_STREAM payload runs to the end of the stream and has no length".to_string(),
+            ));
+        }
         if object_header.object_status != ObjectStatus::Normal {
             if let Some(&object_payload_length) = object_header.object_payload_length.as_ref() {
                 if object_payload_length > 0 {
@@ -57,7 +166,7 @@ impl MessageFramer {
                     tl += object_header.object_id.serialize(w)?;
                     tl += object_payload_length.serialize(w)?;
                     if object_payload_length == 0 {
-                        tl += (object_header.object_status as u64).serialize(w)?
+                        tl += object_header.object_status.serialize(w)?
                     }
                     return Ok(tl);
                 }
@@ -72,7 +181,7 @@ impl MessageFramer {
                     tl += object_header.object_id.serialize(w)?;
                     tl += object_payload_length.serialize(w)?;
                     if object_payload_length == 0 {
-                        tl += (object_header.object_status as u64).serialize(w)?
+                        tl += object_header.object_status.serialize(w)?
                     }
                     return Ok(tl);
                 }
@@ -105,7 +214,7 @@ impl MessageFramer {
                 tl += object_header.object_id.serialize(w)?;
                 tl += object_payload_length.serialize(w)?;
                 if object_payload_length == 0 {
-                    tl += (object_header.object_status as u64).serialize(w)?;
+                    tl += object_header.object_status.serialize(w)?;
                 }
                 Ok(tl)
             }
@@ -125,7 +234,7 @@ impl MessageFramer {
                 tl += object_header.object_id.serialize(w)?;
                 tl += object_payload_length.serialize(w)?;
                 if object_payload_length == 0 {
-                    tl += (object_header.object_status as u64).serialize(w)?;
+                    tl += object_header.object_status.serialize(w)?;
                 }
                 Ok(tl)
             }
@@ -136,7 +245,7 @@ impl MessageFramer {
                 tl += object_header.group_id.serialize(w)?;
                 tl += object_header.object_id.serialize(w)?;
                 tl += object_header.object_send_order.serialize(w)?;
-                tl += (object_header.object_status as u64).serialize(w)?;
+                tl += object_header.object_status.serialize(w)?;
 
                 Ok(tl)
             }
@@ -149,8 +258,17 @@ impl MessageFramer {
         payload: Bytes,
         w: &mut W,
     ) -> Result<usize> {
+        if object_header.object_status != ObjectStatus::Normal && !payload.is_empty() {
+            return Err(Error::ErrInvalidObjectType(
+                "Object status must be kNormal if payload is non-empty".to_string(),
+            ));
+        }
         let mut adjusted_object_header = object_header;
-        adjusted_object_header.object_payload_length = Some(payload.len() as u64);
+        // Only the stream header forms carry a length; an OBJECT_STREAM
+        // payload runs to the end of the stream.
+        if object_header.object_forwarding_preference != ObjectForwardingPreference::Object {
+            adjusted_object_header.object_payload_length = Some(payload.len() as u64);
+        }
         let mut tl =
             MessageFramer::serialize_object_header(adjusted_object_header, is_first_in_stream, w)?;
         tl += payload.serialize(w)?;
@@ -258,9 +376,65 @@ impl MessageFramer {
         tl += object_header.group_id.serialize(w)?;
         tl += object_header.object_id.serialize(w)?;
         tl += object_header.object_send_order.serialize(w)?;
-        tl += (object_header.object_status as u64).serialize(w)?;
+        tl += object_header.object_status.serialize(w)?;
         tl += payload.serialize(w)?;
 
         Ok(tl)
     }
+
+    /// Splits an object too large for a single OBJECT_DATAGRAM into the fewest
+    /// fragments of at most |max_datagram_size| bytes, each framed as its own
+    /// datagram. Fails if that takes more than MAX_DATAGRAM_FRAGMENTS.
+    pub fn serialize_object_datagram_fragments(
+        object_header: ObjectHeader,
+        payload: Bytes,
+        max_datagram_size: usize,
+    ) -> Result<Vec<Bytes>> {
+        if object_header.object_status != ObjectStatus::Normal && !payload.is_empty() {
+            return Err(Error::ErrInvalidObjectType(
+                "Object status must be kNormal if payload is non-empty".to_string(),
+            ));
+        }
+
+        let mut header = vec![];
+        OBJECT_DATAGRAM_FRAGMENT_TYPE.serialize(&mut header)?;
+        object_header.subscribe_id.serialize(&mut header)?;
+        object_header.track_alias.serialize(&mut header)?;
+        object_header.group_id.serialize(&mut header)?;
+        object_header.object_id.serialize(&mut header)?;
+        object_header.object_send_order.serialize(&mut header)?;
+        object_header.object_status.serialize(&mut header)?;
+
+        for fragment_count in 1..=MAX_DATAGRAM_FRAGMENTS {
+            // fragment_index is below fragment_count, so never takes more bytes.
+            let overhead = header.len() + 2 * VarInt::from_u64(fragment_count)?.size();
+            let Some(capacity) = max_datagram_size
+                .checked_sub(overhead)
+                .filter(|&capacity| capacity > 0)
+            else {
+                break;
+            };
+            if payload.len().div_ceil(capacity) as u64 > fragment_count {
+                continue;
+            }
+            let mut datagrams = Vec::with_capacity(fragment_count as usize);
+            for fragment_index in 0..fragment_count {
+                let start = (fragment_index as usize * capacity).min(payload.len());
+                let end = (start + capacity).min(payload.len());
+                let mut bytes = BytesMut::with_capacity(overhead + end - start);
+                bytes.extend_from_slice(&header);
+                fragment_index.serialize(&mut bytes)?;
+                fragment_count.serialize(&mut bytes)?;
+                payload.slice(start..end).serialize(&mut bytes)?;
+                datagrams.push(bytes.freeze());
+            }
+            return Ok(datagrams);
+        }
+        Err(Error::ErrOther(format!(
+            "object of {} bytes does not fit in {} datagrams of {} bytes",
+            payload.len(),
+            MAX_DATAGRAM_FRAGMENTS,
+            max_datagram_size
+        )))
+    }
 }
@@ -1,15 +1,21 @@
-use crate::message::message_framer::MessageFramer;
+use crate::message::announce::Announce;
+use crate::message::client_setup::ClientSetup;
+use crate::message::message_framer::{MessageFramer, MessageFramerTestingConfig};
+use crate::message::message_parser::{ErrorCode, MessageParser, MessageParserEvent};
 use crate::message::message_test::{
-    create_test_message, MessageStructuredData, TestMessageBase, TestObjectDatagramMessage,
-    TestStreamHeaderGroupMessage, TestStreamHeaderTrackMessage, TestStreamMiddlerGroupMessage,
-    TestStreamMiddlerTrackMessage,
+    assert_frames_to_wire_image, create_test_message, MessageStructuredData, TestMessageBase,
+    TestObjectDatagramMessage, TestStreamHeaderGroupMessage, TestStreamHeaderTrackMessage,
+    TestStreamMiddlerGroupMessage, TestStreamMiddlerTrackMessage, TestSubscribeErrorMessage,
+    TestSubscribeMessage,
 };
 use crate::message::object::{ObjectForwardingPreference, ObjectHeader, ObjectStatus};
+use crate::message::server_setup::ServerSetup;
 use crate::message::subscribe::Subscribe;
 use crate::message::subscribe_update::SubscribeUpdate;
-use crate::message::{ControlMessage, FilterType, FullSequence, MessageType};
-use crate::{Error, Result};
-use bytes::{BufMut, Bytes};
+use crate::message::{ControlMessage, FilterType, FullSequence, MessageType, Version};
+use crate::serde::parameters::ParameterKey;
+use crate::{Deserializer, Error, Parameters, Result};
+use bytes::{BufMut, Bytes, BytesMut};
 use rstest::rstest;
 
 struct TestFramerParams {
@@ -107,21 +113,71 @@ fn test_framer_one_message(params: (MessageType, bool)) -> Result<()> {
     let mut buffer = vec![];
     let size = tester.serialize_message(structured_data, &mut buffer)?;
     assert_eq!(size, buffer.len());
-    assert_eq!(buffer.len(), message.packet_sample().len());
-    assert_eq!(&buffer[..], message.packet_sample());
+    assert_frames_to_wire_image(message.as_ref(), &buffer);
+    Ok(())
+}
+
+#[rstest(
+    params => [
+    (MessageType::Subscribe, true),
+    (MessageType::SubscribeOk, true),
+    (MessageType::SubscribeDone, true),
+    (MessageType::Announce, true),
+    (MessageType::TrackStatus, true),
+    (MessageType::ClientSetup, false),
+    (MessageType::ServerSetup, true),
+    (MessageType::GoAway, true),
+    ]
+)]
+fn test_to_vec_matches_bytes_mut(params: (MessageType, bool)) -> Result<()> {
+    let message = create_test_message(params.0, params.1);
+    let MessageStructuredData::Control(control_message) = message.structured_data() else {
+        panic!("expected a control message");
+    };
+    let mut buffer = BytesMut::new();
+    let size = MessageFramer::serialize_control_message(control_message.clone(), &mut buffer)?;
+    let bytes = MessageFramer::to_vec(&control_message)?;
+    assert_eq!(size, bytes.len());
+    assert_eq!(bytes, buffer.as_ref());
+    assert_frames_to_wire_image(message.as_ref(), &bytes);
+    Ok(())
+}
+
+// The framer and the parser must agree on every field: what one writes, the
+// other reads back unchanged, and reframing the parsed message reproduces the
+// bytes.
+#[rstest(
+    params => [
+    (MessageType::Subscribe, true),
+    (MessageType::Announce, true),
+    (MessageType::ServerSetup, true),
+    ]
+)]
+fn test_framer_parser_symmetry(params: (MessageType, bool)) -> Result<()> {
+    let message = create_test_message(params.0, params.1);
+    let MessageStructuredData::Control(control_message) = message.structured_data() else {
+        panic!("expected a control message");
+    };
+    let bytes = MessageFramer::to_vec(&control_message)?;
+
+    let mut parser = MessageParser::new(params.1);
+    parser.process_data(&mut bytes.as_slice(), false);
+    let parsed = match parser.poll_event() {
+        Some(MessageParserEvent::ControlMessage(parsed)) => parsed,
+        event => panic!("unexpected parser event {:?}", event),
+    };
+    assert_eq!(parser.poll_event(), None);
+    assert_eq!(parsed, control_message);
+    assert_eq!(MessageFramer::to_vec(&parsed)?, bytes);
     Ok(())
 }
 
 #[test]
 fn test_group_middler() -> Result<()> {
     let header = TestStreamHeaderGroupMessage::new();
-    let header_object_header =
-        if let MessageStructuredData::Object(object_header) = header.structured_data() {
-            object_header
-        } else {
-            assert!(false);
-            return Err(Error::ErrInvalidMessageType(0));
-        };
+    let MessageStructuredData::Object(header_object_header) = header.structured_data() else {
+        return Err(Error::ErrInvalidMessageType(0));
+    };
 
     let mut buffer1 = vec![];
     let buffer1_size = MessageFramer::serialize_object(
@@ -132,16 +188,12 @@ fn test_group_middler() -> Result<()> {
     )?;
     assert_eq!(buffer1_size, buffer1.len());
     assert_eq!(buffer1.len(), header.total_message_size());
-    assert_eq!(&buffer1[..], header.packet_sample());
+    assert_frames_to_wire_image(&header, &buffer1);
 
     let middler = TestStreamMiddlerGroupMessage::new();
-    let middler_object_header =
-        if let MessageStructuredData::Object(object_header) = middler.structured_data() {
-            object_header
-        } else {
-            assert!(false);
-            return Err(Error::ErrInvalidMessageType(0));
-        };
+    let MessageStructuredData::Object(middler_object_header) = middler.structured_data() else {
+        return Err(Error::ErrInvalidMessageType(0));
+    };
     let mut buffer2 = vec![];
     let buffer2_size = MessageFramer::serialize_object(
         middler_object_header,
@@ -151,20 +203,16 @@ fn test_group_middler() -> Result<()> {
     )?;
     assert_eq!(buffer2_size, buffer2.len());
     assert_eq!(buffer2.len(), middler.total_message_size());
-    assert_eq!(&buffer2[..], middler.packet_sample());
+    assert_frames_to_wire_image(&middler, &buffer2);
     Ok(())
 }
 
 #[test]
 fn test_track_middler() -> Result<()> {
     let header = TestStreamHeaderTrackMessage::new();
-    let header_object_header =
-        if let MessageStructuredData::Object(object_header) = header.structured_data() {
-            object_header
-        } else {
-            assert!(false);
-            return Err(Error::ErrInvalidMessageType(0));
-        };
+    let MessageStructuredData::Object(header_object_header) = header.structured_data() else {
+        return Err(Error::ErrInvalidMessageType(0));
+    };
     let mut buffer1 = vec![];
     let buffer1_size = MessageFramer::serialize_object(
         header_object_header,
@@ -174,16 +222,12 @@ fn test_track_middler() -> Result<()> {
     )?;
     assert_eq!(buffer1_size, buffer1.len());
     assert_eq!(buffer1.len(), header.total_message_size());
-    assert_eq!(&buffer1[..], header.packet_sample());
+    assert_frames_to_wire_image(&header, &buffer1);
 
     let middler = TestStreamMiddlerTrackMessage::new();
-    let middler_object_header =
-        if let MessageStructuredData::Object(object_header) = middler.structured_data() {
-            object_header
-        } else {
-            assert!(false);
-            return Err(Error::ErrInvalidMessageType(0));
-        };
+    let MessageStructuredData::Object(middler_object_header) = middler.structured_data() else {
+        return Err(Error::ErrInvalidMessageType(0));
+    };
     let mut buffer2 = vec![];
     let buffer2_size = MessageFramer::serialize_object(
         middler_object_header,
@@ -193,7 +237,7 @@ fn test_track_middler() -> Result<()> {
     )?;
     assert_eq!(buffer2_size, buffer2.len());
     assert_eq!(buffer2.len(), middler.total_message_size());
-    assert_eq!(&buffer2[..], middler.packet_sample());
+    assert_frames_to_wire_image(&middler, &buffer2);
     Ok(())
 }
 
@@ -232,6 +276,256 @@ fn test_bad_object_input() -> Result<()> {
     Ok(())
 }
 
+#[rstest]
+#[case(ObjectForwardingPreference::Track)]
+#[case(ObjectForwardingPreference::Group)]
+fn test_zero_length_object_writes_status(
+    #[case] object_forwarding_preference: ObjectForwardingPreference,
+) -> Result<()> {
+    for object_status in [
+        ObjectStatus::ObjectDoesNotExist,
+        ObjectStatus::GroupDoesNotExist,
+        ObjectStatus::EndOfGroup,
+        ObjectStatus::EndOfTrack,
+    ] {
+        let object = ObjectHeader {
+            subscribe_id: 3,
+            track_alias: 4,
+            group_id: 5,
+            object_id: 6,
+            object_send_order: 7,
+            object_status,
+            object_forwarding_preference,
+            object_payload_length: Some(0),
+        };
+        let mut buffer = vec![];
+        MessageFramer::serialize_object_header(object, true, &mut buffer)?;
+        assert_eq!(&buffer[buffer.len() - 2..], &[0x00, object_status as u8]);
+        let header_len = buffer.len();
+        let middler = ObjectHeader {
+            object_id: 7,
+            ..object
+        };
+        MessageFramer::serialize_object_header(middler, false, &mut buffer)?;
+        assert_eq!(&buffer[buffer.len() - 2..], &[0x00, object_status as u8]);
+        assert!(buffer.len() > header_len);
+
+        let mut parser = MessageParser::new_data_stream(false);
+        parser.process_data(&mut &buffer[..], true);
+        for object_id in [6, 7] {
+            match parser.poll_event() {
+                Some(MessageParserEvent::ObjectMessage(header, _, payload, true)) => {
+                    assert_eq!(header.object_id, object_id);
+                    assert_eq!(header.object_status, object_status);
+                    assert_eq!(header.object_payload_length, Some(0));
+                    assert!(payload.is_empty());
+                }
+                _ => panic!("expected a complete {:?} object", object_status),
+            }
+        }
+        assert!(parser.poll_event().is_none());
+    }
+    Ok(())
+}
+
+#[rstest]
+#[case(ObjectStatus::Normal)]
+#[case(ObjectStatus::EndOfGroup)]
+fn test_absent_length_writes_no_length_field(#[case] object_status: ObjectStatus) -> Result<()> {
+    let mut object = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        group_id: 5,
+        object_id: 6,
+        object_send_order: 7,
+        object_status,
+        object_forwarding_preference: ObjectForwardingPreference::Object,
+        object_payload_length: None,
+    };
+    let mut buffer = vec![];
+    MessageFramer::serialize_object_header(object, true, &mut buffer)?;
+    assert_eq!(
+        buffer,
+        vec![0x00, 0x03, 0x04, 0x05, 0x06, 0x07, object_status as u8]
+    );
+
+    let mut parser = MessageParser::new_data_stream(false);
+    parser.process_data(&mut &buffer[..], true);
+    match parser.poll_event() {
+        Some(MessageParserEvent::ObjectMessage(header, _, payload, true)) => {
+            assert_eq!(header.object_status, object_status);
+            assert_eq!(header.object_payload_length, None);
+            assert!(payload.is_empty());
+        }
+        _ => panic!("expected a complete object"),
+    }
+
+    for object_forwarding_preference in [
+        ObjectForwardingPreference::Track,
+        ObjectForwardingPreference::Group,
+    ] {
+        object.object_forwarding_preference = object_forwarding_preference;
+        buffer.clear();
+        assert!(
+            MessageFramer::serialize_object_header(object, true, &mut buffer).is_err(),
+            "requires knowing the object length"
+        );
+    }
+    Ok(())
+}
+
+#[rstest]
+#[case(ObjectForwardingPreference::Object, ObjectStatus::EndOfGroup)]
+#[case(ObjectForwardingPreference::Track, ObjectStatus::EndOfTrack)]
+#[case(ObjectForwardingPreference::Group, ObjectStatus::EndOfGroup)]
+fn test_status_object_round_trip(
+    #[case] object_forwarding_preference: ObjectForwardingPreference,
+    #[case] object_status: ObjectStatus,
+) -> Result<()> {
+    let object = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        ..ObjectHeader::status_object(object_forwarding_preference, 5, 6, object_status)
+    };
+
+    let mut buffer = vec![];
+    MessageFramer::serialize_object(object, true, Bytes::new(), &mut buffer)?;
+    let mut parser = MessageParser::new_data_stream(false);
+    parser.process_data(&mut &buffer[..], true);
+    match parser.poll_event() {
+        Some(MessageParserEvent::ObjectMessage(header, _, payload, true)) => {
+            assert_eq!(header.group_id, 5);
+            assert_eq!(header.object_id, 6);
+            assert_eq!(header.object_status, object_status);
+            assert_eq!(
+                header.object_forwarding_preference,
+                object_forwarding_preference
+            );
+            assert!(payload.is_empty());
+        }
+        other => panic!("unexpected parser event: {other:?}"),
+    }
+    Ok(())
+}
+
+#[rstest]
+#[case(ObjectForwardingPreference::Track, &[0x05])]
+#[case(ObjectForwardingPreference::Group, &[])]
+fn test_middler_status_round_trip(
+    #[case] object_forwarding_preference: ObjectForwardingPreference,
+    #[case] group_prefix: &[u8],
+) -> Result<()> {
+    let object = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        group_id: 5,
+        object_id: 6,
+        object_send_order: 7,
+        object_status: ObjectStatus::Normal,
+        object_forwarding_preference,
+        object_payload_length: None,
+    };
+    let mut buffer = vec![];
+    MessageFramer::serialize_object(object, true, Bytes::from_static(b"foo"), &mut buffer)?;
+
+    // A Normal middler with payload writes its length and no status.
+    let normal = ObjectHeader {
+        object_id: 7,
+        ..object
+    };
+    let mut middler = vec![];
+    MessageFramer::serialize_object(normal, false, Bytes::from_static(b"bar"), &mut middler)?;
+    assert_eq!(
+        middler,
+        [group_prefix, &[0x07, 0x03, b'b', b'a', b'r']].concat()
+    );
+    buffer.extend_from_slice(&middler);
+
+    // A status middler writes length 0 followed by the status.
+    let end_of_group = ObjectHeader {
+        object_id: 8,
+        object_status: ObjectStatus::EndOfGroup,
+        ..object
+    };
+    middler.clear();
+    MessageFramer::serialize_object(end_of_group, false, Bytes::new(), &mut middler)?;
+    assert_eq!(middler, [group_prefix, &[0x08, 0x00, 0x03]].concat());
+    buffer.extend_from_slice(&middler);
+
+    let mut parser = MessageParser::new_data_stream(false);
+    parser.process_data(&mut &buffer[..], true);
+    for (object_id, object_status, expected_payload) in [
+        (6, ObjectStatus::Normal, &b"foo"[..]),
+        (7, ObjectStatus::Normal, &b"bar"[..]),
+        (8, ObjectStatus::EndOfGroup, &b""[..]),
+    ] {
+        match parser.poll_event() {
+            Some(MessageParserEvent::ObjectMessage(header, _, payload, true)) => {
+                assert_eq!(header.group_id, 5);
+                assert_eq!(header.object_id, object_id);
+                assert_eq!(header.object_status, object_status);
+                assert_eq!(payload, Bytes::from_static(expected_payload));
+            }
+            other => panic!("expected object {}, got {:?}", object_id, other),
+        }
+    }
+    assert_eq!(parser.poll_event(), None);
+    Ok(())
+}
+
+#[test]
+fn test_object_stream_omits_length() -> Result<()> {
+    let object = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        group_id: 5,
+        object_id: 6,
+        object_send_order: 7,
+        object_status: ObjectStatus::Normal,
+        object_forwarding_preference: ObjectForwardingPreference::Object,
+        object_payload_length: None,
+    };
+    let mut buffer = vec![];
+    MessageFramer::serialize_object(object, true, Bytes::from_static(b"foo"), &mut buffer)?;
+    assert_eq!(
+        buffer,
+        vec![0x00, 0x03, 0x04, 0x05, 0x06, 0x07, 0x00, 0x66, 0x6f, 0x6f]
+    );
+
+    let mut parser = MessageParser::new_data_stream(false);
+    parser.process_data(&mut &buffer[..], true);
+    match parser.poll_event() {
+        Some(MessageParserEvent::ObjectMessage(header, _, payload, true)) => {
+            assert_eq!(header, object);
+            assert_eq!(payload, Bytes::from_static(b"foo"));
+        }
+        _ => panic!("expected a complete object"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_object_stream_with_length_rejected() {
+    let object = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        group_id: 5,
+        object_id: 6,
+        object_send_order: 7,
+        object_status: ObjectStatus::Normal,
+        object_forwarding_preference: ObjectForwardingPreference::Object,
+        object_payload_length: Some(3),
+    };
+    let mut buffer = vec![];
+    assert_eq!(
+        MessageFramer::serialize_object_header(object, true, &mut buffer),
+        Err(Error::ErrInvalidObjectType(
+            "OBJECT_STREAM payload runs to the end of the stream and has no length".to_string()
+        ))
+    );
+    assert!(buffer.is_empty());
+}
+
 #[test]
 fn test_datagram() -> Result<()> {
     let datagram = TestObjectDatagramMessage::new();
@@ -250,7 +544,34 @@ fn test_datagram() -> Result<()> {
     let buffer_size = MessageFramer::serialize_object_datagram(object, payload, &mut buffer)?;
     assert_eq!(buffer.len(), buffer_size);
     assert_eq!(buffer.len(), datagram.total_message_size());
-    assert_eq!(&buffer[..], datagram.packet_sample());
+    assert_frames_to_wire_image(&datagram, &buffer);
+    Ok(())
+}
+
+#[rstest]
+#[case(ObjectStatus::Normal, b"foo".as_slice())]
+#[case(ObjectStatus::ObjectDoesNotExist, b"".as_slice())]
+#[case(ObjectStatus::EndOfTrack, b"".as_slice())]
+fn test_datagram_round_trip(
+    #[case] object_status: ObjectStatus,
+    #[case] payload: &'static [u8],
+) -> Result<()> {
+    let object = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        group_id: 5,
+        object_id: 6,
+        object_send_order: 7,
+        object_status,
+        object_forwarding_preference: ObjectForwardingPreference::Datagram,
+        object_payload_length: None,
+    };
+    let mut buffer = vec![];
+    let _ =
+        MessageFramer::serialize_object_datagram(object, Bytes::from_static(payload), &mut buffer)?;
+    let (parsed, parsed_payload) = MessageParser::process_datagram(&mut &buffer[..])?;
+    assert_eq!(parsed, object);
+    assert_eq!(parsed_payload, Bytes::from_static(payload));
     Ok(())
 }
 
@@ -344,57 +665,30 @@ fn test_all_subscribe_inputs() -> Result<()> {
         for start_object in [None, Some(0)] {
             for end_group in [None, Some(7)] {
                 for end_object in [None, Some(3)] {
-                    let expected_filter_type;
-                    if !start_group.is_some()
-                        && !start_object.is_some()
-                        && !end_group.is_some()
-                        && !end_object.is_some()
-                    {
-                        expected_filter_type = FilterType::LatestObject;
-                    } else if !start_group.is_some()
-                        && start_object.is_some()
-                        && *start_object.as_ref().unwrap() == 0
-                        && !end_group.is_some()
-                        && !end_object.is_some()
-                    {
-                        expected_filter_type = FilterType::LatestGroup;
-                    } else if start_group.is_some()
-                        && start_object.is_some()
-                        && !end_group.is_some()
-                        && !end_object.is_some()
-                    {
-                        expected_filter_type = FilterType::AbsoluteStart(FullSequence {
-                            group_id: start_group.unwrap(),
-                            object_id: start_object.unwrap(),
-                        });
-                    } else if start_group.is_some() && start_object.is_some() && end_group.is_some()
-                    {
-                        if let Some(&end_object) = end_object.as_ref() {
-                            expected_filter_type = FilterType::AbsoluteRange(
-                                FullSequence {
-                                    group_id: start_group.unwrap(),
-                                    object_id: start_object.unwrap(),
-                                },
-                                FullSequence {
-                                    group_id: end_group.unwrap(),
-                                    object_id: end_object,
-                                },
-                            );
-                        } else {
-                            expected_filter_type = FilterType::AbsoluteRange(
-                                FullSequence {
-                                    group_id: start_group.unwrap(),
-                                    object_id: start_object.unwrap(),
-                                },
-                                FullSequence {
-                                    group_id: end_group.unwrap(),
-                                    object_id: u64::MAX,
-                                },
-                            );
-                        }
-                    } else {
-                        continue;
-                    }
+                    let expected_filter_type =
+                        match (start_group, start_object, end_group, end_object) {
+                            (None, None, None, None) => FilterType::LatestObject,
+                            (None, Some(0), None, None) => FilterType::LatestGroup,
+                            (Some(group_id), Some(object_id), None, None) => {
+                                FilterType::AbsoluteStart(FullSequence {
+                                    group_id,
+                                    object_id,
+                                })
+                            }
+                            (Some(group_id), Some(object_id), Some(end_group), end_object) => {
+                                FilterType::AbsoluteRange(
+                                    FullSequence {
+                                        group_id,
+                                        object_id,
+                                    },
+                                    FullSequence {
+                                        group_id: end_group,
+                                        object_id: end_object.unwrap_or(u64::MAX),
+                                    },
+                                )
+                            }
+                            _ => continue,
+                        };
 
                     let subscribe = Subscribe {
                         subscribe_id: 3,
@@ -426,6 +720,149 @@ fn test_all_subscribe_inputs() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_empty_parameters_block() -> Result<()> {
+    for message in [
+        ControlMessage::Announce(Announce {
+            track_namespace: "foo".to_string(),
+            parameters: Parameters::new(),
+        }),
+        ControlMessage::Subscribe(Subscribe {
+            subscribe_id: 1,
+            track_alias: 2,
+            track_namespace: "foo".to_string(),
+            track_name: "abcd".to_string(),
+            filter_type: FilterType::LatestObject,
+            authorization_info: None,
+        }),
+    ] {
+        let mut buffer = vec![];
+        let size = MessageFramer::serialize_control_message(message.clone(), &mut buffer)?;
+        assert_eq!(buffer.last(), Some(&0x00)); // No parameters
+        let (parsed, parsed_size) = ControlMessage::deserialize(&mut buffer.as_slice())?;
+        assert_eq!(parsed, message);
+        assert_eq!(parsed_size, size);
+    }
+
+    // SETUP requires ROLE, so an empty block is read but the message is rejected.
+    for (message, reason) in [
+        (
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: None,
+                path: None,
+                uses_web_transport: true,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            "ROLE parameter missing from CLIENT_SETUP message",
+        ),
+        (
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: None,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            "ROLE parameter missing from SERVER_SETUP message",
+        ),
+    ] {
+        let mut buffer = vec![];
+        let _ = MessageFramer::serialize_control_message(message, &mut buffer)?;
+        assert_eq!(buffer.last(), Some(&0x00)); // No parameters
+        assert_eq!(
+            ControlMessage::deserialize(&mut buffer.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                reason.to_string()
+            ))
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_subscribe_frames_to_wire_image() -> Result<()> {
+    let subscribe = TestSubscribeMessage::new();
+    let MessageStructuredData::Control(control_message) = subscribe.structured_data() else {
+        return Err(Error::ErrInvalidMessageType(MessageType::Subscribe as u64));
+    };
+    let mut buffer = vec![];
+    let size = MessageFramer::serialize_control_message(control_message, &mut buffer)?;
+    assert_eq!(size, buffer.len());
+    assert_frames_to_wire_image(&subscribe, &buffer);
+    Ok(())
+}
+
+#[test]
+fn test_subscribe_error_frames_to_wire_image() -> Result<()> {
+    let subscribe_error = TestSubscribeErrorMessage::new();
+    let MessageStructuredData::Control(control_message) = subscribe_error.structured_data() else {
+        return Err(Error::ErrInvalidMessageType(
+            MessageType::SubscribeError as u64,
+        ));
+    };
+    let mut buffer = vec![];
+    let size = MessageFramer::serialize_control_message(control_message, &mut buffer)?;
+    assert_eq!(size, buffer.len());
+    assert_frames_to_wire_image(&subscribe_error, &buffer);
+    Ok(())
+}
+
+#[test]
+fn test_subscribe_error_long_varints_reframe_to_wire_image() -> Result<()> {
+    let mut expanded = TestSubscribeErrorMessage::new();
+    expanded.expand_varints()?;
+    let (control_message, size) = ControlMessage::deserialize(&mut expanded.packet_sample())?;
+    assert_eq!(size, expanded.packet_sample().len());
+    assert!(expanded.equal_field_values(&MessageStructuredData::Control(control_message.clone())));
+
+    // The framer always picks the shortest varint encoding, so the expanded
+    // image reframes to the canonical one.
+    let buffer = MessageFramer::to_vec(&control_message)?;
+    assert_frames_to_wire_image(&TestSubscribeErrorMessage::new(), &buffer);
+    Ok(())
+}
+
+#[test]
+fn test_forced_varint_length_parses_back() -> Result<()> {
+    let subscribe_error = TestSubscribeErrorMessage::new();
+    let MessageStructuredData::Control(control_message) = subscribe_error.structured_data() else {
+        return Err(Error::ErrInvalidMessageType(
+            MessageType::SubscribeError as u64,
+        ));
+    };
+    let config = MessageFramerTestingConfig::default()
+        .with_forced_varint_length("subscribe_id", 4)
+        .with_forced_varint_length("track_alias", 2);
+    let buffer = MessageFramer::to_vec_for_testing(&control_message, &config)?;
+    assert_eq!(
+        buffer,
+        vec![
+            0x05, 0x80, 0x00, 0x00, 0x02, // subscribe_id = 2, in 4 bytes
+            0x01, // error_code = 1
+            0x03, 0x62, 0x61, 0x72, // reason_phrase = "bar"
+            0x40, 0x04, // track_alias = 4, in 2 bytes
+        ]
+    );
+
+    let (parsed, size) = ControlMessage::deserialize(&mut &buffer[..])?;
+    assert_eq!(size, buffer.len());
+    assert_eq!(parsed, control_message);
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "framed message differs from wire image at byte 13")]
+fn test_assert_frames_to_wire_image_reports_mismatch() {
+    let subscribe = TestSubscribeMessage::new();
+    let mut framed = subscribe.packet_sample().to_vec();
+    framed[13] += 1;
+    assert_frames_to_wire_image(&subscribe, &framed);
+}
+
 #[test]
 fn test_subscribe_end_before_start() -> Result<()> {
     let mut subscribe = Subscribe {
@@ -502,6 +939,15 @@ fn test_subscribe_latest_group_nonzero_object() -> Result<()> {
     Ok(())
 }
 
+fn authorization_parameters(authorization_info: &str) -> Result<Parameters> {
+    let mut parameters = Parameters::new();
+    parameters.insert(
+        ParameterKey::AuthorizationInfo,
+        authorization_info.to_string(),
+    )?;
+    Ok(parameters)
+}
+
 #[test]
 fn test_subscribe_update_end_group_only() -> Result<()> {
     let subscribe_update = SubscribeUpdate {
@@ -514,7 +960,7 @@ fn test_subscribe_update_end_group_only() -> Result<()> {
             group_id: 4,
             object_id: u64::MAX,
         }),
-        authorization_info: Some("bar".to_string()),
+        parameters: authorization_parameters("bar")?,
     };
     let mut buffer = vec![];
     let _ = MessageFramer::serialize_control_message(
@@ -541,7 +987,7 @@ fn test_subscribe_update_increments_end() -> Result<()> {
             group_id: 4,
             object_id: 6,
         }),
-        authorization_info: Some("bar".to_string()),
+        parameters: authorization_parameters("bar")?,
     };
     let mut buffer = vec![];
     let _ = MessageFramer::serialize_control_message(
@@ -568,7 +1014,7 @@ fn test_subscribe_update_invalid_range() -> Result<()> {
             group_id: u64::MAX,
             object_id: 6,
         }),
-        authorization_info: Some("bar".to_string()),
+        parameters: authorization_parameters("bar")?,
     };
     let mut buffer = vec![];
     assert!(
@@ -581,3 +1027,41 @@ fn test_subscribe_update_invalid_range() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn test_object_datagram_fragments_round_trip() -> Result<()> {
+    let header = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        group_id: 5,
+        object_id: 6,
+        object_send_order: 7,
+        object_status: ObjectStatus::Normal,
+        object_forwarding_preference: ObjectForwardingPreference::Datagram,
+        object_payload_length: None,
+    };
+    let payload = Bytes::from_static(b"fragmented payload");
+    let datagrams =
+        MessageFramer::serialize_object_datagram_fragments(header, payload.clone(), 16)?;
+    assert_eq!(datagrams.len(), 3);
+
+    let mut reassembled = BytesMut::new();
+    for (i, datagram) in datagrams.iter().enumerate() {
+        assert!(datagram.len() <= 16);
+        let fragment = MessageParser::process_datagram_fragment(&mut datagram.as_ref())?;
+        assert_eq!(fragment.object_header, header);
+        assert_eq!(fragment.fragment_index, i as u64);
+        assert_eq!(fragment.fragment_count, 3);
+        reassembled.extend_from_slice(&fragment.payload);
+    }
+    assert_eq!(reassembled.freeze(), payload);
+
+    // Only 7 bytes of payload fit per datagram; 16 fragments aren't enough.
+    assert!(MessageFramer::serialize_object_datagram_fragments(
+        header,
+        Bytes::from(vec![0; 200]),
+        16
+    )
+    .is_err());
+    Ok(())
+}
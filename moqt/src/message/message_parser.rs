@@ -1,5 +1,8 @@
-use crate::message::object::{ObjectForwardingPreference, ObjectHeader, ObjectStatus};
-use crate::message::{ControlMessage, MessageType, MAX_MESSSAGE_HEADER_SIZE};
+use crate::message::object::{
+    DatagramFragment, ObjectForwardingPreference, ObjectHeader, ObjectStatus,
+    MAX_DATAGRAM_FRAGMENTS, OBJECT_DATAGRAM_FRAGMENT_TYPE,
+};
+use crate::message::{ControlMessage, MessageType, NamespaceLayout, MAX_MESSSAGE_HEADER_SIZE};
 use crate::serde::Deserializer;
 use crate::{Error, Result};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
@@ -16,6 +19,12 @@ const FETCH_IS_DATAGRAM_LIKE: u64 = 0x40;
 const FETCH_END_OF_NON_EXISTENT_RANGE: u64 = 0x8c;
 const FETCH_END_OF_UNKNOWN_RANGE: u64 = 0x10c;
 
+/// Parsing error reason when an object message arrives on the control stream.
+pub const OBJECT_ON_CONTROL_STREAM: &str = "Received object on control stream";
+
+/// Parsing error reason when a control message arrives on a data stream.
+pub const CONTROL_MESSAGE_ON_DATA_STREAM: &str = "Received control message on data stream";
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum ObjectStreamKind {
     Legacy(MessageType),
@@ -40,16 +49,130 @@ impl Display for ErrorCode {
     }
 }
 
+/// Whether the parser can carry on after a parsing error.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParseErrorSeverity {
+    /// The stream can't be parsed any further.
+    Fatal,
+    /// The offending message was skipped and parsing continues after it.
+    Recoverable,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum MessageParserEvent {
     ParsingError(ErrorCode, String),
+    /// A message was skipped; the parser is still usable.
+    RecoverableParsingError(ErrorCode, String),
     ObjectMessage(ObjectHeader, Bytes, Bytes, bool),
     ControlMessage(ControlMessage),
 }
 
+/// What MessageParser::sniff made of a buffer. Both interpretations are
+/// present when the leading type is valid for either, e.g. 0x05, which is
+/// SUBSCRIBE_ERROR on the control stream and the FETCH stream type.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct SniffResult {
+    pub control_message: Option<ControlMessage>,
+    pub object_header: Option<ObjectHeader>,
+}
+
+/// The stream state of a MessageParser, taken by MessageParser::checkpoint
+/// and handed to MessageParser::restore, e.g. to move a half-parsed stream to
+/// a parser owned by another worker.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParserCheckpoint {
+    length_prefixed: bool,
+    length_prefixed_offered: bool,
+    namespace_layout: NamespaceLayout,
+    buffered_message: BytesMut,
+    object_metadata: Option<ObjectHeader>,
+    object_stream_kind: Option<ObjectStreamKind>,
+    payload_length_remaining: usize,
+    no_more_data: bool,
+    parsing_error: bool,
+}
+
+/// Counters kept by a MessageParser once MessageParser::enable_stats is
+/// called. Only messages the parser consumed are counted.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct ParserStats {
+    varint_sizes: [u64; 4],
+}
+
+impl ParserStats {
+    /// The number of decoded varints that were |length| bytes long (1, 2, 4
+    /// or 8). Longer encodings than the values need point at a peer that
+    /// doesn't write minimal varints.
+    pub fn varints_of_length(&self, length: usize) -> u64 {
+        match length {
+            1 => self.varint_sizes[0],
+            2 => self.varint_sizes[1],
+            4 => self.varint_sizes[2],
+            8 => self.varint_sizes[3],
+            _ => 0,
+        }
+    }
+
+    fn add_varint_sizes(&mut self, sizes: [u64; 4]) {
+        for (total, count) in self.varint_sizes.iter_mut().zip(sizes) {
+            *total += count;
+        }
+    }
+}
+
+// Reads through to |inner|, tallying the varints decoded from it by the
+// encoded length the top two bits of their first byte give. VarInt is the only
+// decoder that starts with get_u8; other single-byte fields are copied out.
+struct VarintCounter<'a, B> {
+    inner: &'a mut B,
+    sizes: [u64; 4],
+}
+
+impl<B: Buf> Buf for VarintCounter<'_, B> {
+    fn remaining(&self) -> usize {
+        self.inner.remaining()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self.inner.chunk()
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        self.inner.advance(cnt)
+    }
+
+    fn get_u8(&mut self) -> u8 {
+        let byte = self.inner.get_u8();
+        self.sizes[usize::from(byte >> 6)] += 1;
+        byte
+    }
+}
+
+/// Hook for observing the messages a MessageParser delivers, e.g. to feed
+/// tracing without touching the parser.
+pub trait MessageObserver {
+    fn on_control(&mut self, control_message: &ControlMessage);
+
+    /// Called for each object message with the length of the payload it
+    /// carries, which is a fragment when the object is delivered in parts.
+    fn on_object(&mut self, object_header: &ObjectHeader, payload_length: usize);
+}
+
 pub struct MessageParser {
     uses_web_transport: bool,
     allow_fetch_streams: bool,
+    // Control streams carry no objects, so object headers are rejected by type
+    // before any object parsing.
+    control_stream: bool,
+    // Data streams carry no control messages, so anything but an object header
+    // is rejected by type before any control parsing.
+    data_stream: bool,
+    // Each control message is preceded by its length as a varint.
+    length_prefixed: bool,
+    // Switch to length_prefixed after a SETUP that advertises it.
+    length_prefixed_offered: bool,
+    // How track namespaces are laid out, updated from a SERVER_SETUP.
+    namespace_layout: NamespaceLayout,
     no_more_data: bool, // Fatal error or fin. No more parsing.
     parsing_error: bool,
 
@@ -70,17 +193,21 @@ pub struct MessageParser {
     payload_length_remaining: usize,
 
     parser_events: VecDeque<MessageParserEvent>,
+
+    observer: Option<Box<dyn MessageObserver + Send>>,
+    stats: Option<ParserStats>,
 }
 
 impl MessageParser {
     pub fn new(use_web_transport: bool) -> Self {
-        Self::new_control(use_web_transport)
-    }
-
-    pub fn new_control(use_web_transport: bool) -> Self {
         Self {
             uses_web_transport: use_web_transport,
             allow_fetch_streams: false,
+            control_stream: false,
+            data_stream: false,
+            length_prefixed: false,
+            length_prefixed_offered: false,
+            namespace_layout: NamespaceLayout::String,
             no_more_data: false,
             parsing_error: false,
 
@@ -90,13 +217,36 @@ impl MessageParser {
             payload_length_remaining: 0,
 
             parser_events: VecDeque::new(),
+
+            observer: None,
+            stats: None,
+        }
+    }
+
+    pub fn new_control(use_web_transport: bool) -> Self {
+        Self {
+            control_stream: true,
+            ..Self::new(use_web_transport)
+        }
+    }
+
+    /// Control stream parser for peers that precede every control message
+    /// with its length as a varint. Messages of unknown type are skipped with a
+    /// RecoverableParsingError.
+    /// Endpoints agree on this framing with the LENGTH_PREFIXED_CONTROL SETUP
+    /// parameter; see offer_length_prefixed_control.
+    pub fn new_length_prefixed_control(use_web_transport: bool) -> Self {
+        Self {
+            length_prefixed: true,
+            ..Self::new_control(use_web_transport)
         }
     }
 
     pub fn new_data_stream(use_web_transport: bool) -> Self {
         Self {
             allow_fetch_streams: true,
-            ..Self::new_control(use_web_transport)
+            data_stream: true,
+            ..Self::new(use_web_transport)
         }
     }
 
@@ -108,11 +258,21 @@ impl MessageParser {
     /// error.
     /// Any calls after sending |fin| = true will be ignored.
     pub fn process_data<R: Buf>(&mut self, buf: &mut R, fin: bool) {
+        // A parser that has reported an error stays inert.
+        if self.parsing_error {
+            return;
+        }
         if self.no_more_data {
             self.parse_error(
+                ParseErrorSeverity::Fatal,
                 ErrorCode::ProtocolViolation,
                 "Data after end of stream".to_string(),
             );
+            return;
+        }
+        // Nothing new to parse; only a fin can change the state.
+        if !fin && !buf.has_remaining() {
+            return;
         }
 
         // Check for early fin
@@ -121,6 +281,7 @@ impl MessageParser {
             if self.object_payload_in_progress() && self.payload_length_remaining > buf.remaining()
             {
                 self.parse_error(
+                    ParseErrorSeverity::Fatal,
                     ErrorCode::ProtocolViolation,
                     "End of stream before complete OBJECT PAYLOAD".to_string(),
                 );
@@ -128,6 +289,7 @@ impl MessageParser {
             }
             if !self.buffered_message.is_empty() && !buf.has_remaining() {
                 self.parse_error(
+                    ParseErrorSeverity::Fatal,
                     ErrorCode::ProtocolViolation,
                     "End of stream before complete message".to_string(),
                 );
@@ -187,8 +349,12 @@ impl MessageParser {
         while self.buffered_message.has_remaining() {
             let message_len = self.process_message(fin);
             if message_len == 0 {
+                // Objects are consumed as soon as their header parses and the
+                // payload is handed out as it arrives, so for an object only
+                // header bytes are left here and count toward the limit.
                 if self.buffered_message.remaining() > MAX_MESSSAGE_HEADER_SIZE {
                     self.parse_error(
+                        ParseErrorSeverity::Fatal,
                         ErrorCode::InternalError,
                         "Cannot parse non-OBJECT messages > 2KB".to_string(),
                     );
@@ -196,6 +362,7 @@ impl MessageParser {
                 }
                 if fin {
                     self.parse_error(
+                        ParseErrorSeverity::Fatal,
                         ErrorCode::ProtocolViolation,
                         "FIN after incomplete message".to_string(),
                     );
@@ -207,6 +374,35 @@ impl MessageParser {
         }
     }
 
+    /// Reads the first message in |bytes| as both a control message and an
+    /// object, without knowing which kind of stream it came from. Stream-type
+    /// rules, such as PATH in CLIENT_SETUP, are not enforced, and anything that
+    /// does not parse is left out of the result. For diagnostics only.
+    pub fn sniff(bytes: &[u8]) -> SniffResult {
+        let control_message = ControlMessage::deserialize(&mut &bytes[..])
+            .ok()
+            .map(|(control_message, _)| control_message);
+        let object_header = if matches!(
+            MessageType::deserialize(&mut &bytes[..]),
+            Ok((MessageType::ObjectDatagram, _))
+        ) {
+            Self::process_datagram(&mut &bytes[..])
+                .ok()
+                .map(|(object_header, _)| object_header)
+        } else {
+            let mut parser = Self::new_data_stream(false);
+            parser.process_data(&mut &bytes[..], false);
+            match parser.poll_event() {
+                Some(MessageParserEvent::ObjectMessage(object_header, ..)) => Some(object_header),
+                _ => None,
+            }
+        };
+        SniffResult {
+            control_message,
+            object_header,
+        }
+    }
+
     /// Provide a separate path for datagrams. Returns the ObjectHeader and payload bytes
     pub fn process_datagram<R: Buf>(r: &mut R) -> Result<(ObjectHeader, Bytes)> {
         let (object_header, _) = MessageParser::parse_object_header(r)?;
@@ -219,11 +415,158 @@ impl MessageParser {
         Ok((object_header, r.copy_to_bytes(r.remaining())))
     }
 
+    /// Parses one datagram written by
+    /// MessageFramer::serialize_object_datagram_fragments.
+    pub fn process_datagram_fragment<R: Buf>(r: &mut R) -> Result<DatagramFragment> {
+        let (message_type, _) = u64::deserialize(r)?;
+        if message_type != OBJECT_DATAGRAM_FRAGMENT_TYPE {
+            return Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "invalid datagram fragment".to_string(),
+            ));
+        }
+        let (subscribe_id, _) = u64::deserialize(r)?;
+        let (track_alias, _) = u64::deserialize(r)?;
+        let (group_id, _) = u64::deserialize(r)?;
+        let (object_id, _) = u64::deserialize(r)?;
+        let (object_send_order, _) = u64::deserialize(r)?;
+        let (object_status, _) = ObjectStatus::deserialize(r)?;
+        let (fragment_index, _) = u64::deserialize(r)?;
+        let (fragment_count, _) = u64::deserialize(r)?;
+        if object_status != ObjectStatus::Normal {
+            return Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "Datagram fragment of an object without payload".to_string(),
+            ));
+        }
+        if !(2..=MAX_DATAGRAM_FRAGMENTS).contains(&fragment_count)
+            || fragment_index >= fragment_count
+        {
+            return Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "Invalid datagram fragment index".to_string(),
+            ));
+        }
+        Ok(DatagramFragment {
+            object_header: ObjectHeader {
+                subscribe_id,
+                track_alias,
+                group_id,
+                object_id,
+                object_send_order,
+                object_status,
+                object_forwarding_preference: ObjectForwardingPreference::Datagram,
+                object_payload_length: None,
+            },
+            fragment_index,
+            fragment_count,
+            payload: r.copy_to_bytes(r.remaining()),
+        })
+    }
+
+    /// Bytes received but not yet parsed into a message.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_message.len()
+    }
+
+    /// Captures the bytes buffered toward the next message, the object in
+    /// mid-delivery, if any, and the framing a SETUP switched to. Events not
+    /// yet polled are not captured, so drain poll_event first.
+    pub fn checkpoint(&self) -> ParserCheckpoint {
+        ParserCheckpoint {
+            length_prefixed: self.length_prefixed,
+            length_prefixed_offered: self.length_prefixed_offered,
+            namespace_layout: self.namespace_layout,
+            buffered_message: self.buffered_message.clone(),
+            object_metadata: self.object_metadata,
+            object_stream_kind: self.object_stream_kind,
+            payload_length_remaining: self.payload_length_remaining,
+            no_more_data: self.no_more_data,
+            parsing_error: self.parsing_error,
+        }
+    }
+
+    /// Continues parsing from |checkpoint|. The parser keeps its own stream
+    /// kind and observer.
+    pub fn restore(&mut self, checkpoint: ParserCheckpoint) {
+        self.length_prefixed = checkpoint.length_prefixed;
+        self.length_prefixed_offered = checkpoint.length_prefixed_offered;
+        self.namespace_layout = checkpoint.namespace_layout;
+        self.buffered_message = checkpoint.buffered_message;
+        self.object_metadata = checkpoint.object_metadata;
+        self.object_stream_kind = checkpoint.object_stream_kind;
+        self.payload_length_remaining = checkpoint.payload_length_remaining;
+        self.no_more_data = checkpoint.no_more_data;
+        self.parsing_error = checkpoint.parsing_error;
+    }
+
+    /// Calls |observer| for every control and object message delivered by
+    /// poll_event.
+    pub fn set_observer(&mut self, observer: Box<dyn MessageObserver + Send>) {
+        self.observer = Some(observer);
+    }
+
+    /// For a control stream whose endpoint advertised LENGTH_PREFIXED_CONTROL
+    /// in its SETUP: once the peer's SETUP advertises it too, the messages
+    /// after it are parsed as if by new_length_prefixed_control.
+    pub fn offer_length_prefixed_control(&mut self) {
+        self.length_prefixed_offered = true;
+    }
+
+    /// Parses track namespaces in |layout|, e.g. the one of the version a
+    /// server requires. The version of a SERVER_SETUP replaces it for the
+    /// messages after it.
+    pub fn set_namespace_layout(&mut self, layout: NamespaceLayout) {
+        self.namespace_layout = layout;
+    }
+
+    /// Starts counting ParserStats for the messages parsed from now on.
+    pub fn enable_stats(&mut self) {
+        self.stats.get_or_insert_with(ParserStats::default);
+    }
+
+    /// The counters collected since enable_stats, if it was called.
+    pub fn stats(&self) -> Option<&ParserStats> {
+        self.stats.as_ref()
+    }
+
     pub fn poll_event(&mut self) -> Option<MessageParserEvent> {
-        self.parser_events.pop_front()
+        let event = self.parser_events.pop_front();
+        if let (Some(observer), Some(event)) = (self.observer.as_mut(), event.as_ref()) {
+            Self::observe(observer.as_mut(), event);
+        }
+        event
+    }
+
+    /// Takes every pending event at once, in the order poll_event would have
+    /// returned them. The observer sees each event before this returns.
+    pub fn take_events(&mut self) -> VecDeque<MessageParserEvent> {
+        let events = std::mem::take(&mut self.parser_events);
+        if let Some(observer) = self.observer.as_mut() {
+            for event in &events {
+                Self::observe(observer.as_mut(), event);
+            }
+        }
+        events
+    }
+
+    fn observe(observer: &mut (dyn MessageObserver + Send), event: &MessageParserEvent) {
+        match event {
+            MessageParserEvent::ControlMessage(control_message) => {
+                observer.on_control(control_message);
+            }
+            MessageParserEvent::ObjectMessage(object_header, _, payload, _) => {
+                observer.on_object(object_header, payload.len());
+            }
+            MessageParserEvent::ParsingError(_, _)
+            | MessageParserEvent::RecoverableParsingError(_, _) => {}
+        }
     }
 
     fn process_message(&mut self, fin: bool) -> usize {
+        if self.length_prefixed {
+            return self.process_length_prefixed_control_message();
+        }
         if self.object_stream_initialized() && !self.object_payload_in_progress() {
             match self.object_stream_kind {
                 Some(ObjectStreamKind::Legacy(message_type)) => {
@@ -248,14 +591,29 @@ impl MessageParser {
             Ok((message_type, _)) => message_type,
             Err(err) => {
                 if let Error::ErrParseError(code, reason) = err {
-                    self.parse_error(code, reason);
+                    self.parse_error(ParseErrorSeverity::Fatal, code, reason);
                 }
                 return 0;
             }
         };
 
-        if message_type == MessageType::ObjectDatagram {
+        if self.control_stream && message_type.is_object_message() {
+            self.parse_error(
+                ParseErrorSeverity::Fatal,
+                ErrorCode::ProtocolViolation,
+                OBJECT_ON_CONTROL_STREAM.to_string(),
+            );
+            0
+        } else if self.data_stream && !message_type.is_object_message() {
             self.parse_error(
+                ParseErrorSeverity::Fatal,
+                ErrorCode::ProtocolViolation,
+                CONTROL_MESSAGE_ON_DATA_STREAM.to_string(),
+            );
+            0
+        } else if message_type == MessageType::ObjectDatagram {
+            self.parse_error(
+                ParseErrorSeverity::Fatal,
                 ErrorCode::ProtocolViolation,
                 "Received OBJECT_DATAGRAM on strea".to_string(),
             );
@@ -267,53 +625,154 @@ impl MessageParser {
             self.process_object(message_type, fin)
         } else {
             let mut msg_reader = self.buffered_message.as_ref();
-            let (control_message, message_len) = match ControlMessage::deserialize(&mut msg_reader)
-            {
-                Ok((mut control_message, message_len)) => {
-                    if let ControlMessage::ClientSetup(client_setup) = &mut control_message {
-                        if self.uses_web_transport && client_setup.path.is_some() {
-                            self.parse_error(
-                                ErrorCode::ProtocolViolation,
-                                "WebTransport connection is using PATH parameter in SETUP"
-                                    .to_string(),
-                            );
-                            return 0;
-                        } else if !self.uses_web_transport && client_setup.path.is_none() {
-                            self.parse_error(
-                                ErrorCode::ProtocolViolation,
-                                "PATH SETUP parameter missing from Client message over QUIC"
-                                    .to_string(),
-                            );
+            let layout = self.namespace_layout;
+            let (control_message, message_len) =
+                match Self::counted(&mut self.stats, &mut msg_reader, |r| {
+                    ControlMessage::deserialize_with_layout(layout, r)
+                }) {
+                    Ok((mut control_message, message_len)) => {
+                        if !self.check_client_setup(&mut control_message) {
                             return 0;
                         }
-                        client_setup.uses_web_transport = self.uses_web_transport;
+                        if self.length_prefixed_offered {
+                            self.length_prefixed = match &control_message {
+                                ControlMessage::ClientSetup(setup) => setup.length_prefixed_control,
+                                ControlMessage::ServerSetup(setup) => setup.length_prefixed_control,
+                                _ => false,
+                            };
+                        }
+                        if let ControlMessage::ServerSetup(setup) = &control_message {
+                            self.namespace_layout = setup.supported_version.namespace_layout();
+                        }
+                        (control_message, message_len)
                     }
-
-                    (control_message, message_len)
-                }
-                Err(err) => {
-                    if let Error::ErrParseError(code, reason) = err {
-                        self.parse_error(code, reason);
+                    Err(err) => {
+                        if let Error::ErrParseError(code, reason) = err {
+                            self.parse_error(ParseErrorSeverity::Fatal, code, reason);
+                        }
+                        return 0;
                     }
-                    return 0;
-                }
-            };
+                };
             self.parser_events
                 .push_back(MessageParserEvent::ControlMessage(control_message));
             message_len
         }
     }
 
+    fn process_length_prefixed_control_message(&mut self) -> usize {
+        let mut reader = self.buffered_message.as_ref();
+        let Ok((length, ll)) = usize::deserialize(&mut reader) else {
+            return 0;
+        };
+        if length > MAX_MESSSAGE_HEADER_SIZE {
+            self.parse_error(
+                ParseErrorSeverity::Fatal,
+                ErrorCode::InternalError,
+                "Cannot parse non-OBJECT messages > 2KB".to_string(),
+            );
+            return 0;
+        }
+        if reader.len() < length {
+            return 0;
+        }
+        let mut message = &reader[..length];
+
+        let mut mt_reader = message;
+        let Ok((message_type, _)) = u64::deserialize(&mut mt_reader) else {
+            self.parse_error(
+                ParseErrorSeverity::Fatal,
+                ErrorCode::ProtocolViolation,
+                "Control message length mismatch".to_string(),
+            );
+            return 0;
+        };
+        match MessageType::try_from(message_type) {
+            // The length lets unknown messages be stepped over.
+            Err(_) => {
+                self.parse_error(
+                    ParseErrorSeverity::Recoverable,
+                    ErrorCode::ProtocolViolation,
+                    format!(
+                        "Skipped control message of unknown type {:#x}",
+                        message_type
+                    ),
+                );
+                return ll + length;
+            }
+            Ok(message_type) if message_type.is_object_message() => {
+                self.parse_error(
+                    ParseErrorSeverity::Fatal,
+                    ErrorCode::ProtocolViolation,
+                    OBJECT_ON_CONTROL_STREAM.to_string(),
+                );
+                return 0;
+            }
+            Ok(_) => {}
+        }
+
+        let layout = self.namespace_layout;
+        let mut control_message = match Self::counted(&mut self.stats, &mut message, |r| {
+            ControlMessage::deserialize_with_layout(layout, r)
+        }) {
+            Ok((control_message, message_len)) if message_len == length => control_message,
+            Ok(_) | Err(Error::ErrUnexpectedEnd | Error::ErrBufferTooShort) => {
+                self.parse_error(
+                    ParseErrorSeverity::Fatal,
+                    ErrorCode::ProtocolViolation,
+                    "Control message length mismatch".to_string(),
+                );
+                return 0;
+            }
+            Err(err) => {
+                if let Error::ErrParseError(code, reason) = err {
+                    self.parse_error(ParseErrorSeverity::Fatal, code, reason);
+                }
+                return 0;
+            }
+        };
+        if !self.check_client_setup(&mut control_message) {
+            return 0;
+        }
+        self.parser_events
+            .push_back(MessageParserEvent::ControlMessage(control_message));
+        ll + length
+    }
+
+    // Applies the transport-dependent PATH rules of CLIENT_SETUP. Returns
+    // false after reporting a parse error.
+    fn check_client_setup(&mut self, control_message: &mut ControlMessage) -> bool {
+        let ControlMessage::ClientSetup(client_setup) = control_message else {
+            return true;
+        };
+        match client_setup.bind_transport(self.uses_web_transport) {
+            Ok(()) => true,
+            Err(Error::ErrParseError(code, reason)) => {
+                self.parse_error(ParseErrorSeverity::Fatal, code, reason);
+                false
+            }
+            Err(err) => {
+                self.parse_error(
+                    ParseErrorSeverity::Fatal,
+                    ErrorCode::InternalError,
+                    err.to_string(),
+                );
+                false
+            }
+        }
+    }
+
     fn process_object(&mut self, message_type: MessageType, fin: bool) -> usize {
         let mut processed_data = 0;
         assert!(!self.object_payload_in_progress());
         if !self.object_stream_initialized() {
             let mut oh_reader = self.buffered_message.as_ref();
-            let (object_metadata, obl) = match MessageParser::parse_object_header(&mut oh_reader) {
+            let (object_metadata, obl) = match Self::counted(&mut self.stats, &mut oh_reader, |r| {
+                MessageParser::parse_object_header(r)
+            }) {
                 Ok((object_metadata, obl)) => (object_metadata, obl),
                 Err(err) => {
                     if let Error::ErrParseError(code, reason) = err {
-                        self.parse_error(code, reason);
+                        self.parse_error(ParseErrorSeverity::Fatal, code, reason);
                     }
                     return 0;
                 }
@@ -324,20 +783,22 @@ impl MessageParser {
         }
 
         let mut payload_reader = &self.buffered_message.as_ref()[processed_data..];
-        match MessageParser::process_object_payload(
-            &mut self.parser_events,
-            &mut self.object_metadata,
-            &mut self.payload_length_remaining,
-            &mut payload_reader,
-            message_type,
-            fin,
-        ) {
+        match Self::counted(&mut self.stats, &mut payload_reader, |r| {
+            MessageParser::process_object_payload(
+                &mut self.parser_events,
+                &mut self.object_metadata,
+                &mut self.payload_length_remaining,
+                r,
+                message_type,
+                fin,
+            )
+        }) {
             Ok(prl) => {
                 processed_data += prl;
             }
             Err(err) => {
                 if let Error::ErrParseError(code, reason) = err {
-                    self.parse_error(code, reason);
+                    self.parse_error(ParseErrorSeverity::Fatal, code, reason);
                 }
             }
         };
@@ -345,6 +806,25 @@ impl MessageParser {
         processed_data
     }
 
+    // Runs a decode step over |r|, adding the varints it read to |stats| when
+    // it succeeds. Failed steps are retried once more data arrives, so they
+    // don't count.
+    fn counted<B: Buf, T>(
+        stats: &mut Option<ParserStats>,
+        r: &mut B,
+        f: impl FnOnce(&mut VarintCounter<'_, B>) -> Result<T>,
+    ) -> Result<T> {
+        let mut counter = VarintCounter {
+            inner: r,
+            sizes: [0; 4],
+        };
+        let result = f(&mut counter);
+        if let (Some(stats), Ok(_)) = (stats.as_mut(), &result) {
+            stats.add_varint_sizes(counter.sizes);
+        }
+        result
+    }
+
     fn parse_object_header<R: Buf>(r: &mut R) -> Result<(ObjectHeader, usize)> {
         let (message_type, mtl) = MessageType::deserialize(r)?;
         let (subscribe_id, sil) = u64::deserialize(r)?;
@@ -362,14 +842,13 @@ impl MessageParser {
             (0, 0)
         };
         let (object_send_order, osol) = u64::deserialize(r)?;
-        let (status, osl) = if message_type == MessageType::ObjectStream
+        let (object_status, osl) = if message_type == MessageType::ObjectStream
             || message_type == MessageType::ObjectDatagram
         {
-            u64::deserialize(r)?
+            ObjectStatus::deserialize(r)?
         } else {
-            (0, 0)
+            (ObjectStatus::Normal, 0)
         };
-        let object_status: ObjectStatus = status.into();
         let object_forwarding_preference: ObjectForwardingPreference =
             message_type.get_object_forwarding_preference()?;
 
@@ -416,27 +895,21 @@ impl MessageParser {
             let (object_payload_length, opl) = u64::deserialize(r)?;
             total_len += opl;
 
-            let mut status = 0; // Defaults to kNormal.
+            let mut object_status = ObjectStatus::Normal;
             if object_payload_length == 0 {
                 let sl;
-                (status, sl) = u64::deserialize(r)?;
+                (object_status, sl) = ObjectStatus::deserialize(r)?;
                 total_len += sl;
             }
 
             if let Some(object_metadata) = object_header.as_mut() {
                 object_metadata.object_id = object_id;
                 object_metadata.object_payload_length = Some(object_payload_length);
-                object_metadata.object_status = status.into();
+                object_metadata.object_status = object_status;
             }
         }
 
         if let Some(object_metadata) = object_header.as_ref() {
-            if object_metadata.object_status == ObjectStatus::Invalid {
-                return Err(Error::ErrParseError(
-                    ErrorCode::ProtocolViolation,
-                    "Invalid object status".to_string(),
-                ));
-            }
             if object_metadata.object_status != ObjectStatus::Normal {
                 // It is impossible to express an explicit length with this status.
                 if (message_type == MessageType::ObjectStream
@@ -506,11 +979,13 @@ impl MessageParser {
         let previous = self.object_metadata;
         let mut reader = self.buffered_message.as_ref();
         let (object_metadata, extension_headers, header_len) =
-            match MessageParser::parse_fetch_header(&mut reader, previous) {
+            match Self::counted(&mut self.stats, &mut reader, |r| {
+                MessageParser::parse_fetch_header(r, previous)
+            }) {
                 Ok(value) => value,
                 Err(Error::ErrUnexpectedEnd | Error::ErrBufferTooShort) => return 0,
                 Err(Error::ErrParseError(code, reason)) => {
-                    self.parse_error(code, reason);
+                    self.parse_error(ParseErrorSeverity::Fatal, code, reason);
                     return 0;
                 }
                 Err(_) => return 0,
@@ -532,6 +1007,7 @@ impl MessageParser {
             .saturating_sub(processed_data);
         if fin && payload_length > available {
             self.parse_error(
+                ParseErrorSeverity::Fatal,
                 ErrorCode::ProtocolViolation,
                 "Received FIN mid-payload".to_string(),
             );
@@ -636,7 +1112,9 @@ impl MessageParser {
                 return Err(Error::ErrUnexpectedEnd);
             }
             total_len += 1;
-            u64::from(r.get_u8())
+            let mut priority = [0];
+            r.copy_to_slice(&mut priority);
+            u64::from(priority[0])
         } else if let Some(previous) = previous {
             previous.object_send_order
         } else {
@@ -684,10 +1162,23 @@ impl MessageParser {
         ))
     }
 
-    fn parse_error(&mut self, error_code: ErrorCode, error_reason: String) {
+    fn parse_error(
+        &mut self,
+        severity: ParseErrorSeverity,
+        error_code: ErrorCode,
+        error_reason: String,
+    ) {
         if self.parsing_error {
             return; // Don't send multiple parse errors.
         }
+        if severity == ParseErrorSeverity::Recoverable {
+            self.parser_events
+                .push_back(MessageParserEvent::RecoverableParsingError(
+                    error_code,
+                    error_reason,
+                ));
+            return;
+        }
         self.no_more_data = true;
         self.parsing_error = true;
         self.parser_events
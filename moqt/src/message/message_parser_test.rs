@@ -1,16 +1,27 @@
-use crate::message::message_parser::{ErrorCode, MessageParser, MessageParserEvent};
+use crate::message::fetch::{Fetch, FetchTarget, StandaloneFetch};
+use crate::message::message_framer::MessageFramer;
+use crate::message::message_parser::{
+    ErrorCode, MessageObserver, MessageParser, MessageParserEvent, CONTROL_MESSAGE_ON_DATA_STREAM,
+};
 use crate::message::message_test::{
     create_test_message, MessageStructuredData, TestMessageBase, TestObjectDatagramMessage,
     TestObjectStreamMessage, TestStreamHeaderGroupMessage, TestStreamHeaderTrackMessage,
     TestStreamMiddlerGroupMessage, TestStreamMiddlerTrackMessage, TestSubscribeDoneMessage,
     TestSubscribeOkMessage,
 };
-use crate::message::object::ObjectHeader;
-use crate::message::{ControlMessage, FilterType, MessageType, MAX_MESSSAGE_HEADER_SIZE};
+use crate::message::object::{ObjectForwardingPreference, ObjectHeader, ObjectStatus};
+use crate::message::server_setup::ServerSetup;
+use crate::message::subscribe::Subscribe;
+use crate::message::subscribe_ok::SubscribeOk;
+use crate::message::{
+    ControlMessage, FilterType, FullSequence, FullTrackName, MessageType, NamespaceLayout, Role,
+    Version, MAX_MESSSAGE_HEADER_SIZE,
+};
 use crate::{Error, Result, Serializer};
 use bytes::Bytes;
 use rstest::rstest;
 use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
 
 struct TestParserParams {
     message_type: MessageType,
@@ -46,6 +57,7 @@ struct TestParserVisitor {
     end_of_message: bool,
     parsing_error: Option<String>,
     parsing_error_code: ErrorCode,
+    recoverable_errors: Vec<String>,
     messages_received: u64,
     last_message: Option<MessageStructuredData>,
 }
@@ -57,6 +69,7 @@ impl TestParserVisitor {
             end_of_message: false,
             parsing_error: None,
             parsing_error_code: ErrorCode::NoError,
+            recoverable_errors: vec![],
             messages_received: 0,
             last_message: None,
         }
@@ -65,6 +78,9 @@ impl TestParserVisitor {
     fn handle_event(&mut self, event: MessageParserEvent) {
         match event {
             MessageParserEvent::ParsingError(code, reason) => self.on_parsing_error(code, reason),
+            MessageParserEvent::RecoverableParsingError(_, reason) => {
+                self.recoverable_errors.push(reason)
+            }
             MessageParserEvent::ObjectMessage(
                 message,
                 _extension_headers,
@@ -75,6 +91,20 @@ impl TestParserVisitor {
         }
     }
 
+    fn last_control(&self) -> Option<&ControlMessage> {
+        match self.last_message.as_ref() {
+            Some(MessageStructuredData::Control(message)) => Some(message),
+            _ => None,
+        }
+    }
+
+    fn last_object(&self) -> Option<&ObjectHeader> {
+        match self.last_message.as_ref() {
+            Some(MessageStructuredData::Object(message)) => Some(message),
+            _ => None,
+        }
+    }
+
     fn on_parsing_error(&mut self, code: ErrorCode, reason: String) {
         self.parsing_error = Some(reason);
         self.parsing_error_code = code;
@@ -249,7 +279,7 @@ fn test_one_message_with_long_varints(params: (MessageType, bool)) -> Result<()>
         );
     }
     assert!(
-        !tester.visitor.parsing_error.is_some(),
+        tester.visitor.parsing_error.is_none(),
         "message type {:?}",
         tester.message_type
     );
@@ -338,7 +368,7 @@ fn test_two_part_message(params: (MessageType, bool)) -> Result<()> {
         tester.message_type
     );
     assert!(
-        !tester.visitor.parsing_error.is_some(),
+        tester.visitor.parsing_error.is_none(),
         "message type {:?}",
         tester.message_type
     );
@@ -436,7 +466,7 @@ fn test_one_byte_at_atime(params: (MessageType, bool)) -> Result<()> {
         tester.message_type
     );
     assert!(
-        !tester.visitor.parsing_error.is_some(),
+        tester.visitor.parsing_error.is_none(),
         "message type {:?}",
         tester.message_type
     );
@@ -540,7 +570,7 @@ fn test_one_byte_at_a_time_longer_varints(params: (MessageType, bool)) -> Result
         tester.message_type
     );
     assert!(
-        !tester.visitor.parsing_error.is_some(),
+        tester.visitor.parsing_error.is_none(),
         "message type {:?}",
         tester.message_type
     );
@@ -701,10 +731,33 @@ fn test_object_stream_separate_fin() -> Result<()> {
     assert_eq!(tester.visitor.messages_received, 2);
     let last_message = tester.visitor.last_message.as_ref().unwrap();
     assert!(message.equal_field_values(last_message));
+    // The FIN is reported against the same object.
+    let object = tester.visitor.last_object().unwrap();
+    assert_eq!((object.group_id, object.object_id), (5, 6));
+    assert!(tester.visitor.last_control().is_none());
     assert!(tester.visitor.object_payload.is_some());
     assert_eq!(tester.visitor.object_payload, Some(Bytes::from_static(b"")));
     assert!(tester.visitor.end_of_message);
-    assert!(!tester.visitor.parsing_error.is_some());
+    assert!(tester.visitor.parsing_error.is_none());
+    Ok(())
+}
+
+#[rstest]
+#[case(MessageType::ObjectStream)]
+#[case(MessageType::StreamHeaderGroup)]
+fn test_empty_data_without_fin(#[case] message_type: MessageType) -> Result<()> {
+    let message = create_test_message(message_type, K_RAW_QUIC);
+    let packet = message.packet_sample();
+    // Mid-header, then mid-payload.
+    for split in [2, packet.len() - 1] {
+        let mut parser = MessageParser::new(K_RAW_QUIC);
+        parser.process_data(&mut &packet[..split], false);
+        while parser.poll_event().is_some() {}
+        let before = parser.checkpoint();
+        parser.process_data(&mut &[][..], false);
+        assert_eq!(parser.checkpoint(), before);
+        assert_eq!(parser.poll_event(), None);
+    }
     Ok(())
 }
 
@@ -758,7 +811,7 @@ fn test_three_part_object() -> Result<()> {
         tester.visitor.object_payload,
         Some(Bytes::from_static(b"deadbeef"))
     );
-    assert!(!tester.visitor.parsing_error.is_some());
+    assert!(tester.visitor.parsing_error.is_none());
 
     Ok(())
 }
@@ -809,7 +862,7 @@ fn test_three_part_object_first_incomplete() -> Result<()> {
         tester.visitor.object_payload,
         Some(Bytes::from_static(b"bar"))
     );
-    assert!(!tester.visitor.parsing_error.is_some());
+    assert!(tester.visitor.parsing_error.is_none());
 
     Ok(())
 }
@@ -833,7 +886,7 @@ fn test_stream_header_group_follow_on() -> Result<()> {
         tester.visitor.object_payload,
         Some(Bytes::from_static(b"foo"))
     );
-    assert!(!tester.visitor.parsing_error.is_some());
+    assert!(tester.visitor.parsing_error.is_none());
     // second part
     let message2 = TestStreamMiddlerGroupMessage::new();
     parser.process_data(&mut message2.packet_sample(), false);
@@ -849,7 +902,30 @@ fn test_stream_header_group_follow_on() -> Result<()> {
         tester.visitor.object_payload,
         Some(Bytes::from_static(b"bar"))
     );
-    assert!(!tester.visitor.parsing_error.is_some());
+    assert!(tester.visitor.parsing_error.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_stream_header_group_middler_looks_like_header() -> Result<()> {
+    let mut tester = TestMessageSpecific::new();
+    let mut parser = MessageParser::new(K_RAW_QUIC);
+    let message1 = TestStreamHeaderGroupMessage::new();
+    parser.process_data(&mut message1.packet_sample(), false);
+    // object_id 0x51 and a 3 byte payload of 0x04 0x05 0x06, which starts
+    // like the stream's own header.
+    let middler = [0x40, 0x51, 0x03, 0x04, 0x05, 0x06];
+    parser.process_data(&mut &middler[..], false);
+    while let Some(event) = parser.poll_event() {
+        tester.visitor.handle_event(event);
+    }
+    assert_eq!(tester.visitor.messages_received, 2);
+    assert!(tester.visitor.parsing_error.is_none());
+    assert_eq!(
+        tester.visitor.object_payload,
+        Some(Bytes::from_static(&[0x04, 0x05, 0x06]))
+    );
 
     Ok(())
 }
@@ -873,7 +949,7 @@ fn test_stream_header_track_follow_on() -> Result<()> {
         tester.visitor.object_payload,
         Some(Bytes::from_static(b"foo"))
     );
-    assert!(!tester.visitor.parsing_error.is_some());
+    assert!(tester.visitor.parsing_error.is_none());
     // second part
     let message2 = TestStreamMiddlerTrackMessage::new();
     parser.process_data(&mut message2.packet_sample(), false);
@@ -889,7 +965,7 @@ fn test_stream_header_track_follow_on() -> Result<()> {
         tester.visitor.object_payload,
         Some(Bytes::from_static(b"bar"))
     );
-    assert!(!tester.visitor.parsing_error.is_some());
+    assert!(tester.visitor.parsing_error.is_none());
 
     Ok(())
 }
@@ -1009,7 +1085,7 @@ fn test_client_setup_role_is_missing() -> Result<()> {
 fn test_server_setup_role_is_missing() -> Result<()> {
     let mut tester = TestMessageSpecific::new();
     let mut parser = MessageParser::new(K_RAW_QUIC);
-    let setup = vec![
+    let setup = [
         0x40, 0x41, 0x01, 0x00, // 1 param
     ];
     parser.process_data(&mut &setup[..], false);
@@ -1338,6 +1414,300 @@ fn test_data_after_fin() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_sniff_subscribe() -> Result<()> {
+    let message = create_test_message(MessageType::Subscribe, K_RAW_QUIC);
+    let result = MessageParser::sniff(message.packet_sample());
+    assert!(result.object_header.is_none());
+    let control_message = MessageStructuredData::Control(
+        result
+            .control_message
+            .ok_or(Error::ErrInvalidMessageType(0))?,
+    );
+    assert!(message.equal_field_values(&control_message));
+
+    Ok(())
+}
+
+#[test]
+fn test_sniff_object_stream() -> Result<()> {
+    let message = TestObjectStreamMessage::new();
+    let result = MessageParser::sniff(message.packet_sample());
+    assert!(result.control_message.is_none());
+    let object_header = MessageStructuredData::Object(
+        result
+            .object_header
+            .ok_or(Error::ErrInvalidMessageType(0))?,
+    );
+    assert!(message.equal_field_values(&object_header));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_checkpoint_mid_control_message() -> Result<()> {
+    let message = create_test_message(MessageType::Subscribe, K_RAW_QUIC);
+    let packet = message.packet_sample();
+    let mut parser = MessageParser::new(K_RAW_QUIC);
+    parser.process_data(&mut &packet[..5], false);
+    assert_eq!(parser.poll_event(), None);
+    let checkpoint = parser.checkpoint();
+
+    let mut tester = TestMessageSpecific::new();
+    let mut restored = MessageParser::new(K_RAW_QUIC);
+    restored.restore(checkpoint);
+    assert_eq!(restored.buffered_bytes(), 5);
+    restored.process_data(&mut &packet[5..], false);
+    while let Some(event) = restored.poll_event() {
+        tester.visitor.handle_event(event);
+    }
+    assert_eq!(tester.visitor.messages_received, 1);
+    assert!(tester.visitor.parsing_error.is_none());
+    let Some(control_message) = tester.visitor.last_control() else {
+        panic!("expected a control message");
+    };
+    assert!(message.equal_field_values(&MessageStructuredData::Control(control_message.clone())));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_checkpoint_keeps_length_prefixed_framing() -> Result<()> {
+    let server_setup = ControlMessage::ServerSetup(ServerSetup {
+        supported_version: Version::Draft04,
+        role: Some(Role::PubSub),
+        length_prefixed_control: true,
+        ..Default::default()
+    });
+    let mut parser = MessageParser::new_control(K_RAW_QUIC);
+    parser.offer_length_prefixed_control();
+    parser.process_data(&mut MessageFramer::to_vec(&server_setup)?.as_slice(), false);
+    assert_eq!(
+        parser.poll_event(),
+        Some(MessageParserEvent::ControlMessage(server_setup))
+    );
+    let checkpoint = parser.checkpoint();
+
+    let (message, bytes) = length_prefixed(MessageType::UnSubscribe)?;
+    let mut tester = TestMessageSpecific::new();
+    let mut restored = MessageParser::new_control(K_RAW_QUIC);
+    restored.restore(checkpoint);
+    restored.process_data(&mut bytes.as_slice(), false);
+    while let Some(event) = restored.poll_event() {
+        tester.visitor.handle_event(event);
+    }
+    assert!(tester.visitor.parsing_error.is_none());
+    assert_eq!(tester.visitor.messages_received, 1);
+    assert!(message.equal_field_values(tester.visitor.last_message.as_ref().unwrap()));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_checkpoint_mid_object_payload() -> Result<()> {
+    let message = TestObjectStreamMessage::new();
+    let packet = message.packet_sample();
+    let mut parser = MessageParser::new(K_RAW_QUIC);
+    parser.process_data(&mut &packet[..packet.len() - 2], false);
+    let Some(MessageParserEvent::ObjectMessage(_, _, first, false)) = parser.poll_event() else {
+        panic!("expected the start of the object");
+    };
+    let checkpoint = parser.checkpoint();
+
+    let mut restored = MessageParser::new(K_RAW_QUIC);
+    restored.restore(checkpoint);
+    restored.process_data(&mut &packet[packet.len() - 2..], true);
+    let Some(MessageParserEvent::ObjectMessage(object_header, _, rest, true)) =
+        restored.poll_event()
+    else {
+        panic!("expected the end of the object");
+    };
+    assert!(message.equal_field_values(&MessageStructuredData::Object(object_header)));
+    assert_eq!([first, rest].concat(), b"foo");
+    assert_eq!(restored.poll_event(), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_varint_size_stats() -> Result<()> {
+    let mut parser = MessageParser::new(K_RAW_QUIC);
+    assert!(parser.stats().is_none());
+    parser.enable_stats();
+    let data = vec![
+        0x0a, 0x40, 0x05, // UNSUBSCRIBE, two-byte subscribe_id
+        0x0a, 0x80, 0x00, 0x00, 0x06, // UNSUBSCRIBE, four-byte subscribe_id
+        0x0a, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, // eight-byte subscribe_id
+        0x0a, 0x80, 0x00, // truncated UNSUBSCRIBE, not counted yet
+    ];
+    parser.process_data(&mut &data[..], false);
+    let mut messages = 0;
+    while let Some(event) = parser.poll_event() {
+        assert!(matches!(event, MessageParserEvent::ControlMessage(_)));
+        messages += 1;
+    }
+    assert_eq!(messages, 3);
+
+    let stats = parser.stats().ok_or(Error::ErrInvalidMessageType(0))?;
+    assert_eq!(stats.varints_of_length(1), 3);
+    assert_eq!(stats.varints_of_length(2), 1);
+    assert_eq!(stats.varints_of_length(4), 1);
+    assert_eq!(stats.varints_of_length(8), 1);
+    assert_eq!(stats.varints_of_length(3), 0);
+
+    // The one-byte content_exists flag of SUBSCRIBE_OK is not a varint.
+    let subscribe_ok = ControlMessage::SubscribeOk(SubscribeOk {
+        subscribe_id: 1,
+        expires: 2,
+        largest_group_object: Some(FullSequence::new(3, 4)),
+    });
+    let mut parser = MessageParser::new(K_RAW_QUIC);
+    parser.enable_stats();
+    parser.process_data(&mut MessageFramer::to_vec(&subscribe_ok)?.as_slice(), false);
+    assert_eq!(
+        parser.poll_event(),
+        Some(MessageParserEvent::ControlMessage(subscribe_ok))
+    );
+    let stats = parser.stats().ok_or(Error::ErrInvalidMessageType(0))?;
+    assert_eq!(stats.varints_of_length(1), 5);
+
+    Ok(())
+}
+
+fn length_prefixed(message_type: MessageType) -> Result<(Box<dyn TestMessageBase>, Vec<u8>)> {
+    let message = create_test_message(message_type, K_RAW_QUIC);
+    let MessageStructuredData::Control(control_message) = message.structured_data() else {
+        return Err(Error::ErrInvalidMessageType(message_type as u64));
+    };
+    let mut bytes = vec![];
+    MessageFramer::serialize_length_prefixed_control_message(&control_message, &mut bytes)?;
+    Ok((message, bytes))
+}
+
+#[rstest]
+#[case(MessageType::Subscribe)]
+#[case(MessageType::Announce)]
+#[case(MessageType::ClientSetup)]
+fn test_length_prefixed_control_message(#[case] message_type: MessageType) -> Result<()> {
+    let (message, bytes) = length_prefixed(message_type)?;
+    let mut tester = TestMessageSpecific::new();
+    let mut parser = MessageParser::new_length_prefixed_control(K_RAW_QUIC);
+    for byte in bytes.chunks(1) {
+        parser.process_data(&mut &byte[..], false);
+    }
+    while let Some(event) = parser.poll_event() {
+        tester.visitor.handle_event(event);
+    }
+    assert!(tester.visitor.parsing_error.is_none());
+    assert_eq!(tester.visitor.messages_received, 1);
+    assert!(message.equal_field_values(tester.visitor.last_message.as_ref().unwrap()));
+
+    Ok(())
+}
+
+#[test]
+fn test_length_prefixed_skips_unknown_message() -> Result<()> {
+    let (message, bytes) = length_prefixed(MessageType::UnSubscribe)?;
+    let mut data = vec![
+        0x04, // length
+        0x3f, 0x01, 0x02, 0x03, // unknown type 0x3f
+    ];
+    data.extend_from_slice(&bytes);
+    let mut tester = TestMessageSpecific::new();
+    let mut parser = MessageParser::new_length_prefixed_control(K_RAW_QUIC);
+    parser.process_data(&mut &data[..], false);
+    // The skipped message is reported ahead of the message that follows it.
+    let first = parser.poll_event();
+    assert_eq!(
+        first,
+        Some(MessageParserEvent::RecoverableParsingError(
+            ErrorCode::ProtocolViolation,
+            "Skipped control message of unknown type 0x3f".to_string(),
+        ))
+    );
+    tester.visitor.handle_event(first.unwrap());
+    while let Some(event) = parser.poll_event() {
+        tester.visitor.handle_event(event);
+    }
+    assert!(tester.visitor.parsing_error.is_none());
+    assert_eq!(
+        tester.visitor.recoverable_errors,
+        vec!["Skipped control message of unknown type 0x3f".to_string()]
+    );
+    assert_eq!(tester.visitor.messages_received, 1);
+    assert!(message.equal_field_values(tester.visitor.last_message.as_ref().unwrap()));
+    assert_eq!(parser.buffered_bytes(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_recoverable_error_keeps_parser_usable() -> Result<()> {
+    let (message, bytes) = length_prefixed(MessageType::UnSubscribe)?;
+    let mut tester = TestMessageSpecific::new();
+    let mut parser = MessageParser::new_length_prefixed_control(K_RAW_QUIC);
+    parser.process_data(&mut &[0x02, 0x3f, 0x00][..], false);
+    while let Some(event) = parser.poll_event() {
+        tester.visitor.handle_event(event);
+    }
+    assert_eq!(tester.visitor.recoverable_errors.len(), 1);
+    assert_eq!(tester.visitor.messages_received, 0);
+
+    // A later message on the same stream still parses.
+    parser.process_data(&mut &bytes[..], true);
+    while let Some(event) = parser.poll_event() {
+        tester.visitor.handle_event(event);
+    }
+    assert!(tester.visitor.parsing_error.is_none());
+    assert_eq!(tester.visitor.messages_received, 1);
+    assert!(message.equal_field_values(tester.visitor.last_message.as_ref().unwrap()));
+
+    Ok(())
+}
+
+#[test]
+fn test_length_prefixed_length_mismatch() -> Result<()> {
+    let data = [
+        0x03, // length covers one byte more than UNSUBSCRIBE
+        0x0a, 0x05, 0x00,
+    ];
+    let mut tester = TestMessageSpecific::new();
+    let mut parser = MessageParser::new_length_prefixed_control(K_RAW_QUIC);
+    parser.process_data(&mut &data[..], false);
+    while let Some(event) = parser.poll_event() {
+        tester.visitor.handle_event(event);
+    }
+    assert_eq!(tester.visitor.messages_received, 0);
+    assert_eq!(
+        tester.visitor.parsing_error,
+        Some("Control message length mismatch".to_string())
+    );
+    assert_eq!(
+        tester.visitor.parsing_error_code,
+        ErrorCode::ProtocolViolation
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_data_after_parse_error_is_ignored() -> Result<()> {
+    let mut parser = MessageParser::new(K_RAW_QUIC);
+    parser.process_data(&mut Bytes::new(), true);
+    parser.process_data(&mut Bytes::from_static(b"foo"), false);
+    parser.process_data(&mut Bytes::from_static(b"bar"), true);
+
+    let mut errors = 0;
+    while let Some(event) = parser.poll_event() {
+        assert!(matches!(event, MessageParserEvent::ParsingError(..)));
+        errors += 1;
+    }
+    assert_eq!(errors, 1);
+    assert_eq!(parser.buffered_bytes(), 0);
+
+    Ok(())
+}
+
 #[test]
 fn test_non_normal_object_has_payload() -> Result<()> {
     let mut tester = TestMessageSpecific::new();
@@ -1416,6 +1786,47 @@ fn test_setup2kb() -> Result<()> {
     Ok(())
 }
 
+#[rstest]
+#[case(ObjectForwardingPreference::Track)]
+#[case(ObjectForwardingPreference::Group)]
+fn test_large_payload_bypasses_2kb_limit(
+    #[case] object_forwarding_preference: ObjectForwardingPreference,
+) -> Result<()> {
+    let payload_length = 1 << 20;
+    let object = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        group_id: 5,
+        object_id: 6,
+        object_send_order: 7,
+        object_status: ObjectStatus::Normal,
+        object_forwarding_preference,
+        object_payload_length: Some(payload_length as u64),
+    };
+    let mut header = vec![];
+    MessageFramer::serialize_object_header(object, true, &mut header)?;
+
+    let mut parser = MessageParser::new(K_RAW_QUIC);
+    let mut received = 0;
+    let mut chunk = header;
+    chunk.extend_from_slice(&[0x2a; 2 * MAX_MESSSAGE_HEADER_SIZE]);
+    while received < payload_length {
+        parser.process_data(&mut &chunk[..], false);
+        while let Some(event) = parser.poll_event() {
+            let MessageParserEvent::ObjectMessage(header, _, payload, fin) = event else {
+                panic!("unexpected event {:?}", event);
+            };
+            assert_eq!(header, object);
+            received += payload.len();
+            assert_eq!(fin, received == payload_length);
+        }
+        let remaining = (payload_length - received).min(3 * MAX_MESSSAGE_HEADER_SIZE);
+        chunk = vec![0x2a; remaining];
+    }
+    assert_eq!(received, payload_length);
+    Ok(())
+}
+
 #[test]
 fn test_unknown_message_type() -> Result<()> {
     let mut tester = TestMessageSpecific::new();
@@ -1453,20 +1864,10 @@ fn test_latest_group() -> Result<()> {
         tester.visitor.handle_event(event);
     }
     assert_eq!(tester.visitor.messages_received, 1);
-    assert!(tester.visitor.last_message.is_some());
-    let message = if let Some(MessageStructuredData::Control(ControlMessage::Subscribe(message))) =
-        tester.visitor.last_message
-    {
-        message
-    } else {
-        assert!(false);
+    let Some(ControlMessage::Subscribe(message)) = tester.visitor.last_control() else {
         return Err(Error::ErrInvalidMessageType(0));
     };
-    if let FilterType::LatestGroup = message.filter_type {
-        assert!(true);
-    } else {
-        assert!(false);
-    }
+    assert_eq!(message.filter_type, FilterType::LatestGroup);
 
     Ok(())
 }
@@ -1488,20 +1889,11 @@ fn test_latest_object() -> Result<()> {
         tester.visitor.handle_event(event);
     }
     assert_eq!(tester.visitor.messages_received, 1);
-    assert!(!tester.visitor.parsing_error.is_some());
-    let message = if let Some(MessageStructuredData::Control(ControlMessage::Subscribe(message))) =
-        tester.visitor.last_message
-    {
-        message
-    } else {
-        assert!(false);
+    assert!(tester.visitor.parsing_error.is_none());
+    let Some(ControlMessage::Subscribe(message)) = tester.visitor.last_control() else {
         return Err(Error::ErrInvalidMessageType(0));
     };
-    if let FilterType::LatestObject = message.filter_type {
-        assert!(true);
-    } else {
-        assert!(false);
-    }
+    assert_eq!(message.filter_type, FilterType::LatestObject);
 
     Ok(())
 }
@@ -1525,21 +1917,14 @@ fn test_absolute_start() -> Result<()> {
         tester.visitor.handle_event(event);
     }
     assert_eq!(tester.visitor.messages_received, 1);
-    assert!(!tester.visitor.parsing_error.is_some());
-    let message = if let Some(MessageStructuredData::Control(ControlMessage::Subscribe(message))) =
-        tester.visitor.last_message
-    {
-        message
-    } else {
-        assert!(false);
+    assert!(tester.visitor.parsing_error.is_none());
+    let Some(ControlMessage::Subscribe(message)) = tester.visitor.last_control() else {
         return Err(Error::ErrInvalidMessageType(0));
     };
-    if let FilterType::AbsoluteStart(start) = message.filter_type {
-        assert_eq!(start.group_id, 4);
-        assert_eq!(start.object_id, 1);
-    } else {
-        assert!(false);
-    }
+    assert_eq!(
+        message.filter_type,
+        FilterType::AbsoluteStart(FullSequence::new(4, 1))
+    );
 
     Ok(())
 }
@@ -1565,23 +1950,14 @@ fn test_absolute_range_explicit_end_object() -> Result<()> {
         tester.visitor.handle_event(event);
     }
     assert_eq!(tester.visitor.messages_received, 1);
-    assert!(!tester.visitor.parsing_error.is_some());
-    let message = if let Some(MessageStructuredData::Control(ControlMessage::Subscribe(message))) =
-        tester.visitor.last_message
-    {
-        message
-    } else {
-        assert!(false);
+    assert!(tester.visitor.parsing_error.is_none());
+    let Some(ControlMessage::Subscribe(message)) = tester.visitor.last_control() else {
         return Err(Error::ErrInvalidMessageType(0));
     };
-    if let FilterType::AbsoluteRange(start, end) = message.filter_type {
-        assert_eq!(start.group_id, 4);
-        assert_eq!(start.object_id, 1);
-        assert_eq!(end.group_id, 7);
-        assert_eq!(end.object_id, 2);
-    } else {
-        assert!(false);
-    }
+    assert_eq!(
+        message.filter_type,
+        FilterType::AbsoluteRange(FullSequence::new(4, 1), FullSequence::new(7, 2))
+    );
 
     Ok(())
 }
@@ -1607,23 +1983,14 @@ fn test_absolute_range_whole_end_group() -> Result<()> {
         tester.visitor.handle_event(event);
     }
     assert_eq!(tester.visitor.messages_received, 1);
-    assert!(!tester.visitor.parsing_error.is_some());
-    let message = if let Some(MessageStructuredData::Control(ControlMessage::Subscribe(message))) =
-        tester.visitor.last_message
-    {
-        message
-    } else {
-        assert!(false);
+    assert!(tester.visitor.parsing_error.is_none());
+    let Some(ControlMessage::Subscribe(message)) = tester.visitor.last_control() else {
         return Err(Error::ErrInvalidMessageType(0));
     };
-    if let FilterType::AbsoluteRange(start, end) = message.filter_type {
-        assert_eq!(start.group_id, 4);
-        assert_eq!(start.object_id, 1);
-        assert_eq!(end.group_id, 7);
-        assert_eq!(end.object_id, u64::MAX);
-    } else {
-        assert!(false);
-    }
+    assert_eq!(
+        message.filter_type,
+        FilterType::AbsoluteRange(FullSequence::new(4, 1), FullSequence::new(7, u64::MAX))
+    );
 
     Ok(())
 }
@@ -1686,7 +2053,7 @@ fn test_absolute_range_exactly_one_object() -> Result<()> {
 fn test_subscribe_update_exactly_one_object() -> Result<()> {
     let mut tester = TestMessageSpecific::new();
     let mut parser = MessageParser::new(K_RAW_QUIC);
-    let subscribe_update = vec![
+    let subscribe_update = [
         0x02, 0x02, 0x03, 0x01, 0x04, 0x07, // start and end sequences
         0x00, // No parameters
     ];
@@ -1860,13 +2227,10 @@ fn test_all_messages_together() -> Result<()> {
         tester.visitor.handle_event(event);
     }
     assert_eq!(tester.visitor.messages_received, fully_received);
-    if let Some(prev_message) = prev_message.as_ref() {
-        let last_message = tester.visitor.last_message.as_ref().unwrap();
-        assert!(prev_message.equal_field_values(last_message));
-    } else {
-        assert!(false);
-    }
-    assert!(!tester.visitor.parsing_error.is_some());
+    let prev_message = prev_message.as_ref().unwrap();
+    let last_message = tester.visitor.last_message.as_ref().unwrap();
+    assert!(prev_message.equal_field_values(last_message));
+    assert!(tester.visitor.parsing_error.is_none());
 
     Ok(())
 }
@@ -1911,7 +2275,7 @@ fn test_truncated_datagram() -> Result<()> {
 
 #[test]
 fn test_very_truncated_datagram() -> Result<()> {
-    let message = vec![0x40];
+    let message = [0x40];
     let result = MessageParser::process_datagram(&mut &message[..]);
     assert!(result.is_err());
     assert_eq!(Err(Error::ErrUnexpectedEnd), result);
@@ -1958,3 +2322,194 @@ fn test_subscribe_done_invalid_content_exists() -> Result<()> {
 
     Ok(())
 }
+
+struct CountingObserver {
+    counts: Arc<Mutex<(usize, usize, usize)>>,
+}
+
+impl MessageObserver for CountingObserver {
+    fn on_control(&mut self, _control_message: &ControlMessage) {
+        self.counts.lock().unwrap().0 += 1;
+    }
+
+    fn on_object(&mut self, _object_header: &ObjectHeader, payload_length: usize) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.1 += 1;
+        counts.2 += payload_length;
+    }
+}
+
+#[test]
+fn test_observer_sees_control_and_object_messages() -> Result<()> {
+    let counts = Arc::new(Mutex::new((0, 0, 0)));
+
+    let mut control_parser = MessageParser::new(K_RAW_QUIC);
+    control_parser.set_observer(Box::new(CountingObserver {
+        counts: counts.clone(),
+    }));
+    control_parser.process_data(&mut TestSubscribeOkMessage::new().packet_sample(), false);
+    control_parser.process_data(&mut TestSubscribeDoneMessage::new().packet_sample(), false);
+    while control_parser.poll_event().is_some() {}
+
+    let mut data_parser = MessageParser::new(K_RAW_QUIC);
+    data_parser.set_observer(Box::new(CountingObserver {
+        counts: counts.clone(),
+    }));
+    data_parser.process_data(
+        &mut TestStreamHeaderGroupMessage::new().packet_sample(),
+        false,
+    );
+    data_parser.process_data(
+        &mut TestStreamMiddlerGroupMessage::new().packet_sample(),
+        false,
+    );
+    let mut payload_length = 0;
+    while let Some(event) = data_parser.poll_event() {
+        if let MessageParserEvent::ObjectMessage(_, _, payload, _) = event {
+            payload_length += payload.len();
+        }
+    }
+
+    assert_eq!(*counts.lock().unwrap(), (2, 2, payload_length));
+    assert!(payload_length > 0);
+    Ok(())
+}
+
+#[test]
+fn test_take_events_drains_queue_in_order() -> Result<()> {
+    let counts = Arc::new(Mutex::new((0, 0, 0)));
+    let mut parser = MessageParser::new(K_RAW_QUIC);
+    parser.set_observer(Box::new(CountingObserver {
+        counts: counts.clone(),
+    }));
+    let subscribe_ok = TestSubscribeOkMessage::new();
+    let subscribe_done = TestSubscribeDoneMessage::new();
+    parser.process_data(&mut subscribe_ok.packet_sample(), false);
+    parser.process_data(&mut subscribe_done.packet_sample(), false);
+    parser.process_data(&mut subscribe_ok.packet_sample(), false);
+
+    let events = parser.take_events();
+    assert_eq!(events.len(), 3);
+    assert!(matches!(
+        events[0],
+        MessageParserEvent::ControlMessage(ControlMessage::SubscribeOk(_))
+    ));
+    assert!(matches!(
+        events[1],
+        MessageParserEvent::ControlMessage(ControlMessage::SubscribeDone(_))
+    ));
+    assert!(matches!(
+        events[2],
+        MessageParserEvent::ControlMessage(ControlMessage::SubscribeOk(_))
+    ));
+    assert_eq!(counts.lock().unwrap().0, 3);
+    assert!(parser.take_events().is_empty());
+    assert!(parser.poll_event().is_none());
+    Ok(())
+}
+
+fn subscribe_bytes() -> Result<Bytes> {
+    let mut bytes = bytes::BytesMut::new();
+    MessageFramer::serialize_control_message(
+        ControlMessage::Subscribe(Subscribe {
+            subscribe_id: 1,
+            track_alias: 2,
+            track_namespace: "foo".to_string(),
+            track_name: "bar".to_string(),
+            filter_type: FilterType::LatestObject,
+            authorization_info: None,
+        }),
+        &mut bytes,
+    )?;
+    Ok(bytes.freeze())
+}
+
+#[test]
+fn test_control_message_bytes_in_object_payload_are_payload() -> Result<()> {
+    let payload = subscribe_bytes()?;
+    let object = ObjectHeader {
+        subscribe_id: 3,
+        track_alias: 4,
+        group_id: 5,
+        object_id: 6,
+        object_send_order: 7,
+        object_status: ObjectStatus::Normal,
+        object_forwarding_preference: ObjectForwardingPreference::Group,
+        object_payload_length: Some(payload.len() as u64),
+    };
+    let mut buffer = vec![];
+    MessageFramer::serialize_object(object, true, payload.clone(), &mut buffer)?;
+    let (header, body) = buffer.split_at(buffer.len() - payload.len());
+
+    let mut parser = MessageParser::new_data_stream(K_RAW_QUIC);
+    parser.process_data(&mut &header[..], false);
+    // The payload starts with a SUBSCRIBE, but it belongs to the object.
+    parser.process_data(&mut &body[..], false);
+    let mut received = vec![];
+    let mut complete = false;
+    for event in parser.take_events() {
+        match event {
+            MessageParserEvent::ObjectMessage(header, _, fragment, fin) => {
+                assert_eq!(header.object_id, 6);
+                assert!(!complete);
+                received.extend_from_slice(&fragment);
+                complete = fin;
+            }
+            other => panic!("unexpected parser event: {other:?}"),
+        }
+    }
+    assert!(complete);
+    assert_eq!(received, payload);
+    Ok(())
+}
+
+#[test]
+fn test_server_setup_version_selects_namespace_layout() -> Result<()> {
+    let server_setup = ControlMessage::ServerSetup(ServerSetup {
+        supported_version: Version::from(0xff000006),
+        role: Some(Role::PubSub),
+        ..Default::default()
+    });
+    let fetch = ControlMessage::Fetch(Fetch {
+        request_id: 1,
+        target: FetchTarget::Standalone(StandaloneFetch {
+            full_track_name: FullTrackName::from_tuple(
+                vec!["live".to_string(), "sports".to_string()],
+                "camera".to_string(),
+            ),
+            start: FullSequence::new(0, 0),
+            end: FullSequence::new(1, 0),
+        }),
+        authorization_info: None,
+    });
+    let mut data = vec![];
+    server_setup.serialize(&mut data)?;
+    fetch.serialize_with_layout(NamespaceLayout::Tuple, &mut data)?;
+
+    let mut parser = MessageParser::new_control(K_RAW_QUIC);
+    parser.process_data(&mut data.as_slice(), false);
+    assert_eq!(
+        parser.poll_event(),
+        Some(MessageParserEvent::ControlMessage(server_setup))
+    );
+    assert_eq!(
+        parser.poll_event(),
+        Some(MessageParserEvent::ControlMessage(fetch))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_control_message_on_data_stream_is_rejected() -> Result<()> {
+    let mut parser = MessageParser::new_data_stream(K_RAW_QUIC);
+    parser.process_data(&mut subscribe_bytes()?, false);
+    assert_eq!(
+        parser.poll_event(),
+        Some(MessageParserEvent::ParsingError(
+            ErrorCode::ProtocolViolation,
+            CONTROL_MESSAGE_ON_DATA_STREAM.to_string()
+        ))
+    );
+    assert!(parser.poll_event().is_none());
+    Ok(())
+}
@@ -4,6 +4,7 @@ use crate::message::announce_error::AnnounceError;
 use crate::message::announce_ok::AnnounceOk;
 use crate::message::client_setup::ClientSetup;
 use crate::message::go_away::GoAway;
+use crate::message::message_framer::MessageFramer;
 use crate::message::object::{ObjectHeader, ObjectStatus};
 use crate::message::server_setup::ServerSetup;
 use crate::message::subscribe::Subscribe;
@@ -17,8 +18,8 @@ use crate::message::unannounce::UnAnnounce;
 use crate::message::unsubscribe::UnSubscribe;
 use crate::message::{ControlMessage, MessageType, Version, MAX_MESSSAGE_HEADER_SIZE};
 use crate::message::{FilterType, FullSequence, Role};
-use crate::{Deserializer, Error, Result, Serializer, VarInt};
-use bytes::{Buf, BufMut};
+use crate::serde::parameters::ParameterKey;
+use crate::{Deserializer, Parameters, Result};
 use std::ops::{Deref, DerefMut};
 
 pub(crate) enum MessageStructuredData {
@@ -81,36 +82,6 @@ impl TestMessage {
         self.wire_image_size = wire_image_size;
     }
 
-    fn write_var_int62with_forced_length<W: BufMut>(
-        v: u64,
-        w: &mut W,
-        write_length: usize,
-    ) -> Result<usize> {
-        let vi: VarInt = v.try_into()?;
-        let min_length = vi.size();
-
-        if write_length == min_length {
-            vi.serialize(w)
-        } else if write_length == 2 {
-            w.put_u8(0b01000000);
-            w.put_u8(v as u8);
-            Ok(2)
-        } else if write_length == 4 {
-            w.put_u8(0b10000000);
-            w.put_u8(0);
-            w.put_u16(v as u16);
-            Ok(4)
-        } else if write_length == 8 {
-            w.put_u8(0b11000000);
-            w.put_u8(0);
-            w.put_u16(0);
-            w.put_u32(v as u32);
-            Ok(8)
-        } else {
-            Err(Error::ErrBufferTooShort)
-        }
-    }
-
     // Expands all the varints in the message, alternating between making them 2,
     // 4, and 8 bytes long. Updates length fields accordingly.
     // Each character in |varints| corresponds to a byte in the original message.
@@ -118,36 +89,67 @@ impl TestMessage {
     // to the next byte.
     fn expand_varints_impl(&mut self, varints: &[u8]) -> Result<()> {
         let mut next_varint_len = 2;
-        let mut reader = &self.wire_image[..self.wire_image_size];
-        let mut writer = vec![];
-        let mut i = 0;
-        while reader.has_remaining() {
-            if i >= varints.len()
-                || varints[{
-                    i += 1;
-                    i - 1
-                }] == b'-'
-            {
-                writer.put_u8(reader.get_u8());
+        let mut forced = vec![];
+        let mut offset = 0;
+        for &c in varints {
+            if offset >= self.wire_image_size {
+                break;
+            }
+            if c == b'-' {
+                offset += 1;
                 continue;
             }
-            let (value, _) = u64::deserialize(&mut reader)?;
-            let _ = TestMessage::write_var_int62with_forced_length(
-                value,
-                &mut writer,
-                next_varint_len,
-            )?;
+            let (_, len) = u64::deserialize(&mut &self.wire_image[offset..self.wire_image_size])?;
+            forced.push((offset, next_varint_len));
+            offset += len;
             next_varint_len *= 2;
             if next_varint_len == 16 {
                 next_varint_len = 2;
             }
         }
+        let writer = MessageFramer::force_varint_lengths(self.wire_image(), &forced)?;
         self.wire_image[0..writer.len()].copy_from_slice(&writer[..]);
         self.wire_image_size = writer.len();
         Ok(())
     }
 }
 
+// Asserts that |framer_output| is byte-for-byte the wire image of |msg|. On
+// mismatch, both images are dumped in hex with the first differing byte marked.
+pub(crate) fn assert_frames_to_wire_image(msg: &dyn TestMessageBase, framer_output: &[u8]) {
+    let expected = msg.packet_sample();
+    if expected == framer_output {
+        return;
+    }
+    let mismatch = expected
+        .iter()
+        .zip(framer_output)
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.len().min(framer_output.len()));
+    let hex = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if i == mismatch {
+                    format!("[{:02x}]", b)
+                } else {
+                    format!("{:02x}", b)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    panic!(
+        "framed message differs from wire image at byte {}\n  expected ({} bytes): {}\n  actual   ({} bytes): {}",
+        mismatch,
+        expected.len(),
+        hex(expected),
+        framer_output.len(),
+        hex(framer_output)
+    );
+}
+
 pub(crate) fn create_test_message(
     message_type: MessageType,
     uses_web_transport: bool,
@@ -691,6 +693,9 @@ impl TestServerSetupMessage {
         let server_setup = ServerSetup {
             supported_version: Version::Unsupported(0x01),
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         };
         let raw_packet = vec![
             0x40, 0x41, // type
@@ -1178,7 +1183,7 @@ pub(crate) struct TestSubscribeUpdateMessage {
 impl TestSubscribeUpdateMessage {
     pub(crate) fn new() -> Self {
         let mut base = TestMessage::new(MessageType::SubscribeUpdate);
-        let subscribe_update = SubscribeUpdate {
+        let mut subscribe_update = SubscribeUpdate {
             subscribe_id: 2,
             start_group_object: FullSequence {
                 group_id: 3,
@@ -1188,12 +1193,19 @@ impl TestSubscribeUpdateMessage {
                 group_id: 4,
                 object_id: 5,
             }),
-            authorization_info: Some("bar".to_string()),
+            parameters: Parameters::new(),
         };
+        subscribe_update
+            .parameters
+            .insert(ParameterKey::AuthorizationInfo, "bar".to_string())
+            .unwrap();
+        // a parameter this implementation doesn't know about is carried as raw bytes
+        subscribe_update.parameters.0.insert(0x05, vec![0x07]);
         let raw_packet = vec![
             0x02, 0x02, 0x03, 0x01, 0x05, 0x06, // start and end sequences
-            0x01, // 1 parameter
+            0x02, // 2 parameters
             0x02, 0x03, 0x62, 0x61, 0x72, // authorization_info = "bar"
+            0x05, 0x01, 0x07, // unknown parameter 0x05
         ];
         base.set_wire_image(&raw_packet, raw_packet.len());
 
@@ -1246,14 +1258,14 @@ impl TestMessageBase for TestSubscribeUpdateMessage {
         if cast.end_group_object != self.subscribe_update.end_group_object {
             return false;
         }
-        if cast.authorization_info != self.subscribe_update.authorization_info {
+        if cast.parameters != self.subscribe_update.parameters {
             return false;
         }
         true
     }
 
     fn expand_varints(&mut self) -> Result<()> {
-        self.expand_varints_impl("vvvvvvvvv---".as_bytes())
+        self.expand_varints_impl("vvvvvvvvv---vv-".as_bytes())
     }
 }
 
@@ -1266,10 +1278,14 @@ pub(crate) struct TestAnnounceMessage {
 impl TestAnnounceMessage {
     pub(crate) fn new() -> Self {
         let mut base = TestMessage::new(MessageType::Announce);
-        let announce = Announce {
+        let mut announce = Announce {
             track_namespace: "foo".to_string(),
-            authorization_info: Some("bar".to_string()),
+            parameters: Parameters::new(),
         };
+        announce
+            .parameters
+            .insert(ParameterKey::AuthorizationInfo, "bar".to_string())
+            .unwrap();
         let raw_packet = vec![
             0x06, 0x03, 0x66, 0x6f, 0x6f, // track_namespace = "foo"
             0x01, // 1 parameter
@@ -1317,7 +1333,7 @@ impl TestMessageBase for TestAnnounceMessage {
         if cast.track_namespace != self.announce.track_namespace {
             return false;
         }
-        if cast.authorization_info != self.announce.authorization_info {
+        if cast.parameters != self.announce.parameters {
             return false;
         }
         true
@@ -1688,7 +1704,7 @@ impl TestTrackStatusMessage {
         let track_status = TrackStatus {
             track_namespace: "foo".to_string(),
             track_name: "abcd".to_string(),
-            status_code: TrackStatusCode::InProgress as u64,
+            status_code: TrackStatusCode::InProgress,
             last_group_object: FullSequence {
                 group_id: 12,
                 object_id: 20,
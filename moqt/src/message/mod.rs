@@ -9,7 +9,7 @@ use crate::message::fetch_ok::FetchOk;
 use crate::message::go_away::GoAway;
 use crate::message::max_request_id::MaxRequestId;
 use crate::message::message_parser::ErrorCode;
-use crate::message::object::ObjectForwardingPreference;
+use crate::message::object::{ObjectForwardingPreference, ObjectStatus};
 use crate::message::requests_blocked::RequestsBlocked;
 use crate::message::server_setup::ServerSetup;
 use crate::message::subscribe::Subscribe;
@@ -23,6 +23,9 @@ use crate::message::unannounce::UnAnnounce;
 use crate::message::unsubscribe::UnSubscribe;
 use crate::{Deserializer, Error, Result, Serializer};
 use bytes::{Buf, BufMut};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 pub mod announce;
 pub mod announce_cancel;
@@ -54,7 +57,7 @@ mod message_framer_test;
 #[cfg(test)]
 mod message_parser_test;
 #[cfg(test)]
-mod message_test;
+pub(crate) mod message_test;
 
 /// The maximum length of a message, excluding and OBJECT payload.
 /// This prevents DoS attack via forcing the parser to buffer a large
@@ -166,10 +169,55 @@ impl Serializer for MessageType {
     }
 }
 
-#[derive(Default, Debug, Clone, Eq, PartialEq, PartialOrd, Hash)]
+/// The maximum number of elements in a track namespace tuple.
+pub const MAX_NAMESPACE_ELEMENTS: usize = 32;
+
+/// How a track namespace is laid out on the wire, picked by
+/// [`Version::namespace_layout`]. Only FETCH carries a [`FullTrackName`] whose
+/// namespace follows it; the other messages keep a single string.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NamespaceLayout {
+    /// The namespace as a single string.
+    String,
+    /// An element count followed by each element.
+    Tuple,
+}
+
+/// Two names are equal when their namespace elements and track names are: a
+/// single-string namespace is the same as a one-element tuple.
+#[derive(Default, Debug, Clone)]
 pub struct FullTrackName {
-    pub track_namespace: String,
+    track_namespace: String,
     pub track_name: String,
+    // The namespace elements when it was built from a tuple; track_namespace then
+    // holds them joined with '/' for display.
+    track_namespace_tuple: Option<Vec<String>>,
+}
+
+impl PartialEq for FullTrackName {
+    fn eq(&self, other: &Self) -> bool {
+        self.namespace_elements() == other.namespace_elements()
+            && self.track_name == other.track_name
+    }
+}
+
+impl Eq for FullTrackName {}
+
+impl PartialOrd for FullTrackName {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(
+            self.namespace_elements()
+                .cmp(&other.namespace_elements())
+                .then_with(|| self.track_name.cmp(&other.track_name)),
+        )
+    }
+}
+
+impl Hash for FullTrackName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.namespace_elements().hash(state);
+        self.track_name.hash(state);
+    }
 }
 
 impl FullTrackName {
@@ -177,33 +225,246 @@ impl FullTrackName {
         Self {
             track_namespace,
             track_name,
+            track_namespace_tuple: None,
+        }
+    }
+
+    pub fn from_tuple(track_namespace: Vec<String>, track_name: String) -> Self {
+        Self {
+            track_namespace: track_namespace.join("/"),
+            track_name,
+            track_namespace_tuple: Some(track_namespace),
+        }
+    }
+
+    /// The namespace as a single string; a tuple's elements are joined with '/'.
+    pub fn track_namespace(&self) -> &str {
+        &self.track_namespace
+    }
+
+    /// Returns the namespace elements; a single-string namespace is one element.
+    pub fn namespace_elements(&self) -> Vec<&str> {
+        if let Some(track_namespace_tuple) = self.track_namespace_tuple.as_ref() {
+            track_namespace_tuple.iter().map(String::as_str).collect()
+        } else {
+            vec![self.track_namespace.as_str()]
+        }
+    }
+
+    /// Rejects an empty track namespace, namespace element or track name, which
+    /// |message| may not carry.
+    pub fn validate(&self, message: &str) -> Result<()> {
+        for element in self.namespace_elements() {
+            validate_track_namespace(element, message)?;
+        }
+        validate_track_name(&self.track_name, message)
+    }
+
+    /// Serializes the name with the namespace in |layout|. A namespace of
+    /// several elements has no string layout.
+    pub fn serialize_with_layout<W: BufMut>(
+        &self,
+        layout: NamespaceLayout,
+        w: &mut W,
+    ) -> Result<usize> {
+        let mut l = 0;
+        if layout == NamespaceLayout::Tuple {
+            let elements = self.namespace_elements();
+            l += elements.len().serialize(w)?;
+            for element in elements {
+                l += element.serialize(w)?;
+            }
+        } else {
+            match self.track_namespace_tuple.as_deref() {
+                Some([element]) => l += element.serialize(w)?,
+                Some(elements) => {
+                    return Err(Error::ErrFrameError(format!(
+                        "cannot encode a {}-element track namespace as a string",
+                        elements.len()
+                    )))
+                }
+                None => l += self.track_namespace.serialize(w)?,
+            }
+        }
+        l += self.track_name.serialize(w)?;
+        Ok(l)
+    }
+
+    pub fn deserialize_with_layout<R: Buf>(
+        layout: NamespaceLayout,
+        r: &mut R,
+    ) -> Result<(Self, usize)> {
+        if layout == NamespaceLayout::String {
+            return Self::deserialize(r);
+        }
+        let (num_elements, mut l) = usize::deserialize(r)?;
+        if num_elements == 0 || num_elements > MAX_NAMESPACE_ELEMENTS {
+            return Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                format!("invalid track namespace tuple length {}", num_elements),
+            ));
+        }
+        let mut track_namespace = Vec::with_capacity(num_elements);
+        for _ in 0..num_elements {
+            let (element, el) = String::deserialize(r)?;
+            track_namespace.push(element);
+            l += el;
+        }
+        let (track_name, tnl) = String::deserialize(r)?;
+        Ok((Self::from_tuple(track_namespace, track_name), l + tnl))
+    }
+}
+
+/// Renders as `namespace/name`, a tuple namespace joined with `/`. A `\` in
+/// either part, and a `/` in the track name, are escaped with `\`, so the
+/// last unescaped `/` separates the two. A tuple of several elements renders
+/// like the single-string namespace they join into.
+impl fmt::Display for FullTrackName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            escape_track_name_part(&self.track_namespace, false),
+            escape_track_name_part(&self.track_name, true)
+        )
+    }
+}
+
+/// Parses `namespace/name`, splitting on the last `/` not escaped with `\`.
+/// The namespace may contain unescaped `/`. Neither part may be empty.
+impl FromStr for FullTrackName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut escaped = false;
+        let mut separator = None;
+        for (index, c) in s.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '/' => separator = Some(index),
+                _ => {}
+            }
+        }
+        let Some(separator) = separator else {
+            return Err(Error::ErrOther(format!(
+                "full track name {:?} is not namespace/name",
+                s
+            )));
+        };
+        let (Some(track_namespace), Some(track_name)) = (
+            unescape_track_name_part(&s[..separator]),
+            unescape_track_name_part(&s[separator + 1..]),
+        ) else {
+            return Err(Error::ErrOther(format!(
+                "full track name {:?} has an invalid escape",
+                s
+            )));
+        };
+        if track_namespace.is_empty() || track_name.is_empty() {
+            return Err(Error::ErrOther(format!(
+                "full track name {:?} has an empty namespace or name",
+                s
+            )));
         }
+        Ok(Self::new(track_namespace, track_name))
+    }
+}
+
+fn escape_track_name_part(part: &str, escape_slash: bool) -> String {
+    let mut escaped = String::with_capacity(part.len());
+    for c in part.chars() {
+        if c == '\\' || (escape_slash && c == '/') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Undoes escape_track_name_part. Returns None for a `\\` that doesn't escape
+// `\\` or `/`.
+fn unescape_track_name_part(part: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(part.len());
+    let mut chars = part.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(c @ ('\\' | '/')) => unescaped.push(c),
+                _ => return None,
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Some(unescaped)
+}
+
+pub(crate) fn validate_track_namespace(track_namespace: &str, message: &str) -> Result<()> {
+    if track_namespace.is_empty() {
+        return Err(Error::ErrParseError(
+            ErrorCode::ProtocolViolation,
+            format!("empty track_namespace in {}", message),
+        ));
     }
+    Ok(())
+}
+
+/// Reads the one-byte boolean SETUP parameter |name|, |size| bytes long, into
+/// |seen|. Fails if the parameter already appeared. Returns the bytes read.
+pub(crate) fn parse_bool_param<R: Buf>(
+    name: &str,
+    seen: &mut Option<bool>,
+    size: usize,
+    r: &mut R,
+) -> Result<usize> {
+    if seen.is_some() {
+        return Err(Error::ErrParseError(
+            ErrorCode::ProtocolViolation,
+            format!("{} parameter appears twice in SETUP", name),
+        ));
+    }
+    if size != 1 {
+        return Err(Error::ErrParseError(
+            ErrorCode::ParameterLengthMismatch,
+            format!("{} parameter must be one byte", name),
+        ));
+    }
+    let (value, vl) = bool::deserialize(r).map_err(|_| {
+        Error::ErrParseError(
+            ErrorCode::ProtocolViolation,
+            format!("Invalid {} parameter", name),
+        )
+    })?;
+    *seen = Some(value);
+    Ok(vl)
+}
+
+pub(crate) fn validate_track_name(track_name: &str, message: &str) -> Result<()> {
+    if track_name.is_empty() {
+        return Err(Error::ErrParseError(
+            ErrorCode::ProtocolViolation,
+            format!("empty track_name in {}", message),
+        ));
+    }
+    Ok(())
 }
 
 impl Deserializer for FullTrackName {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (track_namespace, tnsl) = String::deserialize(r)?;
         let (track_name, tnl) = String::deserialize(r)?;
-        Ok((
-            Self {
-                track_namespace,
-                track_name,
-            },
-            tnsl + tnl,
-        ))
+        Ok((Self::new(track_namespace, track_name), tnsl + tnl))
     }
 }
 
 impl Serializer for FullTrackName {
     fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
-        let mut l = self.track_namespace.serialize(w)?;
-        l += self.track_name.serialize(w)?;
-        Ok(l)
+        self.serialize_with_layout(NamespaceLayout::String, w)
     }
 }
 
-#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Hash)]
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Hash)]
 pub struct FullSequence {
     pub group_id: u64,
     pub object_id: u64,
@@ -223,6 +484,25 @@ impl FullSequence {
             object_id: self.object_id + 1,
         }
     }
+
+    /// Returns the first object of the following group.
+    pub fn next_group(&self) -> Self {
+        Self {
+            group_id: self.group_id + 1,
+            object_id: 0,
+        }
+    }
+
+    /// Returns the sequence that follows an object sent with |status|: the next
+    /// object for Normal and ObjectDoesNotExist, the next group when the group
+    /// is finished or missing, and None once the track has ended.
+    pub fn advance(&self, status: ObjectStatus) -> Option<Self> {
+        match status {
+            ObjectStatus::Normal | ObjectStatus::ObjectDoesNotExist => Some(self.next()),
+            ObjectStatus::GroupDoesNotExist | ObjectStatus::EndOfGroup => Some(self.next_group()),
+            ObjectStatus::EndOfTrack | ObjectStatus::Invalid => None,
+        }
+    }
 }
 
 impl Deserializer for FullSequence {
@@ -257,6 +537,16 @@ pub enum FilterType {
 }
 
 impl FilterType {
+    /// An AbsoluteRange over every object of groups |start_group| through
+    /// |end_group|, however many objects the last group turns out to have.
+    /// The end is framed with the whole-group encoding (end_object 0).
+    pub fn whole_groups(start_group: u64, end_group: u64) -> Self {
+        FilterType::AbsoluteRange(
+            FullSequence::new(start_group, 0),
+            FullSequence::new(end_group, u64::MAX),
+        )
+    }
+
     pub fn value(&self) -> u8 {
         match self {
             FilterType::LatestGroup => 0x1,
@@ -364,6 +654,17 @@ impl From<u64> for Version {
     }
 }
 
+impl Version {
+    /// Drafts before 06 carry a track namespace as a single string, later
+    /// ones as a tuple.
+    pub fn namespace_layout(&self) -> NamespaceLayout {
+        match *self {
+            Version::Unsupported(version) if version >= 0xff000006 => NamespaceLayout::Tuple,
+            _ => NamespaceLayout::String,
+        }
+    }
+}
+
 impl Deserializer for Version {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (v, vl) = u64::deserialize(r)?;
@@ -446,210 +747,366 @@ pub enum ControlMessage {
     ServerSetup(ServerSetup),
 }
 
-impl Deserializer for ControlMessage {
-    fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
-        let (message_type, mtl) = MessageType::deserialize(r)?;
-        match message_type {
-            MessageType::ObjectStream
-            | MessageType::StreamHeaderTrack
-            | MessageType::StreamHeaderGroup
-            | MessageType::ObjectDatagram => Err(Error::ErrInvalidMessageType(message_type as u64)),
-            MessageType::SubscribeUpdate => {
-                let (m, ml) = SubscribeUpdate::deserialize(r)?;
-                Ok((ControlMessage::SubscribeUpdate(m), mtl + ml))
-            }
-            MessageType::Subscribe => {
-                let (m, ml) = Subscribe::deserialize(r)?;
-                Ok((ControlMessage::Subscribe(m), mtl + ml))
-            }
-            MessageType::SubscribeOk => {
-                let (m, ml) = SubscribeOk::deserialize(r)?;
-                Ok((ControlMessage::SubscribeOk(m), mtl + ml))
-            }
-            MessageType::SubscribeError => {
-                let (m, ml) = SubscribeError::deserialize(r)?;
-                Ok((ControlMessage::SubscribeError(m), mtl + ml))
-            }
-            MessageType::Announce => {
-                let (m, ml) = Announce::deserialize(r)?;
-                Ok((ControlMessage::Announce(m), mtl + ml))
-            }
-            MessageType::AnnounceOk => {
-                let (m, ml) = AnnounceOk::deserialize(r)?;
-                Ok((ControlMessage::AnnounceOk(m), mtl + ml))
-            }
-            MessageType::AnnounceError => {
-                let (m, ml) = AnnounceError::deserialize(r)?;
-                Ok((ControlMessage::AnnounceError(m), mtl + ml))
-            }
-            MessageType::UnAnnounce => {
-                let (m, ml) = UnAnnounce::deserialize(r)?;
-                Ok((ControlMessage::UnAnnounce(m), mtl + ml))
-            }
-            MessageType::UnSubscribe => {
-                let (m, ml) = UnSubscribe::deserialize(r)?;
-                Ok((ControlMessage::UnSubscribe(m), mtl + ml))
-            }
-            MessageType::SubscribeDone => {
-                let (m, ml) = SubscribeDone::deserialize(r)?;
-                Ok((ControlMessage::SubscribeDone(m), mtl + ml))
-            }
-            MessageType::AnnounceCancel => {
-                let (m, ml) = AnnounceCancel::deserialize(r)?;
-                Ok((ControlMessage::AnnounceCancel(m), mtl + ml))
-            }
-            MessageType::TrackStatusRequest => {
-                let (m, ml) = TrackStatusRequest::deserialize(r)?;
-                Ok((ControlMessage::TrackStatusRequest(m), mtl + ml))
-            }
-            MessageType::TrackStatus => {
-                let (m, ml) = TrackStatus::deserialize(r)?;
-                Ok((ControlMessage::TrackStatus(m), mtl + ml))
-            }
-            MessageType::GoAway => {
-                let (m, ml) = GoAway::deserialize(r)?;
-                Ok((ControlMessage::GoAway(m), mtl + ml))
-            }
-            MessageType::MaxRequestId => {
-                let (m, ml) = MaxRequestId::deserialize(r)?;
-                Ok((ControlMessage::MaxRequestId(m), mtl + ml))
-            }
-            MessageType::Fetch => {
-                let (m, ml) = Fetch::deserialize(r)?;
-                Ok((ControlMessage::Fetch(m), mtl + ml))
-            }
-            MessageType::FetchCancel => {
-                let (m, ml) = FetchCancel::deserialize(r)?;
-                Ok((ControlMessage::FetchCancel(m), mtl + ml))
-            }
-            MessageType::FetchOk => {
-                let (m, ml) = FetchOk::deserialize(r)?;
-                Ok((ControlMessage::FetchOk(m), mtl + ml))
+// Generates both directions of the ControlMessage codec from one table, so a
+// message type can't be handled in one and forgotten in the other.
+macro_rules! control_message_dispatch {
+    ($(($message_type:path, $variant:ident, $message:ty)),+ $(,)?) => {
+        impl ControlMessage {
+            /// Reads a control message whose track namespace is in |layout|.
+            pub fn deserialize_with_layout<R: Buf>(
+                layout: NamespaceLayout,
+                r: &mut R,
+            ) -> Result<(Self, usize)> {
+                let (message_type, mtl) = MessageType::deserialize(r)?;
+                if message_type == MessageType::Fetch {
+                    let (m, ml) = Fetch::deserialize_with_layout(layout, r)?;
+                    return Ok((ControlMessage::Fetch(m), mtl + ml));
+                }
+                match message_type {
+                    $($message_type => {
+                        let (m, ml) = <$message>::deserialize(r)?;
+                        Ok((ControlMessage::$variant(m), mtl + ml))
+                    })+
+                    MessageType::ObjectStream
+                    | MessageType::StreamHeaderTrack
+                    | MessageType::StreamHeaderGroup
+                    | MessageType::ObjectDatagram => {
+                        Err(Error::ErrInvalidMessageType(message_type as u64))
+                    }
+                }
             }
-            MessageType::RequestsBlocked => {
-                let (m, ml) = RequestsBlocked::deserialize(r)?;
-                Ok((ControlMessage::RequestsBlocked(m), mtl + ml))
+
+            /// Writes the control message with its track namespace in |layout|.
+            pub fn serialize_with_layout<W: BufMut>(
+                &self,
+                layout: NamespaceLayout,
+                w: &mut W,
+            ) -> Result<usize> {
+                if let ControlMessage::Fetch(m) = self {
+                    let mut l = MessageType::Fetch.serialize(w)?;
+                    l += m.serialize_with_layout(layout, w)?;
+                    return Ok(l);
+                }
+                match self {
+                    $(ControlMessage::$variant(m) => {
+                        let mut l = $message_type.serialize(w)?;
+                        l += m.serialize(w)?;
+                        Ok(l)
+                    })+
+                }
             }
-            MessageType::ClientSetup => {
-                let (m, ml) = ClientSetup::deserialize(r)?;
-                Ok((ControlMessage::ClientSetup(m), mtl + ml))
+        }
+
+        impl Deserializer for ControlMessage {
+            fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
+                Self::deserialize_with_layout(NamespaceLayout::String, r)
             }
-            MessageType::ServerSetup => {
-                let (m, ml) = ServerSetup::deserialize(r)?;
-                Ok((ControlMessage::ServerSetup(m), mtl + ml))
+        }
+
+        impl Serializer for ControlMessage {
+            fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
+                self.serialize_with_layout(NamespaceLayout::String, w)
             }
         }
-    }
+    };
 }
 
-impl Serializer for ControlMessage {
-    fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
-        match self {
-            ControlMessage::SubscribeUpdate(subscribe_update) => {
-                let mut l = MessageType::SubscribeUpdate.serialize(w)?;
-                l += subscribe_update.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::Subscribe(subscribe) => {
-                let mut l = MessageType::Subscribe.serialize(w)?;
-                l += subscribe.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::SubscribeOk(subscribe_ok) => {
-                let mut l = MessageType::SubscribeOk.serialize(w)?;
-                l += subscribe_ok.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::SubscribeError(subscribe_error) => {
-                let mut l = MessageType::SubscribeError.serialize(w)?;
-                l += subscribe_error.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::Announce(announce) => {
-                let mut l = MessageType::Announce.serialize(w)?;
-                l += announce.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::AnnounceOk(announce_ok) => {
-                let mut l = MessageType::AnnounceOk.serialize(w)?;
-                l += announce_ok.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::AnnounceError(announce_error) => {
-                let mut l = MessageType::AnnounceError.serialize(w)?;
-                l += announce_error.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::UnAnnounce(unannounce) => {
-                let mut l = MessageType::UnAnnounce.serialize(w)?;
-                l += unannounce.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::UnSubscribe(unsubscribe) => {
-                let mut l = MessageType::UnSubscribe.serialize(w)?;
-                l += unsubscribe.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::SubscribeDone(subscribe_done) => {
-                let mut l = MessageType::SubscribeDone.serialize(w)?;
-                l += subscribe_done.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::AnnounceCancel(announce_cancel) => {
-                let mut l = MessageType::AnnounceCancel.serialize(w)?;
-                l += announce_cancel.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::TrackStatusRequest(track_status_request) => {
-                let mut l = MessageType::TrackStatusRequest.serialize(w)?;
-                l += track_status_request.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::TrackStatus(track_status) => {
-                let mut l = MessageType::TrackStatus.serialize(w)?;
-                l += track_status.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::GoAway(go_away) => {
-                let mut l = MessageType::GoAway.serialize(w)?;
-                l += go_away.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::MaxRequestId(max_request_id) => {
-                let mut l = MessageType::MaxRequestId.serialize(w)?;
-                l += max_request_id.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::Fetch(fetch) => {
-                let mut l = MessageType::Fetch.serialize(w)?;
-                l += fetch.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::FetchCancel(fetch_cancel) => {
-                let mut l = MessageType::FetchCancel.serialize(w)?;
-                l += fetch_cancel.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::FetchOk(fetch_ok) => {
-                let mut l = MessageType::FetchOk.serialize(w)?;
-                l += fetch_ok.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::RequestsBlocked(requests_blocked) => {
-                let mut l = MessageType::RequestsBlocked.serialize(w)?;
-                l += requests_blocked.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::ClientSetup(client_setup) => {
-                let mut l = MessageType::ClientSetup.serialize(w)?;
-                l += client_setup.serialize(w)?;
-                Ok(l)
-            }
-            ControlMessage::ServerSetup(server_setup) => {
-                let mut l = MessageType::ServerSetup.serialize(w)?;
-                l += server_setup.serialize(w)?;
-                Ok(l)
-            }
+control_message_dispatch! {
+    (MessageType::SubscribeUpdate, SubscribeUpdate, SubscribeUpdate),
+    (MessageType::Subscribe, Subscribe, Subscribe),
+    (MessageType::SubscribeOk, SubscribeOk, SubscribeOk),
+    (MessageType::SubscribeError, SubscribeError, SubscribeError),
+    (MessageType::Announce, Announce, Announce),
+    (MessageType::AnnounceOk, AnnounceOk, AnnounceOk),
+    (MessageType::AnnounceError, AnnounceError, AnnounceError),
+    (MessageType::UnAnnounce, UnAnnounce, UnAnnounce),
+    (MessageType::UnSubscribe, UnSubscribe, UnSubscribe),
+    (MessageType::SubscribeDone, SubscribeDone, SubscribeDone),
+    (MessageType::AnnounceCancel, AnnounceCancel, AnnounceCancel),
+    (MessageType::TrackStatusRequest, TrackStatusRequest, TrackStatusRequest),
+    (MessageType::TrackStatus, TrackStatus, TrackStatus),
+    (MessageType::GoAway, GoAway, GoAway),
+    (MessageType::MaxRequestId, MaxRequestId, MaxRequestId),
+    (MessageType::Fetch, Fetch, Fetch),
+    (MessageType::FetchCancel, FetchCancel, FetchCancel),
+    (MessageType::FetchOk, FetchOk, FetchOk),
+    (MessageType::RequestsBlocked, RequestsBlocked, RequestsBlocked),
+    (MessageType::ClientSetup, ClientSetup, ClientSetup),
+    (MessageType::ServerSetup, ServerSetup, ServerSetup),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::fetch::{FetchTarget, StandaloneFetch};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_next_group() {
+        assert_eq!(FullSequence::new(4, 7).next(), FullSequence::new(4, 8));
+        assert_eq!(
+            FullSequence::new(4, 7).next_group(),
+            FullSequence::new(5, 0)
+        );
+        assert_eq!(
+            FullSequence::new(4, 0).next_group(),
+            FullSequence::new(5, 0)
+        );
+    }
+
+    #[test]
+    fn test_advance() {
+        let sequence = FullSequence::new(4, 7);
+        assert_eq!(
+            sequence.advance(ObjectStatus::Normal),
+            Some(FullSequence::new(4, 8))
+        );
+        assert_eq!(
+            sequence.advance(ObjectStatus::ObjectDoesNotExist),
+            Some(FullSequence::new(4, 8))
+        );
+        assert_eq!(
+            sequence.advance(ObjectStatus::GroupDoesNotExist),
+            Some(FullSequence::new(5, 0))
+        );
+        assert_eq!(
+            sequence.advance(ObjectStatus::EndOfGroup),
+            Some(FullSequence::new(5, 0))
+        );
+        assert_eq!(sequence.advance(ObjectStatus::EndOfTrack), None);
+        assert_eq!(sequence.advance(ObjectStatus::Invalid), None);
+    }
+
+    #[test]
+    fn test_full_track_name_string_round_trip() -> Result<()> {
+        for (s, track_namespace, track_name) in [
+            ("live/camera", "live", "camera"),
+            ("live/sports/camera", "live/sports", "camera"),
+        ] {
+            let full_track_name: FullTrackName = s.parse()?;
+            assert_eq!(
+                full_track_name,
+                FullTrackName::new(track_namespace.to_string(), track_name.to_string())
+            );
+            assert_eq!(full_track_name.to_string(), s);
+        }
+        let tuple = FullTrackName::from_tuple(
+            vec!["live".to_string(), "sports".to_string()],
+            "camera".to_string(),
+        );
+        assert_eq!(tuple.to_string(), "live/sports/camera");
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_track_name_list_round_trip() -> Result<()> {
+        let full_track_names = vec![
+            FullTrackName::new("live".to_string(), "camera".to_string()),
+            FullTrackName::new("vod".to_string(), "ab".to_string()),
+        ];
+        let expected_packet = vec![
+            0x02, // two names
+            0x04, b'l', b'i', b'v', b'e', 0x06, b'c', b'a', b'm', b'e', b'r',
+            b'a', // live/camera
+            0x03, b'v', b'o', b'd', 0x02, b'a', b'b', // vod/ab
+        ];
+        let mut packet = vec![];
+        let l = full_track_names.serialize(&mut packet)?;
+        assert_eq!(packet, expected_packet);
+        assert_eq!(l, expected_packet.len());
+
+        let (actual, l) = Vec::<FullTrackName>::deserialize(&mut packet.as_slice())?;
+        assert_eq!(actual, full_track_names);
+        assert_eq!(l, expected_packet.len());
+
+        assert!(Vec::<FullTrackName>::deserialize(&mut &packet[..packet.len() - 1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_track_name_string_round_trip_escapes() -> Result<()> {
+        for (track_namespace, track_name, s) in [
+            ("live", "cam/era", r"live/cam\/era"),
+            ("live/sports", "cam/", r"live/sports/cam\/"),
+            (r"back\slash", r"\", r"back\\slash/\\"),
+            (r"ends\", "camera", r"ends\\/camera"),
+        ] {
+            let full_track_name =
+                FullTrackName::new(track_namespace.to_string(), track_name.to_string());
+            assert_eq!(full_track_name.to_string(), s);
+            assert_eq!(s.parse::<FullTrackName>()?, full_track_name);
         }
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_track_name_malformed_strings() {
+        for s in [
+            "",
+            "camera",
+            "/camera",
+            "live/",
+            "/",
+            r"live\/camera",
+            r"live/cam\era",
+            r"live/camera\",
+        ] {
+            assert!(s.parse::<FullTrackName>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn test_namespace_tuple_round_trip() -> Result<()> {
+        let full_track_name = FullTrackName::from_tuple(
+            vec!["live".to_string(), "sports".to_string()],
+            "camera".to_string(),
+        );
+        let expected_packet: Vec<u8> = vec![
+            0x02, // 2 elements
+            0x04, 0x6c, 0x69, 0x76, 0x65, // "live"
+            0x06, 0x73, 0x70, 0x6f, 0x72, 0x74, 0x73, // "sports"
+            0x06, 0x63, 0x61, 0x6d, 0x65, 0x72, 0x61, // track_name = "camera"
+        ];
+
+        let mut actual_packet = vec![];
+        let l =
+            full_track_name.serialize_with_layout(NamespaceLayout::Tuple, &mut actual_packet)?;
+        assert_eq!(expected_packet, actual_packet);
+        assert_eq!(expected_packet.len(), l);
+
+        let (actual, l) = FullTrackName::deserialize_with_layout(
+            NamespaceLayout::Tuple,
+            &mut expected_packet.as_slice(),
+        )?;
+        assert_eq!(full_track_name, actual);
+        assert_eq!(actual.track_namespace, "live/sports");
+        assert_eq!(actual.namespace_elements(), vec!["live", "sports"]);
+        assert_eq!(expected_packet.len(), l);
+        Ok(())
+    }
+
+    #[test]
+    fn test_namespace_string_layout() -> Result<()> {
+        let full_track_name = FullTrackName::new("live".to_string(), "camera".to_string());
+        let mut packet = vec![];
+        let _ = full_track_name.serialize_with_layout(NamespaceLayout::String, &mut packet)?;
+        assert_eq!(
+            packet,
+            vec![0x04, 0x6c, 0x69, 0x76, 0x65, 0x06, 0x63, 0x61, 0x6d, 0x65, 0x72, 0x61]
+        );
+        let (actual, _) = FullTrackName::deserialize_with_layout(
+            NamespaceLayout::String,
+            &mut packet.as_slice(),
+        )?;
+        assert_eq!(full_track_name, actual);
+
+        // A single-string namespace is a one-element tuple.
+        packet.clear();
+        let _ = full_track_name.serialize_with_layout(NamespaceLayout::Tuple, &mut packet)?;
+        assert_eq!(packet[..6], [0x01, 0x04, 0x6c, 0x69, 0x76, 0x65]);
+
+        let tuple = FullTrackName::from_tuple(
+            vec!["live".to_string(), "sports".to_string()],
+            "camera".to_string(),
+        );
+        assert!(matches!(
+            tuple.serialize_with_layout(NamespaceLayout::String, &mut vec![]),
+            Err(Error::ErrFrameError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_namespace_layout_follows_version() -> Result<()> {
+        assert_eq!(Version::Draft04.namespace_layout(), NamespaceLayout::String);
+        assert_eq!(
+            Version::from(0xff000006).namespace_layout(),
+            NamespaceLayout::Tuple
+        );
+
+        let fetch = ControlMessage::Fetch(Fetch {
+            request_id: 1,
+            target: FetchTarget::Standalone(StandaloneFetch {
+                full_track_name: FullTrackName::from_tuple(
+                    vec!["live".to_string(), "sports".to_string()],
+                    "camera".to_string(),
+                ),
+                start: FullSequence::new(0, 0),
+                end: FullSequence::new(1, 0),
+            }),
+            authorization_info: None,
+        });
+        let mut packet = vec![];
+        let l = fetch.serialize_with_layout(NamespaceLayout::Tuple, &mut packet)?;
+        // Type, request id, fetch type, then the element count.
+        assert_eq!(packet[3], 0x02);
+        let (actual, actual_len) = ControlMessage::deserialize_with_layout(
+            NamespaceLayout::Tuple,
+            &mut packet.as_slice(),
+        )?;
+        assert_eq!(actual, fetch);
+        assert_eq!(actual_len, l);
+
+        // The two elements have no single-string layout.
+        assert!(fetch.serialize(&mut vec![]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_namespace_tuple_length_bounds() {
+        for num_elements in [0u8, MAX_NAMESPACE_ELEMENTS as u8 + 1] {
+            let packet = [num_elements, 0x00];
+            assert!(matches!(
+                FullTrackName::deserialize_with_layout(
+                    NamespaceLayout::Tuple,
+                    &mut packet.as_slice()
+                ),
+                Err(Error::ErrParseError(ErrorCode::ProtocolViolation, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_full_track_name_equality_ignores_representation() {
+        let string = FullTrackName::new("live".to_string(), "camera".to_string());
+        let tuple = FullTrackName::from_tuple(vec!["live".to_string()], "camera".to_string());
+        assert_eq!(string, tuple);
+        let mut names = HashSet::new();
+        names.insert(string);
+        assert!(names.contains(&tuple));
+
+        // A joined namespace is not the same as the elements it joins.
+        let joined = FullTrackName::new("live/sports".to_string(), "camera".to_string());
+        let split = FullTrackName::from_tuple(
+            vec!["live".to_string(), "sports".to_string()],
+            "camera".to_string(),
+        );
+        assert_ne!(joined, split);
+        assert!(split < joined);
+    }
+
+    #[test]
+    fn test_full_track_name_validate() {
+        assert!(FullTrackName::new("live".to_string(), "camera".to_string())
+            .validate("SUBSCRIBE")
+            .is_ok());
+        assert_eq!(
+            FullTrackName::new("live".to_string(), String::new()).validate("SUBSCRIBE"),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "empty track_name in SUBSCRIBE".to_string()
+            ))
+        );
+        assert_eq!(
+            FullTrackName::from_tuple(
+                vec!["live".to_string(), String::new()],
+                "camera".to_string()
+            )
+            .validate("FETCH"),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "empty track_namespace in FETCH".to_string()
+            ))
+        );
     }
 }
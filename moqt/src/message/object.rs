@@ -1,4 +1,7 @@
+use crate::message::message_parser::ErrorCode;
 use crate::message::MessageType;
+use crate::{Deserializer, Error, Result, Serializer};
+use bytes::{Buf, BufMut, Bytes};
 
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ObjectForwardingPreference {
@@ -10,6 +13,8 @@ pub enum ObjectForwardingPreference {
 }
 
 impl ObjectForwardingPreference {
+    /// The message type that starts an object stream (or datagram) with this
+    /// preference; the inverse of MessageType::get_object_forwarding_preference.
     pub(crate) fn get_message_type(&self) -> MessageType {
         match *self {
             ObjectForwardingPreference::Object => MessageType::ObjectStream,
@@ -44,6 +49,25 @@ impl From<u64> for ObjectStatus {
     }
 }
 
+impl Deserializer for ObjectStatus {
+    fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
+        let (value, vl) = u64::deserialize(r)?;
+        match ObjectStatus::from(value) {
+            ObjectStatus::Invalid => Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "Invalid object status".to_string(),
+            )),
+            object_status => Ok((object_status, vl)),
+        }
+    }
+}
+
+impl Serializer for ObjectStatus {
+    fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
+        (*self as u64).serialize(w)
+    }
+}
+
 /// The data contained in every Object message, although the message type
 /// implies some of the values. |payload_length| has no value if the length
 /// is unknown (because it runs to the end of the stream.)
@@ -58,3 +82,109 @@ pub struct ObjectHeader {
     pub object_forwarding_preference: ObjectForwardingPreference,
     pub object_payload_length: Option<u64>,
 }
+
+impl ObjectHeader {
+    /// A header for an object that carries |status| and no payload, such as
+    /// EndOfGroup or EndOfTrack. Objects with a non-Normal status must have an
+    /// empty payload, so the payload length is Some(0), except on an
+    /// OBJECT_STREAM where the payload runs to the end of the stream. The
+    /// caller fills in subscribe_id, track_alias and object_send_order.
+    pub fn status_object(
+        object_forwarding_preference: ObjectForwardingPreference,
+        group_id: u64,
+        object_id: u64,
+        status: ObjectStatus,
+    ) -> Self {
+        Self {
+            group_id,
+            object_id,
+            object_status: status,
+            object_forwarding_preference,
+            object_payload_length: match object_forwarding_preference {
+                ObjectForwardingPreference::Object => None,
+                _ => Some(0),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Not part of the draft: the datagram type of one piece of an object split
+/// across several datagrams. Only sent when both sides advertised
+/// DATAGRAM_FRAGMENTATION in SETUP.
+pub const OBJECT_DATAGRAM_FRAGMENT_TYPE: u64 = 0x3f;
+
+/// The most datagrams a single object may be split across.
+pub const MAX_DATAGRAM_FRAGMENTS: u64 = 16;
+
+/// One piece of an object split across datagrams. Every fragment of an
+/// object carries the same |object_header|; |payload| is slice
+/// |fragment_index| of the |fragment_count| consecutive slices of the object
+/// payload.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DatagramFragment {
+    pub object_header: ObjectHeader,
+    pub fragment_index: u64,
+    pub fragment_count: u64,
+    pub payload: Bytes,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(ObjectStatus::Normal)]
+    #[case(ObjectStatus::ObjectDoesNotExist)]
+    #[case(ObjectStatus::GroupDoesNotExist)]
+    #[case(ObjectStatus::EndOfGroup)]
+    #[case(ObjectStatus::EndOfTrack)]
+    fn test_object_status_round_trip(#[case] object_status: ObjectStatus) -> Result<()> {
+        let mut packet = vec![];
+        let written = object_status.serialize(&mut packet)?;
+        assert_eq!(packet, vec![object_status as u8]);
+        let (actual, read) = ObjectStatus::deserialize(&mut packet.as_slice())?;
+        assert_eq!(actual, object_status);
+        assert_eq!(read, written);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(ObjectForwardingPreference::Object, MessageType::ObjectStream)]
+    #[case(ObjectForwardingPreference::Datagram, MessageType::ObjectDatagram)]
+    #[case(ObjectForwardingPreference::Track, MessageType::StreamHeaderTrack)]
+    #[case(ObjectForwardingPreference::Group, MessageType::StreamHeaderGroup)]
+    fn test_forwarding_preference_message_type_round_trip(
+        #[case] forwarding_preference: ObjectForwardingPreference,
+        #[case] message_type: MessageType,
+    ) -> Result<()> {
+        assert_eq!(forwarding_preference.get_message_type(), message_type);
+        assert_eq!(
+            message_type.get_object_forwarding_preference()?,
+            forwarding_preference
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_control_message_type_has_no_forwarding_preference() {
+        assert_eq!(
+            MessageType::Subscribe.get_object_forwarding_preference(),
+            Err(Error::ErrInvalidMessageType(MessageType::Subscribe as u64))
+        );
+    }
+
+    #[test]
+    fn test_object_status_out_of_range() {
+        for value in [0x05u8, 0x3f] {
+            assert_eq!(
+                ObjectStatus::deserialize(&mut [value].as_slice()),
+                Err(Error::ErrParseError(
+                    ErrorCode::ProtocolViolation,
+                    "Invalid object status".to_string()
+                ))
+            );
+        }
+    }
+}
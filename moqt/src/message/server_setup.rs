@@ -1,5 +1,5 @@
 use crate::message::message_parser::ErrorCode;
-use crate::message::{Role, Version};
+use crate::message::{parse_bool_param, Role, Version};
 use crate::serde::parameters::ParameterKey;
 use crate::{Deserializer, Error, Parameters, Result, Serializer};
 use bytes::{Buf, BufMut};
@@ -8,6 +8,12 @@ use bytes::{Buf, BufMut};
 pub struct ServerSetup {
     pub supported_version: Version,
     pub role: Option<Role>,
+    /// Whether the server can handle objects delivered in fragments.
+    pub partial_object_delivery: bool,
+    /// Whether the server can reassemble objects split across datagrams.
+    pub datagram_fragmentation: bool,
+    /// Whether the server can read control messages preceded by their length.
+    pub length_prefixed_control: bool,
 }
 
 impl Deserializer for ServerSetup {
@@ -18,6 +24,9 @@ impl Deserializer for ServerSetup {
         tl += npl;
 
         let mut role: Option<Role> = None;
+        let mut partial_object_delivery: Option<bool> = None;
+        let mut datagram_fragmentation: Option<bool> = None;
+        let mut length_prefixed_control: Option<bool> = None;
 
         // Parse parameters
         for _ in 0..num_params {
@@ -58,6 +67,31 @@ impl Deserializer for ServerSetup {
                     ErrorCode::ProtocolViolation,
                     "PATH parameter in SERVER_SETUP".to_string(),
                 ));
+            } else if key == ParameterKey::PartialObjectDelivery as u64 {
+                tl += parse_bool_param(
+                    "PARTIAL_OBJECT_DELIVERY",
+                    &mut partial_object_delivery,
+                    size,
+                    r,
+                )?;
+            } else if key == ParameterKey::DatagramFragmentation as u64 {
+                tl += parse_bool_param(
+                    "DATAGRAM_FRAGMENTATION",
+                    &mut datagram_fragmentation,
+                    size,
+                    r,
+                )?;
+            } else if key == ParameterKey::LengthPrefixedControl as u64 {
+                tl += parse_bool_param(
+                    "LENGTH_PREFIXED_CONTROL",
+                    &mut length_prefixed_control,
+                    size,
+                    r,
+                )?;
+            } else {
+                // Parameters this implementation doesn't know about are skipped.
+                r.advance(size);
+                tl += size;
             }
         }
 
@@ -72,6 +106,9 @@ impl Deserializer for ServerSetup {
             Self {
                 supported_version,
                 role,
+                partial_object_delivery: partial_object_delivery.unwrap_or(false),
+                datagram_fragmentation: datagram_fragmentation.unwrap_or(false),
+                length_prefixed_control: length_prefixed_control.unwrap_or(false),
             },
             tl,
         ))
@@ -86,6 +123,15 @@ impl Serializer for ServerSetup {
         if let Some(role) = self.role.as_ref() {
             parameters.insert(ParameterKey::Role, *role)?;
         }
+        if self.partial_object_delivery {
+            parameters.insert(ParameterKey::PartialObjectDelivery, true)?;
+        }
+        if self.datagram_fragmentation {
+            parameters.insert(ParameterKey::DatagramFragmentation, true)?;
+        }
+        if self.length_prefixed_control {
+            parameters.insert(ParameterKey::LengthPrefixedControl, true)?;
+        }
         l += parameters.serialize(w)?;
         Ok(l)
     }
@@ -109,6 +155,68 @@ mod test {
         let expected_message = ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft01,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+        });
+
+        let mut cursor: Cursor<&[u8]> = Cursor::new(expected_packet.as_ref());
+        let (actual_message, actual_len) = ControlMessage::deserialize(&mut cursor)?;
+        assert_eq!(expected_message, actual_message);
+        assert_eq!(expected_packet.len(), actual_len);
+
+        let mut actual_packet = vec![];
+        let _ = expected_message.serialize(&mut actual_packet)?;
+        assert_eq!(expected_packet, actual_packet);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_server_setup_length_prefixed_control() -> Result<()> {
+        let expected_packet: Vec<u8> = vec![
+            0x40, 0x41, // type
+            192, 0, 0, 0, 255, 0, 0, 1,    // version Draft01
+            0x02, // two params
+            0x00, 0x01, 0x03, // role = PubSub
+            0x3d, 0x01, 0x01, // length_prefixed_control = true
+        ];
+
+        let expected_message = ControlMessage::ServerSetup(ServerSetup {
+            supported_version: Version::Draft01,
+            role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: true,
+        });
+
+        let mut cursor: Cursor<&[u8]> = Cursor::new(expected_packet.as_ref());
+        let (actual_message, actual_len) = ControlMessage::deserialize(&mut cursor)?;
+        assert_eq!(expected_message, actual_message);
+        assert_eq!(expected_packet.len(), actual_len);
+
+        let mut actual_packet = vec![];
+        let _ = expected_message.serialize(&mut actual_packet)?;
+        assert_eq!(expected_packet, actual_packet);
+        Ok(())
+    }
+
+    #[test]
+    fn test_server_setup_partial_object_delivery() -> Result<()> {
+        let expected_packet: Vec<u8> = vec![
+            0x40, 0x41, // type
+            192, 0, 0, 0, 255, 0, 0, 1,    // version Draft01
+            0x02, // two params
+            0x00, 0x01, 0x03, // role = PubSub
+            0x3e, 0x01, 0x01, // partial_object_delivery = true
+        ];
+
+        let expected_message = ControlMessage::ServerSetup(ServerSetup {
+            supported_version: Version::Draft01,
+            role: Some(Role::PubSub),
+            partial_object_delivery: true,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         });
 
         let mut cursor: Cursor<&[u8]> = Cursor::new(expected_packet.as_ref());
@@ -120,6 +228,17 @@ mod test {
         let _ = expected_message.serialize(&mut actual_packet)?;
         assert_eq!(expected_packet, actual_packet);
 
+        let invalid_packet: Vec<u8> = vec![
+            0x40, 0x41, 192, 0, 0, 0, 255, 0, 0, 1, 0x02, 0x00, 0x01, 0x03, 0x3e, 0x01,
+            0x02, // partial_object_delivery = 2
+        ];
+        assert_eq!(
+            ControlMessage::deserialize(&mut invalid_packet.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "Invalid PARTIAL_OBJECT_DELIVERY parameter".to_string()
+            ))
+        );
         Ok(())
     }
 }
@@ -1,5 +1,5 @@
 use crate::message::message_parser::ErrorCode;
-use crate::message::FilterType;
+use crate::message::{validate_track_name, validate_track_namespace, FilterType, FullTrackName};
 use crate::serde::parameters::ParameterKey;
 use crate::{Deserializer, Error, Parameters, Result, Serializer};
 use bytes::{Buf, BufMut};
@@ -17,6 +17,29 @@ pub struct Subscribe {
     pub authorization_info: Option<String>,
 }
 
+impl Subscribe {
+    pub fn new(
+        subscribe_id: u64,
+        track_alias: u64,
+        full_track_name: FullTrackName,
+        filter_type: FilterType,
+    ) -> Self {
+        Self {
+            subscribe_id,
+            track_alias,
+            track_namespace: full_track_name.track_namespace,
+            track_name: full_track_name.track_name,
+            filter_type,
+            authorization_info: None,
+        }
+    }
+
+    pub fn with_authorization_info(mut self, authorization_info: String) -> Self {
+        self.authorization_info = Some(authorization_info);
+        self
+    }
+}
+
 impl Deserializer for Subscribe {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (subscribe_id, sil) = u64::deserialize(r)?;
@@ -24,6 +47,8 @@ impl Deserializer for Subscribe {
         let (track_alias, tal) = u64::deserialize(r)?;
         let (track_namespace, tnsl) = String::deserialize(r)?;
         let (track_name, tnl) = String::deserialize(r)?;
+        validate_track_namespace(&track_namespace, "SUBSCRIBE")?;
+        validate_track_name(&track_name, "SUBSCRIBE")?;
 
         let (filter_type, ftl) = FilterType::deserialize(r)?;
 
@@ -82,16 +107,14 @@ impl Serializer for Subscribe {
 
         l += self.filter_type.serialize(w)?;
 
+        let mut parameters = Parameters::new();
         if let Some(authorization_info) = self.authorization_info.as_ref() {
-            let mut parameters = Parameters::new();
             parameters.insert(
                 ParameterKey::AuthorizationInfo,
                 authorization_info.to_string(),
             )?;
-            l += parameters.serialize(w)?;
-        } else {
-            l += 0u64.serialize(w)?;
         }
+        l += parameters.serialize(w)?;
 
         Ok(l)
     }
@@ -140,4 +163,101 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_filter_type_absolute_range_end_object_too_low() -> Result<()> {
+        let packet: Vec<u8> = vec![
+            0x04, // Filter type: Absolute Range
+            0x04, 0x03, // start_group = 4, start_object = 3
+            0x04, 0x02, // end_group = 4, end_object = 1 (offset by one)
+        ];
+        let mut cursor: Cursor<&[u8]> = Cursor::new(packet.as_ref());
+        assert_eq!(
+            FilterType::deserialize(&mut cursor),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "End object comes before start object".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_type_absolute_range_end_group_too_low() -> Result<()> {
+        let packet: Vec<u8> = vec![
+            0x04, // Filter type: Absolute Range
+            0x04, 0x01, // start_group = 4, start_object = 1
+            0x03, 0x00, // end_group = 3, whole group
+        ];
+        let mut cursor: Cursor<&[u8]> = Cursor::new(packet.as_ref());
+        assert_eq!(
+            FilterType::deserialize(&mut cursor),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "End group is less than start group".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_subscribe_empty_names() -> Result<()> {
+        let empty_namespace: Vec<u8> = vec![
+            0x03, 0x01, 0x02, // id and alias
+            0x00, // track_namespace = ""
+            0x04, 0x61, 0x62, 0x63, 0x64, // track_name = "abcd"
+            0x02, // Filter type: Latest Object
+            0x00, // no parameters
+        ];
+        assert_eq!(
+            ControlMessage::deserialize(&mut empty_namespace.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "empty track_namespace in SUBSCRIBE".to_string()
+            ))
+        );
+
+        let empty_name: Vec<u8> = vec![
+            0x03, 0x01, 0x02, // id and alias
+            0x03, 0x66, 0x6f, 0x6f, // track_namespace = "foo"
+            0x00, // track_name = ""
+            0x02, // Filter type: Latest Object
+            0x00, // no parameters
+        ];
+        assert_eq!(
+            ControlMessage::deserialize(&mut empty_name.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "empty track_name in SUBSCRIBE".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_subscribe_new() {
+        let subscribe = Subscribe::new(
+            1,
+            2,
+            FullTrackName::new("foo".to_string(), "abcd".to_string()),
+            FilterType::LatestGroup,
+        )
+        .with_authorization_info("bar".to_string());
+        assert_eq!(
+            subscribe,
+            Subscribe {
+                subscribe_id: 1,
+                track_alias: 2,
+                track_namespace: "foo".to_string(),
+                track_name: "abcd".to_string(),
+                filter_type: FilterType::LatestGroup,
+                authorization_info: Some("bar".to_string()),
+            }
+        );
+        assert_eq!(
+            Subscribe::new(1, 2, FullTrackName::default(), FilterType::LatestObject)
+                .authorization_info,
+            None
+        );
+    }
 }
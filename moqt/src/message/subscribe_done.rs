@@ -15,6 +15,29 @@ pub enum SubscribeDoneCode {
     Expired = 0x6,
 }
 
+impl TryFrom<u64> for SubscribeDoneCode {
+    type Error = Error;
+
+    fn try_from(value: u64) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0x0 => Ok(SubscribeDoneCode::Unsubscribed),
+            0x1 => Ok(SubscribeDoneCode::InternalError),
+            0x2 => Ok(SubscribeDoneCode::Unauthorized),
+            0x3 => Ok(SubscribeDoneCode::TrackEnded),
+            0x4 => Ok(SubscribeDoneCode::SubscriptionEnded),
+            0x5 => Ok(SubscribeDoneCode::GoingAway),
+            0x6 => Ok(SubscribeDoneCode::Expired),
+            _ => Err(Error::ErrInvalidSubscribeDoneCode(value)),
+        }
+    }
+}
+
+impl From<SubscribeDoneCode> for u64 {
+    fn from(value: SubscribeDoneCode) -> Self {
+        value as u64
+    }
+}
+
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct SubscribeDone {
     pub subscribe_id: u64,
@@ -32,7 +55,7 @@ impl Deserializer for SubscribeDone {
         let (status_code, scl) = u64::deserialize(r)?;
         let (reason_phrase, rpl) = String::deserialize(r)?;
 
-        let (exist, el) = bool::deserialize(r).map_err(|err| {
+        let (final_group_object, fgol) = Option::<FullSequence>::deserialize(r).map_err(|err| {
             if let Error::ErrInvalidBooleanValue(b) = err {
                 Error::ErrParseError(
                     ErrorCode::ProtocolViolation,
@@ -42,14 +65,6 @@ impl Deserializer for SubscribeDone {
                 err
             }
         })?;
-        let mut tl = sil + scl + rpl + el;
-        let final_group_object = if exist {
-            let (final_group_object, fgol) = FullSequence::deserialize(r)?;
-            tl += fgol;
-            Some(final_group_object)
-        } else {
-            None
-        };
 
         Ok((
             Self {
@@ -60,7 +75,7 @@ impl Deserializer for SubscribeDone {
 
                 final_group_object,
             },
-            tl,
+            sil + scl + rpl + fgol,
         ))
     }
 }
@@ -72,11 +87,7 @@ impl Serializer for SubscribeDone {
         l += self.status_code.serialize(w)?;
         l += self.reason_phrase.serialize(w)?;
 
-        l += if let Some(group_object_pair) = self.final_group_object.as_ref() {
-            true.serialize(w)? + group_object_pair.serialize(w)?
-        } else {
-            false.serialize(w)?
-        };
+        l += self.final_group_object.serialize(w)?;
 
         Ok(l)
     }
@@ -117,4 +128,51 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_subscribe_done_without_content() -> Result<()> {
+        let expected_packet: Vec<u8> = vec![
+            0x0b, 0x02, 0x03, // subscribe_id = 2, error_code = 3,
+            0x02, 0x68, 0x69, // reason_phrase = "hi"
+            0x00, // content_exists = 0, no final_id
+        ];
+
+        let expected_message = ControlMessage::SubscribeDone(SubscribeDone {
+            subscribe_id: 2,
+            status_code: 3,
+            reason_phrase: "hi".to_string(),
+            final_group_object: None,
+        });
+
+        let mut cursor: Cursor<&[u8]> = Cursor::new(expected_packet.as_ref());
+        let (actual_message, actual_len) = ControlMessage::deserialize(&mut cursor)?;
+        assert_eq!(expected_message, actual_message);
+        assert_eq!(expected_packet.len(), actual_len);
+
+        let mut actual_packet = vec![];
+        let _ = expected_message.serialize(&mut actual_packet)?;
+        assert_eq!(expected_packet, actual_packet);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_subscribe_done_code_round_trip() -> Result<()> {
+        for code in [
+            SubscribeDoneCode::Unsubscribed,
+            SubscribeDoneCode::InternalError,
+            SubscribeDoneCode::Unauthorized,
+            SubscribeDoneCode::TrackEnded,
+            SubscribeDoneCode::SubscriptionEnded,
+            SubscribeDoneCode::GoingAway,
+            SubscribeDoneCode::Expired,
+        ] {
+            assert_eq!(SubscribeDoneCode::try_from(u64::from(code))?, code);
+        }
+        assert_eq!(
+            SubscribeDoneCode::try_from(7),
+            Err(Error::ErrInvalidSubscribeDoneCode(7))
+        );
+        Ok(())
+    }
 }
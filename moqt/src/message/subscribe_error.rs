@@ -7,6 +7,7 @@ pub enum SubscribeErrorCode {
     InternalError = 0,
     InvalidRange = 1,
     RetryTrackAlias = 2,
+    Unauthorized = 3,
 }
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
@@ -2,40 +2,46 @@ use crate::message::message_parser::ErrorCode;
 use crate::message::FullSequence;
 use crate::{Deserializer, Error, Result, Serializer};
 use bytes::{Buf, BufMut};
+use std::time::Duration;
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct SubscribeOk {
     pub subscribe_id: u64,
 
+    /// Milliseconds after which the subscription is no longer valid; 0 means
+    /// it does not expire.
     pub expires: u64,
 
     pub largest_group_object: Option<FullSequence>,
 }
 
+impl SubscribeOk {
+    /// expires as a Duration, or None when the subscription does not expire.
+    pub fn expires_duration(&self) -> Option<Duration> {
+        if self.expires == 0 {
+            return None;
+        }
+        Some(Duration::from_millis(self.expires))
+    }
+}
+
 impl Deserializer for SubscribeOk {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (subscribe_id, sil) = u64::deserialize(r)?;
 
         let (expires, el) = u64::deserialize(r)?;
 
-        let (exist, l) = bool::deserialize(r).map_err(|err| {
-            if let Error::ErrInvalidBooleanValue(b) = err {
-                Error::ErrParseError(
-                    ErrorCode::ProtocolViolation,
-                    format!("SUBSCRIBE_OK ContentExists has invalid value {}", b),
-                )
-            } else {
-                err
-            }
-        })?;
-        let mut tl = sil + el + l;
-        let largest_group_object = if exist {
-            let (largest_group_object, lgol) = FullSequence::deserialize(r)?;
-            tl += lgol;
-            Some(largest_group_object)
-        } else {
-            None
-        };
+        let (largest_group_object, lgol) =
+            Option::<FullSequence>::deserialize(r).map_err(|err| {
+                if let Error::ErrInvalidBooleanValue(b) = err {
+                    Error::ErrParseError(
+                        ErrorCode::ProtocolViolation,
+                        format!("SUBSCRIBE_OK ContentExists has invalid value {}", b),
+                    )
+                } else {
+                    err
+                }
+            })?;
 
         Ok((
             Self {
@@ -45,7 +51,7 @@ impl Deserializer for SubscribeOk {
 
                 largest_group_object,
             },
-            tl,
+            sil + el + lgol,
         ))
     }
 }
@@ -56,11 +62,7 @@ impl Serializer for SubscribeOk {
 
         l += self.expires.serialize(w)?;
 
-        l += if let Some(largest_group_object) = self.largest_group_object.as_ref() {
-            true.serialize(w)? + largest_group_object.serialize(w)?
-        } else {
-            false.serialize(w)?
-        };
+        l += self.largest_group_object.serialize(w)?;
 
         Ok(l)
     }
@@ -99,4 +101,19 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_subscribe_ok_expires_duration() {
+        let mut subscribe_ok = SubscribeOk {
+            subscribe_id: 1,
+            expires: 1500,
+            largest_group_object: None,
+        };
+        assert_eq!(
+            subscribe_ok.expires_duration(),
+            Some(Duration::from_millis(1500))
+        );
+        subscribe_ok.expires = 0;
+        assert_eq!(subscribe_ok.expires_duration(), None);
+    }
 }
@@ -12,7 +12,13 @@ pub struct SubscribeUpdate {
     pub start_group_object: FullSequence,
     pub end_group_object: Option<FullSequence>,
 
-    pub authorization_info: Option<String>,
+    pub parameters: Parameters,
+}
+
+impl SubscribeUpdate {
+    pub fn authorization_info(&self) -> Result<Option<String>> {
+        self.parameters.get(ParameterKey::AuthorizationInfo)
+    }
 }
 
 impl Deserializer for SubscribeUpdate {
@@ -58,34 +64,9 @@ impl Deserializer for SubscribeUpdate {
             Some(end)
         };
 
-        let mut authorization_info: Option<String> = None;
-        let (num_params, mut pl) = u64::deserialize(r)?;
-        // Parse parameters
-        for _ in 0..num_params {
-            let (key, kl) = u64::deserialize(r)?;
-            pl += kl;
-            let (size, sl) = usize::deserialize(r)?;
-            pl += sl;
-
-            if r.remaining() < size {
-                return Err(Error::ErrBufferTooShort);
-            }
-
-            if key == ParameterKey::AuthorizationInfo as u64 {
-                if authorization_info.is_some() {
-                    return Err(Error::ErrParseError(
-                        ErrorCode::ProtocolViolation,
-                        "AUTHORIZATION_INFO parameter appears twice in SUBSCRIBE_UPDATE"
-                            .to_string(),
-                    ));
-                }
-                let mut buf = vec![0; size];
-                r.copy_to_slice(&mut buf);
-                pl += size;
-
-                authorization_info = Some(String::from_utf8(buf)?);
-            }
-        }
+        let (parameters, pl) = Parameters::deserialize_in_message(r, "SUBSCRIBE_UPDATE")?;
+        // make sure authorization_info, if any, is a well-formed string
+        parameters.get::<String>(ParameterKey::AuthorizationInfo)?;
 
         Ok((
             Self {
@@ -94,7 +75,7 @@ impl Deserializer for SubscribeUpdate {
                 start_group_object: start,
                 end_group_object: end,
 
-                authorization_info,
+                parameters,
             },
             sil + sgol + egol + pl,
         ))
@@ -134,16 +115,7 @@ impl Serializer for SubscribeUpdate {
             .serialize(w)?;
         }
 
-        if let Some(authorization_info) = self.authorization_info.as_ref() {
-            let mut parameters = Parameters::new();
-            parameters.insert(
-                ParameterKey::AuthorizationInfo,
-                authorization_info.to_string(),
-            )?;
-            l += parameters.serialize(w)?;
-        } else {
-            l += 0u64.serialize(w)?;
-        }
+        l += self.parameters.serialize(w)?;
 
         Ok(l)
     }
@@ -173,7 +145,11 @@ mod test {
                 group_id: 4,
                 object_id: 5,
             }),
-            authorization_info: Some("bar".to_string()),
+            parameters: {
+                let mut parameters = Parameters::new();
+                parameters.insert(ParameterKey::AuthorizationInfo, "bar".to_string())?;
+                parameters
+            },
         });
 
         let mut cursor: Cursor<&[u8]> = Cursor::new(expected_packet.as_ref());
@@ -1,5 +1,6 @@
+use crate::message::message_parser::ErrorCode;
 use crate::message::FullSequence;
-use crate::{Deserializer, Result, Serializer};
+use crate::{Deserializer, Error, Result, Serializer};
 use bytes::{Buf, BufMut};
 
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
@@ -12,19 +13,67 @@ pub enum TrackStatusCode {
     StatusNotAvailable = 0x4,
 }
 
+impl TryFrom<u64> for TrackStatusCode {
+    type Error = Error;
+
+    fn try_from(value: u64) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0x0 => Ok(Self::InProgress),
+            0x1 => Ok(Self::DoesNotExist),
+            0x2 => Ok(Self::NotYetBegun),
+            0x3 => Ok(Self::Finished),
+            0x4 => Ok(Self::StatusNotAvailable),
+            _ => Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                format!("Invalid TRACK_STATUS status code {}", value),
+            )),
+        }
+    }
+}
+
+impl Deserializer for TrackStatusCode {
+    fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
+        let (value, len) = u64::deserialize(r)?;
+        Ok((value.try_into()?, len))
+    }
+}
+
+impl Serializer for TrackStatusCode {
+    fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
+        (*self as u64).serialize(w)
+    }
+}
+
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct TrackStatus {
     pub track_namespace: String,
     pub track_name: String,
-    pub status_code: u64,
+    pub status_code: TrackStatusCode,
     pub last_group_object: FullSequence,
 }
 
+impl TrackStatus {
+    /// The live edge of the track: the largest group and object the sender
+    /// can serve. Only an InProgress track has one. A track that does not
+    /// exist or has not begun carries no meaningful sequence, and for a
+    /// Finished track, or a relay without upstream status, it is just the
+    /// largest sequence known.
+    pub fn latest_available(&self) -> Option<FullSequence> {
+        match self.status_code {
+            TrackStatusCode::InProgress => Some(self.last_group_object),
+            TrackStatusCode::DoesNotExist
+            | TrackStatusCode::NotYetBegun
+            | TrackStatusCode::Finished
+            | TrackStatusCode::StatusNotAvailable => None,
+        }
+    }
+}
+
 impl Deserializer for TrackStatus {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (track_namespace, tnsl) = String::deserialize(r)?;
         let (track_name, tnl) = String::deserialize(r)?;
-        let (status_code, scl) = u64::deserialize(r)?;
+        let (status_code, scl) = TrackStatusCode::deserialize(r)?;
         let (last_group_object, lgol) = FullSequence::deserialize(r)?;
         Ok((
             Self {
@@ -65,7 +114,7 @@ mod test {
         let expected_message = ControlMessage::TrackStatus(TrackStatus {
             track_namespace: "foo".to_string(),
             track_name: "abcd".to_string(),
-            status_code: TrackStatusCode::InProgress as u64,
+            status_code: TrackStatusCode::InProgress,
             last_group_object: FullSequence {
                 group_id: 12,
                 object_id: 20,
@@ -83,4 +132,44 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_track_status_latest_available() {
+        for (status_code, expected) in [
+            (TrackStatusCode::InProgress, Some(FullSequence::new(12, 20))),
+            (TrackStatusCode::DoesNotExist, None),
+            (TrackStatusCode::NotYetBegun, None),
+            (TrackStatusCode::Finished, None),
+            (TrackStatusCode::StatusNotAvailable, None),
+        ] {
+            let track_status = TrackStatus {
+                track_namespace: "foo".to_string(),
+                track_name: "abcd".to_string(),
+                status_code,
+                last_group_object: FullSequence::new(12, 20),
+            };
+            assert_eq!(
+                track_status.latest_available(),
+                expected,
+                "{:?}",
+                status_code
+            );
+        }
+    }
+
+    #[test]
+    fn test_track_status_unknown_code() {
+        let packet: Vec<u8> = vec![
+            0x03, 0x66, 0x6f, 0x6f, // track_namespace = "foo"
+            0x04, 0x61, 0x62, 0x63, 0x64, // track_name = "abcd"
+            0x05, 0x0c, 0x14, // status, last_group, last_object
+        ];
+        assert_eq!(
+            TrackStatus::deserialize(&mut packet.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "Invalid TRACK_STATUS status code 5".to_string(),
+            ))
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use crate::message::{validate_track_name, validate_track_namespace};
 use crate::{Deserializer, Result, Serializer};
 use bytes::{Buf, BufMut};
 
@@ -11,6 +12,8 @@ impl Deserializer for TrackStatusRequest {
     fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
         let (track_namespace, tnsl) = String::deserialize(r)?;
         let (track_name, tnl) = String::deserialize(r)?;
+        validate_track_namespace(&track_namespace, "TRACK_STATUS_REQUEST")?;
+        validate_track_name(&track_name, "TRACK_STATUS_REQUEST")?;
         Ok((
             Self {
                 track_namespace,
@@ -32,7 +35,9 @@ impl Serializer for TrackStatusRequest {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::message::message_parser::ErrorCode;
     use crate::message::ControlMessage;
+    use crate::Error;
     use std::io::Cursor;
 
     #[test]
@@ -58,4 +63,32 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_track_status_request_empty_names() -> Result<()> {
+        let empty_namespace: Vec<u8> = vec![
+            0x0d, 0x00, // track_namespace = ""
+            0x04, 0x61, 0x62, 0x63, 0x64, // track_name = "abcd"
+        ];
+        assert_eq!(
+            ControlMessage::deserialize(&mut empty_namespace.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "empty track_namespace in TRACK_STATUS_REQUEST".to_string()
+            ))
+        );
+
+        let empty_name: Vec<u8> = vec![
+            0x0d, 0x03, 0x66, 0x6f, 0x6f, // track_namespace = "foo"
+            0x00, // track_name = ""
+        ];
+        assert_eq!(
+            ControlMessage::deserialize(&mut empty_name.as_slice()),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "empty track_name in TRACK_STATUS_REQUEST".to_string()
+            ))
+        );
+        Ok(())
+    }
 }
@@ -1,38 +1,54 @@
+use crate::connection::{SessionErrorCode, StreamErrorCode};
 use crate::message::announce::Announce;
 use crate::message::announce_cancel::AnnounceCancel;
-use crate::message::announce_error::AnnounceError;
+use crate::message::announce_error::{AnnounceError, AnnounceErrorCode};
 use crate::message::announce_ok::AnnounceOk;
 use crate::message::client_setup::ClientSetup;
 use crate::message::fetch::{Fetch, FetchTarget};
 use crate::message::fetch_cancel::FetchCancel;
 use crate::message::fetch_ok::FetchOk;
-use crate::message::go_away::GoAway;
+use crate::message::go_away::{GoAway, SessionUri};
 use crate::message::max_request_id::MaxRequestId;
 use crate::message::message_framer::MessageFramer;
-use crate::message::message_parser::{MessageParser, MessageParserEvent};
-use crate::message::object::{ObjectForwardingPreference, ObjectHeader, ObjectStatus};
+use crate::message::message_parser::{
+    ErrorCode, MessageParser, MessageParserEvent, OBJECT_ON_CONTROL_STREAM,
+};
+use crate::message::object::{
+    ObjectForwardingPreference, ObjectHeader, ObjectStatus, OBJECT_DATAGRAM_FRAGMENT_TYPE,
+};
 use crate::message::requests_blocked::RequestsBlocked;
 use crate::message::server_setup::ServerSetup;
 use crate::message::subscribe::Subscribe;
-use crate::message::subscribe_done::SubscribeDone;
-use crate::message::subscribe_error::SubscribeError;
+use crate::message::subscribe_done::{SubscribeDone, SubscribeDoneCode};
+use crate::message::subscribe_error::{SubscribeError, SubscribeErrorCode};
 use crate::message::subscribe_ok::SubscribeOk;
 use crate::message::subscribe_update::SubscribeUpdate;
-use crate::message::track_status::TrackStatus;
+use crate::message::track_status::{TrackStatus, TrackStatusCode};
 use crate::message::track_status_request::TrackStatusRequest;
 use crate::message::unannounce::UnAnnounce;
 use crate::message::unsubscribe::UnSubscribe;
-use crate::message::{ControlMessage, FilterType, FullSequence, FullTrackName, Role, Version};
+use crate::message::{
+    ControlMessage, FilterType, FullSequence, FullTrackName, NamespaceLayout, Role, Version,
+};
+use crate::serde::parameters::ParameterKey;
 use crate::session::local_track::LocalTrack;
+use crate::session::object_cache::CachedObject;
 use crate::session::remote_track::{RemoteTrack, RemoteTrackOnObjectFragment};
-use crate::{Result, StreamId};
+use crate::session::send_order::SendOrderPolicy;
+use crate::{Deserializer, Parameters, Result, StreamId};
 use bytes::{Bytes, BytesMut};
+use log::warn;
 use sansio::Protocol;
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::time::Instant;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 
 const DEFAULT_INITIAL_MAX_REQUEST_ID: u64 = 100;
 
+// How long an outgoing subscription that expired locally waits for the
+// publisher's SUBSCRIBE_DONE, dropping objects still in flight, before its
+// state is released anyway.
+const EXPIRED_SUBSCRIPTION_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Perspective {
     #[default]
@@ -40,13 +56,95 @@ pub enum Perspective {
     Client,
 }
 
-#[derive(Default, Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Config {
     pub version: Version,
     pub perspective: Perspective,
     pub use_web_transport: bool,
     pub path: String,
+    /// Hands received objects out fragment by fragment as they arrive. Takes
+    /// effect only if the peer also advertises PARTIAL_OBJECT_DELIVERY in SETUP.
     pub deliver_partial_objects: bool,
+    /// Versions a client also accepts besides `version`, offered after it in CLIENT_SETUP.
+    pub additional_versions: Vec<Version>,
+    /// Bounds how many received object fragments may wait to be polled.
+    pub object_event_queue: ObjectEventQueue,
+    /// Holds back objects that arrive ahead of a gap in their group and hands
+    /// them out in object order. Reordering works on whole objects, so it is
+    /// meant to be used without partial delivery.
+    pub reorder_objects: bool,
+    /// How many times a SUBSCRIBE is re-sent with the alias proposed by a
+    /// SUBSCRIBE_ERROR (RetryTrackAlias) before the subscription is reported
+    /// as rejected.
+    pub max_track_alias_retries: usize,
+    /// How many control message buffers handed back with
+    /// [`SessionCore::recycle_buffer`] are kept for reuse.
+    pub buffer_pool_size: usize,
+    /// Advertises DATAGRAM_FRAGMENTATION in SETUP. When the peer does too,
+    /// a datagram object over the max datagram size is split across several
+    /// datagrams instead of failing, and split objects from the peer are
+    /// reassembled before they are delivered.
+    pub datagram_fragmentation: bool,
+    /// Advertises LENGTH_PREFIXED_CONTROL in SETUP. When the peer does too,
+    /// every control message after the SETUP messages is preceded by its
+    /// length, and control messages of unknown type from the peer are skipped.
+    pub length_prefixed_control: bool,
+    /// Re-sends an ANNOUNCE refused with a transient ANNOUNCE_ERROR. None
+    /// reports every ANNOUNCE_ERROR as [`EventOut::AnnounceRejected`].
+    pub announce_retry: Option<AnnounceRetryPolicy>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: Version::default(),
+            perspective: Perspective::default(),
+            use_web_transport: false,
+            path: String::new(),
+            deliver_partial_objects: false,
+            additional_versions: vec![],
+            object_event_queue: ObjectEventQueue::default(),
+            reorder_objects: false,
+            max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+            buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+            announce_retry: None,
+        }
+    }
+}
+
+/// The default for [`Config::max_track_alias_retries`].
+pub const DEFAULT_MAX_TRACK_ALIAS_RETRIES: usize = 3;
+
+/// The default for [`Config::buffer_pool_size`].
+pub const DEFAULT_BUFFER_POOL_SIZE: usize = 16;
+
+/// Limits the number of object events ([`EventOut::ObjectReceived`] and
+/// [`EventOut::CompleteObject`]) waiting in the event queue, so a slow consumer
+/// can't make it grow without bound.
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ObjectEventQueue {
+    #[default]
+    Unbounded,
+    /// At the limit, the oldest queued object event is dropped to make room.
+    /// Suits consumers that only care about the latest objects.
+    DropOldest(usize),
+    /// At the limit, object data is refused with
+    /// [`crate::Error::ErrEventQueueFull`] until events are polled; the caller
+    /// keeps the data and hands it in again later.
+    Backpressure(usize),
+}
+
+/// How an ANNOUNCE refused with InternalError is retried. Other error codes
+/// are not transient and are reported right away.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct AnnounceRetryPolicy {
+    /// How many times the ANNOUNCE is re-sent before the error is reported as
+    /// [`EventOut::AnnounceRejected`].
+    pub max_retries: usize,
+    /// Delay before the first retry, doubled for each retry after it.
+    pub backoff: Duration,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -55,14 +153,19 @@ pub enum StreamPurpose {
     Data,
 }
 
+/// Data received from the transport, with the time it was received.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum ReadInput {
     StreamData {
         stream_id: StreamId,
         data: Bytes,
         fin: bool,
+        now: Instant,
+    },
+    Datagram {
+        bytes: Bytes,
+        now: Instant,
     },
-    Datagram(Bytes),
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -76,12 +179,33 @@ enum SessionState {
 struct Subscription {
     full_track_name: FullTrackName,
     track_alias: u64,
+    // Set when an object overtakes SUBSCRIBE_OK; the publisher can no longer
+    // reject the subscription after that.
+    received_object: bool,
+    // The forwarding preference of objects received before SUBSCRIBE_OK, handed
+    // to the remote track once the subscription is accepted.
+    forwarding_preference: Option<ObjectForwardingPreference>,
+    // Set from a nonzero SUBSCRIBE_OK expires; the subscription is ended
+    // locally once handle_timeout passes it, in case the publisher's
+    // SUBSCRIBE_DONE (Expired) never arrives. Once expired, it is when the
+    // subscription's remaining state is released.
+    expires_at: Option<Instant>,
+    // Kept to re-send the SUBSCRIBE when the publisher asks for another alias.
+    filter_type: FilterType,
+    authorization_info: Option<String>,
+    track_alias_retries: usize,
+    // The subscribe_id the application's SUBSCRIBE went out under, set once
+    // it has been re-sent under a new subscribe_id.
+    original_subscribe_id: Option<u64>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct IncomingSubscribe {
     message: Subscribe,
     accepted: bool,
+    // Set from SUBSCRIBE_OK's expires; the subscription is ended with
+    // SUBSCRIBE_DONE (Expired) once handle_timeout passes it.
+    expires_at: Option<Instant>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -90,6 +214,14 @@ struct IncomingAnnounce {
     accepted: bool,
 }
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct AnnounceRetry {
+    message: Announce,
+    retries: usize,
+    // Set while the ANNOUNCE waits to be re-sent.
+    retry_at: Option<Instant>,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct IncomingFetch {
     message: Fetch,
@@ -102,22 +234,170 @@ struct BufferedFetchFragment {
     extension_headers: Bytes,
     payload: Bytes,
     fin: bool,
+    received_at: Instant,
 }
 
 struct DataStreamState {
     parser: MessageParser,
-    partial_object: Option<(ObjectHeader, BytesMut)>,
+    // The header, extension headers and payload so far of an object not yet
+    // handed out in full. Extension headers only come with the first fragment.
+    partial_object: Option<(ObjectHeader, Bytes, BytesMut)>,
+    // With partial delivery, the latest fragment of an object of unknown
+    // length, held back until more of the object or the FIN arrives.
+    held_fragment: Option<(ObjectHeader, Bytes, Bytes)>,
+    // The object in progress on the stream and when its first byte arrived.
+    object_received_at: Option<(FullSequence, Instant)>,
+    // Set once STOP_SENDING went out; later data on the stream is discarded.
+    stopped: bool,
+}
+
+impl DataStreamState {
+    /// When the first byte of the object at |sequence| arrived, which is
+    /// |now| unless the object was already in progress on the stream.
+    fn object_received_at(&mut self, sequence: FullSequence, now: Instant) -> Instant {
+        match self.object_received_at {
+            Some((in_progress, received_at)) if in_progress == sequence => received_at,
+            _ => {
+                self.object_received_at = Some((sequence, now));
+                now
+            }
+        }
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.parser.buffered_bytes()
+            + self
+                .partial_object
+                .as_ref()
+                .map_or(0, |(_, extension_headers, partial)| {
+                    extension_headers.len() + partial.len()
+                })
+            + self
+                .held_fragment
+                .as_ref()
+                .map_or(0, |(_, extension_headers, held)| {
+                    extension_headers.len() + held.len()
+                })
+    }
 }
 
 struct PendingDataStreamOpen {
     full_track_name: FullTrackName,
     subscribe_id: u64,
     sequence: FullSequence,
+    send_order: u64,
     reusable: bool,
     bytes: BytesMut,
     fin: bool,
 }
 
+// A queued object write, sent after all control writes in send order.
+struct DataWrite {
+    subscribe_id: u64,
+    send_order: u64,
+    output: WriteOutput,
+}
+
+// Object writes waiting to go out. Only the oldest write queued to each stream
+// competes for the next slot, keyed by its send order and then by queue order;
+// later writes to the stream wait behind it.
+#[derive(Default)]
+struct DataWriteQueue {
+    next_position: u64,
+    heads: BTreeMap<(u64, u64), DataWrite>,
+    waiting: HashMap<StreamId, VecDeque<(u64, DataWrite)>>,
+    queued_by_subscribe_id: HashMap<u64, usize>,
+}
+
+impl DataWriteQueue {
+    fn push(&mut self, data_write: DataWrite) {
+        let position = self.next_position;
+        self.next_position += 1;
+        *self
+            .queued_by_subscribe_id
+            .entry(data_write.subscribe_id)
+            .or_default() += 1;
+        if let WriteOutput::SendStream { stream_id, .. } = data_write.output {
+            if let Some(waiting) = self.waiting.get_mut(&stream_id) {
+                waiting.push_back((position, data_write));
+                return;
+            }
+            self.waiting.insert(stream_id, VecDeque::new());
+        }
+        self.heads
+            .insert((data_write.send_order, position), data_write);
+    }
+
+    fn pop(&mut self) -> Option<DataWrite> {
+        let (_, data_write) = self.heads.pop_first()?;
+        if let WriteOutput::SendStream { stream_id, .. } = data_write.output {
+            match self
+                .waiting
+                .get_mut(&stream_id)
+                .and_then(VecDeque::pop_front)
+            {
+                Some((position, next)) => {
+                    self.heads.insert((next.send_order, position), next);
+                }
+                None => {
+                    self.waiting.remove(&stream_id);
+                }
+            }
+        }
+        self.forget(data_write.subscribe_id);
+        Some(data_write)
+    }
+
+    /// Drops every write queued to |stream_id|.
+    fn remove_stream(&mut self, stream_id: StreamId) {
+        let Some(waiting) = self.waiting.remove(&stream_id) else {
+            return;
+        };
+        let mut removed = waiting
+            .into_iter()
+            .map(|(_, data_write)| data_write.subscribe_id)
+            .collect::<Vec<_>>();
+        self.heads.retain(|_, data_write| {
+            let on_stream = matches!(data_write.output, WriteOutput::SendStream { stream_id: id, .. } if id == stream_id);
+            if on_stream {
+                removed.push(data_write.subscribe_id);
+            }
+            !on_stream
+        });
+        for subscribe_id in removed {
+            self.forget(subscribe_id);
+        }
+    }
+
+    fn forget(&mut self, subscribe_id: u64) {
+        if let Some(queued) = self.queued_by_subscribe_id.get_mut(&subscribe_id) {
+            *queued -= 1;
+            if *queued == 0 {
+                self.queued_by_subscribe_id.remove(&subscribe_id);
+            }
+        }
+    }
+
+    fn has_writes_for(&self, subscribe_id: u64) -> bool {
+        self.queued_by_subscribe_id.contains_key(&subscribe_id)
+    }
+
+    fn clear(&mut self) {
+        self.heads.clear();
+        self.waiting.clear();
+        self.queued_by_subscribe_id.clear();
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &DataWrite> {
+        self.heads.values().chain(
+            self.waiting
+                .values()
+                .flatten()
+                .map(|(_, data_write)| data_write),
+        )
+    }
+}
+
 struct PublisherStreamBinding {
     full_track_name: FullTrackName,
     subscribe_id: u64,
@@ -127,7 +407,7 @@ struct PublisherStreamBinding {
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Command {
     Close {
-        code: u64,
+        code: SessionErrorCode,
         reason: String,
     },
     RegisterLocalTrack {
@@ -136,6 +416,10 @@ pub enum Command {
         forwarding_preference: ObjectForwardingPreference,
         next_sequence: Option<FullSequence>,
     },
+    UnpublishTrack {
+        track_namespace: String,
+        track_name: String,
+    },
     Announce {
         track_namespace: String,
         authorization_info: Option<String>,
@@ -158,7 +442,7 @@ pub enum Command {
     TrackStatus {
         track_namespace: String,
         track_name: String,
-        status_code: u64,
+        status_code: TrackStatusCode,
         last_group_object: FullSequence,
     },
     GoAway {
@@ -220,9 +504,17 @@ pub enum Command {
         status: ObjectStatus,
         payload: Bytes,
     },
+    ForwardObject {
+        track_alias: u64,
+        object_header: ObjectHeader,
+        payload: Bytes,
+    },
     Unsubscribe {
         subscribe_id: u64,
     },
+    CancelSubscribe {
+        subscribe_id: u64,
+    },
     Unannounce {
         track_namespace: String,
     },
@@ -240,6 +532,11 @@ pub enum EventIn {
     StreamClosed {
         stream_id: StreamId,
     },
+    /// The peer asked us to stop sending on |stream_id|.
+    StopSendingReceived {
+        stream_id: StreamId,
+        error_code: u64,
+    },
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -260,10 +557,16 @@ pub enum EventOut {
     AnnounceCancelled {
         track_namespace: String,
     },
+    AnnounceWithdrawn {
+        track_namespace: String,
+        reason_phrase: String,
+    },
     TrackStatusRequested(TrackStatusRequest),
     TrackStatusReceived(TrackStatus),
+    /// The peer is going away; None asks the client to reconnect to the
+    /// current URI.
     GoAwayReceived {
-        new_session_uri: String,
+        new_session_uri: Option<SessionUri>,
     },
     MaxRequestIdReceived {
         max_request_id: u64,
@@ -288,6 +591,17 @@ pub enum EventOut {
         expires: u64,
         largest_group_object: Option<FullSequence>,
     },
+    /// Follows SubscribeAccepted when the SUBSCRIBE_OK's largest_group_object
+    /// is before the start of the subscription's absolute filter: none of the
+    /// requested objects exist yet, and they may never be published.
+    SubscribeRangeUnavailable {
+        subscribe_id: u64,
+        full_track_name: FullTrackName,
+        filter_type: FilterType,
+        largest_group_object: FullSequence,
+    },
+    /// Reports the subscribe_id the SUBSCRIBE was first sent under, even when
+    /// it was re-sent for RetryTrackAlias before being rejected.
     SubscribeRejected {
         subscribe_id: u64,
         full_track_name: FullTrackName,
@@ -308,15 +622,53 @@ pub enum EventOut {
         full_track_name: FullTrackName,
         fragment: RemoteTrackOnObjectFragment,
     },
+    /// A whole object. Sent instead of [`EventOut::ObjectReceived`] when
+    /// partial delivery is off, since objects are then buffered until they
+    /// are complete.
+    CompleteObject {
+        full_track_name: FullTrackName,
+        object_header: ObjectHeader,
+        extension_headers: Bytes,
+        payload: Bytes,
+    },
+    GroupCompleted {
+        full_track_name: FullTrackName,
+        group_id: u64,
+        object_count: u64,
+    },
+    /// An object split across datagrams was dropped because some of its
+    /// fragments didn't arrive within
+    /// [`crate::DATAGRAM_REASSEMBLY_TIMEOUT`].
+    DatagramObjectExpired {
+        full_track_name: FullTrackName,
+        sequence: FullSequence,
+    },
     UnsubscribeReceived {
         subscribe_id: u64,
     },
     UnannounceReceived {
         track_namespace: String,
     },
+    /// A stream or datagram failed to parse; the session is being closed.
+    /// `stream_id` is `None` for a datagram.
+    ParseError {
+        stream_id: Option<StreamId>,
+        code: ErrorCode,
+        reason: String,
+    },
     SessionTerminated,
 }
 
+impl EventOut {
+    /// True for the events that carry received object data.
+    pub fn is_object(&self) -> bool {
+        matches!(
+            self,
+            EventOut::ObjectReceived { .. } | EventOut::CompleteObject { .. }
+        )
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum WriteOutput {
     OpenBiStream {
@@ -328,12 +680,40 @@ pub enum WriteOutput {
         fin: bool,
     },
     SendDatagram(Bytes),
+    /// Abandons a data stream; anything still queued for it is dropped.
+    ResetStream {
+        stream_id: StreamId,
+        error_code: StreamErrorCode,
+    },
+    /// Asks the peer to stop sending on an incoming data stream.
+    StopSending {
+        stream_id: StreamId,
+        error_code: StreamErrorCode,
+    },
     Close {
-        code: u64,
+        code: SessionErrorCode,
         reason: String,
     },
 }
 
+impl WriteOutput {
+    /// Bytes the output carries to the transport.
+    fn len(&self) -> usize {
+        match self {
+            WriteOutput::SendStream { bytes, .. } => bytes.len(),
+            WriteOutput::SendDatagram(bytes) => bytes.len(),
+            WriteOutput::OpenBiStream { .. }
+            | WriteOutput::ResetStream { .. }
+            | WriteOutput::StopSending { .. }
+            | WriteOutput::Close { .. } => 0,
+        }
+    }
+}
+
+/// Decides whether an incoming SUBSCRIBE for a track may proceed, given the
+/// AUTHORIZATION_INFO it carried.
+pub type SubscribeAuthorizer = Box<dyn Fn(&FullTrackName, Option<&str>) -> bool + Send + Sync>;
+
 /// SANS-I/O MoQT session state machine.
 ///
 /// `SessionCore` owns protocol state and emits transport actions via
@@ -351,12 +731,18 @@ pub struct SessionCore {
     used_track_aliases: HashSet<u64>,
     pending_outgoing_announces: HashSet<String>,
     active_outgoing_announces: HashSet<String>,
+    withdrawn_outgoing_announces: HashSet<String>,
+    // Outgoing ANNOUNCEs kept for re-sending under Config::announce_retry.
+    announce_retries: HashMap<String, AnnounceRetry>,
     pending_outgoing_fetches: HashMap<u64, Fetch>,
     active_outgoing_fetches: HashMap<u64, Fetch>,
     buffered_outgoing_fetch_objects: HashMap<u64, VecDeque<BufferedFetchFragment>>,
     pending_outgoing_subscribes: HashMap<u64, Subscription>,
     active_outgoing_subscribes: HashMap<u64, Subscription>,
     closing_outgoing_subscribes: HashMap<u64, Subscription>,
+    // Subscribes withdrawn with UNSUBSCRIBE before any response; a late
+    // SUBSCRIBE_OK, SUBSCRIBE_ERROR or SUBSCRIBE_DONE for them is absorbed.
+    cancelled_outgoing_subscribes: HashMap<u64, Subscription>,
     incoming_announces: HashMap<String, IncomingAnnounce>,
     incoming_fetches: HashMap<u64, IncomingFetch>,
     incoming_subscribes: HashMap<u64, IncomingSubscribe>,
@@ -366,15 +752,44 @@ pub struct SessionCore {
     next_remote_track_alias: u64,
     next_request_id: u64,
     next_subscribe_id: u64,
+    // Subscribe IDs are allocated in increasing order, so an incoming
+    // SUBSCRIBE below this one has been reused or reordered.
+    next_incoming_subscribe_id: u64,
+    offered_versions: Vec<Version>,
+    negotiated_version: Option<Version>,
+    max_datagram_size: Option<usize>,
+    object_cache_size: usize,
+    subscribe_authorizer: Option<SubscribeAuthorizer>,
+    send_order_policy: Option<SendOrderPolicy>,
     local_max_request_id: u64,
     peer_max_request_id: Option<u64>,
+    peer_partial_object_delivery: bool,
+    peer_datagram_fragmentation: bool,
+    peer_length_prefixed_control: bool,
+    peer_role: Option<Role>,
     wouts: VecDeque<WriteOutput>,
+    data_wouts: DataWriteQueue,
+    // SUBSCRIBE_DONE messages held until their subscription's object writes
+    // have gone out.
+    held_subscribe_dones: Vec<(u64, WriteOutput)>,
     eouts: VecDeque<EventOut>,
+    queued_object_events: usize,
+    // Cleared buffers handed back by the caller, reused for control messages.
+    buffer_pool: Vec<BytesMut>,
+    // The latest time the caller passed in with a read or a timeout. Deadlines
+    // set by commands count from it.
+    latest_time: Option<Instant>,
 }
 
 impl SessionCore {
     pub fn new(config: Config) -> Self {
         let perspective = config.perspective;
+        let mut offered_versions = vec![config.version];
+        for version in &config.additional_versions {
+            if !offered_versions.contains(version) {
+                offered_versions.push(*version);
+            }
+        }
         Self {
             config,
             state: SessionState::AwaitingSetup,
@@ -387,12 +802,15 @@ impl SessionCore {
             used_track_aliases: HashSet::new(),
             pending_outgoing_announces: HashSet::new(),
             active_outgoing_announces: HashSet::new(),
+            withdrawn_outgoing_announces: HashSet::new(),
+            announce_retries: HashMap::new(),
             pending_outgoing_fetches: HashMap::new(),
             active_outgoing_fetches: HashMap::new(),
             buffered_outgoing_fetch_objects: HashMap::new(),
             pending_outgoing_subscribes: HashMap::new(),
             active_outgoing_subscribes: HashMap::new(),
             closing_outgoing_subscribes: HashMap::new(),
+            cancelled_outgoing_subscribes: HashMap::new(),
             incoming_announces: HashMap::new(),
             incoming_fetches: HashMap::new(),
             incoming_subscribes: HashMap::new(),
@@ -405,40 +823,346 @@ impl SessionCore {
                 Perspective::Client => 0,
             },
             next_subscribe_id: 0,
+            next_incoming_subscribe_id: 0,
+            offered_versions,
+            negotiated_version: None,
+            max_datagram_size: None,
+            object_cache_size: 0,
+            subscribe_authorizer: None,
+            send_order_policy: None,
             local_max_request_id: DEFAULT_INITIAL_MAX_REQUEST_ID,
             peer_max_request_id: Some(DEFAULT_INITIAL_MAX_REQUEST_ID),
+            peer_partial_object_delivery: false,
+            peer_datagram_fragmentation: false,
+            peer_length_prefixed_control: false,
+            peer_role: None,
             wouts: VecDeque::new(),
+            data_wouts: DataWriteQueue::default(),
+            held_subscribe_dones: vec![],
             eouts: VecDeque::new(),
+            queued_object_events: 0,
+            buffer_pool: vec![],
+            latest_time: None,
+        }
+    }
+
+    /// The version agreed in SETUP, or None until the session is established.
+    pub fn negotiated_version(&self) -> Option<Version> {
+        self.negotiated_version
+    }
+
+    /// True when both sides advertised PARTIAL_OBJECT_DELIVERY in SETUP, so
+    /// received objects are handed out fragment by fragment instead of whole.
+    pub fn partial_object_delivery(&self) -> bool {
+        self.config.deliver_partial_objects && self.peer_partial_object_delivery
+    }
+
+    /// True when both sides advertised DATAGRAM_FRAGMENTATION in SETUP, so
+    /// datagram objects may be split across several datagrams.
+    pub fn datagram_fragmentation(&self) -> bool {
+        self.config.datagram_fragmentation && self.peer_datagram_fragmentation
+    }
+
+    /// True when both sides advertised LENGTH_PREFIXED_CONTROL in SETUP, so
+    /// control messages after SETUP are framed with their length.
+    /// How track namespaces are laid out on the control stream: by the
+    /// negotiated version, or before then the configured one.
+    pub fn namespace_layout(&self) -> NamespaceLayout {
+        self.negotiated_version
+            .unwrap_or(self.config.version)
+            .namespace_layout()
+    }
+
+    pub fn length_prefixed_control(&self) -> bool {
+        self.config.length_prefixed_control && self.peer_length_prefixed_control
+    }
+
+    /// Total bytes held by the session: unparsed stream data, partially
+    /// received objects, objects waiting for reordering or their fetch, and
+    /// queued writes and object events. Meant for alarming on runaway
+    /// buffering.
+    pub fn buffered_bytes(&self) -> usize {
+        let parsers = self
+            .control_parser
+            .as_ref()
+            .map_or(0, MessageParser::buffered_bytes)
+            + self
+                .data_streams
+                .values()
+                .map(DataStreamState::buffered_bytes)
+                .sum::<usize>();
+        let remote_tracks = self
+            .remote_tracks
+            .values()
+            .map(RemoteTrack::buffered_bytes)
+            .sum::<usize>();
+        let fetches = self
+            .buffered_outgoing_fetch_objects
+            .values()
+            .flatten()
+            .map(|fragment| fragment.extension_headers.len() + fragment.payload.len())
+            .sum::<usize>();
+        let writes = self
+            .wouts
+            .iter()
+            .chain(self.data_wouts.iter().map(|data_write| &data_write.output))
+            .chain(self.held_subscribe_dones.iter().map(|(_, output)| output))
+            .map(WriteOutput::len)
+            .sum::<usize>()
+            + self
+                .pending_data_stream_opens
+                .iter()
+                .map(|pending_open| pending_open.bytes.len())
+                .sum::<usize>();
+        let events = self
+            .eouts
+            .iter()
+            .map(|event| match event {
+                EventOut::ObjectReceived { fragment, .. } => fragment.len(),
+                EventOut::CompleteObject {
+                    extension_headers,
+                    payload,
+                    ..
+                } => extension_headers.len() + payload.len(),
+                _ => 0,
+            })
+            .sum::<usize>();
+        parsers + remote_tracks + fetches + writes + events
+    }
+
+    /// Namespaces announced by this session that are still awaiting ANNOUNCE_OK or ANNOUNCE_ERROR.
+    pub fn pending_announces(&self) -> Vec<String> {
+        let mut namespaces: Vec<String> = self.pending_outgoing_announces.iter().cloned().collect();
+        namespaces.sort();
+        namespaces
+    }
+
+    /// Schedules the refused ANNOUNCE to be re-sent from handle_timeout when
+    /// Config::announce_retry allows another retry for its error code.
+    fn schedule_announce_retry(&mut self, announce_error: &AnnounceError, now: Instant) -> bool {
+        let Some(policy) = self.config.announce_retry else {
+            return false;
+        };
+        if announce_error.error_code != AnnounceErrorCode::InternalError as u64 {
+            return false;
+        }
+        let Some(retry) = self
+            .announce_retries
+            .get_mut(&announce_error.track_namespace)
+            .filter(|retry| retry.retry_at.is_none() && retry.retries < policy.max_retries)
+        else {
+            return false;
+        };
+        let backoff = policy
+            .backoff
+            .saturating_mul(2u32.saturating_pow(retry.retries as u32));
+        retry.retries += 1;
+        retry.retry_at = Some(now + backoff);
+        true
+    }
+
+    /// Full track name of the remote track received under |track_alias|, so a
+    /// relay can republish its objects under its own aliases.
+    pub fn track_name_for_alias(&self, track_alias: u64) -> Option<&FullTrackName> {
+        self.remote_tracks
+            .get(&track_alias)
+            .map(|remote_track| remote_track.full_track_name())
+    }
+
+    /// Limits the encoded size of outgoing OBJECT_DATAGRAMs; `None` means no limit.
+    /// Publishing a datagram object that doesn't fit fails without sending anything.
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: Option<usize>) {
+        self.max_datagram_size = max_datagram_size;
+    }
+
+    /// Sets how many recently published objects each local track keeps for
+    /// backfilling subscriptions that start in the past; zero disables caching.
+    pub fn set_object_cache_size(&mut self, object_cache_size: usize) {
+        self.object_cache_size = object_cache_size;
+        for local_track in self.local_tracks.values_mut() {
+            local_track.set_object_cache_size(object_cache_size);
+        }
+    }
+
+    /// Returns the cached objects of a local track from `start` up to and
+    /// including `end`.
+    pub fn cached_objects(
+        &self,
+        full_track_name: &FullTrackName,
+        start: FullSequence,
+        end: Option<FullSequence>,
+    ) -> Vec<CachedObject> {
+        self.local_tracks
+            .get(full_track_name)
+            .map(|local_track| {
+                local_track
+                    .cached_objects(start, end)
+                    .into_iter()
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Installs a check run on every incoming SUBSCRIBE before it is surfaced.
+    /// Rejected subscriptions are answered with SUBSCRIBE_ERROR (Unauthorized).
+    pub fn set_subscribe_authorizer(
+        &mut self,
+        authorizer: impl Fn(&FullTrackName, Option<&str>) -> bool + Send + Sync + 'static,
+    ) {
+        self.subscribe_authorizer = Some(Box::new(authorizer));
+    }
+
+    /// Derives the send order of objects published with
+    /// [`Command::PublishObject`] from their sequence, replacing the one the
+    /// command carries. Forwarded objects keep their own send order. None
+    /// restores the caller-supplied send order.
+    pub fn set_send_order_policy(&mut self, policy: Option<SendOrderPolicy>) {
+        self.send_order_policy = policy;
+    }
+
+    /// Hands back the buffer of a [`WriteOutput::SendStream`] once it has
+    /// been written, so later control messages can reuse its allocation.
+    /// Buffers beyond [`Config::buffer_pool_size`] are dropped.
+    pub fn recycle_buffer(&mut self, mut bytes: BytesMut) {
+        if self.buffer_pool.len() < self.config.buffer_pool_size {
+            bytes.clear();
+            self.buffer_pool.push(bytes);
+        }
+    }
+
+    /// Reserves |track_alias| for |full_track_name|, so a later
+    /// [`Command::Subscribe`] for it uses that alias instead of allocating
+    /// one. Fails if the alias or the track already has another mapping.
+    pub fn preregister_track(
+        &mut self,
+        full_track_name: FullTrackName,
+        track_alias: u64,
+    ) -> Result<()> {
+        if let Some(existing) = self.remote_track_aliases.get(&full_track_name) {
+            if *existing == track_alias {
+                return Ok(());
+            }
+            return Err(crate::Error::ErrOther(format!(
+                "track {}:{} already uses track_alias {}",
+                full_track_name.track_namespace(),
+                full_track_name.track_name,
+                existing
+            )));
+        }
+        if self
+            .remote_track_aliases
+            .values()
+            .any(|alias| *alias == track_alias)
+        {
+            return Err(crate::Error::ErrOther(format!(
+                "track_alias {} is already in use",
+                track_alias
+            )));
         }
+        self.remote_track_aliases
+            .insert(full_track_name, track_alias);
+        self.next_remote_track_alias = self
+            .next_remote_track_alias
+            .max(track_alias.saturating_add(1));
+        Ok(())
+    }
+
+    // Tells the application what failed to parse, then closes the session.
+    fn on_parse_error(&mut self, stream_id: Option<StreamId>, code: ErrorCode, reason: String) {
+        self.eouts.push_back(EventOut::ParseError {
+            stream_id,
+            code,
+            reason: reason.clone(),
+        });
+        self.wouts.push_back(WriteOutput::Close { code, reason });
+    }
+
+    /// Drops all per-session state once the session can no longer carry
+    /// traffic.
+    fn clear_session_state(&mut self) {
+        self.state = SessionState::Closed;
+        self.control_stream_id = None;
+        self.control_parser = None;
+        self.remote_tracks.clear();
+        self.local_tracks.clear();
+        self.local_track_by_subscribe_id.clear();
+        self.used_track_aliases.clear();
+        self.pending_outgoing_announces.clear();
+        self.active_outgoing_announces.clear();
+        self.withdrawn_outgoing_announces.clear();
+        self.announce_retries.clear();
+        self.pending_outgoing_fetches.clear();
+        self.active_outgoing_fetches.clear();
+        self.buffered_outgoing_fetch_objects.clear();
+        self.pending_outgoing_subscribes.clear();
+        self.active_outgoing_subscribes.clear();
+        self.closing_outgoing_subscribes.clear();
+        self.cancelled_outgoing_subscribes.clear();
+        self.incoming_announces.clear();
+        self.incoming_subscribes.clear();
+        self.incoming_fetches.clear();
+        self.data_streams.clear();
+        self.pending_data_stream_opens.clear();
+        self.publisher_streams.clear();
+    }
+
+    fn advance_time(&mut self, now: Instant) {
+        self.latest_time = Some(self.latest_time.map_or(now, |latest| latest.max(now)));
     }
 
     fn close_with_protocol_violation(&mut self, reason: impl Into<String>) {
         self.wouts.push_back(WriteOutput::Close {
-            code: 1,
+            code: SessionErrorCode::ProtocolViolation,
             reason: reason.into(),
         });
     }
 
     fn send_control_message(&mut self, control_message: ControlMessage) -> Result<()> {
+        let output = self.frame_control_message(control_message)?;
+        self.wouts.push_back(output);
+        Ok(())
+    }
+
+    fn frame_control_message(&mut self, control_message: ControlMessage) -> Result<WriteOutput> {
         let stream_id = self
             .control_stream_id
             .ok_or_else(|| crate::Error::ErrOther("control stream not established".to_string()))?;
-        let mut bytes = BytesMut::new();
-        let _ = MessageFramer::serialize_control_message(control_message, &mut bytes)?;
-        self.wouts.push_back(WriteOutput::SendStream {
+        let mut bytes = self.buffer_pool.pop().unwrap_or_default();
+        let is_setup = matches!(
+            control_message,
+            ControlMessage::ClientSetup(_) | ControlMessage::ServerSetup(_)
+        );
+        let layout = self.namespace_layout();
+        if self.length_prefixed_control() && !is_setup {
+            let _ = MessageFramer::serialize_length_prefixed_control_message_with_layout(
+                &control_message,
+                layout,
+                &mut bytes,
+            )?;
+        } else {
+            let _ = MessageFramer::serialize_control_message_with_layout(
+                &control_message,
+                layout,
+                &mut bytes,
+            )?;
+        }
+        Ok(WriteOutput::SendStream {
             stream_id,
             bytes,
             fin: false,
-        });
-        Ok(())
+        })
     }
 
     fn send_client_setup(&mut self, stream_id: StreamId) -> Result<()> {
         let mut client_setup = ClientSetup {
-            supported_versions: vec![self.config.version],
+            supported_versions: self.offered_versions.clone(),
             role: Some(Role::PubSub),
             path: None,
             uses_web_transport: self.config.use_web_transport,
+            partial_object_delivery: self.config.deliver_partial_objects,
+            datagram_fragmentation: self.config.datagram_fragmentation,
+            length_prefixed_control: self.config.length_prefixed_control,
         };
         if !self.config.use_web_transport {
             client_setup.path = Some(self.config.path.clone());
@@ -453,6 +1177,9 @@ impl SessionCore {
         let server_setup = ServerSetup {
             supported_version: self.config.version,
             role: Some(Role::PubSub),
+            partial_object_delivery: self.config.deliver_partial_objects,
+            datagram_fragmentation: self.config.datagram_fragmentation,
+            length_prefixed_control: self.config.length_prefixed_control,
         };
 
         self.ensure_control_stream(stream_id);
@@ -465,7 +1192,14 @@ impl SessionCore {
             self.control_stream_id = Some(stream_id);
         }
         if self.control_parser.is_none() {
-            self.control_parser = Some(MessageParser::new(self.config.use_web_transport));
+            let mut parser = MessageParser::new_control(self.config.use_web_transport);
+            if self.config.length_prefixed_control {
+                parser.offer_length_prefixed_control();
+            }
+            // A server only accepts its own version; a client's parser takes
+            // the layout from the SERVER_SETUP.
+            parser.set_namespace_layout(self.config.version.namespace_layout());
+            self.control_parser = Some(parser);
         }
     }
 
@@ -475,6 +1209,9 @@ impl SessionCore {
             .or_insert_with(|| DataStreamState {
                 parser: MessageParser::new_data_stream(self.config.use_web_transport),
                 partial_object: None,
+                held_fragment: None,
+                object_received_at: None,
+                stopped: false,
             })
     }
 
@@ -482,13 +1219,14 @@ impl SessionCore {
         &mut self,
         stream_id: StreamId,
         object_header: ObjectHeader,
-        extension_headers: Bytes,
+        mut extension_headers: Bytes,
         mut payload: Bytes,
         fin: bool,
+        received_at: Instant,
     ) {
-        if !self.config.deliver_partial_objects && !fin {
+        if !self.partial_object_delivery() && !fin {
             let data_stream = self.data_stream(stream_id);
-            if let Some((buffered_header, partial)) = data_stream.partial_object.as_mut() {
+            if let Some((buffered_header, _, partial)) = data_stream.partial_object.as_mut() {
                 if *buffered_header != object_header {
                     self.close_with_protocol_violation(
                         "received new partial object before previous object completed",
@@ -499,14 +1237,16 @@ impl SessionCore {
             } else {
                 let mut partial = BytesMut::new();
                 partial.extend_from_slice(payload.as_ref());
-                data_stream.partial_object = Some((object_header, partial));
+                data_stream.partial_object = Some((object_header, extension_headers, partial));
             }
             return;
         }
 
-        if !self.config.deliver_partial_objects {
+        if !self.partial_object_delivery() {
             let data_stream = self.data_stream(stream_id);
-            if let Some((buffered_header, mut partial)) = data_stream.partial_object.take() {
+            if let Some((buffered_header, buffered_extension_headers, mut partial)) =
+                data_stream.partial_object.take()
+            {
                 if buffered_header != object_header {
                     self.close_with_protocol_violation(
                         "completed object header does not match buffered partial object",
@@ -515,9 +1255,90 @@ impl SessionCore {
                 }
                 partial.extend_from_slice(payload.as_ref());
                 payload = partial.freeze();
+                extension_headers = buffered_extension_headers;
+            }
+        } else if object_header.object_payload_length.is_none() {
+            // An object of unknown length only ends at the stream FIN, which may arrive
+            // without data. Hold back the latest fragment so the FIN rides on real payload
+            // instead of surfacing as an empty trailing fragment.
+            let held = self
+                .data_streams
+                .get_mut(&stream_id)
+                .and_then(|data_stream| data_stream.held_fragment.take());
+            if let Some((held_header, held_extension_headers, held_payload)) = held {
+                if held_header != object_header {
+                    self.close_with_protocol_violation(
+                        "received new partial object before previous object completed",
+                    );
+                    return;
+                }
+                if payload.is_empty() {
+                    payload = held_payload;
+                    extension_headers = held_extension_headers;
+                } else {
+                    self.deliver_object(
+                        held_header,
+                        held_extension_headers,
+                        held_payload,
+                        false,
+                        received_at,
+                    );
+                }
+            }
+            if !fin {
+                if !payload.is_empty() || !extension_headers.is_empty() {
+                    self.data_stream(stream_id).held_fragment =
+                        Some((object_header, extension_headers, payload));
+                }
+                return;
             }
         }
 
+        self.deliver_object(object_header, extension_headers, payload, fin, received_at);
+    }
+
+    fn deliver_object(
+        &mut self,
+        object_header: ObjectHeader,
+        extension_headers: Bytes,
+        payload: Bytes,
+        fin: bool,
+        received_at: Instant,
+    ) {
+        if let Some(subscription) = self
+            .pending_outgoing_subscribes
+            .get_mut(&object_header.subscribe_id)
+            .filter(|subscription| subscription.track_alias == object_header.track_alias)
+        {
+            subscription.received_object = true;
+            let forwarding_preference = object_header.object_forwarding_preference;
+            if *subscription
+                .forwarding_preference
+                .get_or_insert(forwarding_preference)
+                != forwarding_preference
+            {
+                self.close_with_protocol_violation(format!(
+                    "inconsistent forwarding preference for track_alias {}",
+                    object_header.track_alias
+                ));
+                return;
+            }
+            let full_track_name = subscription.full_track_name.clone();
+            self.queue_object_event(EventOut::ObjectReceived {
+                full_track_name,
+                fragment: RemoteTrackOnObjectFragment {
+                    object_header,
+                    extension_headers,
+                    payload,
+                    fin,
+                    received_at,
+                },
+            });
+            return;
+        }
+        if self.is_withdrawn_subscription(object_header.subscribe_id) {
+            return;
+        }
         let Some(full_track_name) = self.resolve_object_track_name(&object_header) else {
             self.close_with_protocol_violation(format!(
                 "received object for unknown subscribe_id {}",
@@ -537,6 +1358,7 @@ impl SessionCore {
                     extension_headers,
                     payload,
                     fin,
+                    received_at,
                 });
             return;
         }
@@ -547,13 +1369,17 @@ impl SessionCore {
             extension_headers,
             payload,
             fin,
+            received_at,
         );
     }
 
-    fn process_stream_data(&mut self, stream_id: StreamId, data: Bytes, fin: bool) {
+    fn process_stream_data(&mut self, stream_id: StreamId, data: Bytes, fin: bool, now: Instant) {
         let mut events = Vec::new();
         {
             let data_stream = self.data_stream(stream_id);
+            if data_stream.stopped {
+                return;
+            }
             data_stream.parser.process_data(&mut data.as_ref(), fin);
             while let Some(event) = data_stream.parser.poll_event() {
                 events.push(event);
@@ -568,8 +1394,11 @@ impl SessionCore {
                         control_message
                     ));
                 }
-                MessageParserEvent::ParsingError(_, reason) => {
-                    self.wouts.push_back(WriteOutput::Close { code: 1, reason });
+                MessageParserEvent::ParsingError(code, reason) => {
+                    self.on_parse_error(Some(stream_id), code, reason);
+                }
+                MessageParserEvent::RecoverableParsingError(code, reason) => {
+                    warn!("stream {}: {}: {}", stream_id, code, reason);
                 }
                 MessageParserEvent::ObjectMessage(
                     object_header,
@@ -577,30 +1406,488 @@ impl SessionCore {
                     payload,
                     fin,
                 ) => {
-                    self.on_object_message(
+                    self.on_data_stream_object(
                         stream_id,
                         object_header,
                         extension_headers,
                         payload,
                         fin,
+                        now,
                     );
                 }
             }
         }
     }
 
-    fn process_datagram(&mut self, bytes: Bytes) {
+    /// Takes an object parsed from a data stream. The parser reads every
+    /// object in the layout of the stream header, so all objects on a stream
+    /// share its forwarding preference.
+    fn on_data_stream_object(
+        &mut self,
+        stream_id: StreamId,
+        object_header: ObjectHeader,
+        extension_headers: Bytes,
+        payload: Bytes,
+        fin: bool,
+        now: Instant,
+    ) {
+        if self.is_withdrawn_subscription(object_header.subscribe_id) {
+            // The stream only carries objects nobody wants any more.
+            let data_stream = self.data_stream(stream_id);
+            data_stream.partial_object = None;
+            data_stream.held_fragment = None;
+            if !data_stream.stopped {
+                data_stream.stopped = true;
+                self.wouts.push_back(WriteOutput::StopSending {
+                    stream_id,
+                    error_code: StreamErrorCode::Cancelled,
+                });
+            }
+            return;
+        }
+        let sequence = FullSequence::new(object_header.group_id, object_header.object_id);
+        let received_at = self
+            .data_stream(stream_id)
+            .object_received_at(sequence, now);
+        self.on_object_message(
+            stream_id,
+            object_header,
+            extension_headers,
+            payload,
+            fin,
+            received_at,
+        );
+    }
+
+    fn process_datagram(&mut self, bytes: Bytes, now: Instant) {
+        if matches!(
+            u64::deserialize(&mut bytes.as_ref()),
+            Ok((OBJECT_DATAGRAM_FRAGMENT_TYPE, _))
+        ) {
+            self.process_datagram_fragment(bytes, now);
+            return;
+        }
         let (object_header, payload) = match MessageParser::process_datagram(&mut bytes.as_ref()) {
             Ok(value) => value,
+            Err(crate::Error::ErrParseError(code, reason)) => {
+                self.on_parse_error(None, code, reason);
+                return;
+            }
             Err(error) => {
                 self.close_with_protocol_violation(error.to_string());
                 return;
             }
         };
-        self.on_object_message(0, object_header, Bytes::new(), payload, true);
+        self.on_object_message(0, object_header, Bytes::new(), payload, true, now);
     }
 
-    #[allow(clippy::too_many_arguments)]
+    /// Holds a fragment of an object split across datagrams in its remote
+    /// track, and delivers the object like an OBJECT_DATAGRAM once the last
+    /// fragment arrives.
+    fn process_datagram_fragment(&mut self, bytes: Bytes, now: Instant) {
+        if !self.datagram_fragmentation() {
+            self.close_with_protocol_violation(
+                "received datagram fragment without negotiating DATAGRAM_FRAGMENTATION",
+            );
+            return;
+        }
+        let fragment = match MessageParser::process_datagram_fragment(&mut bytes.as_ref()) {
+            Ok(fragment) => fragment,
+            Err(crate::Error::ErrParseError(code, reason)) => {
+                self.on_parse_error(None, code, reason);
+                return;
+            }
+            Err(error) => {
+                self.close_with_protocol_violation(error.to_string());
+                return;
+            }
+        };
+        let object_header = fragment.object_header;
+        if self.is_withdrawn_subscription(object_header.subscribe_id) {
+            return;
+        }
+        let full_track_name = self
+            .pending_outgoing_subscribes
+            .get(&object_header.subscribe_id)
+            .filter(|subscription| subscription.track_alias == object_header.track_alias)
+            .map(|subscription| subscription.full_track_name.clone())
+            .or_else(|| self.resolve_object_track_name(&object_header));
+        let Some(full_track_name) = full_track_name else {
+            self.close_with_protocol_violation(format!(
+                "received object for unknown subscribe_id {}",
+                object_header.subscribe_id
+            ));
+            return;
+        };
+        let remote_track = self
+            .remote_tracks
+            .entry(object_header.track_alias)
+            .or_insert_with(|| {
+                RemoteTrack::new(full_track_name.clone(), object_header.track_alias)
+            });
+        if remote_track.full_track_name() != &full_track_name {
+            self.close_with_protocol_violation(format!(
+                "track_alias {} changed track identity",
+                object_header.track_alias
+            ));
+            return;
+        }
+        match remote_track.on_datagram_fragment(fragment, now) {
+            Ok(Some((object_header, payload))) => {
+                self.on_object_message(0, object_header, Bytes::new(), payload, true, now)
+            }
+            Ok(None) => {}
+            Err(error) => self.close_with_protocol_violation(error.to_string()),
+        }
+    }
+
+    /// Frames an object of a local track for every subscribe window that
+    /// includes its sequence, using the track's forwarding preference.
+    fn publish_object(
+        &mut self,
+        full_track_name: FullTrackName,
+        sequence: FullSequence,
+        send_order: u64,
+        status: ObjectStatus,
+        payload: Bytes,
+    ) -> Result<()> {
+        let (forwarding_preference, track_alias, delivery_targets) = {
+            let local_track = self.local_tracks.get_mut(&full_track_name).ok_or_else(|| {
+                crate::Error::ErrOther(format!(
+                    "cannot publish unknown track {}:{}",
+                    full_track_name.track_namespace(),
+                    full_track_name.track_name
+                ))
+            })?;
+            let forwarding_preference = local_track.forwarding_preference();
+            let track_alias = local_track.track_alias().ok_or_else(|| {
+                crate::Error::ErrOther(format!(
+                    "cannot publish unsubscribed track {}:{}",
+                    full_track_name.track_namespace(),
+                    full_track_name.track_name
+                ))
+            })?;
+            let subscribe_ids = local_track
+                .should_send(sequence)
+                .into_iter()
+                .map(|window| {
+                    (
+                        window.subscribe_id(),
+                        local_track.get_send_stream(window.subscribe_id(), sequence),
+                    )
+                })
+                .collect::<Vec<_>>();
+            (forwarding_preference, track_alias, subscribe_ids)
+        };
+        let object_header = ObjectHeader {
+            subscribe_id: 0,
+            track_alias,
+            group_id: sequence.group_id,
+            object_id: sequence.object_id,
+            object_send_order: send_order,
+            object_status: status,
+            object_forwarding_preference: forwarding_preference,
+            object_payload_length: None,
+        };
+        // Frame every datagram before touching any state so that an oversized
+        // object leaves the track untouched and the caller can retry on a stream.
+        let datagrams = self.frame_object_datagrams(object_header, &delivery_targets, &payload)?;
+        let mut completed_subscribe_ids = vec![];
+        if let Some(local_track) = self.local_tracks.get_mut(&full_track_name) {
+            local_track.sent_sequence(sequence, status);
+            local_track.cache_object(sequence, send_order, status, payload.clone());
+            for (subscribe_id, _) in &delivery_targets {
+                if local_track.on_object_sent(*subscribe_id, sequence, status) {
+                    completed_subscribe_ids.push(*subscribe_id);
+                }
+            }
+        }
+        self.send_object(
+            full_track_name,
+            object_header,
+            delivery_targets,
+            datagrams,
+            payload,
+        )?;
+        for subscribe_id in completed_subscribe_ids {
+            self.end_incoming_subscription(
+                subscribe_id,
+                SubscribeDoneCode::SubscriptionEnded,
+                "subscription ended",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Frames |object_header| as an OBJECT_DATAGRAM for each delivery target
+    /// if the track uses datagrams; fails if any exceeds the max datagram size,
+    /// unless DATAGRAM_FRAGMENTATION lets it be split into fragments that fit.
+    fn frame_object_datagrams(
+        &self,
+        object_header: ObjectHeader,
+        delivery_targets: &[(u64, Option<StreamId>)],
+        payload: &Bytes,
+    ) -> Result<Vec<(u64, Bytes)>> {
+        let mut datagrams = vec![];
+        if object_header.object_forwarding_preference != ObjectForwardingPreference::Datagram {
+            return Ok(datagrams);
+        }
+        for (subscribe_id, _) in delivery_targets {
+            let object_header = ObjectHeader {
+                subscribe_id: *subscribe_id,
+                ..object_header
+            };
+            let mut bytes = BytesMut::new();
+            let _ = MessageFramer::serialize_object_datagram(
+                object_header,
+                payload.clone(),
+                &mut bytes,
+            )?;
+            if let Some(max_datagram_size) = self.max_datagram_size {
+                if bytes.len() > max_datagram_size && self.datagram_fragmentation() {
+                    datagrams.extend(
+                        MessageFramer::serialize_object_datagram_fragments(
+                            object_header,
+                            payload.clone(),
+                            max_datagram_size,
+                        )?
+                        .into_iter()
+                        .map(|fragment| (*subscribe_id, fragment)),
+                    );
+                    continue;
+                }
+                if bytes.len() > max_datagram_size {
+                    return Err(crate::Error::ErrOther(format!(
+                        "OBJECT_DATAGRAM of {} bytes exceeds max datagram size {}",
+                        bytes.len(),
+                        max_datagram_size
+                    )));
+                }
+            }
+            datagrams.push((*subscribe_id, bytes.freeze()));
+        }
+        Ok(datagrams)
+    }
+
+    /// Queues |object_header| to every delivery target, as the |datagrams|
+    /// framed for them or on the stream the forwarding preference calls for.
+    fn send_object(
+        &mut self,
+        full_track_name: FullTrackName,
+        object_header: ObjectHeader,
+        delivery_targets: Vec<(u64, Option<StreamId>)>,
+        datagrams: Vec<(u64, Bytes)>,
+        payload: Bytes,
+    ) -> Result<()> {
+        let send_order = object_header.object_send_order;
+        let status = object_header.object_status;
+        let forwarding_preference = object_header.object_forwarding_preference;
+        let sequence = FullSequence::new(object_header.group_id, object_header.object_id);
+        for (subscribe_id, datagram) in datagrams {
+            self.push_data_write(
+                subscribe_id,
+                send_order,
+                WriteOutput::SendDatagram(datagram),
+            );
+        }
+        for (subscribe_id, existing_stream_id) in delivery_targets {
+            let object_header = ObjectHeader {
+                subscribe_id,
+                ..object_header
+            };
+            match forwarding_preference {
+                ObjectForwardingPreference::Datagram => {}
+                ObjectForwardingPreference::Object => {
+                    self.queue_object_stream(
+                        full_track_name.clone(),
+                        subscribe_id,
+                        sequence,
+                        false,
+                        object_header,
+                        payload.clone(),
+                        true,
+                    )?;
+                }
+                ObjectForwardingPreference::Track | ObjectForwardingPreference::Group => {
+                    // Objects on these streams carry their length, so the FIN rides
+                    // on the frame that ends the stream instead of an empty write.
+                    let ends_stream = status == ObjectStatus::EndOfTrack
+                        || (forwarding_preference == ObjectForwardingPreference::Group
+                            && status == ObjectStatus::EndOfGroup);
+                    if let Some(stream_id) = existing_stream_id {
+                        let mut bytes = BytesMut::new();
+                        let _ = MessageFramer::serialize_object(
+                            object_header,
+                            false,
+                            payload.clone(),
+                            &mut bytes,
+                        )?;
+                        self.push_data_write(
+                            subscribe_id,
+                            send_order,
+                            WriteOutput::SendStream {
+                                stream_id,
+                                bytes,
+                                fin: ends_stream,
+                            },
+                        );
+                        if ends_stream {
+                            if let Some(binding) = self.publisher_streams.remove(&stream_id) {
+                                if let Some(local_track) =
+                                    self.local_tracks.get_mut(&binding.full_track_name)
+                                {
+                                    local_track
+                                        .remove_send_stream(binding.subscribe_id, binding.sequence);
+                                }
+                            }
+                        }
+                    } else {
+                        self.queue_object_stream(
+                            full_track_name.clone(),
+                            subscribe_id,
+                            sequence,
+                            !ends_stream,
+                            object_header,
+                            payload.clone(),
+                            ends_stream,
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a subscription just accepted the cached objects of its track from
+    /// `start` up to and including `end`, ahead of the objects published
+    /// from now on.
+    fn replay_cached_objects(
+        &mut self,
+        full_track_name: FullTrackName,
+        subscribe_id: u64,
+        start: FullSequence,
+        end: Option<FullSequence>,
+    ) -> Result<()> {
+        let Some(local_track) = self.local_tracks.get(&full_track_name) else {
+            return Ok(());
+        };
+        let Some(track_alias) = local_track.track_alias() else {
+            return Ok(());
+        };
+        let forwarding_preference = local_track.forwarding_preference();
+        let cached_objects = local_track
+            .cached_objects(start, end)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        for cached_object in cached_objects {
+            let sequence = cached_object.sequence;
+            let Some(local_track) = self.local_tracks.get_mut(&full_track_name) else {
+                return Ok(());
+            };
+            let delivery_targets = vec![(
+                subscribe_id,
+                local_track.get_send_stream(subscribe_id, sequence),
+            )];
+            let completed =
+                local_track.on_object_sent(subscribe_id, sequence, cached_object.status);
+            let object_header = ObjectHeader {
+                subscribe_id: 0,
+                track_alias,
+                group_id: sequence.group_id,
+                object_id: sequence.object_id,
+                object_send_order: cached_object.send_order,
+                object_status: cached_object.status,
+                object_forwarding_preference: forwarding_preference,
+                object_payload_length: None,
+            };
+            let datagrams = self.frame_object_datagrams(
+                object_header,
+                &delivery_targets,
+                &cached_object.payload,
+            )?;
+            self.send_object(
+                full_track_name.clone(),
+                object_header,
+                delivery_targets,
+                datagrams,
+                cached_object.payload,
+            )?;
+            if completed {
+                return self.end_incoming_subscription(
+                    subscribe_id,
+                    SubscribeDoneCode::SubscriptionEnded,
+                    "subscription ended",
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends every accepted subscription to a local track with an EndOfTrack
+    /// object and SUBSCRIBE_DONE (TrackEnded), then forgets the track.
+    fn unpublish_track(&mut self, full_track_name: FullTrackName) -> Result<()> {
+        let local_track = self.local_tracks.get(&full_track_name).ok_or_else(|| {
+            crate::Error::ErrOther(format!(
+                "cannot unpublish unknown track {}:{}",
+                full_track_name.track_namespace(),
+                full_track_name.track_name
+            ))
+        })?;
+        let mut subscribe_ids = self
+            .local_track_by_subscribe_id
+            .iter()
+            .filter(|(_, name)| **name == full_track_name)
+            .map(|(subscribe_id, _)| *subscribe_id)
+            .collect::<Vec<_>>();
+        subscribe_ids.sort_unstable();
+        let track_alias = local_track.track_alias();
+        if let Some(track_alias) = track_alias.filter(|_| !subscribe_ids.is_empty()) {
+            let sequence = *local_track.next_sequence();
+            let delivery_targets = subscribe_ids
+                .iter()
+                .map(|subscribe_id| {
+                    (
+                        *subscribe_id,
+                        local_track.get_send_stream(*subscribe_id, sequence),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let object_header = ObjectHeader {
+                subscribe_id: 0,
+                track_alias,
+                group_id: sequence.group_id,
+                object_id: sequence.object_id,
+                object_send_order: 0,
+                object_status: ObjectStatus::EndOfTrack,
+                object_forwarding_preference: local_track.forwarding_preference(),
+                object_payload_length: None,
+            };
+            let datagrams =
+                self.frame_object_datagrams(object_header, &delivery_targets, &Bytes::new())?;
+            self.send_object(
+                full_track_name.clone(),
+                object_header,
+                delivery_targets,
+                datagrams,
+                Bytes::new(),
+            )?;
+        }
+        for subscribe_id in subscribe_ids {
+            self.end_incoming_subscription(
+                subscribe_id,
+                SubscribeDoneCode::TrackEnded,
+                "track unpublished",
+            )?;
+        }
+        self.local_tracks.remove(&full_track_name);
+        if let Some(track_alias) = track_alias {
+            self.used_track_aliases.remove(&track_alias);
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn queue_object_stream(
         &mut self,
         full_track_name: FullTrackName,
@@ -612,33 +1899,235 @@ impl SessionCore {
         fin: bool,
     ) -> Result<()> {
         let mut bytes = BytesMut::new();
+        let send_order = object_header.object_send_order;
         let _ = MessageFramer::serialize_object(object_header, true, payload, &mut bytes)?;
         self.pending_data_stream_opens
             .push_back(PendingDataStreamOpen {
                 full_track_name,
                 subscribe_id,
                 sequence,
+                send_order,
                 reusable,
                 bytes,
                 fin,
             });
-        self.wouts.push_back(WriteOutput::OpenBiStream {
-            purpose: StreamPurpose::Data,
-        });
+        self.push_data_write(
+            subscribe_id,
+            send_order,
+            WriteOutput::OpenBiStream {
+                purpose: StreamPurpose::Data,
+            },
+        );
         Ok(())
     }
 
-    fn cleanup_incoming_subscription(&mut self, subscribe_id: u64) {
+    fn push_data_write(&mut self, subscribe_id: u64, send_order: u64, output: WriteOutput) {
+        self.data_wouts.push(DataWrite {
+            subscribe_id,
+            send_order,
+            output,
+        });
+    }
+
+    fn has_queued_data_for(&self, subscribe_id: u64) -> bool {
+        self.data_wouts.has_writes_for(subscribe_id)
+            || self
+                .pending_data_stream_opens
+                .iter()
+                .any(|pending_open| pending_open.subscribe_id == subscribe_id)
+    }
+
+    /// Queues each held SUBSCRIBE_DONE whose subscription has no object
+    /// writes left to go out.
+    fn release_subscribe_dones(&mut self) {
+        if self.held_subscribe_dones.is_empty() {
+            return;
+        }
+        for (subscribe_id, output) in std::mem::take(&mut self.held_subscribe_dones) {
+            if self.has_queued_data_for(subscribe_id) {
+                self.held_subscribe_dones.push((subscribe_id, output));
+            } else {
+                self.wouts.push_back(output);
+            }
+        }
+    }
+
+    /// Forgets an incoming subscription but leaves data already queued for it
+    /// alone. Returns the largest sequence delivered to the subscriber.
+    fn release_incoming_subscription(&mut self, subscribe_id: u64) -> Option<FullSequence> {
         self.incoming_subscribes.remove(&subscribe_id);
+        let full_track_name = self.local_track_by_subscribe_id.remove(&subscribe_id)?;
+        let local_track = self.local_tracks.get_mut(&full_track_name)?;
+        let largest_delivered = local_track.largest_delivered(subscribe_id);
+        local_track.delete_window(subscribe_id);
+        largest_delivered
+    }
+
+    /// Forgets an incoming subscription and resets the streams carrying it.
+    fn cleanup_incoming_subscription(&mut self, subscribe_id: u64) -> Option<FullSequence> {
         self.pending_data_stream_opens
             .retain(|pending| pending.subscribe_id != subscribe_id);
-        self.publisher_streams
-            .retain(|_, binding| binding.subscribe_id != subscribe_id);
-        if let Some(full_track_name) = self.local_track_by_subscribe_id.remove(&subscribe_id) {
-            if let Some(local_track) = self.local_tracks.get_mut(&full_track_name) {
-                local_track.delete_window(subscribe_id);
-            }
+        let mut stream_ids = self
+            .publisher_streams
+            .iter()
+            .filter(|(_, binding)| binding.subscribe_id == subscribe_id)
+            .map(|(stream_id, _)| *stream_id)
+            .collect::<Vec<_>>();
+        stream_ids.sort_unstable();
+        for stream_id in stream_ids {
+            self.publisher_streams.remove(&stream_id);
+            self.data_wouts.remove_stream(stream_id);
+            self.wouts.push_back(WriteOutput::ResetStream {
+                stream_id,
+                error_code: StreamErrorCode::Cancelled,
+            });
+        }
+        self.release_incoming_subscription(subscribe_id)
+    }
+
+    /// Tears down an accepted incoming subscription and tells the subscriber
+    /// why with SUBSCRIBE_DONE.
+    fn end_incoming_subscription(
+        &mut self,
+        subscribe_id: u64,
+        status_code: SubscribeDoneCode,
+        reason_phrase: &str,
+    ) -> Result<()> {
+        self.send_subscribe_done(
+            subscribe_id,
+            status_code.into(),
+            reason_phrase.to_string(),
+            None,
+        )
+    }
+
+    /// Sends SUBSCRIBE_DONE for an accepted incoming subscription. Without an
+    /// explicit |final_group_object|, the largest object delivered is reported.
+    fn send_subscribe_done(
+        &mut self,
+        subscribe_id: u64,
+        status_code: u64,
+        reason_phrase: String,
+        final_group_object: Option<FullSequence>,
+    ) -> Result<()> {
+        let largest_delivered = if matches!(
+            SubscribeDoneCode::try_from(status_code),
+            Ok(SubscribeDoneCode::SubscriptionEnded | SubscribeDoneCode::TrackEnded)
+        ) {
+            // The objects that completed the window or ended the track may
+            // still be waiting for their streams to open.
+            self.release_incoming_subscription(subscribe_id)
+        } else {
+            self.cleanup_incoming_subscription(subscribe_id)
+        };
+        let subscribe_done = ControlMessage::SubscribeDone(SubscribeDone {
+            subscribe_id,
+            status_code,
+            reason_phrase,
+            final_group_object: final_group_object.or(largest_delivered),
+        });
+        // The subscriber must see the subscription's last objects before it
+        // learns the subscription is done.
+        if self.has_queued_data_for(subscribe_id) {
+            let output = self.frame_control_message(subscribe_done)?;
+            self.held_subscribe_dones.push((subscribe_id, output));
+            return Ok(());
+        }
+        self.send_control_message(subscribe_done)
+    }
+
+    fn accepted_incoming_subscribes(&self) -> Vec<u64> {
+        let mut subscribe_ids = self
+            .incoming_subscribes
+            .iter()
+            .filter(|(_, incoming_subscribe)| incoming_subscribe.accepted)
+            .map(|(subscribe_id, _)| *subscribe_id)
+            .collect::<Vec<_>>();
+        subscribe_ids.sort_unstable();
+        subscribe_ids
+    }
+
+    // Ends an outgoing subscription whose expiry passed. The publisher ends it
+    // too, so the subscription is kept as cancelled until its SUBSCRIBE_DONE
+    // arrives or the grace period runs out, and the state can be released.
+    fn expire_outgoing_subscription(&mut self, subscribe_id: u64, now: Instant) {
+        let Some(mut subscription) = self.active_outgoing_subscribes.remove(&subscribe_id) else {
+            return;
+        };
+        subscription.expires_at = Some(now + EXPIRED_SUBSCRIPTION_GRACE_PERIOD);
+        self.eouts.push_back(EventOut::SubscribeEnded {
+            subscribe_id,
+            full_track_name: subscription.full_track_name.clone(),
+            track_alias: subscription.track_alias,
+            status_code: SubscribeDoneCode::Expired as u64,
+            reason_phrase: "subscription expired".to_string(),
+            final_group_object: None,
+        });
+        self.cancelled_outgoing_subscribes
+            .insert(subscribe_id, subscription);
+    }
+
+    fn send_subscribe(&mut self, subscription: Subscription) -> Result<()> {
+        let subscribe_id = self.next_subscribe_id;
+        self.next_subscribe_id += 1;
+        let mut subscribe = Subscribe::new(
+            subscribe_id,
+            subscription.track_alias,
+            subscription.full_track_name.clone(),
+            subscription.filter_type,
+        );
+        subscribe.authorization_info = subscription.authorization_info.clone();
+        self.send_control_message(ControlMessage::Subscribe(subscribe))?;
+        self.pending_outgoing_subscribes
+            .insert(subscribe_id, subscription);
+        Ok(())
+    }
+
+    // Re-sends a SUBSCRIBE rejected with RetryTrackAlias under a new
+    // subscribe_id and the proposed alias. Returns false once the retries are
+    // used up or the alias already belongs to another track, so the caller
+    // reports the rejection instead.
+    fn retry_track_alias(
+        &mut self,
+        subscribe_id: u64,
+        subscription: &Subscription,
+        track_alias: u64,
+    ) -> Result<bool> {
+        if subscription.track_alias_retries >= self.config.max_track_alias_retries {
+            return Ok(false);
+        }
+        let alias_taken = self
+            .pending_outgoing_subscribes
+            .values()
+            .chain(self.active_outgoing_subscribes.values())
+            .chain(self.closing_outgoing_subscribes.values())
+            .chain(self.cancelled_outgoing_subscribes.values())
+            .any(|other| {
+                other.track_alias == track_alias
+                    && other.full_track_name != subscription.full_track_name
+            });
+        if alias_taken {
+            return Ok(false);
         }
+
+        self.release_outgoing_subscription_state(subscription);
+        self.remote_track_aliases
+            .insert(subscription.full_track_name.clone(), track_alias);
+        self.next_remote_track_alias = self
+            .next_remote_track_alias
+            .max(track_alias.saturating_add(1));
+        self.send_subscribe(Subscription {
+            full_track_name: subscription.full_track_name.clone(),
+            track_alias,
+            received_object: false,
+            forwarding_preference: None,
+            expires_at: None,
+            filter_type: subscription.filter_type,
+            authorization_info: subscription.authorization_info.clone(),
+            track_alias_retries: subscription.track_alias_retries + 1,
+            original_subscribe_id: subscription.original_subscribe_id.or(Some(subscribe_id)),
+        })?;
+        Ok(true)
     }
 
     fn release_outgoing_subscription_state(&mut self, subscription: &Subscription) {
@@ -647,6 +2136,7 @@ impl SessionCore {
             .values()
             .chain(self.active_outgoing_subscribes.values())
             .chain(self.closing_outgoing_subscribes.values())
+            .chain(self.cancelled_outgoing_subscribes.values())
             .any(|other| other.track_alias == subscription.track_alias);
         if !alias_in_use {
             self.remote_tracks.remove(&subscription.track_alias);
@@ -657,6 +2147,7 @@ impl SessionCore {
             .values()
             .chain(self.active_outgoing_subscribes.values())
             .chain(self.closing_outgoing_subscribes.values())
+            .chain(self.cancelled_outgoing_subscribes.values())
             .any(|other| other.full_track_name == subscription.full_track_name);
         if !track_name_in_use {
             self.remote_track_aliases
@@ -677,6 +2168,15 @@ impl SessionCore {
         }
     }
 
+    // Objects may still be in flight after UNSUBSCRIBE; they are dropped
+    // rather than treated as objects for an unknown subscription.
+    fn is_withdrawn_subscription(&self, subscribe_id: u64) -> bool {
+        self.closing_outgoing_subscribes.contains_key(&subscribe_id)
+            || self
+                .cancelled_outgoing_subscribes
+                .contains_key(&subscribe_id)
+    }
+
     fn resolve_object_track_name(&self, object_header: &ObjectHeader) -> Option<FullTrackName> {
         if let Some(subscription) = self
             .active_outgoing_subscribes
@@ -701,6 +2201,39 @@ impl SessionCore {
         None
     }
 
+    fn queue_object_event(&mut self, event: EventOut) {
+        let event = match event {
+            EventOut::ObjectReceived {
+                full_track_name,
+                fragment,
+            } if !self.partial_object_delivery() && fragment.fin => EventOut::CompleteObject {
+                full_track_name,
+                object_header: fragment.object_header,
+                extension_headers: fragment.extension_headers,
+                payload: fragment.payload,
+            },
+            event => event,
+        };
+        if let ObjectEventQueue::DropOldest(limit) = self.config.object_event_queue {
+            if self.queued_object_events >= limit {
+                let Some(oldest) = self.eouts.iter().position(EventOut::is_object) else {
+                    return;
+                };
+                self.eouts.remove(oldest);
+                self.queued_object_events -= 1;
+            }
+        }
+        self.eouts.push_back(event);
+        self.queued_object_events += 1;
+    }
+
+    fn object_event_queue_full(&self) -> bool {
+        matches!(
+            self.config.object_event_queue,
+            ObjectEventQueue::Backpressure(limit) if self.queued_object_events >= limit
+        )
+    }
+
     fn push_object_received(
         &mut self,
         full_track_name: FullTrackName,
@@ -708,6 +2241,7 @@ impl SessionCore {
         extension_headers: Bytes,
         payload: Bytes,
         fin: bool,
+        received_at: Instant,
     ) {
         let remote_track = self
             .remote_tracks
@@ -729,16 +2263,45 @@ impl SessionCore {
             ));
             return;
         }
+        let completed_group = remote_track.on_object_received(&object_header, fin);
+        let fragment = RemoteTrackOnObjectFragment {
+            object_header,
+            extension_headers,
+            payload,
+            fin,
+            received_at,
+        };
+        let fragments = if self.config.reorder_objects {
+            remote_track.reorder(fragment)
+        } else {
+            vec![fragment]
+        };
+        if let Some(object_count) = completed_group {
+            remote_track.complete_group(object_header.group_id, object_count);
+        }
+        let completed_groups = remote_track.take_completed_groups();
+        self.push_ordered_objects(full_track_name, fragments, completed_groups);
+    }
 
-        self.eouts.push_back(EventOut::ObjectReceived {
-            full_track_name,
-            fragment: RemoteTrackOnObjectFragment {
-                object_header,
-                extension_headers,
-                payload,
-                fin,
-            },
-        });
+    fn push_ordered_objects(
+        &mut self,
+        full_track_name: FullTrackName,
+        fragments: Vec<RemoteTrackOnObjectFragment>,
+        completed_groups: Vec<(u64, u64)>,
+    ) {
+        for fragment in fragments {
+            self.queue_object_event(EventOut::ObjectReceived {
+                full_track_name: full_track_name.clone(),
+                fragment,
+            });
+        }
+        for (group_id, object_count) in completed_groups {
+            self.eouts.push_back(EventOut::GroupCompleted {
+                full_track_name: full_track_name.clone(),
+                group_id,
+                object_count,
+            });
+        }
     }
 
     fn flush_buffered_fetch_objects(&mut self, request_id: u64) {
@@ -759,6 +2322,7 @@ impl SessionCore {
                 fragment.extension_headers,
                 fragment.payload,
                 fragment.fin,
+                fragment.received_at,
             );
         }
     }
@@ -794,7 +2358,7 @@ impl SessionCore {
         request_id.is_multiple_of(2) == (self.config.perspective == Perspective::Server)
     }
 
-    fn on_control_message(&mut self, control_message: ControlMessage) -> Result<()> {
+    fn on_control_message(&mut self, control_message: ControlMessage, now: Instant) -> Result<()> {
         match control_message {
             ControlMessage::ClientSetup(client_setup) => {
                 if self.state != SessionState::AwaitingSetup {
@@ -817,6 +2381,11 @@ impl SessionCore {
                 }
                 let stream_id = self.control_stream_id.expect("control stream set");
                 self.send_server_setup(stream_id)?;
+                self.peer_partial_object_delivery = client_setup.partial_object_delivery;
+                self.peer_datagram_fragmentation = client_setup.datagram_fragmentation;
+                self.peer_length_prefixed_control = client_setup.length_prefixed_control;
+                self.peer_role = client_setup.role;
+                self.negotiated_version = Some(self.config.version);
                 self.state = SessionState::Established;
                 self.eouts.push_back(EventOut::SessionEstablished {
                     peer_role: client_setup.role,
@@ -832,13 +2401,21 @@ impl SessionCore {
                     self.close_with_protocol_violation("received SERVER_SETUP as server");
                     return Ok(());
                 }
-                if server_setup.supported_version != self.config.version {
+                if !self
+                    .offered_versions
+                    .contains(&server_setup.supported_version)
+                {
                     self.close_with_protocol_violation(format!(
-                        "version mismatch: expected {:?}",
-                        self.config.version
+                        "version mismatch: expected one of {:?}",
+                        self.offered_versions
                     ));
                     return Ok(());
                 }
+                self.peer_partial_object_delivery = server_setup.partial_object_delivery;
+                self.peer_datagram_fragmentation = server_setup.datagram_fragmentation;
+                self.peer_length_prefixed_control = server_setup.length_prefixed_control;
+                self.peer_role = server_setup.role;
+                self.negotiated_version = Some(server_setup.supported_version);
                 self.state = SessionState::Established;
                 self.eouts.push_back(EventOut::SessionEstablished {
                     peer_role: server_setup.role,
@@ -850,6 +2427,10 @@ impl SessionCore {
                     self.close_with_protocol_violation("received ANNOUNCE before session setup");
                     return Ok(());
                 }
+                if self.peer_role == Some(Role::Subscriber) {
+                    self.close_with_protocol_violation("received ANNOUNCE from subscriber");
+                    return Ok(());
+                }
                 if self
                     .incoming_announces
                     .contains_key(&announce.track_namespace)
@@ -874,6 +2455,17 @@ impl SessionCore {
                     self.close_with_protocol_violation("received ANNOUNCE_OK before session setup");
                     return Ok(());
                 }
+                if self
+                    .withdrawn_outgoing_announces
+                    .remove(&announce_ok.track_namespace)
+                {
+                    // Cancelled while pending; now that the peer has accepted it,
+                    // UNANNOUNCE can be sent to withdraw it on its side too.
+                    self.send_control_message(ControlMessage::UnAnnounce(UnAnnounce {
+                        track_namespace: announce_ok.track_namespace,
+                    }))?;
+                    return Ok(());
+                }
                 if !self
                     .pending_outgoing_announces
                     .remove(&announce_ok.track_namespace)
@@ -884,8 +2476,18 @@ impl SessionCore {
                     ));
                     return Ok(());
                 }
+                self.announce_retries.remove(&announce_ok.track_namespace);
                 self.active_outgoing_announces
                     .insert(announce_ok.track_namespace.clone());
+                // A namespace re-announced after ANNOUNCE_CANCEL takes
+                // subscriptions again.
+                for local_track in self.local_tracks.values_mut() {
+                    if local_track.full_track_name().track_namespace()
+                        == announce_ok.track_namespace
+                    {
+                        local_track.clear_announce_cancel();
+                    }
+                }
                 self.eouts.push_back(EventOut::AnnounceAccepted {
                     track_namespace: announce_ok.track_namespace,
                 });
@@ -897,9 +2499,15 @@ impl SessionCore {
                     );
                     return Ok(());
                 }
+                if self
+                    .withdrawn_outgoing_announces
+                    .remove(&announce_error.track_namespace)
+                {
+                    return Ok(());
+                }
                 if !self
                     .pending_outgoing_announces
-                    .remove(&announce_error.track_namespace)
+                    .contains(&announce_error.track_namespace)
                 {
                     self.close_with_protocol_violation(format!(
                         "received ANNOUNCE_ERROR for unknown namespace {}",
@@ -907,7 +2515,14 @@ impl SessionCore {
                     ));
                     return Ok(());
                 }
-                self.eouts.push_back(EventOut::AnnounceRejected {
+                if self.schedule_announce_retry(&announce_error, now) {
+                    return Ok(());
+                }
+                self.pending_outgoing_announces
+                    .remove(&announce_error.track_namespace);
+                self.announce_retries
+                    .remove(&announce_error.track_namespace);
+                self.eouts.push_back(EventOut::AnnounceRejected {
                     track_namespace: announce_error.track_namespace,
                     error_code: announce_error.error_code,
                     reason_phrase: announce_error.reason_phrase,
@@ -945,6 +2560,9 @@ impl SessionCore {
                     );
                     return Ok(());
                 }
+                // Only an accepted ANNOUNCE can be cancelled; a pending one is
+                // answered with ANNOUNCE_ERROR instead. Anything else means the
+                // peer's namespace state has diverged from ours.
                 if !self
                     .active_outgoing_announces
                     .remove(&announce_cancel.track_namespace)
@@ -956,7 +2574,7 @@ impl SessionCore {
                     return Ok(());
                 }
                 for local_track in self.local_tracks.values_mut() {
-                    if local_track.full_track_name().track_namespace
+                    if local_track.full_track_name().track_namespace()
                         == announce_cancel.track_namespace
                     {
                         local_track.set_announce_cancel();
@@ -971,6 +2589,10 @@ impl SessionCore {
                     self.close_with_protocol_violation("received SUBSCRIBE before session setup");
                     return Ok(());
                 }
+                if self.peer_role == Some(Role::Publisher) {
+                    self.close_with_protocol_violation("received SUBSCRIBE from publisher");
+                    return Ok(());
+                }
                 if self
                     .incoming_subscribes
                     .contains_key(&subscribe.subscribe_id)
@@ -981,6 +2603,21 @@ impl SessionCore {
                     ));
                     return Ok(());
                 }
+                if subscribe.subscribe_id >= self.local_max_request_id {
+                    self.close_with_protocol_violation(format!(
+                        "received SUBSCRIBE with subscribe_id {} at or above MAX_REQUEST_ID {}",
+                        subscribe.subscribe_id, self.local_max_request_id
+                    ));
+                    return Ok(());
+                }
+                if subscribe.subscribe_id < self.next_incoming_subscribe_id {
+                    self.close_with_protocol_violation(format!(
+                        "received SUBSCRIBE with non-increasing subscribe_id {}",
+                        subscribe.subscribe_id
+                    ));
+                    return Ok(());
+                }
+                self.next_incoming_subscribe_id = subscribe.subscribe_id + 1;
                 if self
                     .local_tracks
                     .get(&FullTrackName::new(
@@ -992,11 +2629,29 @@ impl SessionCore {
                     self.close_with_protocol_violation("received SUBSCRIBE for canceled track");
                     return Ok(());
                 }
+                if let Some(authorizer) = self.subscribe_authorizer.as_ref() {
+                    let full_track_name = FullTrackName::new(
+                        subscribe.track_namespace.clone(),
+                        subscribe.track_name.clone(),
+                    );
+                    if !authorizer(&full_track_name, subscribe.authorization_info.as_deref()) {
+                        self.send_control_message(ControlMessage::SubscribeError(
+                            SubscribeError {
+                                subscribe_id: subscribe.subscribe_id,
+                                error_code: SubscribeErrorCode::Unauthorized as u64,
+                                reason_phrase: "unauthorized".to_string(),
+                                track_alias: subscribe.track_alias,
+                            },
+                        ))?;
+                        return Ok(());
+                    }
+                }
                 self.incoming_subscribes.insert(
                     subscribe.subscribe_id,
                     IncomingSubscribe {
                         message: subscribe.clone(),
                         accepted: false,
+                        expires_at: None,
                     },
                 );
                 self.eouts.push_back(EventOut::SubscribeReceived(subscribe));
@@ -1008,7 +2663,15 @@ impl SessionCore {
                     );
                     return Ok(());
                 }
-                let Some(subscription) = self
+                if self
+                    .cancelled_outgoing_subscribes
+                    .contains_key(&subscribe_ok.subscribe_id)
+                {
+                    // The OK crossed our UNSUBSCRIBE; the publisher answers that
+                    // with SUBSCRIBE_DONE, which finishes the teardown.
+                    return Ok(());
+                }
+                let Some(mut subscription) = self
                     .pending_outgoing_subscribes
                     .remove(&subscribe_ok.subscribe_id)
                 else {
@@ -1018,9 +2681,15 @@ impl SessionCore {
                     ));
                     return Ok(());
                 };
+                // An expires of 0 means the subscription never expires.
+                if subscribe_ok.expires > 0 {
+                    subscription.expires_at =
+                        Some(now + Duration::from_millis(subscribe_ok.expires));
+                }
                 self.active_outgoing_subscribes
                     .insert(subscribe_ok.subscribe_id, subscription.clone());
-                self.remote_tracks
+                let remote_track = self
+                    .remote_tracks
                     .entry(subscription.track_alias)
                     .or_insert_with(|| {
                         RemoteTrack::new(
@@ -1028,13 +2697,44 @@ impl SessionCore {
                             subscription.track_alias,
                         )
                     });
+                if let Some(forwarding_preference) = subscription.forwarding_preference {
+                    if !remote_track.check_forwarding_preference(forwarding_preference) {
+                        self.close_with_protocol_violation(format!(
+                            "inconsistent forwarding preference for track_alias {}",
+                            subscription.track_alias
+                        ));
+                        return Ok(());
+                    }
+                }
                 self.eouts.push_back(EventOut::SubscribeAccepted {
                     subscribe_id: subscribe_ok.subscribe_id,
-                    full_track_name: subscription.full_track_name,
+                    full_track_name: subscription.full_track_name.clone(),
                     track_alias: subscription.track_alias,
                     expires: subscribe_ok.expires,
                     largest_group_object: subscribe_ok.largest_group_object,
                 });
+                let requested_start = match subscription.filter_type {
+                    FilterType::AbsoluteStart(start) | FilterType::AbsoluteRange(start, _) => {
+                        Some(start)
+                    }
+                    FilterType::LatestGroup | FilterType::LatestObject => None,
+                };
+                if let (Some(start), Some(largest_group_object)) =
+                    (requested_start, subscribe_ok.largest_group_object)
+                {
+                    if largest_group_object < start {
+                        warn!(
+                            "subscribe_id {}: largest object {:?} is before requested start {:?}",
+                            subscribe_ok.subscribe_id, largest_group_object, start
+                        );
+                        self.eouts.push_back(EventOut::SubscribeRangeUnavailable {
+                            subscribe_id: subscribe_ok.subscribe_id,
+                            full_track_name: subscription.full_track_name,
+                            filter_type: subscription.filter_type,
+                            largest_group_object,
+                        });
+                    }
+                }
             }
             ControlMessage::SubscribeError(subscribe_error) => {
                 if self.state != SessionState::Established {
@@ -1043,6 +2743,13 @@ impl SessionCore {
                     );
                     return Ok(());
                 }
+                if let Some(subscription) = self
+                    .cancelled_outgoing_subscribes
+                    .remove(&subscribe_error.subscribe_id)
+                {
+                    self.release_outgoing_subscription_state(&subscription);
+                    return Ok(());
+                }
                 let Some(subscription) = self
                     .pending_outgoing_subscribes
                     .remove(&subscribe_error.subscribe_id)
@@ -1053,9 +2760,27 @@ impl SessionCore {
                     ));
                     return Ok(());
                 };
+                if subscription.received_object {
+                    self.close_with_protocol_violation(format!(
+                        "received SUBSCRIBE_ERROR after object for subscribe_id {}",
+                        subscribe_error.subscribe_id
+                    ));
+                    return Ok(());
+                }
+                if subscribe_error.error_code == SubscribeErrorCode::RetryTrackAlias as u64
+                    && self.retry_track_alias(
+                        subscribe_error.subscribe_id,
+                        &subscription,
+                        subscribe_error.track_alias,
+                    )?
+                {
+                    return Ok(());
+                }
                 self.release_outgoing_subscription_state(&subscription);
                 self.eouts.push_back(EventOut::SubscribeRejected {
-                    subscribe_id: subscribe_error.subscribe_id,
+                    subscribe_id: subscription
+                        .original_subscribe_id
+                        .unwrap_or(subscribe_error.subscribe_id),
                     full_track_name: subscription.full_track_name,
                     error_code: subscribe_error.error_code,
                     reason_phrase: subscribe_error.reason_phrase,
@@ -1099,7 +2824,8 @@ impl SessionCore {
                 let Some(local_track) = self.local_tracks.get_mut(&full_track_name) else {
                     self.close_with_protocol_violation(format!(
                         "received SUBSCRIBE_UPDATE for missing track {}:{}",
-                        full_track_name.track_namespace, full_track_name.track_name
+                        full_track_name.track_namespace(),
+                        full_track_name.track_name
                     ));
                     return Ok(());
                 };
@@ -1124,6 +2850,13 @@ impl SessionCore {
                     );
                     return Ok(());
                 }
+                if let Some(subscription) = self
+                    .cancelled_outgoing_subscribes
+                    .remove(&subscribe_done.subscribe_id)
+                {
+                    self.release_outgoing_subscription_state(&subscription);
+                    return Ok(());
+                }
                 let Some(subscription) = self
                     .active_outgoing_subscribes
                     .remove(&subscribe_done.subscribe_id)
@@ -1163,7 +2896,15 @@ impl SessionCore {
                     ));
                     return Ok(());
                 }
-                self.cleanup_incoming_subscription(unsubscribe.subscribe_id);
+                if self.incoming_subscribes[&unsubscribe.subscribe_id].accepted {
+                    self.end_incoming_subscription(
+                        unsubscribe.subscribe_id,
+                        SubscribeDoneCode::Unsubscribed,
+                        "unsubscribed",
+                    )?;
+                } else {
+                    self.cleanup_incoming_subscription(unsubscribe.subscribe_id);
+                }
                 self.eouts.push_back(EventOut::UnsubscribeReceived {
                     subscribe_id: unsubscribe.subscribe_id,
                 });
@@ -1193,9 +2934,18 @@ impl SessionCore {
                     self.close_with_protocol_violation("received GOAWAY before session setup");
                     return Ok(());
                 }
-                self.eouts.push_back(EventOut::GoAwayReceived {
-                    new_session_uri: go_away.new_session_uri,
-                });
+                let new_session_uri = match go_away.session_uri() {
+                    Ok(new_session_uri) => new_session_uri,
+                    Err(_) => {
+                        self.close_with_protocol_violation(format!(
+                            "malformed GOAWAY new_session_uri {:?}",
+                            go_away.new_session_uri
+                        ));
+                        return Ok(());
+                    }
+                };
+                self.eouts
+                    .push_back(EventOut::GoAwayReceived { new_session_uri });
             }
             ControlMessage::MaxRequestId(max_request_id) => {
                 if self.state != SessionState::Established {
@@ -1323,12 +3073,18 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
     type Time = Instant;
 
     fn handle_read(&mut self, msg: ReadInput) -> Result<()> {
+        // Whatever the peer sends after the session is closed is dropped.
+        if self.state == SessionState::Closed {
+            return Ok(());
+        }
         match msg {
             ReadInput::StreamData {
                 stream_id,
                 data,
                 fin,
+                now,
             } => {
+                self.advance_time(now);
                 if self.control_stream_id.is_none() || self.control_stream_id == Some(stream_id) {
                     self.ensure_control_stream(stream_id);
                     let mut events = Vec::new();
@@ -1340,24 +3096,33 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                     for event in events {
                         match event {
                             MessageParserEvent::ControlMessage(control_message) => {
-                                self.on_control_message(control_message)?;
+                                self.on_control_message(control_message, now)?;
                             }
-                            MessageParserEvent::ParsingError(_, reason) => {
-                                self.wouts.push_back(WriteOutput::Close { code: 1, reason });
+                            MessageParserEvent::ParsingError(code, reason) => {
+                                self.on_parse_error(Some(stream_id), code, reason);
+                            }
+                            MessageParserEvent::RecoverableParsingError(code, reason) => {
+                                warn!("stream {}: {}: {}", stream_id, code, reason);
                             }
                             MessageParserEvent::ObjectMessage(_, _, _, _) => {
-                                self.wouts.push_back(WriteOutput::Close {
-                                    code: 1,
-                                    reason: "received object on control stream".to_string(),
-                                });
+                                self.close_with_protocol_violation(OBJECT_ON_CONTROL_STREAM);
                             }
                         }
                     }
                 } else {
-                    self.process_stream_data(stream_id, data, fin);
+                    if self.object_event_queue_full() {
+                        return Err(crate::Error::ErrEventQueueFull);
+                    }
+                    self.process_stream_data(stream_id, data, fin, now);
                 }
             }
-            ReadInput::Datagram(bytes) => self.process_datagram(bytes),
+            ReadInput::Datagram { bytes, now } => {
+                self.advance_time(now);
+                if self.object_event_queue_full() {
+                    return Err(crate::Error::ErrEventQueueFull);
+                }
+                self.process_datagram(bytes, now);
+            }
         }
         Ok(())
     }
@@ -1367,8 +3132,16 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
     }
 
     fn handle_write(&mut self, msg: Command) -> Result<()> {
+        if self.state == SessionState::Closed {
+            return Err(crate::Error::ErrOther("session closed".to_string()));
+        }
         match msg {
             Command::Close { code, reason } => {
+                // Control messages already queued go out ahead of the close;
+                // objects that have not been written yet are dropped.
+                self.data_wouts.clear();
+                self.held_subscribe_dones.clear();
+                self.clear_session_state();
                 self.wouts.push_back(WriteOutput::Close { code, reason });
             }
             Command::RegisterLocalTrack {
@@ -1378,10 +3151,19 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                 next_sequence,
             } => {
                 let full_track_name = FullTrackName::new(track_namespace, track_name);
-                self.local_tracks.insert(
+                let mut local_track = LocalTrack::new(
                     full_track_name.clone(),
-                    LocalTrack::new(full_track_name, forwarding_preference, next_sequence),
+                    forwarding_preference,
+                    next_sequence,
                 );
+                local_track.set_object_cache_size(self.object_cache_size);
+                self.local_tracks.insert(full_track_name, local_track);
+            }
+            Command::UnpublishTrack {
+                track_namespace,
+                track_name,
+            } => {
+                self.unpublish_track(FullTrackName::new(track_namespace, track_name))?;
             }
             Command::Announce {
                 track_namespace,
@@ -1400,10 +3182,25 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                         track_namespace
                     )));
                 }
-                self.send_control_message(ControlMessage::Announce(Announce {
+                let mut parameters = Parameters::new();
+                if let Some(authorization_info) = authorization_info {
+                    parameters.insert(ParameterKey::AuthorizationInfo, authorization_info)?;
+                }
+                let announce = Announce {
                     track_namespace: track_namespace.clone(),
-                    authorization_info,
-                }))?;
+                    parameters,
+                };
+                if self.config.announce_retry.is_some() {
+                    self.announce_retries.insert(
+                        track_namespace.clone(),
+                        AnnounceRetry {
+                            message: announce.clone(),
+                            retries: 0,
+                            retry_at: None,
+                        },
+                    );
+                }
+                self.send_control_message(ControlMessage::Announce(announce))?;
                 self.pending_outgoing_announces.insert(track_namespace);
             }
             Command::AnnounceOk { track_namespace } => {
@@ -1523,6 +3320,13 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                     ));
                 }
                 self.send_control_message(ControlMessage::GoAway(GoAway { new_session_uri }))?;
+                for subscribe_id in self.accepted_incoming_subscribes() {
+                    self.end_incoming_subscription(
+                        subscribe_id,
+                        SubscribeDoneCode::GoingAway,
+                        "going away",
+                    )?;
+                }
             }
             Command::MaxRequestId { max_request_id } => {
                 if self.state != SessionState::Established {
@@ -1646,24 +3450,17 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                             .insert(full_track_name.clone(), track_alias);
                         track_alias
                     };
-                let subscribe_id = self.next_subscribe_id;
-                self.next_subscribe_id += 1;
-                let subscribe = Subscribe {
-                    subscribe_id,
+                self.send_subscribe(Subscription {
+                    full_track_name,
                     track_alias,
-                    track_namespace: full_track_name.track_namespace.clone(),
-                    track_name: full_track_name.track_name.clone(),
+                    received_object: false,
+                    forwarding_preference: None,
+                    expires_at: None,
                     filter_type,
                     authorization_info,
-                };
-                self.send_control_message(ControlMessage::Subscribe(subscribe))?;
-                self.pending_outgoing_subscribes.insert(
-                    subscribe_id,
-                    Subscription {
-                        full_track_name,
-                        track_alias,
-                    },
-                );
+                    track_alias_retries: 0,
+                    original_subscribe_id: None,
+                })?;
             }
             Command::SubscribeOk {
                 subscribe_id,
@@ -1694,13 +3491,15 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                 let local_track = self.local_tracks.get_mut(&full_track_name).ok_or_else(|| {
                     crate::Error::ErrOther(format!(
                         "cannot send SUBSCRIBE_OK for unknown track {}:{}",
-                        full_track_name.track_namespace, full_track_name.track_name
+                        full_track_name.track_namespace(),
+                        full_track_name.track_name
                     ))
                 })?;
                 if local_track.canceled() {
                     return Err(crate::Error::ErrOther(format!(
                         "cannot send SUBSCRIBE_OK for canceled track {}:{}",
-                        full_track_name.track_namespace, full_track_name.track_name
+                        full_track_name.track_namespace(),
+                        full_track_name.track_name
                     )));
                 }
                 if let Some(track_alias) = local_track.track_alias() {
@@ -1734,16 +3533,23 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                     end.map(|seq| seq.object_id),
                 );
                 self.local_track_by_subscribe_id
-                    .insert(subscribe_id, full_track_name);
-                self.incoming_subscribes
+                    .insert(subscribe_id, full_track_name.clone());
+                let incoming_subscribe = self
+                    .incoming_subscribes
                     .get_mut(&subscribe_id)
-                    .expect("incoming subscribe exists")
-                    .accepted = true;
+                    .expect("incoming subscribe exists");
+                incoming_subscribe.accepted = true;
+                if expires > 0 {
+                    incoming_subscribe.expires_at = self
+                        .latest_time
+                        .map(|now| now + Duration::from_millis(expires));
+                }
                 self.send_control_message(ControlMessage::SubscribeOk(SubscribeOk {
                     subscribe_id,
                     expires,
                     largest_group_object,
                 }))?;
+                self.replay_cached_objects(full_track_name, subscribe_id, start, end)?;
             }
             Command::SubscribeError {
                 subscribe_id,
@@ -1787,11 +3593,15 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                         subscribe_id
                     )));
                 }
+                let mut parameters = Parameters::new();
+                if let Some(authorization_info) = authorization_info {
+                    parameters.insert(ParameterKey::AuthorizationInfo, authorization_info)?;
+                }
                 self.send_control_message(ControlMessage::SubscribeUpdate(SubscribeUpdate {
                     subscribe_id,
                     start_group_object,
                     end_group_object,
-                    authorization_info,
+                    parameters,
                 }))?;
             }
             Command::SubscribeDone {
@@ -1817,13 +3627,12 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                         subscribe_id
                     )));
                 }
-                self.cleanup_incoming_subscription(subscribe_id);
-                self.send_control_message(ControlMessage::SubscribeDone(SubscribeDone {
+                self.send_subscribe_done(
                     subscribe_id,
                     status_code,
                     reason_phrase,
                     final_group_object,
-                }))?;
+                )?;
             }
             Command::PublishObject {
                 track_namespace,
@@ -1836,95 +3645,46 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
             } => {
                 let full_track_name = FullTrackName::new(track_namespace, track_name);
                 let sequence = FullSequence::new(group_id, object_id);
-                let (forwarding_preference, track_alias, delivery_targets) = {
-                    let local_track =
-                        self.local_tracks.get_mut(&full_track_name).ok_or_else(|| {
-                            crate::Error::ErrOther(format!(
-                                "cannot publish unknown track {}:{}",
-                                full_track_name.track_namespace, full_track_name.track_name
-                            ))
-                        })?;
-                    let forwarding_preference = local_track.forwarding_preference();
-                    let track_alias = local_track.track_alias().ok_or_else(|| {
+                let send_order = self
+                    .send_order_policy
+                    .map_or(send_order, |policy| policy.send_order(sequence));
+                self.publish_object(full_track_name, sequence, send_order, status, payload)?;
+            }
+            Command::ForwardObject {
+                track_alias,
+                object_header,
+                payload,
+            } => {
+                if let Some(object_payload_length) = object_header.object_payload_length {
+                    if object_payload_length != payload.len() as u64 {
+                        return Err(crate::Error::ErrOther(format!(
+                            "cannot forward {} of {} payload bytes for object {:?}",
+                            payload.len(),
+                            object_payload_length,
+                            FullSequence::new(object_header.group_id, object_header.object_id)
+                        )));
+                    }
+                }
+                let full_track_name = self
+                    .local_tracks
+                    .values()
+                    .find(|local_track| local_track.track_alias() == Some(track_alias))
+                    .map(|local_track| local_track.full_track_name().clone())
+                    .ok_or_else(|| {
                         crate::Error::ErrOther(format!(
-                            "cannot publish unsubscribed track {}:{}",
-                            full_track_name.track_namespace, full_track_name.track_name
+                            "cannot forward object for unknown track_alias {}",
+                            track_alias
                         ))
                     })?;
-                    let subscribe_ids = local_track
-                        .should_send(sequence)
-                        .into_iter()
-                        .map(|window| {
-                            (
-                                window.subscribe_id(),
-                                local_track.get_send_stream(window.subscribe_id(), sequence),
-                            )
-                        })
-                        .collect::<Vec<_>>();
-                    local_track.sent_sequence(sequence, status);
-                    (forwarding_preference, track_alias, subscribe_ids)
-                };
-                for (subscribe_id, existing_stream_id) in delivery_targets {
-                    let object_header = ObjectHeader {
-                        subscribe_id,
-                        track_alias,
-                        group_id,
-                        object_id,
-                        object_send_order: send_order,
-                        object_status: status,
-                        object_forwarding_preference: forwarding_preference,
-                        object_payload_length: None,
-                    };
-                    match forwarding_preference {
-                        ObjectForwardingPreference::Datagram => {
-                            let mut bytes = BytesMut::new();
-                            let _ = MessageFramer::serialize_object_datagram(
-                                object_header,
-                                payload.clone(),
-                                &mut bytes,
-                            )?;
-                            self.wouts
-                                .push_back(WriteOutput::SendDatagram(bytes.freeze()));
-                        }
-                        ObjectForwardingPreference::Object => {
-                            self.queue_object_stream(
-                                full_track_name.clone(),
-                                subscribe_id,
-                                sequence,
-                                false,
-                                object_header,
-                                payload.clone(),
-                                true,
-                            )?;
-                        }
-                        ObjectForwardingPreference::Track | ObjectForwardingPreference::Group => {
-                            if let Some(stream_id) = existing_stream_id {
-                                let mut bytes = BytesMut::new();
-                                let _ = MessageFramer::serialize_object(
-                                    object_header,
-                                    false,
-                                    payload.clone(),
-                                    &mut bytes,
-                                )?;
-                                self.wouts.push_back(WriteOutput::SendStream {
-                                    stream_id,
-                                    bytes,
-                                    fin: false,
-                                });
-                            } else {
-                                self.queue_object_stream(
-                                    full_track_name.clone(),
-                                    subscribe_id,
-                                    sequence,
-                                    true,
-                                    object_header,
-                                    payload.clone(),
-                                    false,
-                                )?;
-                            }
-                        }
-                    }
-                }
+                // The subscriber's negotiated forwarding preference, subscribe_id
+                // and track_alias replace those of the source header.
+                self.publish_object(
+                    full_track_name,
+                    FullSequence::new(object_header.group_id, object_header.object_id),
+                    object_header.object_send_order,
+                    object_header.object_status,
+                    payload,
+                )?;
             }
             Command::Unsubscribe { subscribe_id } => {
                 if self.state != SessionState::Established {
@@ -1951,12 +3711,51 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                     subscribe_id,
                 }))?;
             }
+            Command::CancelSubscribe { subscribe_id } => {
+                if self.state != SessionState::Established {
+                    return Err(crate::Error::ErrOther(
+                        "cannot cancel SUBSCRIBE before session established".to_string(),
+                    ));
+                }
+                let Some(subscription) = self.pending_outgoing_subscribes.remove(&subscribe_id)
+                else {
+                    return Err(crate::Error::ErrOther(format!(
+                        "cannot cancel SUBSCRIBE for subscribe_id {} that is not pending",
+                        subscribe_id
+                    )));
+                };
+                self.cancelled_outgoing_subscribes
+                    .insert(subscribe_id, subscription);
+                self.send_control_message(ControlMessage::UnSubscribe(UnSubscribe {
+                    subscribe_id,
+                }))?;
+            }
             Command::Unannounce { track_namespace } => {
                 if self.state != SessionState::Established {
                     return Err(crate::Error::ErrOther(
                         "cannot send UNANNOUNCE before session established".to_string(),
                     ));
                 }
+                if self.pending_outgoing_announces.remove(&track_namespace) {
+                    let awaiting_retry = self
+                        .announce_retries
+                        .remove(&track_namespace)
+                        .is_some_and(|retry| retry.retry_at.is_some());
+                    // The peer rejects an UNANNOUNCE that precedes ANNOUNCE_OK, so
+                    // nothing is sent. Unless the ANNOUNCE was already rejected and
+                    // is waiting to be retried, the peer may still answer it;
+                    // remember it so that a late ANNOUNCE_OK or ANNOUNCE_ERROR is
+                    // ignored.
+                    if !awaiting_retry {
+                        self.withdrawn_outgoing_announces
+                            .insert(track_namespace.clone());
+                    }
+                    self.eouts.push_back(EventOut::AnnounceWithdrawn {
+                        track_namespace,
+                        reason_phrase: "announce cancelled before response".to_string(),
+                    });
+                    return Ok(());
+                }
                 if !self.active_outgoing_announces.remove(&track_namespace) {
                     return Err(crate::Error::ErrOther(format!(
                         "cannot send UNANNOUNCE for unknown namespace {}",
@@ -1971,11 +3770,21 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
         Ok(())
     }
 
+    /// Control writes go out first, in order; object writes follow by send
+    /// order, see [`SessionCore::next_data_write`].
     fn poll_write(&mut self) -> Option<Self::Wout> {
-        self.wouts.pop_front()
+        self.release_subscribe_dones();
+        if let Some(wout) = self.wouts.pop_front() {
+            return Some(wout);
+        }
+        self.data_wouts.pop().map(|data_write| data_write.output)
     }
 
     fn handle_event(&mut self, evt: EventIn) -> Result<()> {
+        // The transport going away is still reported after a local close.
+        if self.state == SessionState::Closed && !matches!(evt, EventIn::TransportClosed) {
+            return Ok(());
+        }
         match evt {
             EventIn::TransportConnected => {
                 if self.config.perspective == Perspective::Client
@@ -1987,26 +3796,7 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                 }
             }
             EventIn::TransportClosed => {
-                self.state = SessionState::Closed;
-                self.control_stream_id = None;
-                self.control_parser = None;
-                self.remote_tracks.clear();
-                self.local_tracks.clear();
-                self.local_track_by_subscribe_id.clear();
-                self.used_track_aliases.clear();
-                self.pending_outgoing_announces.clear();
-                self.active_outgoing_announces.clear();
-                self.pending_outgoing_fetches.clear();
-                self.active_outgoing_fetches.clear();
-                self.buffered_outgoing_fetch_objects.clear();
-                self.pending_outgoing_subscribes.clear();
-                self.active_outgoing_subscribes.clear();
-                self.closing_outgoing_subscribes.clear();
-                self.incoming_announces.clear();
-                self.incoming_subscribes.clear();
-                self.data_streams.clear();
-                self.pending_data_stream_opens.clear();
-                self.publisher_streams.clear();
+                self.clear_session_state();
                 self.eouts.push_back(EventOut::SessionTerminated);
             }
             EventIn::StreamOpened {
@@ -2022,7 +3812,15 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                     self.ensure_control_stream(stream_id);
                     self.send_client_setup(stream_id)?;
                 } else if local && bidi {
-                    if let Some(pending_open) = self.pending_data_stream_opens.pop_front() {
+                    let next_open = self
+                        .pending_data_stream_opens
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(index, pending)| (pending.send_order, *index))
+                        .map(|(index, _)| index);
+                    if let Some(pending_open) =
+                        next_open.and_then(|index| self.pending_data_stream_opens.remove(index))
+                    {
                         if pending_open.reusable {
                             if let Some(local_track) =
                                 self.local_tracks.get_mut(&pending_open.full_track_name)
@@ -2042,11 +3840,15 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                                 },
                             );
                         }
-                        self.wouts.push_back(WriteOutput::SendStream {
-                            stream_id,
-                            bytes: pending_open.bytes,
-                            fin: pending_open.fin,
-                        });
+                        self.push_data_write(
+                            pending_open.subscribe_id,
+                            pending_open.send_order,
+                            WriteOutput::SendStream {
+                                stream_id,
+                                bytes: pending_open.bytes,
+                                fin: pending_open.fin,
+                            },
+                        );
                     }
                 }
             }
@@ -2055,7 +3857,22 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                     self.control_stream_id = None;
                     self.control_parser = None;
                 } else {
-                    self.data_streams.remove(&stream_id);
+                    // The stream ended without a FIN, so a held fragment is
+                    // handed out as is; the rest of its object never comes.
+                    if let Some(DataStreamState {
+                        held_fragment: Some((object_header, extension_headers, payload)),
+                        object_received_at: Some((_, received_at)),
+                        ..
+                    }) = self.data_streams.remove(&stream_id)
+                    {
+                        self.deliver_object(
+                            object_header,
+                            extension_headers,
+                            payload,
+                            false,
+                            received_at,
+                        );
+                    }
                     if let Some(binding) = self.publisher_streams.remove(&stream_id) {
                         if let Some(local_track) =
                             self.local_tracks.get_mut(&binding.full_track_name)
@@ -2065,20 +3882,181 @@ impl Protocol<ReadInput, Command, EventIn> for SessionCore {
                     }
                 }
             }
+            EventIn::StopSendingReceived {
+                stream_id,
+                error_code,
+            } => {
+                if self.control_stream_id == Some(stream_id) {
+                    self.close_with_protocol_violation("received STOP_SENDING on control stream");
+                    return Ok(());
+                }
+                self.data_wouts.remove_stream(stream_id);
+                // The subscriber no longer wants the stream, so the subscription
+                // feeding it ends.
+                let subscribe_id = self
+                    .publisher_streams
+                    .get(&stream_id)
+                    .map(|binding| binding.subscribe_id);
+                if let Some(subscribe_id) = subscribe_id.filter(|subscribe_id| {
+                    self.incoming_subscribes
+                        .get(subscribe_id)
+                        .is_some_and(|incoming_subscribe| incoming_subscribe.accepted)
+                }) {
+                    self.end_incoming_subscription(
+                        subscribe_id,
+                        SubscribeDoneCode::Unsubscribed,
+                        &format!("STOP_SENDING with error code {}", error_code),
+                    )?;
+                }
+            }
         }
         Ok(())
     }
 
     fn poll_event(&mut self) -> Option<Self::Eout> {
-        self.eouts.pop_front()
+        let event = self.eouts.pop_front()?;
+        if event.is_object() {
+            self.queued_object_events -= 1;
+        }
+        Some(event)
+    }
+
+    fn handle_timeout(&mut self, now: Instant) -> Result<()> {
+        self.advance_time(now);
+        let mut expired = self
+            .incoming_subscribes
+            .iter()
+            .filter(|(_, incoming_subscribe)| {
+                incoming_subscribe
+                    .expires_at
+                    .is_some_and(|expires_at| expires_at <= now)
+            })
+            .map(|(subscribe_id, _)| *subscribe_id)
+            .collect::<Vec<_>>();
+        expired.sort_unstable();
+        for subscribe_id in expired {
+            self.end_incoming_subscription(
+                subscribe_id,
+                SubscribeDoneCode::Expired,
+                "subscription expired",
+            )?;
+        }
+
+        let mut expired = self
+            .active_outgoing_subscribes
+            .iter()
+            .filter(|(_, subscription)| {
+                subscription
+                    .expires_at
+                    .is_some_and(|expires_at| expires_at <= now)
+            })
+            .map(|(subscribe_id, _)| *subscribe_id)
+            .collect::<Vec<_>>();
+        expired.sort_unstable();
+        for subscribe_id in expired {
+            self.expire_outgoing_subscription(subscribe_id, now);
+        }
+
+        let mut released = self
+            .cancelled_outgoing_subscribes
+            .iter()
+            .filter(|(_, subscription)| {
+                subscription
+                    .expires_at
+                    .is_some_and(|release_at| release_at <= now)
+            })
+            .map(|(subscribe_id, _)| *subscribe_id)
+            .collect::<Vec<_>>();
+        released.sort_unstable();
+        for subscribe_id in released {
+            if let Some(subscription) = self.cancelled_outgoing_subscribes.remove(&subscribe_id) {
+                self.release_outgoing_subscription_state(&subscription);
+            }
+        }
+
+        let mut retries = self
+            .announce_retries
+            .iter()
+            .filter(|(_, retry)| retry.retry_at.is_some_and(|retry_at| retry_at <= now))
+            .map(|(track_namespace, _)| track_namespace.clone())
+            .collect::<Vec<_>>();
+        retries.sort_unstable();
+        for track_namespace in retries {
+            let Some(retry) = self.announce_retries.get_mut(&track_namespace) else {
+                continue;
+            };
+            retry.retry_at = None;
+            let announce = retry.message.clone();
+            self.send_control_message(ControlMessage::Announce(announce))?;
+        }
+
+        let mut track_aliases = self.remote_tracks.keys().copied().collect::<Vec<_>>();
+        track_aliases.sort_unstable();
+        for track_alias in track_aliases {
+            let Some(remote_track) = self.remote_tracks.get_mut(&track_alias) else {
+                continue;
+            };
+            for sequence in remote_track.expire_datagram_fragments(now) {
+                self.eouts.push_back(EventOut::DatagramObjectExpired {
+                    full_track_name: remote_track.full_track_name().clone(),
+                    sequence,
+                });
+            }
+            let fragments = remote_track.release_held_objects(now);
+            let completed_groups = remote_track.take_completed_groups();
+            let full_track_name = remote_track.full_track_name().clone();
+            self.push_ordered_objects(full_track_name, fragments, completed_groups);
+        }
+        Ok(())
+    }
+
+    fn poll_timeout(&mut self) -> Option<Instant> {
+        self.incoming_subscribes
+            .values()
+            .filter_map(|incoming_subscribe| incoming_subscribe.expires_at)
+            .chain(
+                self.active_outgoing_subscribes
+                    .values()
+                    .chain(self.cancelled_outgoing_subscribes.values())
+                    .filter_map(|subscription| subscription.expires_at),
+            )
+            .chain(
+                self.remote_tracks
+                    .values()
+                    .filter_map(RemoteTrack::next_datagram_expiry),
+            )
+            .chain(
+                self.remote_tracks
+                    .values()
+                    .filter_map(RemoteTrack::next_reorder_deadline),
+            )
+            .chain(
+                self.announce_retries
+                    .values()
+                    .filter_map(|retry| retry.retry_at),
+            )
+            .min()
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::message::message_parser::MessageParser;
+    use crate::message::message_parser::{MessageParser, CONTROL_MESSAGE_ON_DATA_STREAM};
     use crate::message::object::{ObjectForwardingPreference, ObjectStatus};
+    use crate::session::remote_track::{DATAGRAM_REASSEMBLY_TIMEOUT, MAX_REORDER_DELAY};
+
+    fn poll_subscribe_done(protocol: &mut SessionCore) -> SubscribeDone {
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected SUBSCRIBE_DONE bytes");
+        };
+        let mut parser = MessageParser::new(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        match parser.poll_event() {
+            Some(MessageParserEvent::ControlMessage(ControlMessage::SubscribeDone(done))) => done,
+            other => panic!("unexpected parser event: {other:?}"),
+        }
+    }
 
     fn client_config(use_web_transport: bool) -> Config {
         Config {
@@ -2087,6 +4065,14 @@ mod test {
             use_web_transport,
             path: "/moq".to_string(),
             deliver_partial_objects: false,
+            additional_versions: vec![],
+            object_event_queue: ObjectEventQueue::Unbounded,
+            reorder_objects: false,
+            max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+            buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+            announce_retry: None,
         }
     }
 
@@ -2097,6 +4083,14 @@ mod test {
             use_web_transport,
             path: "/moq".to_string(),
             deliver_partial_objects: false,
+            additional_versions: vec![],
+            object_event_queue: ObjectEventQueue::Unbounded,
+            reorder_objects: false,
+            max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+            buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+            announce_retry: None,
         }
     }
 
@@ -2193,6 +4187,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -2202,6 +4199,7 @@ mod test {
             stream_id: 11,
             data: client_setup_bytes.freeze(),
             fin: true,
+            now: Instant::now(),
         })?;
 
         let Some(WriteOutput::SendStream {
@@ -2230,6 +4228,7 @@ mod test {
                 path: Some("/moq".to_string())
             })
         );
+        assert_eq!(protocol.negotiated_version(), Some(Version::Draft04));
         Ok(())
     }
 
@@ -2240,6 +4239,9 @@ mod test {
             ControlMessage::ServerSetup(ServerSetup {
                 supported_version: Version::Draft04,
                 role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut server_setup_bytes,
         )?;
@@ -2249,6 +4251,7 @@ mod test {
             stream_id: 13,
             data: server_setup_bytes.freeze(),
             fin: true,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -2262,79 +4265,358 @@ mod test {
     }
 
     #[test]
-    fn client_sends_fetch_after_session_established() -> Result<()> {
-        let mut protocol = SessionCore::new(client_config(false));
-        protocol.handle_read(ReadInput::StreamData {
-            stream_id: 17,
-            data: {
-                let mut bytes = BytesMut::new();
-                let _ = MessageFramer::serialize_control_message(
-                    ControlMessage::ServerSetup(ServerSetup {
-                        supported_version: Version::Draft04,
-                        role: Some(Role::PubSub),
-                    }),
-                    &mut bytes,
-                )?;
-                bytes.freeze()
-            },
-            fin: false,
-        })?;
-        let _ = protocol.poll_event();
+    fn client_accepts_any_offered_version_from_server_setup() -> Result<()> {
+        let mut config = client_config(false);
+        config.additional_versions = vec![Version::Draft03];
+        let mut protocol = SessionCore::new(config);
+        assert_eq!(protocol.negotiated_version(), None);
 
-        protocol.handle_write(Command::Fetch {
-            target: FetchTarget::Standalone(crate::message::fetch::StandaloneFetch {
-                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
-                start: FullSequence::new(4, 1),
-                end: FullSequence::new(7, 9),
-            }),
-            authorization_info: Some("token".to_string()),
+        protocol.handle_event(EventIn::StreamOpened {
+            stream_id: 9,
+            bidi: true,
+            local: true,
         })?;
-
-        let Some(WriteOutput::SendStream {
-            stream_id, bytes, ..
-        }) = protocol.poll_write()
-        else {
-            panic!("expected fetch bytes");
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected setup bytes");
         };
-        assert_eq!(stream_id, 17);
-
         let mut parser = MessageParser::new(false);
         parser.process_data(&mut bytes.as_ref(), false);
         match parser.poll_event() {
-            Some(MessageParserEvent::ControlMessage(ControlMessage::Fetch(fetch))) => {
-                assert_eq!(fetch.request_id, 0);
+            Some(MessageParserEvent::ControlMessage(ControlMessage::ClientSetup(client_setup))) => {
                 assert_eq!(
-                    fetch.target,
-                    FetchTarget::Standalone(crate::message::fetch::StandaloneFetch {
-                        full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
-                        start: FullSequence::new(4, 1),
-                        end: FullSequence::new(7, 9),
-                    })
+                    client_setup.supported_versions,
+                    vec![Version::Draft04, Version::Draft03]
                 );
-                assert_eq!(fetch.authorization_info, Some("token".to_string()));
             }
-            _ => panic!("unexpected parser event"),
+            other => panic!("unexpected parser event: {other:?}"),
         }
-        Ok(())
-    }
 
-    #[test]
-    fn client_receives_fetch_ok_for_active_fetch() -> Result<()> {
-        let mut protocol = SessionCore::new(client_config(false));
+        let mut server_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft03,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut server_setup_bytes,
+        )?;
         protocol.handle_read(ReadInput::StreamData {
-            stream_id: 19,
-            data: {
-                let mut bytes = BytesMut::new();
-                let _ = MessageFramer::serialize_control_message(
-                    ControlMessage::ServerSetup(ServerSetup {
-                        supported_version: Version::Draft04,
-                        role: Some(Role::PubSub),
-                    }),
-                    &mut bytes,
+            stream_id: 9,
+            data: server_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(protocol.poll_write(), None);
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::SessionEstablished {
+                peer_role: Some(Role::PubSub),
+                path: None
+            })
+        );
+        assert_eq!(protocol.negotiated_version(), Some(Version::Draft03));
+        Ok(())
+    }
+
+    #[test]
+    fn client_rejects_server_setup_with_version_not_offered() -> Result<()> {
+        let mut server_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft03,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut server_setup_bytes,
+        )?;
+
+        let mut protocol = SessionCore::new(client_config(true));
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 13,
+            data: server_setup_bytes.freeze(),
+            fin: true,
+            now: Instant::now(),
+        })?;
+
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                ..
+            })
+        ));
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn client_rejects_server_setup_after_session_established() -> Result<()> {
+        let mut server_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut server_setup_bytes,
+        )?;
+        let server_setup_bytes = server_setup_bytes.freeze();
+
+        let mut protocol = SessionCore::new(client_config(false));
+        for _ in 0..2 {
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 13,
+                data: server_setup_bytes.clone(),
+                fin: false,
+                now: Instant::now(),
+            })?;
+        }
+
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SessionEstablished { .. })
+        ));
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received duplicate SERVER_SETUP".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn server_rejects_client_setup_after_session_established() -> Result<()> {
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        let client_setup_bytes = client_setup_bytes.freeze();
+
+        let mut protocol = SessionCore::new(server_config(false));
+        for _ in 0..2 {
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 51,
+                data: client_setup_bytes.clone(),
+                fin: false,
+                now: Instant::now(),
+            })?;
+        }
+
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendStream { .. })
+        ));
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received duplicate CLIENT_SETUP".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn client_frames_control_messages_after_negotiated_setup() -> Result<()> {
+        let mut config = client_config(false);
+        config.length_prefixed_control = true;
+        let mut protocol = SessionCore::new(config);
+        let mut bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: true,
+            }),
+            &mut bytes,
+        )?;
+        // The server's next message rides in the same read, already framed.
+        let _ = MessageFramer::serialize_length_prefixed_control_message(
+            &ControlMessage::MaxRequestId(MaxRequestId {
+                max_request_id: 120,
+            }),
+            &mut bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 49,
+            data: bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        assert!(protocol.length_prefixed_control());
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SessionEstablished { .. })
+        ));
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::MaxRequestIdReceived {
+                max_request_id: 120
+            })
+        );
+        while protocol.poll_write().is_some() {}
+
+        protocol.handle_write(Command::MaxRequestId {
+            max_request_id: 1000,
+        })?;
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected MAX_REQUEST_ID bytes");
+        };
+        let mut parser = MessageParser::new_length_prefixed_control(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        assert_eq!(
+            parser.poll_event(),
+            Some(MessageParserEvent::ControlMessage(
+                ControlMessage::MaxRequestId(MaxRequestId {
+                    max_request_id: 1000,
+                })
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn server_negotiates_partial_object_delivery() -> Result<()> {
+        for (local, peer) in [(true, true), (true, false), (false, true)] {
+            let mut config = server_config(false);
+            config.deliver_partial_objects = local;
+            let mut protocol = SessionCore::new(config);
+            let mut client_setup_bytes = BytesMut::new();
+            let _ = MessageFramer::serialize_control_message(
+                ControlMessage::ClientSetup(ClientSetup {
+                    supported_versions: vec![Version::Draft04],
+                    role: Some(Role::PubSub),
+                    path: Some("/moq".to_string()),
+                    uses_web_transport: false,
+                    partial_object_delivery: peer,
+                    datagram_fragmentation: false,
+                    length_prefixed_control: false,
+                }),
+                &mut client_setup_bytes,
+            )?;
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 51,
+                data: client_setup_bytes.freeze(),
+                fin: false,
+                now: Instant::now(),
+            })?;
+
+            let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+                panic!("expected SERVER_SETUP bytes");
+            };
+            let (server_setup, _) = crate::Deserializer::deserialize(&mut bytes.as_ref())?;
+            let ControlMessage::ServerSetup(server_setup) = server_setup else {
+                panic!("expected SERVER_SETUP");
+            };
+            assert_eq!(server_setup.partial_object_delivery, local);
+            assert_eq!(protocol.partial_object_delivery(), local && peer);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn client_sends_fetch_after_session_established() -> Result<()> {
+        let mut protocol = SessionCore::new(client_config(false));
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 17,
+            data: {
+                let mut bytes = BytesMut::new();
+                let _ = MessageFramer::serialize_control_message(
+                    ControlMessage::ServerSetup(ServerSetup {
+                        supported_version: Version::Draft04,
+                        role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
+                    }),
+                    &mut bytes,
+                )?;
+                bytes.freeze()
+            },
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+
+        protocol.handle_write(Command::Fetch {
+            target: FetchTarget::Standalone(crate::message::fetch::StandaloneFetch {
+                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                start: FullSequence::new(4, 1),
+                end: FullSequence::new(7, 9),
+            }),
+            authorization_info: Some("token".to_string()),
+        })?;
+
+        let Some(WriteOutput::SendStream {
+            stream_id, bytes, ..
+        }) = protocol.poll_write()
+        else {
+            panic!("expected fetch bytes");
+        };
+        assert_eq!(stream_id, 17);
+
+        let mut parser = MessageParser::new(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        match parser.poll_event() {
+            Some(MessageParserEvent::ControlMessage(ControlMessage::Fetch(fetch))) => {
+                assert_eq!(fetch.request_id, 0);
+                assert_eq!(
+                    fetch.target,
+                    FetchTarget::Standalone(crate::message::fetch::StandaloneFetch {
+                        full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                        start: FullSequence::new(4, 1),
+                        end: FullSequence::new(7, 9),
+                    })
+                );
+                assert_eq!(fetch.authorization_info, Some("token".to_string()));
+            }
+            _ => panic!("unexpected parser event"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn client_receives_fetch_ok_for_active_fetch() -> Result<()> {
+        let mut protocol = SessionCore::new(client_config(false));
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 19,
+            data: {
+                let mut bytes = BytesMut::new();
+                let _ = MessageFramer::serialize_control_message(
+                    ControlMessage::ServerSetup(ServerSetup {
+                        supported_version: Version::Draft04,
+                        role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
+                    }),
+                    &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -2361,6 +4643,7 @@ mod test {
             stream_id: 19,
             data: fetch_ok_bytes.freeze(),
             fin: true,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -2385,12 +4668,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -2427,6 +4714,7 @@ mod test {
             stream_id: 22,
             data: object_bytes.freeze(),
             fin: true,
+            now: Instant::now(),
         })?;
         assert_eq!(protocol.poll_event(), None);
 
@@ -2443,6 +4731,7 @@ mod test {
             stream_id: 20,
             data: fetch_ok_bytes.freeze(),
             fin: true,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -2455,21 +4744,18 @@ mod test {
         );
         assert_eq!(
             protocol.poll_event(),
-            Some(EventOut::ObjectReceived {
+            Some(EventOut::CompleteObject {
                 full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
-                fragment: RemoteTrackOnObjectFragment {
-                    object_header,
-                    extension_headers,
-                    payload: Bytes::from_static(b"abc"),
-                    fin: true,
-                },
+                object_header,
+                extension_headers,
+                payload: Bytes::from_static(b"abc"),
             })
         );
         Ok(())
     }
 
     #[test]
-    fn client_buffers_multiple_fetch_objects_until_fetch_ok() -> Result<()> {
+    fn client_keeps_extension_headers_of_fetch_object_split_across_reads() -> Result<()> {
         let mut protocol = SessionCore::new(client_config(false));
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 20,
@@ -2479,12 +4765,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -2498,7 +4788,7 @@ mod test {
         })?;
         let _ = protocol.poll_write();
 
-        let first = ObjectHeader {
+        let object_header = ObjectHeader {
             subscribe_id: 0,
             track_alias: 0,
             group_id: 1,
@@ -2508,35 +4798,29 @@ mod test {
             object_forwarding_preference: ObjectForwardingPreference::Track,
             object_payload_length: Some(3),
         };
-        let second = ObjectHeader {
-            subscribe_id: 0,
-            track_alias: 0,
-            group_id: 1,
-            object_id: 1,
-            object_send_order: 0,
-            object_status: ObjectStatus::Normal,
-            object_forwarding_preference: ObjectForwardingPreference::Track,
-            object_payload_length: Some(3),
-        };
+        let extension_headers = Bytes::from_static(b"ext");
         let mut object_bytes = BytesMut::new();
-        let _ = MessageFramer::serialize_fetch_object_with_previous(
-            first,
-            None,
-            Bytes::new(),
+        let _ = MessageFramer::serialize_fetch_object(
+            object_header,
+            true,
+            extension_headers.clone(),
             Bytes::from_static(b"abc"),
             &mut object_bytes,
         )?;
-        let _ = MessageFramer::serialize_fetch_object_with_previous(
-            second,
-            Some(first),
-            Bytes::new(),
-            Bytes::from_static(b"def"),
-            &mut object_bytes,
-        )?;
+        // The extension headers arrive with the first part of the object
+        // only, and must still be handed out with the whole object.
+        let rest = object_bytes.split_off(object_bytes.len() - 2).freeze();
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 22,
             data: object_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 22,
+            data: rest,
             fin: true,
+            now: Instant::now(),
         })?;
         assert_eq!(protocol.poll_event(), None);
 
@@ -2545,7 +4829,7 @@ mod test {
             ControlMessage::FetchOk(FetchOk {
                 request_id: 0,
                 end_of_track: false,
-                end_location: FullSequence::new(1, 1),
+                end_location: FullSequence::new(1, 0),
             }),
             &mut fetch_ok_bytes,
         )?;
@@ -2553,6 +4837,7 @@ mod test {
             stream_id: 20,
             data: fetch_ok_bytes.freeze(),
             fin: true,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -2560,38 +4845,145 @@ mod test {
             Some(EventOut::FetchAccepted {
                 request_id: 0,
                 end_of_track: false,
-                end_location: FullSequence::new(1, 1),
-            })
-        );
-        assert_eq!(
-            protocol.poll_event(),
-            Some(EventOut::ObjectReceived {
-                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
-                fragment: RemoteTrackOnObjectFragment {
-                    object_header: first,
-                    extension_headers: Bytes::new(),
-                    payload: Bytes::from_static(b"abc"),
-                    fin: true,
-                },
+                end_location: FullSequence::new(1, 0),
             })
         );
         assert_eq!(
             protocol.poll_event(),
-            Some(EventOut::ObjectReceived {
+            Some(EventOut::CompleteObject {
                 full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
-                fragment: RemoteTrackOnObjectFragment {
-                    object_header: second,
-                    extension_headers: Bytes::new(),
-                    payload: Bytes::from_static(b"def"),
-                    fin: true,
-                },
+                object_header,
+                extension_headers,
+                payload: Bytes::from_static(b"abc"),
             })
         );
         Ok(())
     }
 
     #[test]
-    fn server_sends_odd_fetch_request_ids() -> Result<()> {
+    fn client_buffers_multiple_fetch_objects_until_fetch_ok() -> Result<()> {
+        let mut protocol = SessionCore::new(client_config(false));
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 20,
+            data: {
+                let mut bytes = BytesMut::new();
+                let _ = MessageFramer::serialize_control_message(
+                    ControlMessage::ServerSetup(ServerSetup {
+                        supported_version: Version::Draft04,
+                        role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
+                    }),
+                    &mut bytes,
+                )?;
+                bytes.freeze()
+            },
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+
+        protocol.handle_write(Command::Fetch {
+            target: FetchTarget::Standalone(crate::message::fetch::StandaloneFetch {
+                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                start: FullSequence::new(0, 0),
+                end: FullSequence::new(1, 0),
+            }),
+            authorization_info: None,
+        })?;
+        let _ = protocol.poll_write();
+
+        let first = ObjectHeader {
+            subscribe_id: 0,
+            track_alias: 0,
+            group_id: 1,
+            object_id: 0,
+            object_send_order: 0,
+            object_status: ObjectStatus::Normal,
+            object_forwarding_preference: ObjectForwardingPreference::Track,
+            object_payload_length: Some(3),
+        };
+        let second = ObjectHeader {
+            subscribe_id: 0,
+            track_alias: 0,
+            group_id: 1,
+            object_id: 1,
+            object_send_order: 0,
+            object_status: ObjectStatus::Normal,
+            object_forwarding_preference: ObjectForwardingPreference::Track,
+            object_payload_length: Some(3),
+        };
+        let mut object_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_fetch_object_with_previous(
+            first,
+            None,
+            Bytes::new(),
+            Bytes::from_static(b"abc"),
+            &mut object_bytes,
+        )?;
+        let _ = MessageFramer::serialize_fetch_object_with_previous(
+            second,
+            Some(first),
+            Bytes::new(),
+            Bytes::from_static(b"def"),
+            &mut object_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 22,
+            data: object_bytes.freeze(),
+            fin: true,
+            now: Instant::now(),
+        })?;
+        assert_eq!(protocol.poll_event(), None);
+
+        let mut fetch_ok_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::FetchOk(FetchOk {
+                request_id: 0,
+                end_of_track: false,
+                end_location: FullSequence::new(1, 1),
+            }),
+            &mut fetch_ok_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 20,
+            data: fetch_ok_bytes.freeze(),
+            fin: true,
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::FetchAccepted {
+                request_id: 0,
+                end_of_track: false,
+                end_location: FullSequence::new(1, 1),
+            })
+        );
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::CompleteObject {
+                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                object_header: first,
+                extension_headers: Bytes::new(),
+                payload: Bytes::from_static(b"abc"),
+            })
+        );
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::CompleteObject {
+                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                object_header: second,
+                extension_headers: Bytes::new(),
+                payload: Bytes::from_static(b"def"),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn server_sends_odd_fetch_request_ids() -> Result<()> {
         let mut protocol = SessionCore::new(server_config(false));
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 24,
@@ -2603,12 +4995,16 @@ mod test {
                         role: Some(Role::PubSub),
                         path: Some("/moq".to_string()),
                         uses_web_transport: false,
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -2665,6 +5061,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -2672,6 +5071,7 @@ mod test {
             stream_id: 26,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -2693,12 +5093,13 @@ mod test {
             stream_id: 26,
             data: fetch_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
             protocol.poll_write(),
             Some(WriteOutput::Close {
-                code: 1,
+                code: SessionErrorCode::ProtocolViolation,
                 reason: "request ID evenness incorrect".to_string(),
             })
         );
@@ -2715,6 +5116,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -2722,6 +5126,7 @@ mod test {
             stream_id: 28,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -2743,12 +5148,13 @@ mod test {
             stream_id: 28,
             data: fetch_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
             protocol.poll_write(),
             Some(WriteOutput::Close {
-                code: 1,
+                code: SessionErrorCode::ProtocolViolation,
                 reason: "received request with too large ID".to_string(),
             })
         );
@@ -2765,6 +5171,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -2772,6 +5181,7 @@ mod test {
             stream_id: 21,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -2796,6 +5206,7 @@ mod test {
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -2835,6 +5246,7 @@ mod test {
                 bytes.freeze()
             },
             fin: true,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -2855,12 +5267,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -2883,6 +5299,7 @@ mod test {
                 bytes.freeze()
             },
             fin: true,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -2911,12 +5328,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -2938,7 +5359,7 @@ mod test {
         match parser.poll_event() {
             Some(MessageParserEvent::ControlMessage(ControlMessage::Announce(announce))) => {
                 assert_eq!(announce.track_namespace, "live");
-                assert_eq!(announce.authorization_info, None);
+                assert_eq!(announce.authorization_info()?, None);
             }
             _ => panic!("unexpected parser event"),
         }
@@ -2962,12 +5383,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -2988,6 +5413,7 @@ mod test {
             stream_id: 20,
             data: announce_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_event(),
@@ -3007,6 +5433,7 @@ mod test {
             stream_id: 20,
             data: announce_cancel_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_event(),
@@ -3034,12 +5461,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -3060,6 +5491,7 @@ mod test {
             stream_id: 21,
             data: announce_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -3074,6 +5506,7 @@ mod test {
             stream_id: 21,
             data: announce_cancel_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -3093,137 +5526,542 @@ mod test {
             stream_id: 21,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_write(),
             Some(WriteOutput::Close {
-                code: 1,
+                code: SessionErrorCode::ProtocolViolation,
                 reason: "received SUBSCRIBE for canceled track".to_string(),
             })
         );
         Ok(())
     }
 
-    #[test]
-    fn server_receives_announce_accepts_and_receives_unannounce() -> Result<()> {
-        let mut protocol = SessionCore::new(server_config(false));
-        let mut client_setup_bytes = BytesMut::new();
-        let _ = MessageFramer::serialize_control_message(
-            ControlMessage::ClientSetup(ClientSetup {
-                supported_versions: vec![Version::Draft04],
+    fn client_with_announced_camera() -> Result<SessionCore> {
+        let mut protocol = SessionCore::new(client_config(false));
+        protocol.handle_write(Command::RegisterLocalTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            forwarding_preference: ObjectForwardingPreference::Datagram,
+            next_sequence: None,
+        })?;
+        read_control_message(
+            &mut protocol,
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
                 role: Some(Role::PubSub),
-                path: Some("/moq".to_string()),
-                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
-            &mut client_setup_bytes,
         )?;
-        protocol.handle_read(ReadInput::StreamData {
-            stream_id: 22,
-            data: client_setup_bytes.freeze(),
-            fin: false,
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::Announce {
+            track_namespace: "live".to_string(),
+            authorization_info: None,
         })?;
         let _ = protocol.poll_write();
+        read_control_message(
+            &mut protocol,
+            ControlMessage::AnnounceOk(AnnounceOk {
+                track_namespace: "live".to_string(),
+            }),
+        )?;
         let _ = protocol.poll_event();
+        Ok(protocol)
+    }
 
-        let announce = Announce {
-            track_namespace: "live".to_string(),
-            authorization_info: None,
-        };
-        let mut announce_bytes = BytesMut::new();
-        let _ = MessageFramer::serialize_control_message(
-            ControlMessage::Announce(announce.clone()),
-            &mut announce_bytes,
+    fn client_with_pending_announce(policy: AnnounceRetryPolicy) -> Result<SessionCore> {
+        let mut config = client_config(false);
+        config.announce_retry = Some(policy);
+        let mut protocol = SessionCore::new(config);
+        read_control_message(
+            &mut protocol,
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
         )?;
-        protocol.handle_read(ReadInput::StreamData {
-            stream_id: 22,
-            data: announce_bytes.freeze(),
-            fin: false,
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::Announce {
+            track_namespace: "live".to_string(),
+            authorization_info: Some("token".to_string()),
         })?;
-        assert_eq!(
-            protocol.poll_event(),
-            Some(EventOut::AnnounceReceived(announce))
-        );
+        Ok(protocol)
+    }
 
-        protocol.handle_write(Command::AnnounceOk {
+    fn announce_error(error_code: AnnounceErrorCode) -> ControlMessage {
+        ControlMessage::AnnounceError(AnnounceError {
             track_namespace: "live".to_string(),
-        })?;
-        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
-            panic!("expected ANNOUNCE_OK bytes");
+            error_code: error_code as u64,
+            reason_phrase: "try again".to_string(),
+        })
+    }
+
+    #[test]
+    fn announce_is_retried_with_backoff_after_transient_error() -> Result<()> {
+        let backoff = Duration::from_millis(100);
+        let mut protocol = client_with_pending_announce(AnnounceRetryPolicy {
+            max_retries: 2,
+            backoff,
+        })?;
+        let Some(WriteOutput::SendStream { bytes: first, .. }) = protocol.poll_write() else {
+            panic!("expected ANNOUNCE bytes");
         };
-        let mut parser = MessageParser::new(false);
-        parser.process_data(&mut bytes.as_ref(), false);
-        match parser.poll_event() {
-            Some(MessageParserEvent::ControlMessage(ControlMessage::AnnounceOk(announce_ok))) => {
-                assert_eq!(announce_ok.track_namespace, "live");
-            }
-            _ => panic!("unexpected parser event"),
+
+        let start = Instant::now();
+        for retry in 0..2 {
+            let received_at = start + Duration::from_secs(retry as u64);
+            read_control_message_at(
+                &mut protocol,
+                announce_error(AnnounceErrorCode::InternalError),
+                received_at,
+            )?;
+            assert_eq!(protocol.poll_event(), None);
+            assert_eq!(protocol.poll_write(), None);
+            assert_eq!(protocol.pending_announces(), vec!["live".to_string()]);
+            let retry_at = protocol.poll_timeout().expect("announce retry scheduled");
+            assert_eq!(retry_at, received_at + backoff * 2u32.pow(retry));
+
+            protocol.handle_timeout(retry_at - Duration::from_millis(1))?;
+            assert_eq!(protocol.poll_write(), None);
+            protocol.handle_timeout(retry_at)?;
+            let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+                panic!("expected re-sent ANNOUNCE bytes");
+            };
+            assert_eq!(bytes, first);
+            assert_eq!(protocol.poll_timeout(), None);
         }
 
-        let mut unannounce_bytes = BytesMut::new();
-        let _ = MessageFramer::serialize_control_message(
-            ControlMessage::UnAnnounce(UnAnnounce {
+        read_control_message(
+            &mut protocol,
+            ControlMessage::AnnounceOk(AnnounceOk {
                 track_namespace: "live".to_string(),
             }),
-            &mut unannounce_bytes,
         )?;
-        protocol.handle_read(ReadInput::StreamData {
-            stream_id: 22,
-            data: unannounce_bytes.freeze(),
-            fin: false,
-        })?;
         assert_eq!(
             protocol.poll_event(),
-            Some(EventOut::UnannounceReceived {
+            Some(EventOut::AnnounceAccepted {
                 track_namespace: "live".to_string(),
             })
         );
+        assert!(protocol.pending_announces().is_empty());
+        assert!(protocol.announce_retries.is_empty());
         Ok(())
     }
 
     #[test]
-    fn client_sends_track_status_request_after_session_established() -> Result<()> {
-        let mut protocol = SessionCore::new(client_config(false));
-        protocol.handle_read(ReadInput::StreamData {
-            stream_id: 24,
-            data: {
-                let mut bytes = BytesMut::new();
-                let _ = MessageFramer::serialize_control_message(
-                    ControlMessage::ServerSetup(ServerSetup {
-                        supported_version: Version::Draft04,
-                        role: Some(Role::PubSub),
-                    }),
-                    &mut bytes,
-                )?;
-                bytes.freeze()
-            },
-            fin: false,
+    fn cancelling_announce_awaiting_retry_sends_nothing() -> Result<()> {
+        let mut protocol = client_with_pending_announce(AnnounceRetryPolicy {
+            max_retries: 2,
+            backoff: Duration::from_millis(100),
         })?;
-        let _ = protocol.poll_event();
+        let _ = protocol.poll_write();
+        read_control_message(
+            &mut protocol,
+            announce_error(AnnounceErrorCode::InternalError),
+        )?;
+        let retry_at = protocol.poll_timeout().expect("announce retry scheduled");
 
-        protocol.handle_write(Command::TrackStatusRequest {
+        protocol.handle_write(Command::Unannounce {
             track_namespace: "live".to_string(),
-            track_name: "camera".to_string(),
         })?;
+        assert_eq!(protocol.poll_write(), None);
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::AnnounceWithdrawn {
+                track_namespace: "live".to_string(),
+                reason_phrase: "announce cancelled before response".to_string(),
+            })
+        );
+        assert_eq!(protocol.poll_timeout(), None);
+        protocol.handle_timeout(retry_at)?;
+        assert_eq!(protocol.poll_write(), None);
+        assert!(protocol.pending_announces().is_empty());
+        assert!(protocol.withdrawn_outgoing_announces.is_empty());
+        Ok(())
+    }
 
-        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
-            panic!("expected track status request bytes");
+    #[test]
+    fn announce_error_is_reported_when_not_transient_or_out_of_retries() -> Result<()> {
+        let policy = AnnounceRetryPolicy {
+            max_retries: 1,
+            backoff: Duration::from_millis(100),
         };
-        let mut parser = MessageParser::new(false);
-        parser.process_data(&mut bytes.as_ref(), false);
-        match parser.poll_event() {
-            Some(MessageParserEvent::ControlMessage(ControlMessage::TrackStatusRequest(
-                request,
-            ))) => {
-                assert_eq!(request.track_namespace, "live");
-                assert_eq!(request.track_name, "camera");
-            }
-            _ => panic!("unexpected parser event"),
-        }
+        let rejected = |error_code: AnnounceErrorCode| EventOut::AnnounceRejected {
+            track_namespace: "live".to_string(),
+            error_code: error_code as u64,
+            reason_phrase: "try again".to_string(),
+        };
+
+        let mut protocol = client_with_pending_announce(policy)?;
+        let _ = protocol.poll_write();
+        read_control_message(
+            &mut protocol,
+            announce_error(AnnounceErrorCode::AnnounceNotSupported),
+        )?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(rejected(AnnounceErrorCode::AnnounceNotSupported))
+        );
+        assert_eq!(protocol.poll_timeout(), None);
+
+        let mut protocol = client_with_pending_announce(policy)?;
+        let _ = protocol.poll_write();
+        read_control_message(
+            &mut protocol,
+            announce_error(AnnounceErrorCode::InternalError),
+        )?;
+        let retry_at = protocol.poll_timeout().expect("announce retry scheduled");
+        protocol.handle_timeout(retry_at)?;
+        let _ = protocol.poll_write();
+        read_control_message(
+            &mut protocol,
+            announce_error(AnnounceErrorCode::InternalError),
+        )?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(rejected(AnnounceErrorCode::InternalError))
+        );
+        assert_eq!(protocol.poll_timeout(), None);
+        assert!(protocol.pending_announces().is_empty());
         Ok(())
     }
 
     #[test]
-    fn server_receives_track_status_request_and_sends_track_status() -> Result<()> {
+    fn reannounce_after_announce_cancel_accepts_subscribe() -> Result<()> {
+        let mut protocol = client_with_announced_camera()?;
+        read_control_message(
+            &mut protocol,
+            ControlMessage::AnnounceCancel(AnnounceCancel {
+                track_namespace: "live".to_string(),
+            }),
+        )?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::AnnounceCancelled {
+                track_namespace: "live".to_string(),
+            })
+        );
+        assert!(protocol.active_outgoing_announces.is_empty());
+
+        protocol.handle_write(Command::Announce {
+            track_namespace: "live".to_string(),
+            authorization_info: None,
+        })?;
+        let _ = protocol.poll_write();
+        read_control_message(
+            &mut protocol,
+            ControlMessage::AnnounceOk(AnnounceOk {
+                track_namespace: "live".to_string(),
+            }),
+        )?;
+        let _ = protocol.poll_event();
+
+        let subscribe = Subscribe {
+            subscribe_id: 3,
+            track_alias: 7,
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            filter_type: FilterType::LatestObject,
+            authorization_info: None,
+        };
+        read_control_message(&mut protocol, ControlMessage::Subscribe(subscribe.clone()))?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeReceived(subscribe))
+        );
+        assert_eq!(protocol.poll_write(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn announce_cancel_for_unknown_namespace_closes_session() -> Result<()> {
+        let mut protocol = client_with_announced_camera()?;
+        read_control_message(
+            &mut protocol,
+            ControlMessage::AnnounceCancel(AnnounceCancel {
+                track_namespace: "vod".to_string(),
+            }),
+        )?;
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received ANNOUNCE_CANCEL for unknown namespace vod".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn goaway_surfaces_parsed_session_uri() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+        read_control_message(
+            &mut protocol,
+            ControlMessage::GoAway(GoAway {
+                new_session_uri: String::new(),
+            }),
+        )?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::GoAwayReceived {
+                new_session_uri: None,
+            })
+        );
+
+        read_control_message(
+            &mut protocol,
+            ControlMessage::GoAway(GoAway {
+                new_session_uri: "https://relay.example.com/moq".to_string(),
+            }),
+        )?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::GoAwayReceived {
+                new_session_uri: Some(SessionUri {
+                    scheme: "https".to_string(),
+                    authority: "relay.example.com".to_string(),
+                    path: "/moq".to_string(),
+                }),
+            })
+        );
+        assert_eq!(protocol.poll_write(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_goaway_uri_closes_session() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+        read_control_message(
+            &mut protocol,
+            ControlMessage::GoAway(GoAway {
+                new_session_uri: "relay.example.com".to_string(),
+            }),
+        )?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "malformed GOAWAY new_session_uri \"relay.example.com\"".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    // Hands everything |from| writes on the control stream to |to|.
+    fn forward_control_writes(from: &mut SessionCore, to: &mut SessionCore) -> Result<()> {
+        while let Some(wout) = from.poll_write() {
+            match wout {
+                WriteOutput::SendStream { bytes, fin, .. } => {
+                    to.handle_read(ReadInput::StreamData {
+                        stream_id: 0,
+                        data: bytes.freeze(),
+                        fin,
+                        now: Instant::now(),
+                    })?
+                }
+                WriteOutput::OpenBiStream { .. } => from.handle_event(EventIn::StreamOpened {
+                    stream_id: 0,
+                    bidi: true,
+                    local: true,
+                })?,
+                wout => panic!("unexpected write {:?}", wout),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cancelling_pending_announce_keeps_both_sessions_open() -> Result<()> {
+        let mut client = SessionCore::new(client_config(false));
+        let mut server = SessionCore::new(server_config(false));
+        client.handle_event(EventIn::TransportConnected)?;
+        forward_control_writes(&mut client, &mut server)?;
+        forward_control_writes(&mut server, &mut client)?;
+        assert!(matches!(
+            client.poll_event(),
+            Some(EventOut::SessionEstablished { .. })
+        ));
+        assert!(matches!(
+            server.poll_event(),
+            Some(EventOut::SessionEstablished { .. })
+        ));
+
+        client.handle_write(Command::Announce {
+            track_namespace: "live".to_string(),
+            authorization_info: None,
+        })?;
+        client.handle_write(Command::Unannounce {
+            track_namespace: "live".to_string(),
+        })?;
+        assert_eq!(
+            client.poll_event(),
+            Some(EventOut::AnnounceWithdrawn {
+                track_namespace: "live".to_string(),
+                reason_phrase: "announce cancelled before response".to_string(),
+            })
+        );
+        forward_control_writes(&mut client, &mut server)?;
+        assert!(matches!(
+            server.poll_event(),
+            Some(EventOut::AnnounceReceived(_))
+        ));
+
+        // The answer crosses the cancel; the client withdraws the namespace
+        // once it has been accepted.
+        server.handle_write(Command::AnnounceOk {
+            track_namespace: "live".to_string(),
+        })?;
+        forward_control_writes(&mut server, &mut client)?;
+        assert_eq!(client.poll_event(), None);
+        forward_control_writes(&mut client, &mut server)?;
+        assert_eq!(
+            server.poll_event(),
+            Some(EventOut::UnannounceReceived {
+                track_namespace: "live".to_string(),
+            })
+        );
+        assert_eq!(client.state, SessionState::Established);
+        assert_eq!(server.state, SessionState::Established);
+        Ok(())
+    }
+
+    #[test]
+    fn server_receives_announce_accepts_and_receives_unannounce() -> Result<()> {
+        let mut protocol = SessionCore::new(server_config(false));
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 22,
+            data: client_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_write();
+        let _ = protocol.poll_event();
+
+        let announce = Announce {
+            track_namespace: "live".to_string(),
+            parameters: Parameters::new(),
+        };
+        let mut announce_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Announce(announce.clone()),
+            &mut announce_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 22,
+            data: announce_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::AnnounceReceived(announce))
+        );
+
+        protocol.handle_write(Command::AnnounceOk {
+            track_namespace: "live".to_string(),
+        })?;
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected ANNOUNCE_OK bytes");
+        };
+        let mut parser = MessageParser::new(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        match parser.poll_event() {
+            Some(MessageParserEvent::ControlMessage(ControlMessage::AnnounceOk(announce_ok))) => {
+                assert_eq!(announce_ok.track_namespace, "live");
+            }
+            _ => panic!("unexpected parser event"),
+        }
+
+        let mut unannounce_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::UnAnnounce(UnAnnounce {
+                track_namespace: "live".to_string(),
+            }),
+            &mut unannounce_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 22,
+            data: unannounce_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::UnannounceReceived {
+                track_namespace: "live".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn client_sends_track_status_request_after_session_established() -> Result<()> {
+        let mut protocol = SessionCore::new(client_config(false));
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 24,
+            data: {
+                let mut bytes = BytesMut::new();
+                let _ = MessageFramer::serialize_control_message(
+                    ControlMessage::ServerSetup(ServerSetup {
+                        supported_version: Version::Draft04,
+                        role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
+                    }),
+                    &mut bytes,
+                )?;
+                bytes.freeze()
+            },
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+
+        protocol.handle_write(Command::TrackStatusRequest {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+        })?;
+
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected track status request bytes");
+        };
+        let mut parser = MessageParser::new(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        match parser.poll_event() {
+            Some(MessageParserEvent::ControlMessage(ControlMessage::TrackStatusRequest(
+                request,
+            ))) => {
+                assert_eq!(request.track_namespace, "live");
+                assert_eq!(request.track_name, "camera");
+            }
+            _ => panic!("unexpected parser event"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn server_receives_track_status_request_and_sends_track_status() -> Result<()> {
         let mut protocol = SessionCore::new(server_config(false));
         let mut client_setup_bytes = BytesMut::new();
         let _ = MessageFramer::serialize_control_message(
@@ -3232,6 +6070,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -3239,6 +6080,7 @@ mod test {
             stream_id: 26,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -3255,6 +6097,7 @@ mod test {
             stream_id: 26,
             data: request_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_event(),
@@ -3267,7 +6110,7 @@ mod test {
         protocol.handle_write(Command::TrackStatus {
             track_namespace: "live".to_string(),
             track_name: "camera".to_string(),
-            status_code: 0,
+            status_code: TrackStatusCode::InProgress,
             last_group_object: FullSequence::new(7, 2),
         })?;
         let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
@@ -3279,7 +6122,7 @@ mod test {
             Some(MessageParserEvent::ControlMessage(ControlMessage::TrackStatus(status))) => {
                 assert_eq!(status.track_namespace, "live");
                 assert_eq!(status.track_name, "camera");
-                assert_eq!(status.status_code, 0);
+                assert_eq!(status.status_code, TrackStatusCode::InProgress);
                 assert_eq!(status.last_group_object, FullSequence::new(7, 2));
             }
             _ => panic!("unexpected parser event"),
@@ -3298,12 +6141,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -3312,7 +6159,7 @@ mod test {
             ControlMessage::TrackStatus(TrackStatus {
                 track_namespace: "live".to_string(),
                 track_name: "camera".to_string(),
-                status_code: 0,
+                status_code: TrackStatusCode::InProgress,
                 last_group_object: FullSequence::new(4, 9),
             }),
             &mut status_bytes,
@@ -3321,13 +6168,14 @@ mod test {
             stream_id: 28,
             data: status_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_event(),
             Some(EventOut::TrackStatusReceived(TrackStatus {
                 track_namespace: "live".to_string(),
                 track_name: "camera".to_string(),
-                status_code: 0,
+                status_code: TrackStatusCode::InProgress,
                 last_group_object: FullSequence::new(4, 9),
             }))
         );
@@ -3343,11 +6191,16 @@ mod test {
             stream_id: 28,
             data: goaway_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_event(),
             Some(EventOut::GoAwayReceived {
-                new_session_uri: "moq://next".to_string(),
+                new_session_uri: Some(SessionUri {
+                    scheme: "moq".to_string(),
+                    authority: "next".to_string(),
+                    path: String::new(),
+                }),
             })
         );
         Ok(())
@@ -3364,12 +6217,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: true,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -3418,12 +6275,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::Subscribe {
@@ -3447,6 +6308,7 @@ mod test {
             stream_id: 23,
             data: subscribe_ok_bytes.freeze(),
             fin: true,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -3472,6 +6334,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -3479,6 +6344,7 @@ mod test {
             stream_id: 25,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -3500,6 +6366,7 @@ mod test {
             stream_id: 25,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -3510,36 +6377,373 @@ mod test {
     }
 
     #[test]
-    fn server_sends_subscribe_ok_command() -> Result<()> {
+    fn server_rejects_subscribe_from_publisher() -> Result<()> {
         let mut protocol = SessionCore::new(server_config(false));
-        protocol.handle_write(Command::RegisterLocalTrack {
-            track_namespace: "live".to_string(),
-            track_name: "camera".to_string(),
-            forwarding_preference: ObjectForwardingPreference::Datagram,
-            next_sequence: None,
-        })?;
         let mut client_setup_bytes = BytesMut::new();
         let _ = MessageFramer::serialize_control_message(
             ControlMessage::ClientSetup(ClientSetup {
                 supported_versions: vec![Version::Draft04],
-                role: Some(Role::PubSub),
+                role: Some(Role::Publisher),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
-        protocol.handle_read(ReadInput::StreamData {
-            stream_id: 27,
-            data: client_setup_bytes.freeze(),
-            fin: false,
-        })?;
-        let _ = protocol.poll_write();
-        let _ = protocol.poll_event();
-        let mut subscribe_bytes = BytesMut::new();
         let _ = MessageFramer::serialize_control_message(
             ControlMessage::Subscribe(Subscribe {
-                subscribe_id: 3,
-                track_alias: 4,
+                subscribe_id: 7,
+                track_alias: 9,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type: FilterType::LatestGroup,
+                authorization_info: None,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 25,
+            data: client_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendStream { .. })
+        ));
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received SUBSCRIBE from publisher".to_string(),
+            })
+        );
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SessionEstablished { .. })
+        ));
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn server_rejects_subscribe_with_non_increasing_subscribe_id() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        while protocol.poll_write().is_some() {}
+
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 3,
+                track_alias: 10,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type: FilterType::LatestGroup,
+                authorization_info: None,
+            }),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received SUBSCRIBE with non-increasing subscribe_id 3".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn server_rejects_subscribe_at_max_request_id() -> Result<()> {
+        let mut protocol = SessionCore::new(server_config(false));
+        let max_request_id = DEFAULT_INITIAL_MAX_REQUEST_ID;
+        let mut bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut bytes,
+        )?;
+        for subscribe_id in [max_request_id - 1, max_request_id] {
+            let _ = MessageFramer::serialize_control_message(
+                ControlMessage::Subscribe(Subscribe {
+                    subscribe_id,
+                    track_alias: subscribe_id,
+                    track_namespace: "live".to_string(),
+                    track_name: "camera".to_string(),
+                    filter_type: FilterType::LatestGroup,
+                    authorization_info: None,
+                }),
+                &mut bytes,
+            )?;
+        }
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SessionEstablished { .. })
+        ));
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeReceived(Subscribe {
+                subscribe_id: 99,
+                ..
+            }))
+        ));
+
+        let _ = protocol.poll_write(); // SERVER_SETUP
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received SUBSCRIBE with subscribe_id 100 at or above MAX_REQUEST_ID 100"
+                    .to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn server_accepts_subscribe_with_zero_track_alias() -> Result<()> {
+        let mut protocol = SessionCore::new(server_config(false));
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        let subscribe = Subscribe {
+            subscribe_id: 0,
+            track_alias: 0,
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            filter_type: FilterType::LatestGroup,
+            authorization_info: None,
+        };
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(subscribe.clone()),
+            &mut client_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 25,
+            data: client_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        // Draft-04 reserves no track alias value.
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendStream { .. })
+        ));
+        assert_eq!(protocol.poll_write(), None);
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SessionEstablished { .. })
+        ));
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeReceived(subscribe))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn client_rejects_announce_from_subscriber() -> Result<()> {
+        let mut protocol = SessionCore::new(client_config(false));
+        let mut server_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::Subscriber),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut server_setup_bytes,
+        )?;
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Announce(Announce {
+                track_namespace: "live".to_string(),
+                parameters: Parameters::new(),
+            }),
+            &mut server_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 13,
+            data: server_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received ANNOUNCE from subscriber".to_string(),
+            })
+        );
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SessionEstablished { .. })
+        ));
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn server_rejects_subscribe_refused_by_authorizer() -> Result<()> {
+        let mut protocol = SessionCore::new(server_config(false));
+        protocol.set_subscribe_authorizer(|full_track_name, authorization_info| {
+            full_track_name.track_namespace() != "private" || authorization_info == Some("secret")
+        });
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 25,
+            data: client_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_write();
+        let _ = protocol.poll_event();
+
+        let mut subscribe = Subscribe {
+            subscribe_id: 7,
+            track_alias: 9,
+            track_namespace: "private".to_string(),
+            track_name: "camera".to_string(),
+            filter_type: FilterType::LatestGroup,
+            authorization_info: Some("guess".to_string()),
+        };
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(subscribe.clone()),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 25,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(protocol.poll_event(), None);
+        let Some(WriteOutput::SendStream {
+            stream_id, bytes, ..
+        }) = protocol.poll_write()
+        else {
+            panic!("expected SUBSCRIBE_ERROR");
+        };
+        assert_eq!(stream_id, 25);
+        let mut parser = MessageParser::new(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        assert_eq!(
+            parser.poll_event(),
+            Some(MessageParserEvent::ControlMessage(
+                ControlMessage::SubscribeError(SubscribeError {
+                    subscribe_id: 7,
+                    error_code: SubscribeErrorCode::Unauthorized as u64,
+                    reason_phrase: "unauthorized".to_string(),
+                    track_alias: 9,
+                })
+            ))
+        );
+
+        subscribe.subscribe_id = 8;
+        subscribe.authorization_info = Some("secret".to_string());
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(subscribe.clone()),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 25,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeReceived(subscribe))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn server_sends_subscribe_ok_command() -> Result<()> {
+        let mut protocol = SessionCore::new(server_config(false));
+        protocol.handle_write(Command::RegisterLocalTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            forwarding_preference: ObjectForwardingPreference::Datagram,
+            next_sequence: None,
+        })?;
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 27,
+            data: client_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_write();
+        let _ = protocol.poll_event();
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 3,
+                track_alias: 4,
                 track_namespace: "live".to_string(),
                 track_name: "camera".to_string(),
                 filter_type: FilterType::LatestGroup,
@@ -3551,6 +6755,7 @@ mod test {
             stream_id: 27,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -3587,12 +6792,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::Subscribe {
@@ -3616,6 +6825,7 @@ mod test {
             stream_id: 29,
             data: subscribe_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -3636,7 +6846,7 @@ mod test {
                 assert_eq!(update.subscribe_id, 0);
                 assert_eq!(update.start_group_object, FullSequence::new(8, 1));
                 assert_eq!(update.end_group_object, Some(FullSequence::new(10, 5)));
-                assert_eq!(update.authorization_info, None);
+                assert_eq!(update.authorization_info()?, None);
             }
             _ => panic!("unexpected parser event"),
         }
@@ -3659,6 +6869,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -3666,6 +6879,7 @@ mod test {
             stream_id: 31,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -3687,6 +6901,7 @@ mod test {
             stream_id: 31,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -3696,12 +6911,15 @@ mod test {
         })?;
         let _ = protocol.poll_write();
 
-        let update = SubscribeUpdate {
+        let mut update = SubscribeUpdate {
             subscribe_id: 7,
             start_group_object: FullSequence::new(3, 1),
             end_group_object: Some(FullSequence::new(5, 9)),
-            authorization_info: Some("authz".to_string()),
+            parameters: Parameters::new(),
         };
+        update
+            .parameters
+            .insert(ParameterKey::AuthorizationInfo, "authz".to_string())?;
         let mut update_bytes = BytesMut::new();
         let _ = MessageFramer::serialize_control_message(
             ControlMessage::SubscribeUpdate(update.clone()),
@@ -3711,6 +6929,7 @@ mod test {
             stream_id: 31,
             data: update_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -3737,6 +6956,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -3744,6 +6966,7 @@ mod test {
             stream_id: 81,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -3767,6 +6990,7 @@ mod test {
             stream_id: 81,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -3781,7 +7005,7 @@ mod test {
             subscribe_id: 7,
             start_group_object: FullSequence::new(5, 0),
             end_group_object: Some(FullSequence::new(5, 2)),
-            authorization_info: None,
+            parameters: Parameters::new(),
         };
         let _ = MessageFramer::serialize_control_message(
             ControlMessage::SubscribeUpdate(update.clone()),
@@ -3791,6 +7015,7 @@ mod test {
             stream_id: 81,
             data: update_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_event(),
@@ -3845,6 +7070,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -3852,6 +7080,7 @@ mod test {
             stream_id: 83,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -3875,6 +7104,7 @@ mod test {
             stream_id: 83,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -3890,7 +7120,7 @@ mod test {
                 subscribe_id: 7,
                 start_group_object: FullSequence::new(4, 0),
                 end_group_object: Some(FullSequence::new(6, 0)),
-                authorization_info: None,
+                parameters: Parameters::new(),
             }),
             &mut update_bytes,
         )?;
@@ -3898,12 +7128,13 @@ mod test {
             stream_id: 83,
             data: update_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
             protocol.poll_write(),
             Some(WriteOutput::Close {
-                code: 1,
+                code: SessionErrorCode::ProtocolViolation,
                 reason: "invalid SUBSCRIBE_UPDATE window for subscribe_id 7".to_string()
             })
         );
@@ -3921,12 +7152,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::Subscribe {
@@ -3950,6 +7185,7 @@ mod test {
             stream_id: 85,
             data: subscribe_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -3980,6 +7216,7 @@ mod test {
             stream_id: 85,
             data: subscribe_done_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_event(),
@@ -3996,7 +7233,7 @@ mod test {
     }
 
     #[test]
-    fn unsubscribe_then_object_causes_protocol_violation() -> Result<()> {
+    fn unsubscribe_then_late_object_stops_stream() -> Result<()> {
         let mut protocol = SessionCore::new(client_config(false));
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 87,
@@ -4006,12 +7243,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::Subscribe {
@@ -4034,6 +7275,7 @@ mod test {
             stream_id: 87,
             data: subscribe_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -4059,17 +7301,491 @@ mod test {
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 89,
             data: object_bytes.freeze(),
-            fin: true,
+            fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(
             protocol.poll_write(),
-            Some(WriteOutput::Close {
-                code: 1,
-                reason: "received object for unknown subscribe_id 0".to_string()
+            Some(WriteOutput::StopSending {
+                stream_id: 89,
+                error_code: StreamErrorCode::Cancelled,
             })
         );
-        Ok(())
-    }
+        assert_eq!(protocol.poll_event(), None);
+
+        // Whatever is still in flight on the stream is discarded.
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 89,
+            data: Bytes::from_static(b"more"),
+            fin: true,
+            now: Instant::now(),
+        })?;
+        assert_eq!(protocol.poll_write(), None);
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    fn client_with_pending_subscribe() -> Result<SessionCore> {
+        let mut protocol = SessionCore::new(client_config(false));
+        let mut server_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut server_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 91,
+            data: server_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::Subscribe {
+            track_namespace: "foo".to_string(),
+            track_name: "bar".to_string(),
+            filter_type: FilterType::LatestObject,
+            authorization_info: None,
+        })?;
+        let _ = protocol.poll_write();
+        Ok(protocol)
+    }
+
+    #[test]
+    fn subscribe_ok_before_requested_start_warns_range_unavailable() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+        let range = FilterType::whole_groups(5, 7);
+        let start = FilterType::AbsoluteStart(FullSequence::new(1, 0));
+        for filter_type in [range, start] {
+            protocol.handle_write(Command::Subscribe {
+                track_namespace: "foo".to_string(),
+                track_name: "baz".to_string(),
+                filter_type,
+                authorization_info: None,
+            })?;
+            let _ = protocol.poll_write();
+        }
+        let largest_group_object = FullSequence::new(2, 3);
+        let subscribe_ok = |subscribe_id| {
+            ControlMessage::SubscribeOk(SubscribeOk {
+                subscribe_id,
+                expires: 0,
+                largest_group_object: Some(largest_group_object),
+            })
+        };
+
+        read_control_message(&mut protocol, subscribe_ok(1))?;
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeAccepted {
+                subscribe_id: 1,
+                ..
+            })
+        ));
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeRangeUnavailable {
+                subscribe_id: 1,
+                full_track_name: FullTrackName::new("foo".to_string(), "baz".to_string()),
+                filter_type: range,
+                largest_group_object,
+            })
+        );
+
+        // Objects at or after the start are available, so no warning.
+        read_control_message(&mut protocol, subscribe_ok(2))?;
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeAccepted {
+                subscribe_id: 2,
+                ..
+            })
+        ));
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn subscribe_frames_whole_group_range() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+        protocol.handle_write(Command::Subscribe {
+            track_namespace: "foo".to_string(),
+            track_name: "baz".to_string(),
+            filter_type: FilterType::whole_groups(4, 7),
+            authorization_info: None,
+        })?;
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected SUBSCRIBE bytes");
+        };
+        assert_eq!(
+            bytes.as_ref(),
+            &[
+                0x03, // type
+                0x01, // subscribe_id
+                0x01, // track_alias
+                0x03, b'f', b'o', b'o', // track_namespace
+                0x03, b'b', b'a', b'z', // track_name
+                0x04, // filter_type = AbsoluteRange
+                0x04, 0x00, // start_group, start_object
+                0x07, 0x00, // end_group, end_object = whole group
+                0x00, // no parameters
+            ]
+        );
+        Ok(())
+    }
+
+    fn read_control_message(
+        protocol: &mut SessionCore,
+        control_message: ControlMessage,
+    ) -> Result<()> {
+        read_control_message_at(protocol, control_message, Instant::now())
+    }
+
+    fn read_control_message_at(
+        protocol: &mut SessionCore,
+        control_message: ControlMessage,
+        now: Instant,
+    ) -> Result<()> {
+        let mut bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(control_message, &mut bytes)?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 91,
+            data: bytes.freeze(),
+            fin: false,
+            now,
+        })
+    }
+
+    fn assert_unsubscribe_sent(protocol: &mut SessionCore, subscribe_id: u64) {
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected UNSUBSCRIBE bytes");
+        };
+        let mut parser = MessageParser::new(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        assert_eq!(
+            parser.poll_event(),
+            Some(MessageParserEvent::ControlMessage(
+                ControlMessage::UnSubscribe(UnSubscribe { subscribe_id })
+            ))
+        );
+    }
+
+    #[test]
+    fn cancel_pending_subscribe_sends_unsubscribe_and_absorbs_late_error() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+
+        protocol.handle_write(Command::CancelSubscribe { subscribe_id: 0 })?;
+        assert_unsubscribe_sent(&mut protocol, 0);
+        assert!(protocol.pending_outgoing_subscribes.is_empty());
+        assert!(protocol
+            .handle_write(Command::CancelSubscribe { subscribe_id: 0 })
+            .is_err());
+
+        read_control_message(
+            &mut protocol,
+            ControlMessage::SubscribeError(SubscribeError {
+                subscribe_id: 0,
+                error_code: 0,
+                reason_phrase: "gone".to_string(),
+                track_alias: 0,
+            }),
+        )?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.poll_write(), None);
+        assert!(protocol.cancelled_outgoing_subscribes.is_empty());
+        assert!(protocol.remote_track_aliases.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_pending_subscribe_drops_objects_in_flight() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+
+        protocol.handle_write(Command::CancelSubscribe { subscribe_id: 0 })?;
+        assert_unsubscribe_sent(&mut protocol, 0);
+
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(0)?,
+            now: Instant::now(),
+        })?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.poll_write(), None);
+        assert!(protocol.remote_tracks.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_pending_subscribe_tears_down_subscription_when_ok_races() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+
+        protocol.handle_write(Command::CancelSubscribe { subscribe_id: 0 })?;
+        assert_unsubscribe_sent(&mut protocol, 0);
+
+        // SUBSCRIBE_OK was already in flight when UNSUBSCRIBE went out.
+        read_control_message(
+            &mut protocol,
+            ControlMessage::SubscribeOk(SubscribeOk {
+                subscribe_id: 0,
+                expires: 0,
+                largest_group_object: None,
+            }),
+        )?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.poll_write(), None);
+        assert!(protocol.active_outgoing_subscribes.is_empty());
+        assert!(protocol.remote_tracks.is_empty());
+
+        read_control_message(
+            &mut protocol,
+            ControlMessage::SubscribeDone(SubscribeDone {
+                subscribe_id: 0,
+                status_code: SubscribeDoneCode::Unsubscribed.into(),
+                reason_phrase: "unsubscribed".to_string(),
+                final_group_object: None,
+            }),
+        )?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.poll_write(), None);
+        assert!(protocol.cancelled_outgoing_subscribes.is_empty());
+        assert!(protocol.remote_track_aliases.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn client_retries_track_alias_until_limit() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+
+        for retry in 0..DEFAULT_MAX_TRACK_ALIAS_RETRIES as u64 {
+            read_control_message(
+                &mut protocol,
+                ControlMessage::SubscribeError(SubscribeError {
+                    subscribe_id: retry,
+                    error_code: SubscribeErrorCode::RetryTrackAlias as u64,
+                    reason_phrase: "alias in use".to_string(),
+                    track_alias: 10 + retry,
+                }),
+            )?;
+            assert_eq!(protocol.poll_event(), None);
+            let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+                panic!("expected SUBSCRIBE bytes");
+            };
+            let mut parser = MessageParser::new(false);
+            parser.process_data(&mut bytes.as_ref(), false);
+            let Some(MessageParserEvent::ControlMessage(ControlMessage::Subscribe(subscribe))) =
+                parser.poll_event()
+            else {
+                panic!("expected SUBSCRIBE");
+            };
+            assert_eq!(subscribe.subscribe_id, retry + 1);
+            assert_eq!(subscribe.track_alias, 10 + retry);
+            assert_eq!(subscribe.filter_type, FilterType::LatestObject);
+        }
+
+        let last = DEFAULT_MAX_TRACK_ALIAS_RETRIES as u64;
+        read_control_message(
+            &mut protocol,
+            ControlMessage::SubscribeError(SubscribeError {
+                subscribe_id: last,
+                error_code: SubscribeErrorCode::RetryTrackAlias as u64,
+                reason_phrase: "alias in use".to_string(),
+                track_alias: 10 + last,
+            }),
+        )?;
+        assert_eq!(protocol.poll_write(), None);
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeRejected {
+                subscribe_id: 0,
+                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                error_code: SubscribeErrorCode::RetryTrackAlias as u64,
+                reason_phrase: "alias in use".to_string(),
+                track_alias: 10 + last,
+            })
+        );
+        assert!(protocol.pending_outgoing_subscribes.is_empty());
+        assert!(protocol.remote_track_aliases.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn recycled_buffers_frame_control_messages() -> Result<()> {
+        let mut config = client_config(false);
+        config.buffer_pool_size = 2;
+        let mut protocol = SessionCore::new(config);
+        read_control_message(
+            &mut protocol,
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+        )?;
+        let _ = protocol.poll_event();
+
+        for i in 0..100 {
+            let track_namespace = format!("namespace-{}", i);
+            protocol.handle_write(Command::Announce {
+                track_namespace: track_namespace.clone(),
+                authorization_info: None,
+            })?;
+            let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+                panic!("expected ANNOUNCE on the control stream");
+            };
+            let expected = MessageFramer::to_vec(&ControlMessage::Announce(Announce {
+                track_namespace,
+                parameters: Parameters::new(),
+            }))?;
+            assert_eq!(&bytes[..], &expected[..]);
+            // Hand back a buffer the session did not produce too, so the
+            // pool has more candidates than it may keep.
+            protocol.recycle_buffer(bytes);
+            protocol.recycle_buffer(BytesMut::from(&b"stale"[..]));
+            assert!(protocol.buffer_pool.len() <= 2);
+        }
+        assert_eq!(protocol.buffer_pool.len(), 2);
+        assert!(protocol.buffer_pool.iter().all(BytesMut::is_empty));
+        Ok(())
+    }
+
+    #[test]
+    fn subscribe_reuses_preregistered_track_alias() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+        let camera = FullTrackName::new("live".to_string(), "camera".to_string());
+        protocol.preregister_track(camera.clone(), 42)?;
+        assert!(protocol.preregister_track(camera.clone(), 43).is_err());
+        assert!(protocol
+            .preregister_track(
+                FullTrackName::new("live".to_string(), "mic".to_string()),
+                42
+            )
+            .is_err());
+        // "foo":"bar" is already subscribed with alias 0.
+        assert!(protocol
+            .preregister_track(FullTrackName::new("live".to_string(), "mic".to_string()), 0)
+            .is_err());
+
+        for (track_name, track_alias) in [("camera", 42), ("mic", 43)] {
+            protocol.handle_write(Command::Subscribe {
+                track_namespace: "live".to_string(),
+                track_name: track_name.to_string(),
+                filter_type: FilterType::LatestObject,
+                authorization_info: None,
+            })?;
+            let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+                panic!("expected SUBSCRIBE bytes");
+            };
+            let mut parser = MessageParser::new(false);
+            parser.process_data(&mut bytes.as_ref(), false);
+            let Some(MessageParserEvent::ControlMessage(ControlMessage::Subscribe(subscribe))) =
+                parser.poll_event()
+            else {
+                panic!("expected SUBSCRIBE");
+            };
+            assert_eq!(subscribe.track_name, track_name);
+            assert_eq!(subscribe.track_alias, track_alias);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn client_delivers_object_received_before_subscribe_ok() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(0)?,
+            now: Instant::now(),
+        })?;
+        assert_eq!(received_object_id(protocol.poll_event()), Some(0));
+        assert_eq!(
+            protocol.pending_outgoing_subscribes[&0].forwarding_preference,
+            Some(ObjectForwardingPreference::Datagram)
+        );
+
+        read_control_message(
+            &mut protocol,
+            ControlMessage::SubscribeOk(SubscribeOk {
+                subscribe_id: 0,
+                expires: 0,
+                largest_group_object: None,
+            }),
+        )?;
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeAccepted {
+                subscribe_id: 0,
+                ..
+            })
+        ));
+        assert_eq!(protocol.poll_write(), None);
+
+        // The preference seen before SUBSCRIBE_OK binds later objects.
+        let mut object_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_object(
+            ObjectHeader {
+                subscribe_id: 0,
+                track_alias: 0,
+                group_id: 0,
+                object_id: 1,
+                object_send_order: 0,
+                object_status: ObjectStatus::Normal,
+                object_forwarding_preference: ObjectForwardingPreference::Object,
+                object_payload_length: None,
+            },
+            true,
+            Bytes::from_static(b"x"),
+            &mut object_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 93,
+            data: object_bytes.freeze(),
+            fin: true,
+            now: Instant::now(),
+        })?;
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "inconsistent forwarding preference for track_alias 0".to_string()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn client_rejects_subscribe_error_after_object() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(0)?,
+            now: Instant::now(),
+        })?;
+        assert_eq!(received_object_id(protocol.poll_event()), Some(0));
+
+        read_control_message(
+            &mut protocol,
+            ControlMessage::SubscribeError(SubscribeError {
+                subscribe_id: 0,
+                error_code: 0,
+                reason_phrase: "no".to_string(),
+                track_alias: 0,
+            }),
+        )?;
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received SUBSCRIBE_ERROR after object for subscribe_id 0".to_string()
+            })
+        );
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
 
     #[test]
     fn server_sends_subscribe_done_for_accepted_subscription() -> Result<()> {
@@ -4087,6 +7803,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -4094,6 +7813,7 @@ mod test {
             stream_id: 33,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -4114,6 +7834,7 @@ mod test {
             stream_id: 33,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -4158,12 +7879,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::Subscribe {
@@ -4187,6 +7912,7 @@ mod test {
             stream_id: 35,
             data: subscribe_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -4204,6 +7930,7 @@ mod test {
             stream_id: 35,
             data: subscribe_done_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
@@ -4231,12 +7958,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::Subscribe {
@@ -4259,6 +7990,7 @@ mod test {
             stream_id: 41,
             data: subscribe_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -4283,18 +8015,16 @@ mod test {
             stream_id: 43,
             data: object_bytes.freeze(),
             fin: true,
+            now: Instant::now(),
         })?;
 
         assert_eq!(
             protocol.poll_event(),
-            Some(EventOut::ObjectReceived {
+            Some(EventOut::CompleteObject {
                 full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
-                fragment: RemoteTrackOnObjectFragment {
-                    object_header,
-                    extension_headers: Bytes::new(),
-                    payload: Bytes::from_static(b"abc"),
-                    fin: true,
-                },
+                object_header,
+                extension_headers: Bytes::new(),
+                payload: Bytes::from_static(b"abc"),
             })
         );
         Ok(())
@@ -4302,8 +8032,107 @@ mod test {
 
     #[test]
     fn client_buffers_partial_object_until_complete_when_disabled() -> Result<()> {
+        // Partial delivery needs both the local config and the peer's SETUP.
+        for (local, peer) in [(false, true), (true, false)] {
+            let mut config = client_config(false);
+            config.deliver_partial_objects = local;
+            let mut protocol = SessionCore::new(config);
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 45,
+                data: {
+                    let mut bytes = BytesMut::new();
+                    let _ = MessageFramer::serialize_control_message(
+                        ControlMessage::ServerSetup(ServerSetup {
+                            supported_version: Version::Draft04,
+                            role: Some(Role::PubSub),
+                            partial_object_delivery: peer,
+                            datagram_fragmentation: false,
+                            length_prefixed_control: false,
+                        }),
+                        &mut bytes,
+                    )?;
+                    bytes.freeze()
+                },
+                fin: false,
+                now: Instant::now(),
+            })?;
+            let _ = protocol.poll_event();
+            protocol.handle_write(Command::Subscribe {
+                track_namespace: "foo".to_string(),
+                track_name: "bar".to_string(),
+                filter_type: FilterType::LatestObject,
+                authorization_info: None,
+            })?;
+            let _ = protocol.poll_write();
+            let mut subscribe_ok_bytes = BytesMut::new();
+            let _ = MessageFramer::serialize_control_message(
+                ControlMessage::SubscribeOk(SubscribeOk {
+                    subscribe_id: 0,
+                    expires: 30,
+                    largest_group_object: None,
+                }),
+                &mut subscribe_ok_bytes,
+            )?;
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 45,
+                data: subscribe_ok_bytes.freeze(),
+                fin: false,
+                now: Instant::now(),
+            })?;
+            let _ = protocol.poll_event();
+            assert!(!protocol.partial_object_delivery());
+
+            let object_header = ObjectHeader {
+                subscribe_id: 0,
+                track_alias: 0,
+                group_id: 1,
+                object_id: 9,
+                object_send_order: 0,
+                object_status: ObjectStatus::Normal,
+                object_forwarding_preference: ObjectForwardingPreference::Track,
+                object_payload_length: Some(5),
+            };
+            let mut object_bytes = BytesMut::new();
+            let _ = MessageFramer::serialize_object(
+                object_header,
+                true,
+                Bytes::from_static(b"hello"),
+                &mut object_bytes,
+            )?;
+            let first = object_bytes.split_to(10).freeze();
+            let second = object_bytes.freeze();
+
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 47,
+                data: first,
+                fin: false,
+                now: Instant::now(),
+            })?;
+            assert_eq!(protocol.poll_event(), None);
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 47,
+                data: second,
+                fin: true,
+                now: Instant::now(),
+            })?;
+
+            assert_eq!(
+                protocol.poll_event(),
+                Some(EventOut::CompleteObject {
+                    full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                    object_header,
+                    extension_headers: Bytes::new(),
+                    payload: Bytes::from_static(b"hello"),
+                })
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn client_partial_mode_attaches_separate_fin_to_last_fragment() -> Result<()> {
         let mut config = client_config(false);
-        config.deliver_partial_objects = false;
+        config.deliver_partial_objects = true;
         let mut protocol = SessionCore::new(config);
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 45,
@@ -4313,12 +8142,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: true,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::Subscribe {
@@ -4341,6 +8174,7 @@ mod test {
             stream_id: 45,
             data: subscribe_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
@@ -4351,31 +8185,101 @@ mod test {
             object_id: 9,
             object_send_order: 0,
             object_status: ObjectStatus::Normal,
-            object_forwarding_preference: ObjectForwardingPreference::Track,
-            object_payload_length: Some(5),
+            object_forwarding_preference: ObjectForwardingPreference::Object,
+            object_payload_length: None,
         };
         let mut object_bytes = BytesMut::new();
         let _ = MessageFramer::serialize_object(
             object_header,
             true,
-            Bytes::from_static(b"hello"),
+            Bytes::from_static(b"foobar"),
             &mut object_bytes,
         )?;
-        let first = object_bytes.split_to(10).freeze();
-        let second = object_bytes.freeze();
+        let second = object_bytes.split_off(object_bytes.len() - 3).freeze();
+        let first = object_bytes.freeze();
+        let fragment =
+            |payload: &'static [u8], fin: bool, received_at: Instant| EventOut::ObjectReceived {
+                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                fragment: RemoteTrackOnObjectFragment {
+                    object_header,
+                    extension_headers: Bytes::new(),
+                    payload: Bytes::from_static(payload),
+                    fin,
+                    received_at,
+                },
+            };
 
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 47,
             data: first,
             fin: false,
+            now: Instant::now(),
         })?;
         assert_eq!(protocol.poll_event(), None);
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 47,
             data: second,
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let event = protocol.poll_event();
+        let Some(EventOut::ObjectReceived {
+            fragment: RemoteTrackOnObjectFragment { received_at, .. },
+            ..
+        }) = event
+        else {
+            panic!("expected ObjectReceived, got {event:?}");
+        };
+        assert_eq!(event, Some(fragment(b"foo", false, received_at)));
+        assert_eq!(protocol.poll_event(), None);
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 47,
+            data: Bytes::new(),
             fin: true,
+            now: Instant::now(),
+        })?;
+        assert_eq!(
+            protocol.poll_event(),
+            Some(fragment(b"bar", true, received_at))
+        );
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn client_partial_mode_hands_out_held_fragment_when_stream_closes() -> Result<()> {
+        let mut config = client_config(false);
+        config.deliver_partial_objects = true;
+        let mut protocol = client_with_active_subscription(config)?;
+        let object_header = ObjectHeader {
+            subscribe_id: 0,
+            track_alias: 0,
+            group_id: 1,
+            object_id: 9,
+            object_send_order: 0,
+            object_status: ObjectStatus::Normal,
+            object_forwarding_preference: ObjectForwardingPreference::Object,
+            object_payload_length: None,
+        };
+        let mut object_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_object(
+            object_header,
+            true,
+            Bytes::from_static(b"foobar"),
+            &mut object_bytes,
+        )?;
+        let _ = object_bytes.split_off(object_bytes.len() - 3);
+        let now = Instant::now();
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 47,
+            data: object_bytes.freeze(),
+            fin: false,
+            now,
         })?;
+        assert_eq!(protocol.poll_event(), None);
+        assert!(protocol.buffered_bytes() > 0);
 
+        protocol.handle_event(EventIn::StreamClosed { stream_id: 47 })?;
         assert_eq!(
             protocol.poll_event(),
             Some(EventOut::ObjectReceived {
@@ -4383,16 +8287,73 @@ mod test {
                 fragment: RemoteTrackOnObjectFragment {
                     object_header,
                     extension_headers: Bytes::new(),
-                    payload: Bytes::from_static(b"hello"),
-                    fin: true,
+                    payload: Bytes::from_static(b"foo"),
+                    fin: false,
+                    received_at: now,
                 },
             })
         );
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.buffered_bytes(), 0);
         Ok(())
     }
 
     #[test]
-    fn client_receives_object_datagram_for_active_subscription() -> Result<()> {
+    fn object_fragments_carry_first_byte_receive_time() -> Result<()> {
+        let mut config = client_config(false);
+        config.deliver_partial_objects = true;
+        let mut protocol = client_with_active_subscription(config)?;
+        let object_header = |object_id| ObjectHeader {
+            subscribe_id: 0,
+            track_alias: 0,
+            group_id: 0,
+            object_id,
+            object_send_order: 0,
+            object_status: ObjectStatus::Normal,
+            object_forwarding_preference: ObjectForwardingPreference::Group,
+            object_payload_length: Some(4),
+        };
+        let mut first_object = BytesMut::new();
+        let _ = MessageFramer::serialize_object(
+            object_header(0),
+            true,
+            Bytes::from_static(b"abcd"),
+            &mut first_object,
+        )?;
+        let first_object_rest = first_object.split_off(first_object.len() - 2).freeze();
+        let mut second_object = BytesMut::new();
+        let _ = MessageFramer::serialize_object(
+            object_header(1),
+            false,
+            Bytes::from_static(b"efgh"),
+            &mut second_object,
+        )?;
+        let mut receive = |data: Bytes| -> Result<Instant> {
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 47,
+                data,
+                fin: false,
+                now: Instant::now(),
+            })?;
+            match protocol.poll_event() {
+                Some(EventOut::ObjectReceived { fragment, .. }) => Ok(fragment.received_at),
+                other => panic!("unexpected event: {other:?}"),
+            }
+        };
+
+        let before = Instant::now();
+        let first = receive(first_object.freeze())?;
+        // The rest of the first object keeps the time of its first byte.
+        assert_eq!(receive(first_object_rest)?, first);
+        let second = receive(second_object.freeze())?;
+        assert!(before <= first);
+        assert!(first <= second);
+        assert!(second <= Instant::now());
+        Ok(())
+    }
+
+    #[test]
+    fn client_emits_group_completed_on_end_of_group() -> Result<()> {
         let mut protocol = SessionCore::new(client_config(false));
         protocol.handle_read(ReadInput::StreamData {
             stream_id: 49,
@@ -4402,12 +8363,16 @@ mod test {
                     ControlMessage::ServerSetup(ServerSetup {
                         supported_version: Version::Draft04,
                         role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
                     }),
                     &mut bytes,
                 )?;
                 bytes.freeze()
             },
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::Subscribe {
@@ -4430,125 +8395,1432 @@ mod test {
             stream_id: 49,
             data: subscribe_ok_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
 
-        let object_header = ObjectHeader {
+        let mut object_header = ObjectHeader {
             subscribe_id: 0,
             track_alias: 0,
             group_id: 3,
-            object_id: 4,
+            object_id: 0,
             object_send_order: 0,
             object_status: ObjectStatus::Normal,
             object_forwarding_preference: ObjectForwardingPreference::Datagram,
             object_payload_length: None,
         };
+        for (object_id, status, payload) in [
+            (0, ObjectStatus::Normal, &b"a"[..]),
+            (1, ObjectStatus::Normal, &b"b"[..]),
+            (2, ObjectStatus::Normal, &b"c"[..]),
+            (3, ObjectStatus::EndOfGroup, &b""[..]),
+        ] {
+            object_header.object_id = object_id;
+            object_header.object_status = status;
+            let mut datagram = BytesMut::new();
+            let _ = MessageFramer::serialize_object_datagram(
+                object_header,
+                Bytes::copy_from_slice(payload),
+                &mut datagram,
+            )?;
+            protocol.handle_read(ReadInput::Datagram {
+                bytes: datagram.freeze(),
+                now: Instant::now(),
+            })?;
+        }
+
+        for _ in 0..4 {
+            assert!(matches!(
+                protocol.poll_event(),
+                Some(EventOut::CompleteObject { .. })
+            ));
+        }
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::GroupCompleted {
+                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                group_id: 3,
+                object_count: 3,
+            })
+        );
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    fn client_with_active_subscription(config: Config) -> Result<SessionCore> {
+        // The server advertises PARTIAL_OBJECT_DELIVERY and DATAGRAM_FRAGMENTATION
+        // whenever the client does.
+        let partial_object_delivery = config.deliver_partial_objects;
+        let datagram_fragmentation = config.datagram_fragmentation;
+        let mut protocol = SessionCore::new(config);
+        let mut server_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::PubSub),
+                partial_object_delivery,
+                datagram_fragmentation,
+                length_prefixed_control: false,
+            }),
+            &mut server_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 49,
+            data: server_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        protocol.handle_write(Command::Subscribe {
+            track_namespace: "foo".to_string(),
+            track_name: "bar".to_string(),
+            filter_type: FilterType::LatestObject,
+            authorization_info: None,
+        })?;
+        let mut subscribe_ok_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::SubscribeOk(SubscribeOk {
+                subscribe_id: 0,
+                expires: 0,
+                largest_group_object: None,
+            }),
+            &mut subscribe_ok_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 49,
+            data: subscribe_ok_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        while protocol.poll_write().is_some() {}
+        while protocol.poll_event().is_some() {}
+        Ok(protocol)
+    }
+
+    fn object_datagram(object_id: u64) -> Result<Bytes> {
         let mut datagram = BytesMut::new();
         let _ = MessageFramer::serialize_object_datagram(
-            object_header,
-            Bytes::from_static(b"xyz"),
+            ObjectHeader {
+                subscribe_id: 0,
+                track_alias: 0,
+                group_id: 0,
+                object_id,
+                object_send_order: 0,
+                object_status: ObjectStatus::Normal,
+                object_forwarding_preference: ObjectForwardingPreference::Datagram,
+                object_payload_length: None,
+            },
+            Bytes::from_static(b"x"),
             &mut datagram,
         )?;
-        protocol.handle_read(ReadInput::Datagram(datagram.freeze()))?;
+        Ok(datagram.freeze())
+    }
+
+    fn fragmented_object_datagrams(object_id: u64, payload: &'static [u8]) -> Result<Vec<Bytes>> {
+        MessageFramer::serialize_object_datagram_fragments(
+            ObjectHeader {
+                subscribe_id: 0,
+                track_alias: 0,
+                group_id: 0,
+                object_id,
+                object_send_order: 0,
+                object_status: ObjectStatus::Normal,
+                object_forwarding_preference: ObjectForwardingPreference::Datagram,
+                object_payload_length: None,
+            },
+            Bytes::from_static(payload),
+            // Nine bytes of header leave room for five payload bytes each.
+            14,
+        )
+    }
+
+    #[test]
+    fn client_reassembles_fragmented_datagram() -> Result<()> {
+        let mut config = client_config(false);
+        config.datagram_fragmentation = true;
+        let mut protocol = client_with_active_subscription(config)?;
+        assert!(protocol.datagram_fragmentation());
+
+        let datagrams = fragmented_object_datagrams(0, b"0123456789")?;
+        assert_eq!(datagrams.len(), 2);
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: datagrams[1].clone(),
+            now: Instant::now(),
+        })?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.buffered_bytes(), 5);
+
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: datagrams[0].clone(),
+            now: Instant::now(),
+        })?;
+        match protocol.poll_event() {
+            Some(EventOut::CompleteObject {
+                object_header,
+                payload,
+                ..
+            }) => {
+                assert_eq!(object_header.object_id, 0);
+                assert_eq!(payload, Bytes::from_static(b"0123456789"));
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+        assert_eq!(protocol.buffered_bytes(), 0);
+        assert_eq!(protocol.poll_timeout(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_datagram_fragment_expires() -> Result<()> {
+        let mut config = client_config(false);
+        config.datagram_fragmentation = true;
+        let mut protocol = client_with_active_subscription(config)?;
+
+        let datagrams = fragmented_object_datagrams(1, b"0123456789")?;
+        let received_at = Instant::now();
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: datagrams[0].clone(),
+            now: received_at,
+        })?;
+        let deadline = protocol.poll_timeout().expect("reassembly deadline");
+        assert_eq!(deadline, received_at + DATAGRAM_REASSEMBLY_TIMEOUT);
 
+        protocol.handle_timeout(deadline - Duration::from_millis(1))?;
+        assert_eq!(protocol.poll_event(), None);
+        protocol.handle_timeout(deadline)?;
         assert_eq!(
             protocol.poll_event(),
-            Some(EventOut::ObjectReceived {
+            Some(EventOut::DatagramObjectExpired {
                 full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
-                fragment: RemoteTrackOnObjectFragment {
-                    object_header,
-                    extension_headers: Bytes::new(),
-                    payload: Bytes::from_static(b"xyz"),
-                    fin: true,
-                },
+                sequence: FullSequence::new(0, 1),
+            })
+        );
+        assert_eq!(protocol.poll_timeout(), None);
+        assert_eq!(protocol.buffered_bytes(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn datagram_fragment_without_negotiation_closes_session() -> Result<()> {
+        let mut protocol = client_with_active_subscription(client_config(false))?;
+        let datagrams = fragmented_object_datagrams(0, b"0123456789")?;
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: datagrams[0].clone(),
+            now: Instant::now(),
+        })?;
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received datagram fragment without negotiating DATAGRAM_FRAGMENTATION"
+                    .to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn closed_session_ignores_data_from_peer() -> Result<()> {
+        let mut protocol = client_with_active_subscription(client_config(false))?;
+        protocol.handle_write(Command::Close {
+            code: SessionErrorCode::NoError,
+            reason: "done".to_string(),
+        })?;
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::NoError,
+                ..
+            })
+        ));
+
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 3,
+            data: Bytes::from_static(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x61]),
+            fin: true,
+            now: Instant::now(),
+        })?;
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: Bytes::from_static(&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x61]),
+            now: Instant::now(),
+        })?;
+        protocol.handle_event(EventIn::StreamOpened {
+            stream_id: 7,
+            bidi: false,
+            local: false,
+        })?;
+        assert_eq!(protocol.poll_write(), None);
+        assert_eq!(protocol.poll_event(), None);
+
+        protocol.handle_event(EventIn::TransportClosed)?;
+        assert_eq!(protocol.poll_event(), Some(EventOut::SessionTerminated));
+        Ok(())
+    }
+
+    fn received_object_id(event: Option<EventOut>) -> Option<u64> {
+        match event {
+            Some(EventOut::ObjectReceived { fragment, .. }) => {
+                Some(fragment.object_header.object_id)
+            }
+            Some(EventOut::CompleteObject { object_header, .. }) => Some(object_header.object_id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn client_reorders_objects_within_group() -> Result<()> {
+        let mut config = client_config(false);
+        config.reorder_objects = true;
+        let mut protocol = client_with_active_subscription(config)?;
+
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(0)?,
+            now: Instant::now(),
+        })?;
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(2)?,
+            now: Instant::now(),
+        })?;
+        assert_eq!(received_object_id(protocol.poll_event()), Some(0));
+        assert_eq!(protocol.poll_event(), None);
+
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(1)?,
+            now: Instant::now(),
+        })?;
+        assert_eq!(received_object_id(protocol.poll_event()), Some(1));
+        assert_eq!(received_object_id(protocol.poll_event()), Some(2));
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn client_releases_held_objects_after_reorder_delay() -> Result<()> {
+        let mut config = client_config(false);
+        config.reorder_objects = true;
+        let mut protocol = client_with_active_subscription(config)?;
+        let received_at = Instant::now();
+
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(1)?,
+            now: received_at,
+        })?;
+        assert_eq!(protocol.poll_event(), None);
+        let deadline = received_at + MAX_REORDER_DELAY;
+        assert!(protocol
+            .poll_timeout()
+            .is_some_and(|timeout| timeout <= deadline));
+
+        protocol.handle_timeout(deadline)?;
+        assert_eq!(received_object_id(protocol.poll_event()), Some(1));
+        assert_eq!(protocol.poll_event(), None);
+
+        // Object 0 was given up on and is handed out as it arrives.
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(0)?,
+            now: deadline,
+        })?;
+        assert_eq!(received_object_id(protocol.poll_event()), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_error_is_reported_before_close() -> Result<()> {
+        let mut protocol = client_with_active_subscription(client_config(false))?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 3,
+            // OBJECT_STREAM with object status 0x06
+            data: Bytes::from_static(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06]),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::ParseError {
+                stream_id: Some(3),
+                code: ErrorCode::ProtocolViolation,
+                reason: "Invalid object status".to_string(),
+            })
+        );
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "Invalid object status".to_string(),
             })
         );
         Ok(())
     }
 
     #[test]
-    fn server_accepts_subscribe_for_registered_track_and_publishes_datagram() -> Result<()> {
-        let mut protocol = SessionCore::new(server_config(false));
-        protocol.handle_write(Command::RegisterLocalTrack {
-            track_namespace: "live".to_string(),
-            track_name: "camera".to_string(),
-            forwarding_preference: ObjectForwardingPreference::Datagram,
-            next_sequence: None,
-        })?;
-
-        let mut client_setup_bytes = BytesMut::new();
-        let _ = MessageFramer::serialize_control_message(
-            ControlMessage::ClientSetup(ClientSetup {
-                supported_versions: vec![Version::Draft04],
-                role: Some(Role::PubSub),
-                path: Some("/moq".to_string()),
-                uses_web_transport: false,
-            }),
-            &mut client_setup_bytes,
+    fn buffered_bytes_sums_session_buffers() -> Result<()> {
+        let mut protocol = client_with_active_subscription(client_config(false))?;
+        while protocol.poll_write().is_some() {}
+        while protocol.poll_event().is_some() {}
+        assert_eq!(protocol.buffered_bytes(), 0);
+
+        // A queued object event carrying a one-byte payload.
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(0)?,
+            now: Instant::now(),
+        })?;
+        assert_eq!(protocol.buffered_bytes(), 1);
+
+        // Two payload bytes of a five-byte object, held until it completes.
+        let mut partial = BytesMut::new();
+        let _ = MessageFramer::serialize_object_header(
+            ObjectHeader {
+                subscribe_id: 0,
+                track_alias: 0,
+                group_id: 1,
+                object_id: 0,
+                object_send_order: 0,
+                object_status: ObjectStatus::Normal,
+                object_forwarding_preference: ObjectForwardingPreference::Group,
+                object_payload_length: Some(5),
+            },
+            true,
+            &mut partial,
+        )?;
+        partial.extend_from_slice(b"ab");
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 3,
+            data: partial.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        assert_eq!(protocol.buffered_bytes(), 3);
+
+        // A stream header cut short in the parser.
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 7,
+            data: Bytes::from_static(&[crate::message::MessageType::StreamHeaderGroup as u8]),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        assert_eq!(protocol.buffered_bytes(), 4);
+
+        // A queued control write.
+        protocol.handle_write(Command::Unsubscribe { subscribe_id: 0 })?;
+        let unsubscribe = MessageFramer::to_vec(&ControlMessage::UnSubscribe(UnSubscribe {
+            subscribe_id: 0,
+        }))?;
+        assert_eq!(protocol.buffered_bytes(), 4 + unsubscribe.len());
+
+        while protocol.poll_write().is_some() {}
+        while protocol.poll_event().is_some() {}
+        assert_eq!(protocol.buffered_bytes(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn object_on_control_stream_is_rejected_before_object_parsing() -> Result<()> {
+        let mut protocol = client_with_active_subscription(client_config(false))?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 49,
+            // OBJECT_STREAM header; object status 0x06 would fail object parsing.
+            data: Bytes::from_static(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06]),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::ParseError {
+                stream_id: Some(49),
+                code: ErrorCode::ProtocolViolation,
+                reason: OBJECT_ON_CONTROL_STREAM.to_string(),
+            })
+        );
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: OBJECT_ON_CONTROL_STREAM.to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn control_message_on_data_stream_is_rejected_before_control_parsing() -> Result<()> {
+        let mut protocol = client_with_active_subscription(client_config(false))?;
+        let mut unsubscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::UnSubscribe(UnSubscribe { subscribe_id: 0 }),
+            &mut unsubscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 3,
+            data: unsubscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::ParseError {
+                stream_id: Some(3),
+                code: ErrorCode::ProtocolViolation,
+                reason: CONTROL_MESSAGE_ON_DATA_STREAM.to_string(),
+            })
+        );
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: CONTROL_MESSAGE_ON_DATA_STREAM.to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn client_drops_oldest_object_event_when_queue_is_full() -> Result<()> {
+        let mut config = client_config(false);
+        config.object_event_queue = ObjectEventQueue::DropOldest(2);
+        let mut protocol = client_with_active_subscription(config)?;
+
+        for object_id in 0..4 {
+            protocol.handle_read(ReadInput::Datagram {
+                bytes: object_datagram(object_id)?,
+                now: Instant::now(),
+            })?;
+        }
+
+        assert_eq!(received_object_id(protocol.poll_event()), Some(2));
+        assert_eq!(received_object_id(protocol.poll_event()), Some(3));
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn client_refuses_object_data_while_event_queue_is_full() -> Result<()> {
+        let mut config = client_config(false);
+        config.object_event_queue = ObjectEventQueue::Backpressure(2);
+        let mut protocol = client_with_active_subscription(config)?;
+
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(0)?,
+            now: Instant::now(),
+        })?;
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(1)?,
+            now: Instant::now(),
+        })?;
+        assert_eq!(
+            protocol.handle_read(ReadInput::Datagram {
+                bytes: object_datagram(2)?,
+                now: Instant::now(),
+            }),
+            Err(crate::Error::ErrEventQueueFull)
+        );
+        assert_eq!(
+            protocol.handle_read(ReadInput::StreamData {
+                stream_id: 3,
+                data: Bytes::from_static(&[0x00]),
+                fin: false,
+                now: Instant::now(),
+            }),
+            Err(crate::Error::ErrEventQueueFull)
+        );
+
+        assert_eq!(received_object_id(protocol.poll_event()), Some(0));
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(2)?,
+            now: Instant::now(),
+        })?;
+        assert_eq!(received_object_id(protocol.poll_event()), Some(1));
+        assert_eq!(received_object_id(protocol.poll_event()), Some(2));
+        assert_eq!(protocol.poll_event(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn client_receives_object_datagram_for_active_subscription() -> Result<()> {
+        let mut protocol = SessionCore::new(client_config(false));
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 49,
+            data: {
+                let mut bytes = BytesMut::new();
+                let _ = MessageFramer::serialize_control_message(
+                    ControlMessage::ServerSetup(ServerSetup {
+                        supported_version: Version::Draft04,
+                        role: Some(Role::PubSub),
+                        partial_object_delivery: false,
+                        datagram_fragmentation: false,
+                        length_prefixed_control: false,
+                    }),
+                    &mut bytes,
+                )?;
+                bytes.freeze()
+            },
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::Subscribe {
+            track_namespace: "foo".to_string(),
+            track_name: "bar".to_string(),
+            filter_type: FilterType::LatestObject,
+            authorization_info: None,
+        })?;
+        let _ = protocol.poll_write();
+        let mut subscribe_ok_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::SubscribeOk(SubscribeOk {
+                subscribe_id: 0,
+                expires: 30,
+                largest_group_object: None,
+            }),
+            &mut subscribe_ok_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 49,
+            data: subscribe_ok_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+
+        let object_header = ObjectHeader {
+            subscribe_id: 0,
+            track_alias: 0,
+            group_id: 3,
+            object_id: 4,
+            object_send_order: 0,
+            object_status: ObjectStatus::Normal,
+            object_forwarding_preference: ObjectForwardingPreference::Datagram,
+            object_payload_length: None,
+        };
+        let mut datagram = BytesMut::new();
+        let _ = MessageFramer::serialize_object_datagram(
+            object_header,
+            Bytes::from_static(b"xyz"),
+            &mut datagram,
+        )?;
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: datagram.freeze(),
+            now: Instant::now(),
+        })?;
+
+        assert_eq!(
+            protocol.poll_event(),
+            Some(EventOut::CompleteObject {
+                full_track_name: FullTrackName::new("foo".to_string(), "bar".to_string()),
+                object_header,
+                extension_headers: Bytes::new(),
+                payload: Bytes::from_static(b"xyz"),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn server_accepts_subscribe_for_registered_track_and_publishes_datagram() -> Result<()> {
+        let mut protocol = SessionCore::new(server_config(false));
+        protocol.handle_write(Command::RegisterLocalTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            forwarding_preference: ObjectForwardingPreference::Datagram,
+            next_sequence: None,
+        })?;
+
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: client_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_write();
+        let _ = protocol.poll_event();
+
+        let subscribe = Subscribe {
+            subscribe_id: 7,
+            track_alias: 9,
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            filter_type: FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+            authorization_info: None,
+        };
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(subscribe),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+
+        protocol.handle_write(Command::SubscribeOk {
+            subscribe_id: 7,
+            expires: 60,
+            largest_group_object: None,
+        })?;
+        let _ = protocol.poll_write();
+
+        protocol.handle_write(Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id: 0,
+            send_order: 0,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from_static(b"frame"),
+        })?;
+
+        let Some(WriteOutput::SendDatagram(bytes)) = protocol.poll_write() else {
+            panic!("expected datagram output");
+        };
+        let (object_header, payload) = MessageParser::process_datagram(&mut bytes.as_ref())?;
+        assert_eq!(
+            object_header,
+            ObjectHeader {
+                subscribe_id: 7,
+                track_alias: 9,
+                group_id: 0,
+                object_id: 0,
+                object_send_order: 0,
+                object_status: ObjectStatus::Normal,
+                object_forwarding_preference: ObjectForwardingPreference::Datagram,
+                object_payload_length: None,
+            }
+        );
+        assert_eq!(payload, Bytes::from_static(b"frame"));
+        Ok(())
+    }
+
+    #[test]
+    fn server_caches_published_objects_for_backfill() -> Result<()> {
+        let mut protocol = SessionCore::new(server_config(false));
+        protocol.set_object_cache_size(2);
+        protocol.handle_write(Command::RegisterLocalTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            forwarding_preference: ObjectForwardingPreference::Datagram,
+            next_sequence: None,
+        })?;
+
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: client_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_write();
+        let _ = protocol.poll_event();
+
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 7,
+                track_alias: 9,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type: FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+                authorization_info: None,
+            }),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::SubscribeOk {
+            subscribe_id: 7,
+            expires: 60,
+            largest_group_object: None,
+        })?;
+        let _ = protocol.poll_write();
+
+        for object_id in 0..3 {
+            protocol.handle_write(Command::PublishObject {
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                group_id: 0,
+                object_id,
+                send_order: 0,
+                status: ObjectStatus::Normal,
+                payload: Bytes::from(vec![object_id as u8]),
+            })?;
+        }
+
+        let cached = protocol.cached_objects(
+            &FullTrackName::new("live".to_string(), "camera".to_string()),
+            FullSequence::new(0, 0),
+            None,
+        );
+        assert_eq!(
+            cached
+                .iter()
+                .map(|object| (object.sequence, object.payload.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (FullSequence::new(0, 1), Bytes::from_static(&[1])),
+                (FullSequence::new(0, 2), Bytes::from_static(&[2])),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn server_replays_cached_objects_to_new_subscription() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        protocol.set_object_cache_size(2);
+        for object_id in 0..3 {
+            protocol.handle_write(Command::PublishObject {
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                group_id: 0,
+                object_id,
+                send_order: 0,
+                status: ObjectStatus::Normal,
+                payload: Bytes::from(vec![object_id as u8]),
+            })?;
+        }
+        while protocol.poll_write().is_some() {}
+
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 8,
+                track_alias: 9,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type: FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+                authorization_info: None,
+            }),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::SubscribeOk {
+            subscribe_id: 8,
+            expires: 0,
+            largest_group_object: None,
+        })?;
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendStream { stream_id: 51, .. })
+        ));
+
+        // Object 0 fell out of the cache; the rest are replayed in order.
+        for object_id in 1..3 {
+            let Some(WriteOutput::SendDatagram(bytes)) = protocol.poll_write() else {
+                panic!("expected replayed datagram");
+            };
+            let (object_header, payload) = MessageParser::process_datagram(&mut bytes.as_ref())?;
+            assert_eq!(object_header.subscribe_id, 8);
+            assert_eq!(object_header.object_id, object_id);
+            assert_eq!(payload, Bytes::from(vec![object_id as u8]));
+        }
+        assert_eq!(protocol.poll_write(), None);
+        Ok(())
+    }
+
+    fn server_with_accepted_subscribe(
+        filter_type: FilterType,
+        expires: u64,
+    ) -> Result<SessionCore> {
+        server_with_accepted_subscribe_to(
+            ObjectForwardingPreference::Datagram,
+            filter_type,
+            expires,
+        )
+    }
+
+    fn server_with_accepted_subscribe_to(
+        forwarding_preference: ObjectForwardingPreference,
+        filter_type: FilterType,
+        expires: u64,
+    ) -> Result<SessionCore> {
+        let mut protocol = SessionCore::new(server_config(false));
+        protocol.handle_write(Command::RegisterLocalTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            forwarding_preference,
+            next_sequence: None,
+        })?;
+
+        let mut client_setup_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![Version::Draft04],
+                role: Some(Role::PubSub),
+                path: Some("/moq".to_string()),
+                uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut client_setup_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: client_setup_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_write();
+        let _ = protocol.poll_event();
+
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 7,
+                track_alias: 9,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type,
+                authorization_info: None,
+            }),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::SubscribeOk {
+            subscribe_id: 7,
+            expires,
+            largest_group_object: None,
+        })?;
+        let _ = protocol.poll_write();
+        Ok(protocol)
+    }
+
+    #[test]
+    fn unpublish_track_ends_every_subscription() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 8,
+                track_alias: 9,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type: FilterType::LatestObject,
+                authorization_info: None,
+            }),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::SubscribeOk {
+            subscribe_id: 8,
+            expires: 0,
+            largest_group_object: None,
+        })?;
+        let _ = protocol.poll_write();
+
+        protocol.handle_write(Command::UnpublishTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+        })?;
+
+        // Each subscription's EndOfTrack goes out before its SUBSCRIBE_DONE.
+        let mut subscribe_ids = vec![];
+        for _ in 0..2 {
+            let Some(WriteOutput::SendDatagram(bytes)) = protocol.poll_write() else {
+                panic!("expected EndOfTrack datagram");
+            };
+            let (object_header, payload) = MessageParser::process_datagram(&mut bytes.as_ref())?;
+            assert_eq!(object_header.track_alias, 9);
+            assert_eq!(object_header.object_status, ObjectStatus::EndOfTrack);
+            assert!(payload.is_empty());
+            let done = poll_subscribe_done(&mut protocol);
+            assert_eq!(done.subscribe_id, object_header.subscribe_id);
+            assert_eq!(done.status_code, SubscribeDoneCode::TrackEnded as u64);
+            subscribe_ids.push(done.subscribe_id);
+        }
+        subscribe_ids.sort();
+        assert_eq!(subscribe_ids, vec![7, 8]);
+        assert_eq!(protocol.poll_write(), None);
+
+        assert!(protocol.local_tracks.is_empty());
+        assert!(protocol.local_track_by_subscribe_id.is_empty());
+        assert!(protocol
+            .handle_write(Command::UnpublishTrack {
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+            })
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn relay_forwards_object_to_overlapping_subscribers() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        let mut subscribe_bytes = BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::Subscribe(Subscribe {
+                subscribe_id: 8,
+                track_alias: 9,
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                filter_type: FilterType::AbsoluteRange(
+                    FullSequence::new(1, 0),
+                    FullSequence::new(2, 0),
+                ),
+                authorization_info: None,
+            }),
+            &mut subscribe_bytes,
+        )?;
+        protocol.handle_read(ReadInput::StreamData {
+            stream_id: 51,
+            data: subscribe_bytes.freeze(),
+            fin: false,
+            now: Instant::now(),
+        })?;
+        let _ = protocol.poll_event();
+        protocol.handle_write(Command::SubscribeOk {
+            subscribe_id: 8,
+            expires: 0,
+            largest_group_object: None,
+        })?;
+        let _ = protocol.poll_write();
+
+        // Received upstream on a Track stream under a different subscription.
+        let source = ObjectHeader {
+            subscribe_id: 3,
+            track_alias: 4,
+            group_id: 1,
+            object_id: 5,
+            object_send_order: 2,
+            object_status: ObjectStatus::Normal,
+            object_forwarding_preference: ObjectForwardingPreference::Track,
+            object_payload_length: Some(5),
+        };
+        protocol.handle_write(Command::ForwardObject {
+            track_alias: 9,
+            object_header: source,
+            payload: Bytes::from_static(b"frame"),
+        })?;
+
+        let mut subscribe_ids = vec![];
+        while let Some(output) = protocol.poll_write() {
+            let WriteOutput::SendDatagram(bytes) = output else {
+                panic!("expected datagram output, got {:?}", output);
+            };
+            let (object_header, payload) = MessageParser::process_datagram(&mut bytes.as_ref())?;
+            assert_eq!(
+                object_header,
+                ObjectHeader {
+                    subscribe_id: object_header.subscribe_id,
+                    track_alias: 9,
+                    object_forwarding_preference: ObjectForwardingPreference::Datagram,
+                    object_payload_length: None,
+                    ..source
+                }
+            );
+            assert_eq!(payload, Bytes::from_static(b"frame"));
+            subscribe_ids.push(object_header.subscribe_id);
+        }
+        subscribe_ids.sort();
+        assert_eq!(subscribe_ids, vec![7, 8]);
+
+        // Outside the second window only the first subscriber receives it.
+        protocol.handle_write(Command::ForwardObject {
+            track_alias: 9,
+            object_header: ObjectHeader {
+                group_id: 3,
+                object_id: 0,
+                ..source
+            },
+            payload: Bytes::from_static(b"frame"),
+        })?;
+        let Some(WriteOutput::SendDatagram(bytes)) = protocol.poll_write() else {
+            panic!("expected datagram output");
+        };
+        let (object_header, _) = MessageParser::process_datagram(&mut bytes.as_ref())?;
+        assert_eq!(object_header.subscribe_id, 7);
+        assert!(protocol.poll_write().is_none());
+
+        assert!(protocol
+            .handle_write(Command::ForwardObject {
+                track_alias: 4,
+                object_header: source,
+                payload: Bytes::from_static(b"frame"),
+            })
+            .is_err());
+        assert!(protocol
+            .handle_write(Command::ForwardObject {
+                track_alias: 9,
+                object_header: source,
+                payload: Bytes::from_static(b"fra"),
+            })
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn control_write_flushes_before_queued_object() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        protocol.handle_write(Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id: 0,
+            send_order: 1000,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from_static(b"frame"),
+        })?;
+        protocol.handle_write(Command::Announce {
+            track_namespace: "live".to_string(),
+            authorization_info: None,
+        })?;
+
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendStream { stream_id: 51, .. })
+        ));
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendDatagram(_))
+        ));
+        assert_eq!(protocol.poll_write(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn queued_objects_flush_by_send_order() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        for (object_id, send_order) in [(0, 5), (1, 1), (2, 5)] {
+            protocol.handle_write(Command::PublishObject {
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                group_id: 0,
+                object_id,
+                send_order,
+                status: ObjectStatus::Normal,
+                payload: Bytes::from_static(b"frame"),
+            })?;
+        }
+
+        let mut sent = vec![];
+        while let Some(WriteOutput::SendDatagram(bytes)) = protocol.poll_write() {
+            let (object_header, _) = MessageParser::process_datagram(&mut bytes.as_ref())?;
+            sent.push(object_header.object_id);
+        }
+        assert_eq!(sent, vec![1, 0, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn send_order_policy_replaces_published_send_order() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        protocol.set_send_order_policy(Some(SendOrderPolicy::GroupThenObject));
+        for (group_id, object_id, send_order) in [(1, 0, 0), (0, 1, 9), (0, 0, 9)] {
+            protocol.handle_write(Command::PublishObject {
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                group_id,
+                object_id,
+                send_order,
+                status: ObjectStatus::Normal,
+                payload: Bytes::from_static(b"frame"),
+            })?;
+        }
+
+        let mut sent = vec![];
+        while let Some(WriteOutput::SendDatagram(bytes)) = protocol.poll_write() {
+            let (object_header, _) = MessageParser::process_datagram(&mut bytes.as_ref())?;
+            sent.push((
+                object_header.group_id,
+                object_header.object_id,
+                object_header.object_send_order,
+            ));
+        }
+        assert_eq!(sent, vec![(0, 0, 0), (0, 1, 1), (1, 0, 1 << 32)]);
+        Ok(())
+    }
+
+    #[test]
+    fn publisher_ends_exhausted_subscription() -> Result<()> {
+        let mut protocol = server_with_accepted_subscribe(
+            FilterType::AbsoluteRange(FullSequence::new(0, 0), FullSequence::new(0, 1)),
+            0,
+        )?;
+        for object_id in 0..2 {
+            protocol.handle_write(Command::PublishObject {
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                group_id: 0,
+                object_id,
+                send_order: 0,
+                status: ObjectStatus::Normal,
+                payload: Bytes::from_static(b"frame"),
+            })?;
+            assert!(matches!(
+                protocol.poll_write(),
+                Some(WriteOutput::SendDatagram(_))
+            ));
+        }
+
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.subscribe_id, 7);
+        assert_eq!(
+            done.status_code,
+            SubscribeDoneCode::SubscriptionEnded as u64
+        );
+        assert_eq!(done.final_group_object, Some(FullSequence::new(0, 1)));
+        assert!(!protocol.incoming_subscribes.contains_key(&7));
+        assert_eq!(protocol.poll_write(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn publisher_ends_subscriptions_when_going_away() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        protocol.handle_write(Command::GoAway {
+            new_session_uri: "https://example.com/moq".to_string(),
+        })?;
+
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected GOAWAY bytes");
+        };
+        let mut parser = MessageParser::new(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        assert!(matches!(
+            parser.poll_event(),
+            Some(MessageParserEvent::ControlMessage(ControlMessage::GoAway(
+                _
+            )))
+        ));
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.subscribe_id, 7);
+        assert_eq!(done.status_code, SubscribeDoneCode::GoingAway as u64);
+        assert!(!protocol.incoming_subscribes.contains_key(&7));
+        Ok(())
+    }
+
+    #[test]
+    fn publisher_ends_expired_subscription() -> Result<()> {
+        let mut protocol = server_with_accepted_subscribe(
+            FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+            60_000,
+        )?;
+        let expires_at = protocol.poll_timeout().expect("expiry timer armed");
+
+        protocol.handle_timeout(expires_at - Duration::from_millis(1))?;
+        assert_eq!(protocol.poll_write(), None);
+
+        protocol.handle_timeout(expires_at)?;
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.subscribe_id, 7);
+        assert_eq!(done.status_code, SubscribeDoneCode::Expired as u64);
+        assert_eq!(protocol.poll_timeout(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn subscriber_ends_expired_subscription() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+        let accepted_at = Instant::now();
+        read_control_message_at(
+            &mut protocol,
+            ControlMessage::SubscribeOk(SubscribeOk {
+                subscribe_id: 0,
+                expires: 30_000,
+                largest_group_object: None,
+            }),
+            accepted_at,
+        )?;
+        assert!(matches!(
+            protocol.poll_event(),
+            Some(EventOut::SubscribeAccepted { .. })
+        ));
+        let expires_at = protocol.poll_timeout().expect("expiry timer armed");
+        assert_eq!(expires_at, accepted_at + Duration::from_secs(30));
+
+        protocol.handle_timeout(expires_at - Duration::from_millis(1))?;
+        assert_eq!(protocol.poll_event(), None);
+
+        protocol.handle_timeout(expires_at)?;
+        match protocol.poll_event() {
+            Some(EventOut::SubscribeEnded {
+                subscribe_id,
+                status_code,
+                ..
+            }) => {
+                assert_eq!(subscribe_id, 0);
+                assert_eq!(status_code, SubscribeDoneCode::Expired as u64);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(
+            protocol.poll_timeout(),
+            Some(expires_at + EXPIRED_SUBSCRIPTION_GRACE_PERIOD)
+        );
+        assert_eq!(protocol.poll_write(), None);
+
+        // Objects the publisher sent before it expired the subscription are
+        // dropped.
+        protocol.handle_read(ReadInput::Datagram {
+            bytes: object_datagram(0)?,
+            now: expires_at,
+        })?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.poll_write(), None);
+
+        // The publisher's own SUBSCRIBE_DONE finishes the teardown quietly.
+        read_control_message(
+            &mut protocol,
+            ControlMessage::SubscribeDone(SubscribeDone {
+                subscribe_id: 0,
+                status_code: SubscribeDoneCode::Expired as u64,
+                reason_phrase: "subscription expired".to_string(),
+                final_group_object: None,
+            }),
+        )?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.poll_write(), None);
+        assert!(protocol.cancelled_outgoing_subscribes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn expired_subscription_released_without_subscribe_done() -> Result<()> {
+        let mut protocol = client_with_pending_subscribe()?;
+        read_control_message(
+            &mut protocol,
+            ControlMessage::SubscribeOk(SubscribeOk {
+                subscribe_id: 0,
+                expires: 30_000,
+                largest_group_object: None,
+            }),
+        )?;
+        let _ = protocol.poll_event();
+        let expires_at = protocol.poll_timeout().expect("expiry timer armed");
+        protocol.handle_timeout(expires_at)?;
+        let _ = protocol.poll_event();
+
+        let release_at = protocol.poll_timeout().expect("release timer armed");
+        protocol.handle_timeout(release_at)?;
+        assert_eq!(protocol.poll_event(), None);
+        assert_eq!(protocol.poll_write(), None);
+        assert_eq!(protocol.poll_timeout(), None);
+        assert!(protocol.cancelled_outgoing_subscribes.is_empty());
+        assert!(protocol.remote_track_aliases.is_empty());
+        assert!(protocol.remote_tracks.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn zero_expires_subscription_persists() -> Result<()> {
+        let mut protocol = client_with_active_subscription(client_config(false))?;
+        assert_eq!(protocol.poll_timeout(), None);
+
+        protocol.handle_timeout(Instant::now() + Duration::from_secs(3600))?;
+        assert_eq!(protocol.poll_event(), None);
+        assert!(protocol.active_outgoing_subscribes.contains_key(&0));
+        Ok(())
+    }
+
+    #[test]
+    fn subscribe_done_without_delivered_objects_has_no_final_object() -> Result<()> {
+        let mut protocol = server_with_accepted_subscribe(
+            FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+            60_000,
         )?;
-        protocol.handle_read(ReadInput::StreamData {
-            stream_id: 51,
-            data: client_setup_bytes.freeze(),
-            fin: false,
-        })?;
-        let _ = protocol.poll_write();
-        let _ = protocol.poll_event();
+        let expires_at = protocol.poll_timeout().expect("expiry timer armed");
+        protocol.handle_timeout(expires_at)?;
 
-        let subscribe = Subscribe {
-            subscribe_id: 7,
-            track_alias: 9,
-            track_namespace: "live".to_string(),
-            track_name: "camera".to_string(),
-            filter_type: FilterType::AbsoluteStart(FullSequence::new(0, 0)),
-            authorization_info: None,
+        let Some(WriteOutput::SendStream { bytes, .. }) = protocol.poll_write() else {
+            panic!("expected SUBSCRIBE_DONE bytes");
         };
-        let mut subscribe_bytes = BytesMut::new();
-        let _ = MessageFramer::serialize_control_message(
-            ControlMessage::Subscribe(subscribe),
-            &mut subscribe_bytes,
-        )?;
-        protocol.handle_read(ReadInput::StreamData {
-            stream_id: 51,
-            data: subscribe_bytes.freeze(),
-            fin: false,
-        })?;
-        let _ = protocol.poll_event();
+        // content_exists = 0 ends the message.
+        assert_eq!(bytes.last(), Some(&0x00));
+        let mut parser = MessageParser::new(false);
+        parser.process_data(&mut bytes.as_ref(), false);
+        let Some(MessageParserEvent::ControlMessage(ControlMessage::SubscribeDone(done))) =
+            parser.poll_event()
+        else {
+            panic!("expected SUBSCRIBE_DONE");
+        };
+        assert_eq!(done.subscribe_id, 7);
+        assert_eq!(done.final_group_object, None);
+        Ok(())
+    }
 
-        protocol.handle_write(Command::SubscribeOk {
-            subscribe_id: 7,
-            expires: 60,
-            largest_group_object: None,
-        })?;
-        let _ = protocol.poll_write();
+    #[test]
+    fn subscribe_done_command_reports_largest_delivered_object() -> Result<()> {
+        let mut protocol =
+            server_with_accepted_subscribe(FilterType::AbsoluteStart(FullSequence::new(0, 0)), 0)?;
+        for object_id in 0..2 {
+            protocol.handle_write(Command::PublishObject {
+                track_namespace: "live".to_string(),
+                track_name: "camera".to_string(),
+                group_id: 0,
+                object_id,
+                send_order: 0,
+                status: ObjectStatus::Normal,
+                payload: Bytes::from_static(b"x"),
+            })?;
+        }
+        while protocol.poll_write().is_some() {}
 
-        protocol.handle_write(Command::PublishObject {
-            track_namespace: "live".to_string(),
-            track_name: "camera".to_string(),
-            group_id: 0,
-            object_id: 0,
-            send_order: 0,
-            status: ObjectStatus::Normal,
-            payload: Bytes::from_static(b"frame"),
+        protocol.handle_write(Command::SubscribeDone {
+            subscribe_id: 7,
+            status_code: SubscribeDoneCode::TrackEnded as u64,
+            reason_phrase: "track ended".to_string(),
+            final_group_object: None,
         })?;
-
-        let Some(WriteOutput::SendDatagram(bytes)) = protocol.poll_write() else {
-            panic!("expected datagram output");
-        };
-        let (object_header, payload) = MessageParser::process_datagram(&mut bytes.as_ref())?;
-        assert_eq!(
-            object_header,
-            ObjectHeader {
-                subscribe_id: 7,
-                track_alias: 9,
-                group_id: 0,
-                object_id: 0,
-                object_send_order: 0,
-                object_status: ObjectStatus::Normal,
-                object_forwarding_preference: ObjectForwardingPreference::Datagram,
-                object_payload_length: None,
-            }
-        );
-        assert_eq!(payload, Bytes::from_static(b"frame"));
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.final_group_object, Some(FullSequence::new(0, 1)));
         Ok(())
     }
 
@@ -4569,6 +9841,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -4576,6 +9851,7 @@ mod test {
             stream_id: 53,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -4596,6 +9872,7 @@ mod test {
             stream_id: 53,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -4614,8 +9891,12 @@ mod test {
             stream_id: 53,
             data: unsubscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.subscribe_id, 7);
+        assert_eq!(done.status_code, SubscribeDoneCode::Unsubscribed as u64);
 
         protocol.handle_write(Command::PublishObject {
             track_namespace: "live".to_string(),
@@ -4648,6 +9929,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -4655,6 +9939,7 @@ mod test {
             stream_id: 55,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -4675,6 +9960,7 @@ mod test {
             stream_id: 55,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -4710,7 +9996,12 @@ mod test {
             stream_id: 55,
             data: unsubscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
+        assert_eq!(
+            poll_subscribe_done(&mut protocol).status_code,
+            SubscribeDoneCode::Unsubscribed as u64
+        );
 
         assert!(protocol.pending_data_stream_opens.is_empty());
         assert!(protocol.publisher_streams.is_empty());
@@ -4741,6 +10032,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -4748,6 +10042,7 @@ mod test {
             stream_id: 59,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -4768,6 +10063,7 @@ mod test {
             stream_id: 59,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -4809,6 +10105,253 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn end_of_group_frame_carries_group_stream_fin() -> Result<()> {
+        let mut protocol = server_with_accepted_subscribe_to(
+            ObjectForwardingPreference::Group,
+            FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+            0,
+        )?;
+        let publish = |object_id, status, payload| Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id,
+            send_order: 0,
+            status,
+            payload,
+        };
+        protocol.handle_write(publish(
+            0,
+            ObjectStatus::Normal,
+            Bytes::from_static(b"frame"),
+        ))?;
+        let _ = protocol.poll_write();
+        protocol.handle_event(EventIn::StreamOpened {
+            stream_id: 61,
+            bidi: true,
+            local: true,
+        })?;
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendStream {
+                stream_id: 61,
+                fin: false,
+                ..
+            })
+        ));
+
+        protocol.handle_write(publish(1, ObjectStatus::EndOfGroup, Bytes::new()))?;
+        let Some(WriteOutput::SendStream {
+            stream_id,
+            bytes,
+            fin,
+        }) = protocol.poll_write()
+        else {
+            panic!("expected END_OF_GROUP frame");
+        };
+        assert_eq!(stream_id, 61);
+        assert!(fin);
+        // object_id 1, length 0, status EndOfGroup
+        assert_eq!(
+            bytes.as_ref(),
+            &[0x01, 0x00, ObjectStatus::EndOfGroup as u8]
+        );
+        assert_eq!(protocol.poll_write(), None);
+        assert!(protocol.publisher_streams.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn unpublish_track_sends_end_of_track_before_subscribe_done() -> Result<()> {
+        let mut protocol = server_with_accepted_subscribe_to(
+            ObjectForwardingPreference::Track,
+            FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+            0,
+        )?;
+        protocol.handle_write(Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id: 0,
+            send_order: 0,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from_static(b"frame"),
+        })?;
+        let _ = protocol.poll_write();
+        protocol.handle_event(EventIn::StreamOpened {
+            stream_id: 61,
+            bidi: true,
+            local: true,
+        })?;
+
+        protocol.handle_write(Command::UnpublishTrack {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+        })?;
+
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendStream {
+                stream_id: 61,
+                fin: false,
+                ..
+            })
+        ));
+        let Some(WriteOutput::SendStream {
+            stream_id,
+            bytes,
+            fin,
+        }) = protocol.poll_write()
+        else {
+            panic!("expected END_OF_TRACK frame");
+        };
+        assert_eq!(stream_id, 61);
+        assert!(fin);
+        // group_id 0, object_id 1, length 0, status EndOfTrack
+        assert_eq!(
+            bytes.as_ref(),
+            &[0x00, 0x01, 0x00, ObjectStatus::EndOfTrack as u8]
+        );
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.subscribe_id, 7);
+        assert_eq!(done.status_code, SubscribeDoneCode::TrackEnded as u64);
+        assert_eq!(protocol.poll_write(), None);
+        Ok(())
+    }
+
+    fn server_with_queued_stream_object() -> Result<SessionCore> {
+        let mut protocol = server_with_accepted_subscribe_to(
+            ObjectForwardingPreference::Track,
+            FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+            0,
+        )?;
+        protocol.handle_write(Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id: 0,
+            send_order: 0,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from_static(b"frame"),
+        })?;
+        let _ = protocol.poll_write();
+        protocol.handle_event(EventIn::StreamOpened {
+            stream_id: 61,
+            bidi: true,
+            local: true,
+        })?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn final_subscribe_done_keeps_queued_objects() -> Result<()> {
+        let mut protocol = server_with_queued_stream_object()?;
+        protocol.handle_write(Command::SubscribeDone {
+            subscribe_id: 7,
+            status_code: SubscribeDoneCode::TrackEnded.into(),
+            reason_phrase: "track ended".to_string(),
+            final_group_object: None,
+        })?;
+
+        assert!(matches!(
+            protocol.poll_write(),
+            Some(WriteOutput::SendStream { stream_id: 61, .. })
+        ));
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.status_code, SubscribeDoneCode::TrackEnded as u64);
+        assert_eq!(done.final_group_object, Some(FullSequence::new(0, 0)));
+        assert_eq!(protocol.poll_write(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn error_subscribe_done_resets_streams() -> Result<()> {
+        let mut protocol = server_with_queued_stream_object()?;
+        protocol.handle_write(Command::SubscribeDone {
+            subscribe_id: 7,
+            status_code: SubscribeDoneCode::InternalError.into(),
+            reason_phrase: "internal error".to_string(),
+            final_group_object: None,
+        })?;
+
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::ResetStream {
+                stream_id: 61,
+                error_code: StreamErrorCode::Cancelled,
+            })
+        );
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.status_code, SubscribeDoneCode::InternalError as u64);
+        assert_eq!(protocol.poll_write(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn stop_sending_on_data_stream_ends_subscription() -> Result<()> {
+        let mut protocol = server_with_accepted_subscribe_to(
+            ObjectForwardingPreference::Track,
+            FilterType::AbsoluteStart(FullSequence::new(0, 0)),
+            0,
+        )?;
+        protocol.handle_write(Command::PublishObject {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            group_id: 0,
+            object_id: 0,
+            send_order: 0,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from_static(b"frame"),
+        })?;
+        let _ = protocol.poll_write();
+        protocol.handle_event(EventIn::StreamOpened {
+            stream_id: 61,
+            bidi: true,
+            local: true,
+        })?;
+        assert!(protocol.publisher_streams.contains_key(&61));
+
+        protocol.handle_event(EventIn::StopSendingReceived {
+            stream_id: 61,
+            error_code: 3,
+        })?;
+
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::ResetStream {
+                stream_id: 61,
+                error_code: StreamErrorCode::Cancelled,
+            })
+        );
+        let done = poll_subscribe_done(&mut protocol);
+        assert_eq!(done.subscribe_id, 7);
+        assert_eq!(done.status_code, SubscribeDoneCode::Unsubscribed as u64);
+        // The object queued for the stopped stream is dropped.
+        assert_eq!(protocol.poll_write(), None);
+        assert!(protocol.publisher_streams.is_empty());
+        assert!(!protocol.incoming_subscribes.contains_key(&7));
+        assert!(!protocol.local_track_by_subscribe_id.contains_key(&7));
+        Ok(())
+    }
+
+    #[test]
+    fn stop_sending_on_control_stream_closes_session() -> Result<()> {
+        let mut protocol = server_with_accepted_subscribe(FilterType::LatestObject, 0)?;
+        protocol.handle_event(EventIn::StopSendingReceived {
+            stream_id: 51,
+            error_code: 0,
+        })?;
+        assert_eq!(
+            protocol.poll_write(),
+            Some(WriteOutput::Close {
+                code: SessionErrorCode::ProtocolViolation,
+                reason: "received STOP_SENDING on control stream".to_string(),
+            })
+        );
+        Ok(())
+    }
+
     #[test]
     fn server_publishes_object_stream_for_registered_track() -> Result<()> {
         let mut protocol = SessionCore::new(server_config(false));
@@ -4826,6 +10369,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -4833,6 +10379,7 @@ mod test {
             stream_id: 61,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -4853,6 +10400,7 @@ mod test {
             stream_id: 61,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -4944,6 +10492,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -4951,6 +10502,7 @@ mod test {
             stream_id: 65,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -4971,6 +10523,7 @@ mod test {
             stream_id: 65,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -5062,6 +10615,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -5069,6 +10625,7 @@ mod test {
             stream_id: 69,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -5089,6 +10646,7 @@ mod test {
             stream_id: 69,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -5180,6 +10738,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -5187,6 +10748,7 @@ mod test {
             stream_id: 73,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -5207,6 +10769,7 @@ mod test {
             stream_id: 73,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
@@ -5328,6 +10891,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -5335,6 +10901,7 @@ mod test {
             stream_id: 77,
             data: client_setup_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_write();
         let _ = protocol.poll_event();
@@ -5355,6 +10922,7 @@ mod test {
             stream_id: 77,
             data: subscribe_bytes.freeze(),
             fin: false,
+            now: Instant::now(),
         })?;
         let _ = protocol.poll_event();
         protocol.handle_write(Command::SubscribeOk {
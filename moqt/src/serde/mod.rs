@@ -31,7 +31,10 @@ impl Deserializer for bool {
         if !r.has_remaining() {
             return Err(Error::ErrBufferTooShort);
         }
-        let b = r.get_u8();
+        // Not get_u8, which the parser's varint stats take for a varint.
+        let mut b = [0];
+        r.copy_to_slice(&mut b);
+        let b = b[0];
         match b {
             0 => Ok((false, 1)),
             1 => Ok((true, 1)),
@@ -73,13 +76,117 @@ impl Deserializer for String {
     }
 }
 
-impl Serializer for String {
+impl Serializer for str {
     fn serialize<B: BufMut>(&self, w: &mut B) -> Result<usize> {
         let l = self.len().serialize(w)?;
         if w.remaining_mut() < self.len() {
             return Err(Error::ErrBufferTooShort);
         }
-        w.put(self.as_ref());
+        w.put(self.as_bytes());
         Ok(l + self.len())
     }
 }
+
+impl Serializer for String {
+    fn serialize<B: BufMut>(&self, w: &mut B) -> Result<usize> {
+        self.as_str().serialize(w)
+    }
+}
+
+/// An optional value is written as a presence byte (0 or 1) followed by the
+/// value when present. Any other presence byte is ErrInvalidBooleanValue.
+impl<T: Serializer> Serializer for Option<T> {
+    fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
+        if let Some(value) = self.as_ref() {
+            Ok(true.serialize(w)? + value.serialize(w)?)
+        } else {
+            false.serialize(w)
+        }
+    }
+}
+
+impl<T: Deserializer> Deserializer for Option<T> {
+    fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
+        let (exists, el) = bool::deserialize(r)?;
+        if exists {
+            let (value, vl) = T::deserialize(r)?;
+            Ok((Some(value), el + vl))
+        } else {
+            Ok((None, el))
+        }
+    }
+}
+
+/// A list is written as its element count, a varint, followed by the
+/// elements.
+impl<T: Serializer> Serializer for Vec<T> {
+    fn serialize<W: BufMut>(&self, w: &mut W) -> Result<usize> {
+        let mut l = self.len().serialize(w)?;
+        for value in self {
+            l += value.serialize(w)?;
+        }
+        Ok(l)
+    }
+}
+
+impl<T: Deserializer> Deserializer for Vec<T> {
+    fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
+        let (count, mut l) = usize::deserialize(r)?;
+        // The count comes off the wire, so don't let it size the allocation.
+        let mut values = Vec::with_capacity(count.min(r.remaining()));
+        for _ in 0..count {
+            let (value, vl) = T::deserialize(r)?;
+            values.push(value);
+            l += vl;
+        }
+        Ok((values, l))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::FullSequence;
+
+    #[test]
+    fn test_str_matches_string() -> Result<()> {
+        let mut borrowed = vec![];
+        let bl = "foo".serialize(&mut borrowed)?;
+        let mut owned = vec![];
+        let ol = "foo".to_string().serialize(&mut owned)?;
+        assert_eq!(borrowed, owned);
+        assert_eq!(borrowed, vec![0x03, b'f', b'o', b'o']);
+        assert_eq!(bl, ol);
+        Ok(())
+    }
+
+    #[test]
+    fn test_option_round_trip() -> Result<()> {
+        for (value, expected_packet) in [
+            (Some(FullSequence::new(4, 1)), vec![0x01, 0x04, 0x01]),
+            (None, vec![0x00]),
+        ] {
+            let mut packet = vec![];
+            let l = value.serialize(&mut packet)?;
+            assert_eq!(expected_packet, packet);
+            assert_eq!(expected_packet.len(), l);
+
+            let (actual, l) = Option::<FullSequence>::deserialize(&mut packet.as_slice())?;
+            assert_eq!(value, actual);
+            assert_eq!(expected_packet.len(), l);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_option_invalid_presence_byte() {
+        assert_eq!(
+            Option::<FullSequence>::deserialize(&mut [0x02, 0x04, 0x01].as_slice()),
+            Err(Error::ErrInvalidBooleanValue(2))
+        );
+        assert_eq!(
+            Option::<FullSequence>::deserialize(&mut [0x01, 0x04].as_slice()),
+            Err(Error::ErrUnexpectedEnd)
+        );
+    }
+}
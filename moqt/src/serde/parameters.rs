@@ -1,6 +1,7 @@
+use crate::message::message_parser::ErrorCode;
 use crate::serde::{Deserializer, Serializer};
 use crate::{Error, Result};
-use bytes::BufMut;
+use bytes::{Buf, BufMut};
 use std::collections::HashMap;
 use std::io::Cursor;
 
@@ -10,6 +11,15 @@ pub enum ParameterKey {
     Role = 0,
     Path = 1,
     AuthorizationInfo = 2,
+    /// Not part of the draft: advertises in SETUP that the sender can handle
+    /// objects delivered in fragments.
+    PartialObjectDelivery = 0x3e,
+    /// Not part of the draft: advertises in SETUP that the sender can
+    /// reassemble objects split across several datagrams.
+    DatagramFragmentation = 0x3f,
+    /// Not part of the draft: advertises in SETUP that the sender can read
+    /// control messages preceded by their length.
+    LengthPrefixedControl = 0x3d,
 }
 
 impl TryFrom<u64> for ParameterKey {
@@ -20,6 +30,9 @@ impl TryFrom<u64> for ParameterKey {
             0x1 => Ok(ParameterKey::Role),
             0x2 => Ok(ParameterKey::Path),
             0x3 => Ok(ParameterKey::AuthorizationInfo),
+            0x3d => Ok(ParameterKey::LengthPrefixedControl),
+            0x3e => Ok(ParameterKey::PartialObjectDelivery),
+            0x3f => Ok(ParameterKey::DatagramFragmentation),
             _ => Err(Error::ErrUnsupportedParameter(value)),
         }
     }
@@ -38,9 +51,20 @@ impl Serializer for Parameters {
         for kind in kinds {
             l += kind.serialize(w)?;
             let value = &self.0[&kind];
-            if !(kind == ParameterKey::Path as u64
-                || kind == ParameterKey::AuthorizationInfo as u64)
-            {
+            if kind == ParameterKey::Path as u64 || kind == ParameterKey::AuthorizationInfo as u64 {
+                // String values already carry their own length prefix, make sure it covers
+                // exactly the bytes that follow so the peer's parser won't reject the frame.
+                let mut r = &value[..];
+                let (declared_len, dl) = usize::deserialize(&mut r)?;
+                if declared_len + dl != value.len() {
+                    return Err(Error::ErrFrameError(format!(
+                        "parameter {} declares length {} but carries {} bytes",
+                        kind,
+                        declared_len,
+                        value.len() - dl
+                    )));
+                }
+            } else {
                 l += value.len().serialize(w)?;
             }
             if w.remaining_mut() < value.len() {
@@ -54,6 +78,61 @@ impl Serializer for Parameters {
     }
 }
 
+impl Deserializer for Parameters {
+    fn deserialize<R: Buf>(r: &mut R) -> Result<(Self, usize)> {
+        Self::deserialize_with(r, |_| Error::ErrDuplicateParameter)
+    }
+}
+
+impl Parameters {
+    /// Deserializes the parameters of |message|, reporting a parameter that
+    /// appears twice as a protocol violation naming its key.
+    pub(crate) fn deserialize_in_message<R: Buf>(
+        r: &mut R,
+        message: &str,
+    ) -> Result<(Self, usize)> {
+        Self::deserialize_with(r, |kind| {
+            let reason = if kind == ParameterKey::AuthorizationInfo as u64 {
+                format!("AUTHORIZATION_INFO parameter appears twice in {}", message)
+            } else {
+                format!("parameter {:#x} appears twice in {}", kind, message)
+            };
+            Error::ErrParseError(ErrorCode::ProtocolViolation, reason)
+        })
+    }
+
+    fn deserialize_with<R: Buf>(
+        r: &mut R,
+        duplicate_error: impl Fn(u64) -> Error,
+    ) -> Result<(Self, usize)> {
+        let mut parameters = Parameters::new();
+        let (num_params, mut l) = u64::deserialize(r)?;
+        for _ in 0..num_params {
+            let (kind, kl) = u64::deserialize(r)?;
+            l += kl;
+            let (size, sl) = usize::deserialize(r)?;
+            l += sl;
+            if r.remaining() < size {
+                return Err(Error::ErrBufferTooShort);
+            }
+            // String values are kept with their length prefix, the same way insert()
+            // stores them, so that serialize() writes them back unchanged.
+            let mut value = Vec::with_capacity(sl + size);
+            if kind == ParameterKey::Path as u64 || kind == ParameterKey::AuthorizationInfo as u64 {
+                size.serialize(&mut value)?;
+            }
+            let start = value.len();
+            value.resize(start + size, 0);
+            r.copy_to_slice(&mut value[start..]);
+            l += size;
+            if parameters.0.insert(kind, value).is_some() {
+                return Err(duplicate_error(kind));
+            }
+        }
+        Ok((parameters, l))
+    }
+}
+
 impl Parameters {
     pub fn new() -> Self {
         Self::default()
@@ -73,6 +152,15 @@ impl Parameters {
         self.0.contains_key(&(key as u64))
     }
 
+    pub fn get<P: Deserializer>(&self, key: ParameterKey) -> Result<Option<P>> {
+        if let Some(value) = self.0.get(&(key as u64)) {
+            let (p, _) = P::deserialize(&mut value.as_slice())?;
+            Ok(Some(p))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn remove<P: Deserializer>(&mut self, key: ParameterKey) -> Result<Option<P>> {
         if let Some(value) = self.0.remove(&(key as u64)) {
             let mut cursor = Cursor::new(value);
@@ -117,4 +205,122 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_params_round_trip() -> Result<()> {
+        let mut params = Parameters::new();
+        params.insert(ParameterKey::Role, Role::PubSub)?;
+        params.insert(ParameterKey::AuthorizationInfo, "password".to_string())?;
+
+        let mut buf = vec![];
+        let written = params.serialize(&mut buf)?;
+        let (decoded, read) = Parameters::deserialize(&mut buf.as_slice())?;
+        assert_eq!(written, read);
+        assert_eq!(params, decoded);
+        assert_eq!(
+            Some("password".to_string()),
+            decoded.get(ParameterKey::AuthorizationInfo)?
+        );
+        assert!(decoded.contains(ParameterKey::AuthorizationInfo));
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_empty_round_trip() -> Result<()> {
+        let mut buf = vec![];
+        assert_eq!(Parameters::new().serialize(&mut buf)?, 1);
+        assert_eq!(buf, vec![0x00]);
+
+        let mut r = &[0x00, 0xff][..];
+        let (params, read) = Parameters::deserialize(&mut r)?;
+        assert_eq!(read, 1);
+        assert!(params.0.is_empty());
+        // Nothing past the count is consumed.
+        assert_eq!(r, &[0xff]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_duplicate_on_wire() -> Result<()> {
+        let packet: Vec<u8> = vec![
+            0x02, // 2 parameters
+            0x02, 0x03, 0x62, 0x61, 0x72, // authorization_info = "bar"
+            0x02, 0x03, 0x62, 0x61, 0x72, // authorization_info = "bar"
+        ];
+        assert_eq!(
+            Parameters::deserialize(&mut packet.as_slice()),
+            Err(Error::ErrDuplicateParameter)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_duplicate_in_message_names_key() -> Result<()> {
+        let packet: Vec<u8> = vec![
+            0x02, // 2 parameters
+            0x05, 0x01, 0x00, // unknown parameter 0x05
+            0x05, 0x01, 0x00, // unknown parameter 0x05
+        ];
+        assert_eq!(
+            Parameters::deserialize_in_message(&mut packet.as_slice(), "ANNOUNCE"),
+            Err(Error::ErrParseError(
+                ErrorCode::ProtocolViolation,
+                "parameter 0x5 appears twice in ANNOUNCE".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_length_mismatch() -> Result<()> {
+        let mut params = Parameters::new();
+        // PATH declares 5 bytes of string but only carries 2
+        params
+            .0
+            .insert(ParameterKey::Path as u64, vec![0x05, b'/', b'm']);
+
+        let mut buf = vec![];
+        let result = params.serialize(&mut buf);
+        assert!(matches!(result, Err(Error::ErrFrameError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_large_value_round_trip() -> Result<()> {
+        let token = "t".repeat(200);
+        let raw = vec![0xab; 200];
+        let mut params = Parameters::new();
+        params.insert(ParameterKey::AuthorizationInfo, token.clone())?;
+        params.0.insert(0x21, raw.clone());
+
+        let mut buf = vec![];
+        let written = params.serialize(&mut buf)?;
+        assert_eq!(written, buf.len());
+        // 200 does not fit a one-byte varint, so both lengths take two bytes.
+        assert_eq!(&buf[..4], &[0x02, 0x02, 0x40, 0xc8]);
+        assert_eq!(&buf[4..204], token.as_bytes());
+        assert_eq!(&buf[204..207], &[0x21, 0x40, 0xc8]);
+        assert_eq!(&buf[207..], raw.as_slice());
+
+        let (decoded, read) = Parameters::deserialize(&mut buf.as_slice())?;
+        assert_eq!(read, written);
+        assert_eq!(decoded, params);
+        assert_eq!(Some(token), decoded.get(ParameterKey::AuthorizationInfo)?);
+        assert_eq!(decoded.0.get(&0x21), Some(&raw));
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_large_value_truncated() -> Result<()> {
+        let mut packet: Vec<u8> = vec![
+            0x01, // 1 parameter
+            0x21, 0x40, 0xc8, // 200 byte value
+        ];
+        packet.extend(std::iter::repeat_n(0xab, 199));
+        assert_eq!(
+            Parameters::deserialize(&mut packet.as_slice()),
+            Err(Error::ErrBufferTooShort)
+        );
+        Ok(())
+    }
 }
@@ -60,6 +60,26 @@ impl VarInt {
             unreachable!("malformed VarInt");
         }
     }
+
+    /// Encodes this value in exactly |length| bytes, which may be longer than
+    /// the minimal encoding. Only interop tests need a non-minimal encoding.
+    pub fn serialize_with_length<W: BufMut>(self, w: &mut W, length: usize) -> Result<usize> {
+        if !matches!(length, 1 | 2 | 4 | 8) {
+            return Err(Error::ErrOther(format!("invalid varint length {}", length)));
+        }
+        if length < self.size() {
+            return Err(Error::ErrVarIntBoundsExceeded);
+        }
+        if w.remaining_mut() < length {
+            return Err(Error::ErrBufferTooShort);
+        }
+        // The two-bit length tag is log2(length), in the top bits of the
+        // encoding.
+        let tag = u64::from(length.trailing_zeros());
+        let bytes = (tag << (length * 8 - 2) | self.0).to_be_bytes();
+        w.put_slice(&bytes[8 - length..]);
+        Ok(length)
+    }
 }
 
 impl From<VarInt> for u64 {
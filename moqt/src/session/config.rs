@@ -1,4 +1,8 @@
 use crate::message::Version;
+use crate::protocol::{
+    AnnounceRetryPolicy, ObjectEventQueue, DEFAULT_BUFFER_POOL_SIZE,
+    DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+};
 
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Perspective {
@@ -7,11 +11,59 @@ pub enum Perspective {
     Client,
 }
 
-#[derive(Default, Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Config {
     pub version: Version,
     pub perspective: Perspective,
     pub use_web_transport: bool,
     pub path: String,
+    /// Hands received objects out fragment by fragment as they arrive. Takes
+    /// effect only if the peer also advertises PARTIAL_OBJECT_DELIVERY in SETUP.
     pub deliver_partial_objects: bool,
+    /// Versions a client also accepts besides `version`, offered after it in CLIENT_SETUP.
+    pub additional_versions: Vec<Version>,
+    /// Bounds how many received object fragments may wait to be polled.
+    pub object_event_queue: ObjectEventQueue,
+    /// Holds back objects that arrive ahead of a gap in their group and hands
+    /// them out in object order. Reordering works on whole objects, so it is
+    /// meant to be used without partial delivery.
+    pub reorder_objects: bool,
+    /// How many times a SUBSCRIBE is re-sent with the alias proposed by a
+    /// SUBSCRIBE_ERROR (RetryTrackAlias) before the subscription is reported
+    /// as rejected.
+    pub max_track_alias_retries: usize,
+    /// How many control message buffers handed back with
+    /// [`crate::SessionCore::recycle_buffer`] are kept for reuse.
+    pub buffer_pool_size: usize,
+    /// Advertises DATAGRAM_FRAGMENTATION in SETUP. When the peer does too,
+    /// a datagram object over the max datagram size is split across several
+    /// datagrams instead of failing, and split objects from the peer are
+    /// reassembled before they are delivered.
+    pub datagram_fragmentation: bool,
+    /// Advertises LENGTH_PREFIXED_CONTROL in SETUP. When the peer does too,
+    /// control messages after SETUP are preceded by their length.
+    pub length_prefixed_control: bool,
+    /// Re-sends an ANNOUNCE refused with a transient ANNOUNCE_ERROR; see
+    /// [`crate::AnnounceRetryPolicy`].
+    pub announce_retry: Option<AnnounceRetryPolicy>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: Version::default(),
+            perspective: Perspective::default(),
+            use_web_transport: false,
+            path: String::new(),
+            deliver_partial_objects: false,
+            additional_versions: vec![],
+            object_event_queue: ObjectEventQueue::default(),
+            reorder_objects: false,
+            max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+            buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+            announce_retry: None,
+        }
+    }
 }
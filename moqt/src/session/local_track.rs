@@ -1,7 +1,9 @@
 use crate::message::object::{ObjectForwardingPreference, ObjectStatus};
 use crate::message::{FullSequence, FullTrackName};
+use crate::session::object_cache::{CachedObject, ObjectCache};
 use crate::session::subscribe_window::{SubscribeWindow, SubscribeWindows};
 use crate::StreamId;
+use bytes::Bytes;
 use log::error;
 use std::collections::HashMap;
 
@@ -35,6 +37,9 @@ pub struct LocalTrack {
     // Entry does not exist, if no kGroupDoesNotExist, EndOfGroup, or
     // EndOfTrack has been received for that group.
     max_object_ids: HashMap<u64, u64>,
+    // The most recently published objects, kept to backfill subscriptions
+    // that start before next_sequence.
+    object_cache: ObjectCache,
 
     // If true, the session has received ANNOUNCE_CANCELED for this namespace.
     // Additional subscribes will be a protocol error, and the track can be
@@ -62,6 +67,7 @@ impl LocalTrack {
                 }
             },
             max_object_ids: Default::default(),
+            object_cache: ObjectCache::default(),
             announce_canceled: false,
         }
     }
@@ -166,10 +172,7 @@ impl LocalTrack {
                 self.max_object_ids
                     .insert(sequence.group_id, sequence.object_id);
                 if self.next_sequence <= sequence {
-                    self.next_sequence = FullSequence {
-                        group_id: sequence.group_id + 1,
-                        object_id: 0,
-                    };
+                    self.next_sequence = sequence.next_group();
                 }
             }
             ObjectStatus::EndOfTrack => {
@@ -182,6 +185,36 @@ impl LocalTrack {
         }
     }
 
+    /// Sets how many published objects are kept for backfill; zero disables
+    /// the cache.
+    pub fn set_object_cache_size(&mut self, size: usize) {
+        self.object_cache.set_capacity(size);
+    }
+
+    pub fn cache_object(
+        &mut self,
+        sequence: FullSequence,
+        send_order: u64,
+        status: ObjectStatus,
+        payload: Bytes,
+    ) {
+        self.object_cache.insert(CachedObject {
+            sequence,
+            send_order,
+            status,
+            payload,
+        });
+    }
+
+    /// Returns the cached objects from |start| up to and including |end|.
+    pub fn cached_objects(
+        &self,
+        start: FullSequence,
+        end: Option<FullSequence>,
+    ) -> Vec<&CachedObject> {
+        self.object_cache.get_range(start, end)
+    }
+
     pub fn has_subscriber(&self) -> bool {
         !self.windows.is_empty()
     }
@@ -190,6 +223,25 @@ impl LocalTrack {
         self.windows.get_window(subscribe_id)
     }
 
+    /// Records that |sequence| was delivered to |subscribe_id|. Returns true if
+    /// that completed the subscription.
+    pub fn on_object_sent(
+        &mut self,
+        subscribe_id: u64,
+        sequence: FullSequence,
+        status: ObjectStatus,
+    ) -> bool {
+        self.windows
+            .get_window_mut(subscribe_id)
+            .is_some_and(|window| window.on_object_sent(sequence, status))
+    }
+
+    pub fn largest_delivered(&self, subscribe_id: u64) -> Option<FullSequence> {
+        self.windows
+            .get_window(subscribe_id)
+            .and_then(SubscribeWindow::largest_delivered)
+    }
+
     pub fn get_send_stream(&self, subscribe_id: u64, sequence: FullSequence) -> Option<StreamId> {
         self.windows
             .get_window(subscribe_id)
@@ -202,9 +254,7 @@ impl LocalTrack {
         start: FullSequence,
         end: Option<FullSequence>,
     ) -> bool {
-        self.windows
-            .get_window_mut(subscribe_id)
-            .is_some_and(|window| window.update_start_end(start, end))
+        self.windows.update_window(subscribe_id, start, end)
     }
 
     pub fn add_send_stream(
@@ -233,6 +283,9 @@ impl LocalTrack {
     pub fn set_announce_cancel(&mut self) {
         self.announce_canceled = true;
     }
+    pub fn clear_announce_cancel(&mut self) {
+        self.announce_canceled = false;
+    }
     pub fn canceled(&self) -> bool {
         self.announce_canceled
     }
@@ -337,6 +390,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_local_track_test_cached_objects() -> Result<()> {
+        let track = &mut LocalTrackTest::new().track;
+        track.cache_object(
+            FullSequence::new(4, 1),
+            0,
+            ObjectStatus::Normal,
+            Bytes::from_static(b"a"),
+        );
+        assert!(track
+            .cached_objects(FullSequence::new(0, 0), None)
+            .is_empty());
+
+        track.set_object_cache_size(2);
+        for object_id in 1..4 {
+            track.cache_object(
+                FullSequence::new(4, object_id),
+                0,
+                ObjectStatus::Normal,
+                Bytes::from_static(b"a"),
+            );
+        }
+        let cached = track.cached_objects(FullSequence::new(4, 0), Some(FullSequence::new(4, 2)));
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].sequence, FullSequence::new(4, 2));
+        Ok(())
+    }
+
     #[test]
     fn test_local_track_test_should_send() -> Result<()> {
         let track = &mut LocalTrackTest::new().track;
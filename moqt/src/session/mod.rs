@@ -1,13 +1,18 @@
-use crate::connection::Connection;
-use crate::driver::SessionDriver;
+use crate::connection::{Connection, SessionErrorCode};
+use crate::driver::{SessionDriver, SessionTransport};
+use crate::message::object::ObjectHeader;
+use crate::message::{FullTrackName, Version};
 use crate::protocol::{self, Command, EventOut};
+use crate::session::send_order::SendOrderPolicy;
 use crate::{Result, StreamId};
 use bytes::Bytes;
 use std::time::Instant;
 
 pub mod config;
 pub(crate) mod local_track;
+pub(crate) mod object_cache;
 pub(crate) mod remote_track;
+pub mod send_order;
 mod subscribe_window;
 
 impl From<config::Perspective> for protocol::Perspective {
@@ -27,31 +32,41 @@ impl From<config::Config> for protocol::Config {
             use_web_transport: value.use_web_transport,
             path: value.path,
             deliver_partial_objects: value.deliver_partial_objects,
+            additional_versions: value.additional_versions,
+            object_event_queue: value.object_event_queue,
+            reorder_objects: value.reorder_objects,
+            max_track_alias_retries: value.max_track_alias_retries,
+            buffer_pool_size: value.buffer_pool_size,
+            datagram_fragmentation: value.datagram_fragmentation,
+            length_prefixed_control: value.length_prefixed_control,
+            announce_retry: value.announce_retry,
         }
     }
 }
 
-/// Convenience wrapper that binds [`SessionDriver`] to the crate's `Connection`.
-pub struct Session {
-    driver: SessionDriver<Connection>,
+/// Convenience wrapper that binds [`SessionDriver`] to a transport: the
+/// crate's `Connection` unless another [`SessionTransport`] is given, so the
+/// session can run on any event loop.
+pub struct Session<T: SessionTransport = Connection> {
+    driver: SessionDriver<T>,
 }
 
-impl Session {
-    pub fn new(config: config::Config, conn: Connection) -> Self {
+impl<T: SessionTransport> Session<T> {
+    pub fn new(config: config::Config, conn: T) -> Self {
         Self {
             driver: SessionDriver::new(config.into(), conn),
         }
     }
 
-    pub fn transport(&self) -> &Connection {
+    pub fn transport(&self) -> &T {
         self.driver.transport()
     }
 
-    pub fn transport_mut(&mut self) -> &mut Connection {
+    pub fn transport_mut(&mut self) -> &mut T {
         self.driver.transport_mut()
     }
 
-    pub fn into_transport(self) -> Connection {
+    pub fn into_transport(self) -> T {
         self.driver.into_transport()
     }
 
@@ -71,6 +86,10 @@ impl Session {
         self.driver.on_stream_closed(stream_id)
     }
 
+    pub fn on_stop_sending(&mut self, stream_id: StreamId, error_code: u64) -> Result<()> {
+        self.driver.on_stop_sending(stream_id, error_code)
+    }
+
     pub fn on_stream_data(&mut self, stream_id: StreamId, data: Bytes, fin: bool) -> Result<()> {
         self.driver.on_stream_data(stream_id, data, fin)
     }
@@ -87,6 +106,106 @@ impl Session {
         self.driver.poll_event()
     }
 
+    /// Installs a check run on every incoming SUBSCRIBE; see
+    /// [`crate::SessionCore::set_subscribe_authorizer`].
+    pub fn set_subscribe_authorizer(
+        &mut self,
+        authorizer: impl Fn(&FullTrackName, Option<&str>) -> bool + Send + Sync + 'static,
+    ) {
+        self.driver
+            .protocol_mut()
+            .set_subscribe_authorizer(authorizer);
+    }
+
+    /// Derives the send order of published objects; see
+    /// [`crate::SessionCore::set_send_order_policy`].
+    pub fn set_send_order_policy(&mut self, policy: Option<SendOrderPolicy>) {
+        self.driver.protocol_mut().set_send_order_policy(policy);
+    }
+
+    /// Reserves a track_alias for a track before subscribing to it; see
+    /// [`crate::SessionCore::preregister_track`].
+    pub fn preregister_track(
+        &mut self,
+        full_track_name: FullTrackName,
+        track_alias: u64,
+    ) -> Result<()> {
+        self.driver
+            .protocol_mut()
+            .preregister_track(full_track_name, track_alias)
+    }
+
+    /// Total bytes buffered by the session; see
+    /// [`crate::SessionCore::buffered_bytes`].
+    pub fn buffered_bytes(&self) -> usize {
+        self.driver.protocol().buffered_bytes()
+    }
+
+    /// Version agreed in SETUP; see [`crate::SessionCore::negotiated_version`].
+    pub fn negotiated_version(&self) -> Option<Version> {
+        self.driver.protocol().negotiated_version()
+    }
+
+    /// Namespaces announced by this session that are still awaiting a response.
+    pub fn pending_announces(&self) -> Vec<String> {
+        self.driver.protocol().pending_announces()
+    }
+
+    /// Full track name received under |track_alias|; see
+    /// [`crate::SessionCore::track_name_for_alias`].
+    pub fn track_name_for_alias(&self, track_alias: u64) -> Option<&FullTrackName> {
+        self.driver.protocol().track_name_for_alias(track_alias)
+    }
+
+    /// Withdraws an announce with UNANNOUNCE. If the announce was still pending,
+    /// nothing is sent: [`EventOut::AnnounceWithdrawn`] is emitted and any late
+    /// response is ignored.
+    pub fn cancel_announce(&mut self, track_namespace: impl Into<String>) -> Result<()> {
+        self.driver.handle_command(Command::Unannounce {
+            track_namespace: track_namespace.into(),
+        })
+    }
+
+    /// Ends every subscription to a local track with an EndOfTrack object and
+    /// SUBSCRIBE_DONE (TrackEnded), then removes the track.
+    pub fn unpublish_track(&mut self, full_track_name: FullTrackName) -> Result<()> {
+        self.driver.handle_command(Command::UnpublishTrack {
+            track_namespace: full_track_name.track_namespace().to_string(),
+            track_name: full_track_name.track_name,
+        })
+    }
+
+    /// Relays a complete object to every local subscriber of the track whose
+    /// window includes its sequence. `track_alias` is the alias local
+    /// subscribers use for the track; each copy is reframed with the track's
+    /// forwarding preference rather than the one in `object_header`.
+    pub fn forward_object(
+        &mut self,
+        track_alias: u64,
+        object_header: ObjectHeader,
+        payload: Bytes,
+    ) -> Result<()> {
+        self.driver.handle_command(Command::ForwardObject {
+            track_alias,
+            object_header,
+            payload,
+        })
+    }
+
+    /// Withdraws a SUBSCRIBE that has not been answered yet with UNSUBSCRIBE.
+    /// No further events are emitted for it, even if SUBSCRIBE_OK was already
+    /// on the wire.
+    pub fn cancel_pending_subscribe(&mut self, subscribe_id: u64) -> Result<()> {
+        self.driver
+            .handle_command(Command::CancelSubscribe { subscribe_id })
+    }
+
+    /// Closes the session; see [`SessionDriver::close`]. Session state is
+    /// discarded and every later command fails.
+    pub fn close(&mut self, error_code: SessionErrorCode, reason: &str) -> Result<()> {
+        self.driver.close(error_code, reason)
+    }
+
     pub fn handle_timeout(&mut self, now: Instant) -> Result<()> {
         self.driver.handle_timeout(now)
     }
@@ -99,6 +218,7 @@ impl Session {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::message::announce_ok::AnnounceOk;
     use crate::message::client_setup::ClientSetup;
     use crate::message::message_framer::MessageFramer;
     use crate::message::object::ObjectForwardingPreference;
@@ -106,6 +226,10 @@ mod test {
     use crate::message::subscribe::Subscribe;
     use crate::message::subscribe_ok::SubscribeOk;
     use crate::message::{ControlMessage, FilterType, FullSequence, FullTrackName, Role, Version};
+    use crate::protocol::ObjectEventQueue;
+    use crate::protocol::StreamPurpose;
+    use crate::protocol::{DEFAULT_BUFFER_POOL_SIZE, DEFAULT_MAX_TRACK_ALIAS_RETRIES};
+    use crate::Deserializer;
 
     fn client_config() -> config::Config {
         config::Config {
@@ -114,6 +238,14 @@ mod test {
             use_web_transport: false,
             path: "/moq".to_string(),
             deliver_partial_objects: false,
+            additional_versions: vec![],
+            object_event_queue: ObjectEventQueue::Unbounded,
+            reorder_objects: false,
+            max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+            buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+            announce_retry: None,
         }
     }
 
@@ -124,6 +256,14 @@ mod test {
             use_web_transport: false,
             path: "/moq".to_string(),
             deliver_partial_objects: false,
+            additional_versions: vec![],
+            object_event_queue: ObjectEventQueue::Unbounded,
+            reorder_objects: false,
+            max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+            buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
+            announce_retry: None,
         }
     }
 
@@ -138,6 +278,9 @@ mod test {
             ControlMessage::ServerSetup(ServerSetup {
                 supported_version: Version::Draft04,
                 role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut server_setup_bytes,
         )?;
@@ -154,6 +297,22 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn session_wrapper_rejects_calls_after_close() -> Result<()> {
+        let mut session = Session::new(client_config(), Connection::QUIC);
+        session.on_transport_connected()?;
+
+        session.close(SessionErrorCode::NoError, "done")?;
+
+        assert_eq!(
+            session.cancel_announce("live"),
+            Err(crate::Error::ErrOther("session closed".to_string()))
+        );
+        assert!(session.close(SessionErrorCode::NoError, "done").is_err());
+        assert_eq!(session.buffered_bytes(), 0);
+        Ok(())
+    }
+
     #[test]
     fn session_wrapper_emits_termination_on_transport_inactive() -> Result<()> {
         let mut session = Session::new(client_config(), Connection::QUIC);
@@ -175,6 +334,9 @@ mod test {
             ControlMessage::ServerSetup(ServerSetup {
                 supported_version: Version::Draft04,
                 role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut server_setup_bytes,
         )?;
@@ -230,6 +392,9 @@ mod test {
                 role: Some(Role::PubSub),
                 path: Some("/moq".to_string()),
                 uses_web_transport: false,
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
             }),
             &mut client_setup_bytes,
         )?;
@@ -257,4 +422,194 @@ mod test {
         );
         Ok(())
     }
+
+    fn established_client_session() -> Result<Session> {
+        let mut session = Session::new(client_config(), Connection::QUIC);
+        session.on_transport_connected()?;
+
+        let mut server_setup_bytes = bytes::BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut server_setup_bytes,
+        )?;
+        session.on_stream_data(0, server_setup_bytes.freeze(), false)?;
+        let _ = session.poll_event();
+        Ok(session)
+    }
+
+    #[test]
+    fn session_wrapper_lists_pending_announces() -> Result<()> {
+        let mut session = established_client_session()?;
+
+        for track_namespace in ["video", "audio"] {
+            session.handle_command(Command::Announce {
+                track_namespace: track_namespace.to_string(),
+                authorization_info: None,
+            })?;
+        }
+        assert_eq!(
+            session.pending_announces(),
+            vec!["audio".to_string(), "video".to_string()]
+        );
+
+        let mut announce_ok_bytes = bytes::BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::AnnounceOk(AnnounceOk {
+                track_namespace: "video".to_string(),
+            }),
+            &mut announce_ok_bytes,
+        )?;
+        session.on_stream_data(0, announce_ok_bytes.freeze(), false)?;
+
+        assert_eq!(session.pending_announces(), vec!["audio".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn session_wrapper_resolves_track_alias() -> Result<()> {
+        let mut session = established_client_session()?;
+        session.handle_command(Command::Subscribe {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            filter_type: FilterType::LatestObject,
+            authorization_info: None,
+        })?;
+
+        let mut subscribe_ok_bytes = bytes::BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::SubscribeOk(SubscribeOk {
+                subscribe_id: 0,
+                expires: 0,
+                largest_group_object: None,
+            }),
+            &mut subscribe_ok_bytes,
+        )?;
+        session.on_stream_data(0, subscribe_ok_bytes.freeze(), false)?;
+        let Some(EventOut::SubscribeAccepted { track_alias, .. }) = session.poll_event() else {
+            panic!("expected SubscribeAccepted");
+        };
+
+        assert_eq!(
+            session.track_name_for_alias(track_alias),
+            Some(&FullTrackName::new(
+                "live".to_string(),
+                "camera".to_string()
+            ))
+        );
+        assert_eq!(session.track_name_for_alias(track_alias + 1), None);
+        Ok(())
+    }
+
+    #[test]
+    fn session_wrapper_cancels_pending_announce() -> Result<()> {
+        let mut session = established_client_session()?;
+
+        session.handle_command(Command::Announce {
+            track_namespace: "video".to_string(),
+            authorization_info: None,
+        })?;
+        session.cancel_announce("video")?;
+
+        assert!(session.pending_announces().is_empty());
+        assert_eq!(
+            session.poll_event(),
+            Some(EventOut::AnnounceWithdrawn {
+                track_namespace: "video".to_string(),
+                reason_phrase: "announce cancelled before response".to_string(),
+            })
+        );
+
+        // A late response is not reported; the namespace is withdrawn instead.
+        let mut announce_ok_bytes = bytes::BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::AnnounceOk(AnnounceOk {
+                track_namespace: "video".to_string(),
+            }),
+            &mut announce_ok_bytes,
+        )?;
+        session.on_stream_data(0, announce_ok_bytes.freeze(), false)?;
+        assert_eq!(session.poll_event(), None);
+
+        assert!(session.cancel_announce("video").is_err());
+        Ok(())
+    }
+
+    // Keeps everything the session writes, for sessions run without a network.
+    #[derive(Default)]
+    struct InMemoryTransport {
+        next_stream_id: StreamId,
+        streams: Vec<(StreamId, Bytes)>,
+    }
+
+    impl SessionTransport for InMemoryTransport {
+        fn open_bi_stream(&mut self, _purpose: StreamPurpose) -> Result<StreamId> {
+            let stream_id = self.next_stream_id;
+            self.next_stream_id += 4;
+            Ok(stream_id)
+        }
+
+        fn send_stream(
+            &mut self,
+            stream_id: StreamId,
+            bytes: bytes::BytesMut,
+            _fin: bool,
+        ) -> Result<()> {
+            self.streams.push((stream_id, bytes.freeze()));
+            Ok(())
+        }
+
+        fn send_datagram(&mut self, _bytes: Bytes) -> Result<()> {
+            Ok(())
+        }
+
+        fn close(&mut self, _code: SessionErrorCode, _reason: String) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn session_wrapper_runs_on_custom_transport() -> Result<()> {
+        let mut session = Session::new(client_config(), InMemoryTransport::default());
+        session.on_transport_connected()?;
+
+        let (stream_id, bytes) = session.transport_mut().streams.remove(0);
+        assert_eq!(stream_id, 0);
+        let (client_setup, _) = ControlMessage::deserialize(&mut bytes.as_ref())?;
+        assert!(matches!(client_setup, ControlMessage::ClientSetup(_)));
+
+        let mut server_setup_bytes = bytes::BytesMut::new();
+        let _ = MessageFramer::serialize_control_message(
+            ControlMessage::ServerSetup(ServerSetup {
+                supported_version: Version::Draft04,
+                role: Some(Role::PubSub),
+                partial_object_delivery: false,
+                datagram_fragmentation: false,
+                length_prefixed_control: false,
+            }),
+            &mut server_setup_bytes,
+        )?;
+        session.on_stream_data(0, server_setup_bytes.freeze(), false)?;
+        let _ = session.poll_event();
+
+        session.handle_command(Command::Subscribe {
+            track_namespace: "live".to_string(),
+            track_name: "camera".to_string(),
+            filter_type: FilterType::LatestObject,
+            authorization_info: None,
+        })?;
+        let (stream_id, bytes) = session.into_transport().streams.remove(0);
+        assert_eq!(stream_id, 0);
+        let (subscribe, _) = ControlMessage::deserialize(&mut bytes.as_ref())?;
+        let ControlMessage::Subscribe(subscribe) = subscribe else {
+            panic!("expected SUBSCRIBE, got {subscribe:?}");
+        };
+        assert_eq!(subscribe.track_name, "camera");
+        Ok(())
+    }
 }
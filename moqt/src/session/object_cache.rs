@@ -0,0 +1,159 @@
+use crate::message::object::ObjectStatus;
+use crate::message::FullSequence;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+
+/// An object kept around after it was published, so that it can be replayed
+/// to a subscriber whose window starts before the live edge.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CachedObject {
+    pub sequence: FullSequence,
+    pub send_order: u64,
+    pub status: ObjectStatus,
+    pub payload: Bytes,
+}
+
+/// Keeps the most recent objects of a track, ordered by sequence. Once more
+/// than `capacity` objects are cached, the oldest ones are evicted. A capacity
+/// of zero disables caching.
+#[derive(Default, Debug)]
+pub struct ObjectCache {
+    capacity: usize,
+    objects: BTreeMap<FullSequence, CachedObject>,
+}
+
+impl ObjectCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            objects: BTreeMap::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn insert(&mut self, object: CachedObject) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.objects.insert(object.sequence, object);
+        self.evict();
+    }
+
+    /// Returns the cached objects in [start, end] in sequence order; no end
+    /// means everything from start on.
+    pub fn get_range(&self, start: FullSequence, end: Option<FullSequence>) -> Vec<&CachedObject> {
+        if end.is_some_and(|end| end < start) {
+            return vec![];
+        }
+        self.objects
+            .range(start..)
+            .take_while(|(sequence, _)| end.is_none_or(|end| **sequence <= end))
+            .map(|(_, object)| object)
+            .collect()
+    }
+
+    fn evict(&mut self) {
+        while self.objects.len() > self.capacity {
+            self.objects.pop_first();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Result;
+
+    fn object(group_id: u64, object_id: u64) -> CachedObject {
+        CachedObject {
+            sequence: FullSequence::new(group_id, object_id),
+            send_order: 0,
+            status: ObjectStatus::Normal,
+            payload: Bytes::from(format!("{group_id}:{object_id}")),
+        }
+    }
+
+    fn sequences(objects: Vec<&CachedObject>) -> Vec<FullSequence> {
+        objects.into_iter().map(|object| object.sequence).collect()
+    }
+
+    #[test]
+    fn test_object_cache_evicts_oldest() -> Result<()> {
+        let mut cache = ObjectCache::new(3);
+        cache.insert(object(0, 0));
+        cache.insert(object(0, 1));
+        cache.insert(object(1, 0));
+        cache.insert(object(1, 1));
+        assert_eq!(cache.len(), 3);
+        assert_eq!(
+            sequences(cache.get_range(FullSequence::new(0, 0), None)),
+            vec![
+                FullSequence::new(0, 1),
+                FullSequence::new(1, 0),
+                FullSequence::new(1, 1)
+            ]
+        );
+
+        cache.set_capacity(1);
+        assert_eq!(
+            sequences(cache.get_range(FullSequence::new(0, 0), None)),
+            vec![FullSequence::new(1, 1)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_cache_disabled() -> Result<()> {
+        let mut cache = ObjectCache::new(0);
+        cache.insert(object(0, 0));
+        assert!(cache.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_cache_get_range() -> Result<()> {
+        let mut cache = ObjectCache::new(10);
+        for group_id in 0..3 {
+            for object_id in 0..3 {
+                cache.insert(object(group_id, object_id));
+            }
+        }
+        assert_eq!(
+            sequences(cache.get_range(FullSequence::new(0, 2), Some(FullSequence::new(1, 1)))),
+            vec![
+                FullSequence::new(0, 2),
+                FullSequence::new(1, 0),
+                FullSequence::new(1, 1)
+            ]
+        );
+        assert_eq!(
+            sequences(cache.get_range(FullSequence::new(2, 1), None)),
+            vec![FullSequence::new(2, 1), FullSequence::new(2, 2)]
+        );
+        assert!(cache
+            .get_range(FullSequence::new(1, 0), Some(FullSequence::new(0, 5)))
+            .is_empty());
+        assert!(cache.get_range(FullSequence::new(3, 0), None).is_empty());
+
+        let objects = cache.get_range(FullSequence::new(1, 2), Some(FullSequence::new(1, 2)));
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].payload, Bytes::from_static(b"1:2"));
+        Ok(())
+    }
+}
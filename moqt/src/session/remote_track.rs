@@ -1,6 +1,33 @@
-use crate::message::object::{ObjectForwardingPreference, ObjectHeader};
-use crate::message::FullTrackName;
-use bytes::Bytes;
+use crate::message::object::{
+    DatagramFragment, ObjectForwardingPreference, ObjectHeader, ObjectStatus,
+};
+use crate::message::{FullSequence, FullTrackName};
+use crate::{Error, Result};
+use bytes::{Bytes, BytesMut};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How far past the next expected object a group's reorder buffer waits. An
+/// object further ahead gives up on the missing ones below the bound.
+pub const MAX_REORDER_GAP: u64 = 16;
+
+/// How long a held object waits for the gap before it to close, counted from
+/// when it was received. The objects missing in front of it are then given up.
+pub const MAX_REORDER_DELAY: Duration = Duration::from_millis(500);
+
+/// How many groups the reorder buffer keeps track of. A new group past the
+/// bound releases whatever the oldest one holds, and later objects of the
+/// oldest group pass straight through.
+pub const MAX_REORDER_GROUPS: usize = 16;
+
+/// How many open groups a track counts received objects for. A new group
+/// past the bound drops the count of the oldest one, and the end of a group
+/// whose count was dropped is not reported.
+pub const MAX_COUNTED_GROUPS: usize = 16;
+
+/// How long the fragments of an object split across datagrams wait for the
+/// rest of the object, counted from the first fragment to arrive.
+pub const DATAGRAM_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct RemoteTrackOnReply {
@@ -14,6 +41,21 @@ pub struct RemoteTrackOnObjectFragment {
     pub extension_headers: Bytes,
     pub payload: Bytes,
     pub fin: bool,
+    /// When the first byte of the object arrived, shared by every fragment
+    /// of the object. For an object split across datagrams it is when the
+    /// last fragment arrived.
+    pub received_at: Instant,
+}
+
+impl RemoteTrackOnObjectFragment {
+    /// Bytes carried by the fragment: its extension headers and payload.
+    pub fn len(&self) -> usize {
+        self.extension_headers.len() + self.payload.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 /// A track on the peer to which the session has subscribed.
@@ -22,6 +64,31 @@ pub struct RemoteTrack {
     full_track_name: FullTrackName,
     track_alias: u64,
     forwarding_preference: Option<ObjectForwardingPreference>,
+    // Number of complete objects received so far in each open group.
+    group_object_counts: BTreeMap<u64, u64>,
+    // Groups below this may have had their count dropped to stay within
+    // MAX_COUNTED_GROUPS.
+    counted_groups_start: u64,
+    // Next object id to hand out in each group being reordered.
+    next_object_ids: BTreeMap<u64, u64>,
+    // Groups that ended, with their object count, until the reorder buffer
+    // hands out the last object held for them.
+    completed_groups: BTreeMap<u64, u64>,
+    // Objects held back until the gap before them closes.
+    reorder_buffer: BTreeMap<FullSequence, RemoteTrackOnObjectFragment>,
+    // Objects out of the reorder buffer, waiting for poll_ordered.
+    ordered_objects: BTreeMap<FullSequence, (ObjectHeader, Bytes)>,
+    // The lowest sequence poll_ordered may still return.
+    next_ordered: FullSequence,
+    // Objects split across datagrams, waiting for their missing fragments.
+    partial_datagrams: BTreeMap<FullSequence, PartialDatagram>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct PartialDatagram {
+    object_header: ObjectHeader,
+    fragments: Vec<Option<Bytes>>,
+    expires_at: Instant,
 }
 
 impl RemoteTrack {
@@ -30,6 +97,14 @@ impl RemoteTrack {
             full_track_name,
             track_alias,
             forwarding_preference: None,
+            group_object_counts: BTreeMap::new(),
+            counted_groups_start: 0,
+            next_object_ids: BTreeMap::new(),
+            completed_groups: BTreeMap::new(),
+            reorder_buffer: BTreeMap::new(),
+            ordered_objects: BTreeMap::new(),
+            next_ordered: FullSequence::default(),
+            partial_datagrams: BTreeMap::new(),
         }
     }
 
@@ -52,6 +127,309 @@ impl RemoteTrack {
         self.forwarding_preference = Some(preference);
         true
     }
+
+    /// Records a received object fragment. Returns the number of objects the
+    /// group held once an EndOfGroup object for it arrives.
+    pub fn on_object_received(&mut self, object_header: &ObjectHeader, fin: bool) -> Option<u64> {
+        let group_id = object_header.group_id;
+        match object_header.object_status {
+            ObjectStatus::EndOfGroup => match self.group_object_counts.remove(&group_id) {
+                Some(object_count) => Some(object_count),
+                None if group_id >= self.counted_groups_start => Some(0),
+                None => None,
+            },
+            ObjectStatus::Normal if fin => {
+                if !self.group_object_counts.contains_key(&group_id)
+                    && self.group_object_counts.len() >= MAX_COUNTED_GROUPS
+                {
+                    match self.group_object_counts.first_key_value() {
+                        Some((&oldest, _)) if oldest < group_id => {
+                            self.group_object_counts.remove(&oldest);
+                            self.counted_groups_start = self.counted_groups_start.max(oldest + 1);
+                        }
+                        _ => {
+                            self.counted_groups_start = self.counted_groups_start.max(group_id + 1);
+                            return None;
+                        }
+                    }
+                }
+                *self.group_object_counts.entry(group_id).or_insert(0) += 1;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Payload bytes held in the reorder buffer, waiting for
+    /// [`RemoteTrack::poll_ordered`], or waiting for the rest of a fragmented
+    /// datagram object.
+    pub fn buffered_bytes(&self) -> usize {
+        self.reorder_buffer
+            .values()
+            .map(RemoteTrackOnObjectFragment::len)
+            .sum::<usize>()
+            + self
+                .ordered_objects
+                .values()
+                .map(|(_, payload)| payload.len())
+                .sum::<usize>()
+            + self
+                .partial_datagrams
+                .values()
+                .flat_map(|partial| partial.fragments.iter().flatten())
+                .map(Bytes::len)
+                .sum::<usize>()
+    }
+
+    /// Takes one fragment of an object split across datagrams and returns the
+    /// whole object once its last missing fragment arrives. Fails if the
+    /// fragment disagrees with the ones received before it for that object.
+    pub fn on_datagram_fragment(
+        &mut self,
+        fragment: DatagramFragment,
+        now: Instant,
+    ) -> Result<Option<(ObjectHeader, Bytes)>> {
+        let object_header = fragment.object_header;
+        let sequence = FullSequence::new(object_header.group_id, object_header.object_id);
+        let partial = self
+            .partial_datagrams
+            .entry(sequence)
+            .or_insert_with(|| PartialDatagram {
+                object_header,
+                fragments: vec![None; fragment.fragment_count as usize],
+                expires_at: now + DATAGRAM_REASSEMBLY_TIMEOUT,
+            });
+        let consistent = partial.object_header == object_header
+            && partial.fragments.len() as u64 == fragment.fragment_count;
+        let Some(slot) = partial
+            .fragments
+            .get_mut(fragment.fragment_index as usize)
+            .filter(|_| consistent)
+        else {
+            return Err(Error::ErrOther(format!(
+                "datagram fragment {} of {} does not match object {}/{}",
+                fragment.fragment_index,
+                fragment.fragment_count,
+                sequence.group_id,
+                sequence.object_id
+            )));
+        };
+        *slot = Some(fragment.payload);
+        if partial.fragments.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+
+        let Some(partial) = self.partial_datagrams.remove(&sequence) else {
+            return Ok(None);
+        };
+        let mut payload = BytesMut::new();
+        for fragment in partial.fragments.into_iter().flatten() {
+            payload.extend_from_slice(&fragment);
+        }
+        Ok(Some((partial.object_header, payload.freeze())))
+    }
+
+    /// When the oldest incomplete fragmented object runs out of time.
+    pub fn next_datagram_expiry(&self) -> Option<Instant> {
+        self.partial_datagrams
+            .values()
+            .map(|partial| partial.expires_at)
+            .min()
+    }
+
+    /// Drops the fragmented objects still missing fragments
+    /// [`DATAGRAM_REASSEMBLY_TIMEOUT`] after their first fragment arrived, and
+    /// returns their sequences.
+    pub fn expire_datagram_fragments(&mut self, now: Instant) -> Vec<FullSequence> {
+        let expired = self
+            .partial_datagrams
+            .iter()
+            .filter(|(_, partial)| partial.expires_at <= now)
+            .map(|(sequence, _)| *sequence)
+            .collect::<Vec<_>>();
+        for sequence in &expired {
+            self.partial_datagrams.remove(sequence);
+        }
+        expired
+    }
+
+    /// Takes a complete object received at `now`, in any order, for
+    /// [`RemoteTrack::poll_ordered`]. It goes through the reorder buffer like
+    /// the objects given to [`RemoteTrack::reorder`]. An object behind one
+    /// already returned by [`RemoteTrack::poll_ordered`] is dropped.
+    pub fn insert_object(&mut self, object_header: ObjectHeader, payload: Bytes, now: Instant) {
+        if FullSequence::new(object_header.group_id, object_header.object_id) < self.next_ordered {
+            return;
+        }
+        let fragment = RemoteTrackOnObjectFragment {
+            object_header,
+            extension_headers: Bytes::new(),
+            payload,
+            fin: true,
+            received_at: now,
+        };
+        let ready = self.reorder(fragment);
+        self.push_ordered(ready);
+    }
+
+    /// Returns the next object given to [`RemoteTrack::insert_object`] in
+    /// FullSequence order, as far as objects have arrived. An object waiting
+    /// on a gap in its group holds back every later object, including those
+    /// of later groups, until the gap closes, the group ends, the gap grows
+    /// past [`MAX_REORDER_GAP`], or by `now` the object has been held for
+    /// [`MAX_REORDER_DELAY`].
+    pub fn poll_ordered(&mut self, now: Instant) -> Option<(ObjectHeader, Bytes)> {
+        let released = self.release_held_objects(now);
+        self.push_ordered(released);
+        let (&sequence, _) = self.ordered_objects.first_key_value()?;
+        if self
+            .reorder_buffer
+            .first_key_value()
+            .is_some_and(|(held, _)| *held < sequence)
+        {
+            return None;
+        }
+        let (_, ordered_object) = self.ordered_objects.pop_first()?;
+        self.next_ordered = sequence.next();
+        Some(ordered_object)
+    }
+
+    /// Takes a received object and returns the objects now ready in group
+    /// order. Groups are expected to start at object 0. An object is held
+    /// while an earlier one in its group is missing, until the gap grows past
+    /// [`MAX_REORDER_GAP`], an EndOfGroup object flushes the group, or
+    /// [`RemoteTrack::release_held_objects`] gives up on the gap. Objects
+    /// behind the ones already handed out, objects of groups older than the
+    /// [`MAX_REORDER_GROUPS`] tracked ones, and fragments that don't end an
+    /// object, pass straight through.
+    pub fn reorder(
+        &mut self,
+        fragment: RemoteTrackOnObjectFragment,
+    ) -> Vec<RemoteTrackOnObjectFragment> {
+        let object_header = fragment.object_header;
+        let group_id = object_header.group_id;
+        let mut ready = vec![];
+        if !self.next_object_ids.contains_key(&group_id)
+            && self.next_object_ids.len() >= MAX_REORDER_GROUPS
+        {
+            match self.next_object_ids.first_key_value() {
+                Some((&oldest, _)) if oldest < group_id => {
+                    ready = self.drain_group(oldest, u64::MAX);
+                    self.next_object_ids.remove(&oldest);
+                }
+                _ => return vec![fragment],
+            }
+        }
+        let mut next_object_id = self.next_object_ids.get(&group_id).copied().unwrap_or(0);
+        if !fragment.fin || object_header.object_id < next_object_id {
+            ready.push(fragment);
+            return ready;
+        }
+        self.reorder_buffer.insert(
+            FullSequence::new(group_id, object_header.object_id),
+            fragment,
+        );
+
+        if object_header.object_status == ObjectStatus::EndOfGroup {
+            next_object_id = u64::MAX;
+        } else if object_header.object_id > next_object_id + MAX_REORDER_GAP {
+            next_object_id = object_header.object_id - MAX_REORDER_GAP;
+        }
+        ready.extend(self.drain_group(group_id, next_object_id));
+        ready
+    }
+
+    /// When the object held longest for reordering gives up on its gap.
+    pub fn next_reorder_deadline(&self) -> Option<Instant> {
+        self.reorder_buffer
+            .values()
+            .map(|fragment| fragment.received_at + MAX_REORDER_DELAY)
+            .min()
+    }
+
+    /// Gives up on the objects missing in front of the ones held
+    /// [`MAX_REORDER_DELAY`] or longer, and returns the objects now ready in
+    /// group order.
+    pub fn release_held_objects(&mut self, now: Instant) -> Vec<RemoteTrackOnObjectFragment> {
+        let mut release_through = BTreeMap::new();
+        for (sequence, fragment) in &self.reorder_buffer {
+            if fragment.received_at + MAX_REORDER_DELAY <= now {
+                release_through.insert(sequence.group_id, sequence.object_id);
+            }
+        }
+        let mut ready = vec![];
+        for (group_id, object_id) in release_through {
+            ready.extend(self.drain_group(group_id, object_id));
+        }
+        ready
+    }
+
+    /// Records that a group ended with `object_count` objects, for
+    /// [`RemoteTrack::take_completed_groups`].
+    pub fn complete_group(&mut self, group_id: u64, object_count: u64) {
+        self.completed_groups.insert(group_id, object_count);
+    }
+
+    /// Returns the groups given to [`RemoteTrack::complete_group`], with
+    /// their object counts, once the reorder buffer holds none of their
+    /// objects.
+    pub fn take_completed_groups(&mut self) -> Vec<(u64, u64)> {
+        let drained = self
+            .completed_groups
+            .keys()
+            .copied()
+            .filter(|group_id| {
+                self.reorder_buffer
+                    .range(group_sequences(*group_id))
+                    .next()
+                    .is_none()
+            })
+            .collect::<Vec<_>>();
+        drained
+            .into_iter()
+            .filter_map(|group_id| {
+                let object_count = self.completed_groups.remove(&group_id)?;
+                Some((group_id, object_count))
+            })
+            .collect()
+    }
+
+    fn push_ordered(&mut self, fragments: Vec<RemoteTrackOnObjectFragment>) {
+        for fragment in fragments {
+            let object_header = fragment.object_header;
+            self.ordered_objects.insert(
+                FullSequence::new(object_header.group_id, object_header.object_id),
+                (object_header, fragment.payload),
+            );
+        }
+    }
+
+    // Hands out the held objects of a group up to and including
+    // `next_object_id`, then those following on without a gap. The group
+    // keeps its position so stragglers pass straight through.
+    fn drain_group(
+        &mut self,
+        group_id: u64,
+        next_object_id: u64,
+    ) -> Vec<RemoteTrackOnObjectFragment> {
+        let mut next_object_id =
+            next_object_id.max(self.next_object_ids.get(&group_id).copied().unwrap_or(0));
+        let mut ready = vec![];
+        while let Some((&sequence, _)) = self.reorder_buffer.range(group_sequences(group_id)).next()
+        {
+            if sequence.object_id > next_object_id {
+                break;
+            }
+            ready.extend(self.reorder_buffer.remove(&sequence));
+            next_object_id = next_object_id.max(sequence.object_id.saturating_add(1));
+        }
+        self.next_object_ids.insert(group_id, next_object_id);
+        ready
+    }
+}
+
+fn group_sequences(group_id: u64) -> std::ops::RangeInclusive<FullSequence> {
+    FullSequence::new(group_id, 0)..=FullSequence::new(group_id, u64::MAX)
 }
 
 #[cfg(test)]
@@ -93,4 +471,358 @@ mod test {
         assert!(!track.check_forwarding_preference(ObjectForwardingPreference::Datagram));
         Ok(())
     }
+
+    #[test]
+    fn test_remote_track_test_group_completion() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        let mut header = ObjectHeader {
+            group_id: 3,
+            object_forwarding_preference: ObjectForwardingPreference::Group,
+            ..Default::default()
+        };
+        assert_eq!(track.on_object_received(&header, false), None);
+        assert_eq!(track.on_object_received(&header, true), None);
+        header.object_id = 1;
+        assert_eq!(track.on_object_received(&header, true), None);
+        header.group_id = 4;
+        assert_eq!(track.on_object_received(&header, true), None);
+
+        header.group_id = 3;
+        header.object_id = 2;
+        header.object_status = ObjectStatus::EndOfGroup;
+        assert_eq!(track.on_object_received(&header, true), Some(2));
+        header.group_id = 4;
+        assert_eq!(track.on_object_received(&header, true), Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_track_test_group_counts_are_bounded() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        let mut header = ObjectHeader {
+            object_forwarding_preference: ObjectForwardingPreference::Group,
+            ..Default::default()
+        };
+        // None of the groups end, as when their EndOfGroup objects are lost.
+        for group_id in 0..MAX_COUNTED_GROUPS as u64 + 2 {
+            header.group_id = group_id;
+            assert_eq!(track.on_object_received(&header, true), None);
+        }
+        assert_eq!(track.group_object_counts.len(), MAX_COUNTED_GROUPS);
+
+        header.object_status = ObjectStatus::EndOfGroup;
+        header.group_id = 1;
+        assert_eq!(track.on_object_received(&header, true), None);
+        header.group_id = MAX_COUNTED_GROUPS as u64 + 1;
+        assert_eq!(track.on_object_received(&header, true), Some(1));
+        Ok(())
+    }
+
+    fn object(group_id: u64, object_id: u64, status: ObjectStatus) -> RemoteTrackOnObjectFragment {
+        RemoteTrackOnObjectFragment {
+            object_header: ObjectHeader {
+                group_id,
+                object_id,
+                object_status: status,
+                object_forwarding_preference: ObjectForwardingPreference::Object,
+                ..Default::default()
+            },
+            extension_headers: Bytes::new(),
+            payload: Bytes::new(),
+            fin: true,
+            received_at: Instant::now(),
+        }
+    }
+
+    fn object_ids(fragments: Vec<RemoteTrackOnObjectFragment>) -> Vec<u64> {
+        fragments
+            .iter()
+            .map(|fragment| fragment.object_header.object_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_remote_track_test_reorder() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        assert_eq!(
+            object_ids(track.reorder(object(3, 1, ObjectStatus::Normal))),
+            vec![]
+        );
+        assert_eq!(
+            object_ids(track.reorder(object(4, 0, ObjectStatus::Normal))),
+            vec![0]
+        );
+        assert_eq!(
+            object_ids(track.reorder(object(3, 0, ObjectStatus::Normal))),
+            vec![0, 1]
+        );
+
+        // EndOfGroup hands out whatever is held, gaps and all.
+        assert_eq!(
+            object_ids(track.reorder(object(3, 4, ObjectStatus::Normal))),
+            vec![]
+        );
+        assert_eq!(
+            object_ids(track.reorder(object(3, 6, ObjectStatus::EndOfGroup))),
+            vec![4, 6]
+        );
+        assert_eq!(
+            object_ids(track.reorder(object(3, 3, ObjectStatus::Normal))),
+            vec![3]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_track_test_reorder_releases_after_delay() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        let held = object(3, 2, ObjectStatus::Normal);
+        let received_at = held.received_at;
+        assert_eq!(object_ids(track.reorder(held)), vec![]);
+        assert_eq!(
+            track.next_reorder_deadline(),
+            Some(received_at + MAX_REORDER_DELAY)
+        );
+        assert_eq!(
+            object_ids(track.release_held_objects(received_at + MAX_REORDER_DELAY / 2)),
+            vec![]
+        );
+        assert_eq!(
+            object_ids(track.release_held_objects(received_at + MAX_REORDER_DELAY)),
+            vec![2]
+        );
+        assert_eq!(track.next_reorder_deadline(), None);
+
+        // The objects given up on pass straight through when they turn up.
+        assert_eq!(
+            object_ids(track.reorder(object(3, 1, ObjectStatus::Normal))),
+            vec![1]
+        );
+        assert_eq!(
+            object_ids(track.reorder(object(3, 4, ObjectStatus::Normal))),
+            vec![]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_track_test_reorder_tracks_bounded_groups() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        assert_eq!(
+            object_ids(track.reorder(object(0, 1, ObjectStatus::Normal))),
+            vec![]
+        );
+        for group_id in 1..MAX_REORDER_GROUPS as u64 {
+            assert_eq!(
+                object_ids(track.reorder(object(group_id, 0, ObjectStatus::Normal))),
+                vec![0]
+            );
+        }
+        assert_eq!(track.next_object_ids.len(), MAX_REORDER_GROUPS);
+
+        // A new group pushes out the oldest, releasing what it held.
+        let group_id = MAX_REORDER_GROUPS as u64;
+        assert_eq!(
+            object_ids(track.reorder(object(group_id, 0, ObjectStatus::Normal))),
+            vec![1, 0]
+        );
+        assert_eq!(track.next_object_ids.len(), MAX_REORDER_GROUPS);
+        assert!(!track.next_object_ids.contains_key(&0));
+
+        // Objects of a group no longer tracked pass straight through.
+        assert_eq!(
+            object_ids(track.reorder(object(0, 3, ObjectStatus::Normal))),
+            vec![3]
+        );
+        assert_eq!(track.next_object_ids.len(), MAX_REORDER_GROUPS);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_track_test_completed_group_waits_for_held_objects() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        let held = object(3, 2, ObjectStatus::Normal);
+        let received_at = held.received_at;
+        assert_eq!(object_ids(track.reorder(held)), vec![]);
+        track.complete_group(3, 3);
+        track.complete_group(4, 1);
+        assert_eq!(track.take_completed_groups(), vec![(4, 1)]);
+
+        assert_eq!(
+            object_ids(track.release_held_objects(received_at + MAX_REORDER_DELAY)),
+            vec![2]
+        );
+        assert_eq!(track.take_completed_groups(), vec![(3, 3)]);
+        assert_eq!(track.take_completed_groups(), vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_track_test_poll_ordered() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        let now = Instant::now();
+        for (group_id, object_id) in [(3, 2), (4, 0), (3, 1)] {
+            let fragment = object(group_id, object_id, ObjectStatus::Normal);
+            track.insert_object(
+                fragment.object_header,
+                Bytes::from(format!("{}/{}", group_id, object_id)),
+                now,
+            );
+        }
+        // Group 3 is still missing object 0, which holds back group 4 too.
+        assert_eq!(track.poll_ordered(now), None);
+        assert_eq!(track.buffered_bytes(), 9);
+
+        let fragment = object(3, 0, ObjectStatus::Normal);
+        track.insert_object(fragment.object_header, Bytes::from_static(b"3/0"), now);
+        let mut drained = vec![];
+        while let Some((object_header, payload)) = track.poll_ordered(now) {
+            assert_eq!(
+                payload,
+                Bytes::from(format!(
+                    "{}/{}",
+                    object_header.group_id, object_header.object_id
+                ))
+            );
+            drained.push(FullSequence::new(
+                object_header.group_id,
+                object_header.object_id,
+            ));
+        }
+        assert_eq!(
+            drained,
+            vec![
+                FullSequence::new(3, 0),
+                FullSequence::new(3, 1),
+                FullSequence::new(3, 2),
+                FullSequence::new(4, 0)
+            ]
+        );
+        assert_eq!(track.buffered_bytes(), 0);
+
+        // An object behind the ones already returned is dropped.
+        let fragment = object(3, 5, ObjectStatus::Normal);
+        track.insert_object(fragment.object_header, Bytes::from_static(b"3/5"), now);
+        assert_eq!(track.poll_ordered(now), None);
+        assert_eq!(track.buffered_bytes(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_track_test_poll_ordered_gives_up_on_lost_object() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        let now = Instant::now();
+        // Object 3/0 is lost.
+        for (group_id, object_id) in [(3, 1), (4, 0), (5, 0)] {
+            let fragment = object(group_id, object_id, ObjectStatus::Normal);
+            track.insert_object(fragment.object_header, Bytes::new(), now);
+        }
+        assert_eq!(track.poll_ordered(now), None);
+        assert_eq!(track.poll_ordered(now + MAX_REORDER_DELAY / 2), None);
+
+        let mut drained = vec![];
+        while let Some((object_header, _)) = track.poll_ordered(now + MAX_REORDER_DELAY) {
+            drained.push(FullSequence::new(
+                object_header.group_id,
+                object_header.object_id,
+            ));
+        }
+        assert_eq!(
+            drained,
+            vec![
+                FullSequence::new(3, 1),
+                FullSequence::new(4, 0),
+                FullSequence::new(5, 0)
+            ]
+        );
+
+        // The lost object turning up late is dropped.
+        let fragment = object(3, 0, ObjectStatus::Normal);
+        track.insert_object(
+            fragment.object_header,
+            Bytes::new(),
+            now + MAX_REORDER_DELAY,
+        );
+        assert_eq!(track.poll_ordered(now + MAX_REORDER_DELAY), None);
+        Ok(())
+    }
+
+    fn datagram_fragment(index: u64, count: u64, payload: &'static [u8]) -> DatagramFragment {
+        DatagramFragment {
+            object_header: ObjectHeader {
+                group_id: 2,
+                object_id: 7,
+                object_forwarding_preference: ObjectForwardingPreference::Datagram,
+                ..Default::default()
+            },
+            fragment_index: index,
+            fragment_count: count,
+            payload: Bytes::from_static(payload),
+        }
+    }
+
+    #[test]
+    fn test_remote_track_test_datagram_fragments() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        let now = Instant::now();
+        assert_eq!(
+            track.on_datagram_fragment(datagram_fragment(2, 3, b"ef"), now)?,
+            None
+        );
+        assert_eq!(
+            track.on_datagram_fragment(datagram_fragment(0, 3, b"ab"), now)?,
+            None
+        );
+        // A fragment claiming a different split of the same object.
+        assert!(track
+            .on_datagram_fragment(datagram_fragment(1, 2, b"cd"), now)
+            .is_err());
+        assert_eq!(track.buffered_bytes(), 4);
+        assert_eq!(
+            track.next_datagram_expiry(),
+            Some(now + DATAGRAM_REASSEMBLY_TIMEOUT)
+        );
+
+        let (object_header, payload) = track
+            .on_datagram_fragment(datagram_fragment(1, 3, b"cd"), now)?
+            .unwrap();
+        assert_eq!(
+            FullSequence::new(object_header.group_id, object_header.object_id),
+            FullSequence::new(2, 7)
+        );
+        assert_eq!(payload, Bytes::from_static(b"abcdef"));
+        assert_eq!(track.next_datagram_expiry(), None);
+
+        track.on_datagram_fragment(datagram_fragment(0, 2, b"ab"), now)?;
+        assert_eq!(track.expire_datagram_fragments(now), vec![]);
+        assert_eq!(
+            track.expire_datagram_fragments(now + DATAGRAM_REASSEMBLY_TIMEOUT),
+            vec![FullSequence::new(2, 7)]
+        );
+        assert_eq!(track.buffered_bytes(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_track_test_reorder_gap_bound() -> Result<()> {
+        let track = &mut RemoteTrackTest::new().track;
+        assert_eq!(
+            object_ids(track.reorder(object(0, 1, ObjectStatus::Normal))),
+            vec![]
+        );
+        assert_eq!(
+            object_ids(track.reorder(object(0, MAX_REORDER_GAP, ObjectStatus::Normal))),
+            vec![]
+        );
+        assert_eq!(
+            object_ids(track.reorder(object(0, MAX_REORDER_GAP + 1, ObjectStatus::Normal))),
+            vec![1]
+        );
+        // Object 0 was given up on and now passes straight through.
+        assert_eq!(
+            object_ids(track.reorder(object(0, 0, ObjectStatus::Normal))),
+            vec![0]
+        );
+        Ok(())
+    }
 }
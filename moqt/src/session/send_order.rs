@@ -0,0 +1,59 @@
+use crate::message::FullSequence;
+
+// GroupThenObject packs the group into the bits above the object, keeping the
+// result within the 62 bits a varint can carry.
+const OBJECT_ID_BITS: u32 = 32;
+const MAX_OBJECT_ID: u64 = (1 << OBJECT_ID_BITS) - 1;
+const MAX_GROUP_ID: u64 = (1 << (62 - OBJECT_ID_BITS)) - 1;
+
+/// Assigns the send order of published objects from their sequence. Objects
+/// with a lower send order are written first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOrderPolicy {
+    /// Earlier groups go first, and objects within a group in order. Group
+    /// and object IDs too large to pack saturate.
+    GroupThenObject,
+    /// Every object gets the same send order, so objects are written in the
+    /// order they are published.
+    Fixed(u64),
+}
+
+impl SendOrderPolicy {
+    pub fn send_order(&self, sequence: FullSequence) -> u64 {
+        match *self {
+            SendOrderPolicy::GroupThenObject => {
+                (sequence.group_id.min(MAX_GROUP_ID) << OBJECT_ID_BITS)
+                    | sequence.object_id.min(MAX_OBJECT_ID)
+            }
+            SendOrderPolicy::Fixed(send_order) => send_order,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_group_then_object_send_order() {
+        let policy = SendOrderPolicy::GroupThenObject;
+        assert_eq!(policy.send_order(FullSequence::new(0, 0)), 0);
+        assert_eq!(policy.send_order(FullSequence::new(0, 7)), 7);
+        assert_eq!(policy.send_order(FullSequence::new(1, 0)), 1 << 32);
+        assert!(
+            policy.send_order(FullSequence::new(1, 0))
+                > policy.send_order(FullSequence::new(0, u64::MAX))
+        );
+        assert_eq!(
+            policy.send_order(FullSequence::new(u64::MAX, u64::MAX)),
+            (1 << 62) - 1
+        );
+    }
+
+    #[test]
+    fn test_fixed_send_order() {
+        let policy = SendOrderPolicy::Fixed(5);
+        assert_eq!(policy.send_order(FullSequence::new(0, 0)), 5);
+        assert_eq!(policy.send_order(FullSequence::new(9, 3)), 5);
+    }
+}
@@ -20,11 +20,19 @@ pub struct SubscribeWindow {
     // Store open streams for this subscription. If the forwarding preference is
     // kTrack, there is one entry under sequence (0, 0). If kGroup, each entry is
     // under (group, 0). If kObject, it's tracked under the full sequence. If
-    // kDatagram, the map is empty.
-    send_streams: HashMap<FullSequence, StreamId>,
+    // kDatagram, the map is empty. The second key component is the send order
+    // when stream_per_send_order is set, and 0 otherwise.
+    send_streams: HashMap<(FullSequence, u64), StreamId>,
     // The forwarding preference for this track; informs how the streams are
     // mapped.
     forwarding_preference: ObjectForwardingPreference,
+    // If true, objects that would share a stream under the forwarding
+    // preference get a separate stream per send order, so a higher priority
+    // object is never queued behind lower priority data. kTrack then uses one
+    // stream per send order, kGroup one per (group, send order). kObject
+    // already has a stream per object and kDatagram has none, so neither is
+    // affected.
+    stream_per_send_order: bool,
 }
 
 impl SubscribeWindow {
@@ -40,17 +48,24 @@ impl SubscribeWindow {
             start,
             end,
             largest_delivered: None,
-            next_to_backfill: if start < next_object {
-                Some(start)
-            } else {
-                None
-            },
+            // A start at or beyond next_object is in the future: nothing
+            // already published is owed, so delivery begins when it arrives.
+            next_to_backfill: (start < next_object).then_some(start),
             original_next_object: next_object,
             send_streams: Default::default(),
             forwarding_preference,
+            stream_per_send_order: false,
         }
     }
 
+    pub fn set_stream_per_send_order(&mut self, stream_per_send_order: bool) {
+        self.stream_per_send_order = stream_per_send_order;
+    }
+
+    pub fn stream_per_send_order(&self) -> bool {
+        self.stream_per_send_order
+    }
+
     pub fn subscribe_id(&self) -> u64 {
         self.subscribe_id
     }
@@ -69,23 +84,48 @@ impl SubscribeWindow {
 
     /// Returns the stream to send |sequence| on, if already opened.
     pub fn get_stream_for_sequence(&self, seq: FullSequence) -> Option<&StreamId> {
-        let index = self.sequence_to_index(seq);
+        self.get_stream_for_sequence_and_send_order(seq, 0)
+    }
+
+    /// Like get_stream_for_sequence, but picks the stream for |send_order| when
+    /// streams are split per send order.
+    pub fn get_stream_for_sequence_and_send_order(
+        &self,
+        seq: FullSequence,
+        send_order: u64,
+    ) -> Option<&StreamId> {
+        let index = self.stream_index(seq, send_order);
         self.send_streams.get(&index)
     }
 
     /// Records what stream is being used for a track, group, or object depending
     /// on |forwarding_preference|. Triggers QUIC_BUG if already assigned.
     pub fn add_stream(&mut self, group_id: u64, object_id: u64, stream_id: StreamId) -> Result<()> {
+        self.add_stream_with_send_order(group_id, object_id, 0, stream_id)
+    }
+
+    /// Like add_stream, but records the stream under |send_order| when streams
+    /// are split per send order.
+    pub fn add_stream_with_send_order(
+        &mut self,
+        group_id: u64,
+        object_id: u64,
+        send_order: u64,
+        stream_id: StreamId,
+    ) -> Result<()> {
         if !self.in_window(FullSequence {
             group_id,
             object_id,
         }) {
             return Ok(());
         }
-        let index = self.sequence_to_index(FullSequence {
-            group_id,
-            object_id,
-        });
+        let index = self.stream_index(
+            FullSequence {
+                group_id,
+                object_id,
+            },
+            send_order,
+        );
         if self.forwarding_preference == ObjectForwardingPreference::Datagram {
             return Err(Error::ErrOther("Adding a stream for datagram".to_string()));
         }
@@ -97,10 +137,22 @@ impl SubscribeWindow {
     }
 
     pub fn remove_stream(&mut self, group_id: u64, object_id: u64) {
-        let index = self.sequence_to_index(FullSequence {
-            group_id,
-            object_id,
-        });
+        self.remove_stream_with_send_order(group_id, object_id, 0);
+    }
+
+    pub fn remove_stream_with_send_order(
+        &mut self,
+        group_id: u64,
+        object_id: u64,
+        send_order: u64,
+    ) {
+        let index = self.stream_index(
+            FullSequence {
+                group_id,
+                object_id,
+            },
+            send_order,
+        );
         self.send_streams.remove(&index);
     }
 
@@ -127,21 +179,7 @@ impl SubscribeWindow {
         if sequence < self.original_next_object {
             if let Some(next_to_backfill) = self.next_to_backfill.as_ref() {
                 if *next_to_backfill <= sequence {
-                    match status {
-                        ObjectStatus::Normal | ObjectStatus::ObjectDoesNotExist => {
-                            self.next_to_backfill = Some(sequence.next());
-                        }
-                        ObjectStatus::EndOfGroup => {
-                            self.next_to_backfill = Some(FullSequence {
-                                group_id: sequence.group_id + 1,
-                                object_id: 0,
-                            });
-                        }
-                        _ => {
-                            // Includes kEndOfTrack.
-                            self.next_to_backfill = None;
-                        }
-                    }
+                    self.next_to_backfill = sequence.advance(status);
                 }
             }
 
@@ -163,6 +201,24 @@ impl SubscribeWindow {
         self.largest_delivered
     }
 
+    /// Returns true if publishing |seq| now would send it on this
+    /// subscription: it is in the window and either beyond the largest
+    /// delivered object or still owed as a backfill.
+    pub fn would_deliver(&self, seq: FullSequence) -> bool {
+        if !self.in_window(seq) {
+            return false;
+        }
+        if self
+            .largest_delivered
+            .is_none_or(|largest_delivered| largest_delivered < seq)
+        {
+            return true;
+        }
+        self.next_to_backfill
+            .is_some_and(|next_to_backfill| next_to_backfill <= seq)
+            && seq < self.original_next_object
+    }
+
     /// Returns true if the updated values are valid.
     pub fn update_start_end(&mut self, start: FullSequence, end: Option<FullSequence>) -> bool {
         // Can't make the subscription window bigger.
@@ -184,6 +240,21 @@ impl SubscribeWindow {
         true
     }
 
+    // Adds the send order to the sequence index if streams are split per send
+    // order.
+    fn stream_index(&self, sequence: FullSequence, send_order: u64) -> (FullSequence, u64) {
+        let send_order = if self.stream_per_send_order
+            && matches!(
+                self.forwarding_preference,
+                ObjectForwardingPreference::Track | ObjectForwardingPreference::Group
+            ) {
+            send_order
+        } else {
+            0
+        };
+        (self.sequence_to_index(sequence), send_order)
+    }
+
     // Converts an object sequence number into one that matches the way that
     // stream IDs are being mapped. (See the comment for send_streams_ below.)
     fn sequence_to_index(&self, sequence: FullSequence) -> FullSequence {
@@ -208,8 +279,70 @@ impl SubscribeWindow {
     }
 }
 
+// A set of subscribe windows nested inside the outermost one. Objects outside
+// [start, end] can skip every member window without looking at it, and those
+// inside skip the members that start after them. A lookup still visits every
+// member that starts at or before the object, so an open-ended window that
+// absorbs many later windows makes lookups past them linear in their number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CoalescedWindows {
+    start: FullSequence,
+    end: Option<FullSequence>,
+    // Member windows as (start, subscribe_id), sorted by start.
+    members: Vec<(FullSequence, u64)>,
+}
+
+impl CoalescedWindows {
+    fn insert_member(&mut self, start: FullSequence, subscribe_id: u64) {
+        let index = self
+            .members
+            .partition_point(|(member_start, _)| *member_start <= start);
+        self.members.insert(index, (start, subscribe_id));
+    }
+
+    fn absorb(&mut self, other: &CoalescedWindows) {
+        for &(start, subscribe_id) in &other.members {
+            self.insert_member(start, subscribe_id);
+        }
+    }
+
+    // The members starting at or before |sequence|.
+    fn started_members(&self, sequence: FullSequence) -> &[(FullSequence, u64)] {
+        let started = self
+            .members
+            .partition_point(|(start, _)| *start <= sequence);
+        &self.members[..started]
+    }
+
+    fn contains(&self, start: FullSequence, end: Option<FullSequence>) -> bool {
+        range_contains((self.start, self.end), (start, end))
+    }
+
+    fn is_contained_by(&self, start: FullSequence, end: Option<FullSequence>) -> bool {
+        range_contains((start, end), (self.start, self.end))
+    }
+}
+
+// Returns true if |inner| lies within |outer|. A missing end is open-ended.
+fn range_contains(
+    outer: (FullSequence, Option<FullSequence>),
+    inner: (FullSequence, Option<FullSequence>),
+) -> bool {
+    outer.0 <= inner.0
+        && match (outer.1, inner.1) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(outer_end), Some(inner_end)) => inner_end <= outer_end,
+        }
+}
+
 pub struct SubscribeWindows {
     windows: HashMap<u64, SubscribeWindow>,
+    // Windows grouped so that each group's members nest inside its range. Every
+    // subscribe_id in |windows| appears in exactly one group. No group's range
+    // contains another's, so sorted by start the ends increase too, and the
+    // groups holding a sequence form one run found by binary search.
+    coalesced: Vec<CoalescedWindows>,
     forwarding_preference: ObjectForwardingPreference,
 }
 
@@ -217,26 +350,36 @@ impl SubscribeWindows {
     pub fn new(forwarding_preference: ObjectForwardingPreference) -> Self {
         Self {
             windows: HashMap::new(),
+            coalesced: vec![],
             forwarding_preference,
         }
     }
 
-    /// Returns a vector of subscribe IDs that apply to the object. They will be in
-    /// reverse order of the add_window calls.
+    /// Returns the windows that apply to the object, in no particular order.
     pub fn sequence_is_subscribed(&self, sequence: FullSequence) -> Vec<&SubscribeWindow> {
         let mut retval = vec![];
 
-        for window in self.windows.values() {
-            if window.in_window(sequence) {
-                retval.push(window)
+        let started = self
+            .coalesced
+            .partition_point(|group| group.start <= sequence);
+        let ended = self.coalesced[..started]
+            .partition_point(|group| group.end.is_some_and(|end| end < sequence));
+        for group in &self.coalesced[ended..started] {
+            for (_, subscribe_id) in group.started_members(sequence) {
+                if let Some(window) = self.windows.get(subscribe_id) {
+                    if window.in_window(sequence) {
+                        retval.push(window)
+                    }
+                }
             }
         }
 
         retval
     }
 
-    /// |start_group| and |start_object| must be absolute sequence numbers. An
-    /// optimization could consolidate overlapping subscribe windows.
+    /// |start_group| and |start_object| must be absolute sequence numbers. A
+    /// window nested inside an existing one, or containing existing ones, is
+    /// coalesced with them so that lookups skip the whole set at once.
     pub fn add_window(
         &mut self,
         subscribe_id: u64,
@@ -244,6 +387,7 @@ impl SubscribeWindows {
         start: FullSequence,
         end: Option<FullSequence>,
     ) {
+        self.remove_window(subscribe_id);
         self.windows.insert(
             subscribe_id,
             SubscribeWindow::new(
@@ -254,10 +398,116 @@ impl SubscribeWindows {
                 end,
             ),
         );
+        self.coalesce(subscribe_id, start, end);
+    }
+
+    /// Narrows the window of |subscribe_id| as SubscribeWindow::update_start_end
+    /// does, and regroups it to match. Returns false if there is no such
+    /// window or the update would widen it.
+    pub fn update_window(
+        &mut self,
+        subscribe_id: u64,
+        start: FullSequence,
+        end: Option<FullSequence>,
+    ) -> bool {
+        let Some(window) = self.windows.get_mut(&subscribe_id) else {
+            return false;
+        };
+        if !window.update_start_end(start, end) {
+            return false;
+        }
+        self.uncoalesce(subscribe_id);
+        self.coalesce(subscribe_id, start, end);
+        true
+    }
+
+    // Adds |subscribe_id|, covering |start| to |end|, to a group.
+    fn coalesce(&mut self, subscribe_id: u64, start: FullSequence, end: Option<FullSequence>) {
+        if let Some(group) = self
+            .coalesced
+            .iter_mut()
+            .find(|group| group.contains(start, end))
+        {
+            group.insert_member(start, subscribe_id);
+            return;
+        }
+
+        // The new window becomes the outer range of every group it contains.
+        let mut group = CoalescedWindows {
+            start,
+            end,
+            members: vec![(start, subscribe_id)],
+        };
+        self.coalesced.retain(|existing| {
+            if existing.is_contained_by(start, end) {
+                group.absorb(existing);
+                false
+            } else {
+                true
+            }
+        });
+        self.insert_coalesced(group);
+    }
+
+    // Keeps |coalesced| sorted by start.
+    fn insert_coalesced(&mut self, group: CoalescedWindows) {
+        let index = self
+            .coalesced
+            .partition_point(|existing| existing.start < group.start);
+        self.coalesced.insert(index, group);
     }
 
     pub fn remove_window(&mut self, subscribe_id: u64) {
-        self.windows.remove(&subscribe_id);
+        if self.windows.remove(&subscribe_id).is_none() {
+            return;
+        }
+        self.uncoalesce(subscribe_id);
+    }
+
+    // Takes |subscribe_id| out of its group, shrinking the group's range to
+    // the members left in |windows|.
+    fn uncoalesce(&mut self, subscribe_id: u64) {
+        let Some(index) = self
+            .coalesced
+            .iter()
+            .position(|group| group.members.iter().any(|(_, id)| *id == subscribe_id))
+        else {
+            return;
+        };
+        let mut group = self.coalesced.remove(index);
+        group.members.retain(|(_, id)| *id != subscribe_id);
+        if group.members.is_empty() {
+            return;
+        }
+        // Shrink the range to the remaining members; it stays a superset of each.
+        let mut start: Option<FullSequence> = None;
+        let mut end = Some(FullSequence::default());
+        for window in group
+            .members
+            .iter()
+            .filter_map(|(_, id)| self.windows.get(id))
+        {
+            if start.is_none_or(|start| window.start < start) {
+                start = Some(window.start);
+            }
+            end = match (end, window.end) {
+                (Some(end), Some(window_end)) if end < window_end => Some(window_end),
+                (Some(end), Some(_)) => Some(end),
+                _ => None,
+            };
+        }
+        group.start = start.unwrap_or_default();
+        group.end = end;
+        // The narrower range may now fit inside another group.
+        if let Some(container) = self
+            .coalesced
+            .iter_mut()
+            .find(|existing| existing.contains(group.start, group.end))
+        {
+            container.absorb(&group);
+            return;
+        }
+        self.insert_coalesced(group);
     }
 
     pub fn is_empty(&self) -> bool {
@@ -315,6 +565,63 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_subscribe_window_test_would_deliver() -> Result<()> {
+        let test = SubscribeWindowTest::new();
+        let mut window = SubscribeWindow::new(
+            test.subscribe_id,
+            ObjectForwardingPreference::Object,
+            test.right_edge,
+            test.start,
+            Some(test.end),
+        );
+        assert!(window.would_deliver(FullSequence::new(4, 0)));
+        assert!(!window.on_object_sent(FullSequence::new(4, 7), ObjectStatus::Normal));
+
+        // Below largest_delivered, but still owed as a backfill.
+        assert!(window.would_deliver(FullSequence::new(4, 2)));
+        // Below largest_delivered and already past the backfill range.
+        assert!(!window.would_deliver(FullSequence::new(4, 6)));
+        // Ahead of largest_delivered, within the window.
+        assert!(window.would_deliver(FullSequence::new(4, 8)));
+        assert!(window.would_deliver(FullSequence::new(5, 5)));
+        // Outside the window.
+        assert!(!window.would_deliver(FullSequence::new(3, 12)));
+        assert!(!window.would_deliver(FullSequence::new(5, 6)));
+
+        for object_id in 0..5 {
+            window.on_object_sent(FullSequence::new(4, object_id), ObjectStatus::Normal);
+        }
+        assert!(!window.would_deliver(FullSequence::new(4, 2)));
+        assert!(window.would_deliver(FullSequence::new(4, 8)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_subscribe_window_test_future_start() -> Result<()> {
+        let test = SubscribeWindowTest::new();
+        let future_start = FullSequence::new(6, 0);
+        let mut window = SubscribeWindow::new(
+            test.subscribe_id,
+            ObjectForwardingPreference::Group,
+            test.right_edge,
+            future_start,
+            Some(FullSequence::new(6, 2)),
+        );
+        assert_eq!(window.next_to_backfill, None);
+        assert!(!window.would_deliver(test.right_edge));
+        assert!(!window.would_deliver(FullSequence::new(5, 9)));
+        assert!(window.would_deliver(future_start));
+
+        assert!(!window.on_object_sent(future_start, ObjectStatus::Normal));
+        assert_eq!(window.largest_delivered(), Some(future_start));
+        assert_eq!(window.next_to_backfill, None);
+        assert!(!window.on_object_sent(FullSequence::new(6, 1), ObjectStatus::Normal));
+        // Nothing to backfill, so reaching the end completes the subscription.
+        assert!(window.on_object_sent(FullSequence::new(6, 2), ObjectStatus::Normal));
+        Ok(())
+    }
+
     #[test]
     fn test_subscribe_window_test_add_query_remove_stream_id_track() -> Result<()> {
         let test = SubscribeWindowTest::new();
@@ -335,9 +642,9 @@ mod test {
             Some(2).as_ref()
         );
         window.remove_stream(7, 2);
-        assert!(!window
+        assert!(window
             .get_stream_for_sequence(FullSequence::new(4, 0))
-            .is_some());
+            .is_none());
         Ok(())
     }
 
@@ -352,9 +659,9 @@ mod test {
             Some(test.end),
         );
         assert!(window.add_stream(4, 0, 2).is_ok());
-        assert!(!window
+        assert!(window
             .get_stream_for_sequence(FullSequence::new(5, 0))
-            .is_some());
+            .is_none());
         assert!(window.add_stream(5, 2, 6).is_ok());
         assert_eq!(
             Error::ErrOther("Stream already added".to_string()),
@@ -369,9 +676,9 @@ mod test {
             Some(6).as_ref()
         );
         window.remove_stream(5, 1);
-        assert!(!window
+        assert!(window
             .get_stream_for_sequence(FullSequence::new(5, 2))
-            .is_some());
+            .is_none());
         Ok(())
     }
 
@@ -400,16 +707,73 @@ mod test {
             window.get_stream_for_sequence(FullSequence::new(4, 2)),
             Some(10).as_ref()
         );
-        assert!(!window
+        assert!(window
             .get_stream_for_sequence(FullSequence::new(4, 4))
-            .is_some());
-        assert!(!window
+            .is_none());
+        assert!(window
             .get_stream_for_sequence(FullSequence::new(5, 0))
-            .is_some());
+            .is_none());
         window.remove_stream(4, 2);
-        assert!(!window
+        assert!(window
             .get_stream_for_sequence(FullSequence::new(4, 2))
-            .is_some());
+            .is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_subscribe_window_test_stream_per_send_order_group() -> Result<()> {
+        let test = SubscribeWindowTest::new();
+        let mut window = SubscribeWindow::new(
+            test.subscribe_id,
+            ObjectForwardingPreference::Group,
+            test.right_edge,
+            test.start,
+            Some(test.end),
+        );
+        window.set_stream_per_send_order(true);
+        assert!(window.add_stream_with_send_order(4, 0, 1, 2).is_ok());
+        assert!(window.add_stream_with_send_order(4, 1, 7, 6).is_ok());
+        assert_eq!(
+            window.get_stream_for_sequence_and_send_order(FullSequence::new(4, 3), 1),
+            Some(2).as_ref()
+        );
+        assert_eq!(
+            window.get_stream_for_sequence_and_send_order(FullSequence::new(4, 3), 7),
+            Some(6).as_ref()
+        );
+        assert!(window
+            .get_stream_for_sequence_and_send_order(FullSequence::new(5, 0), 1)
+            .is_none());
+        window.remove_stream_with_send_order(4, 2, 7);
+        assert!(window
+            .get_stream_for_sequence_and_send_order(FullSequence::new(4, 1), 7)
+            .is_none());
+        assert_eq!(
+            window.get_stream_for_sequence_and_send_order(FullSequence::new(4, 1), 1),
+            Some(2).as_ref()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_subscribe_window_test_send_order_ignored_by_default() -> Result<()> {
+        let test = SubscribeWindowTest::new();
+        let mut window = SubscribeWindow::new(
+            test.subscribe_id,
+            ObjectForwardingPreference::Track,
+            test.right_edge,
+            test.start,
+            Some(test.end),
+        );
+        assert!(window.add_stream_with_send_order(4, 0, 1, 2).is_ok());
+        assert_eq!(
+            Error::ErrOther("Stream already added".to_string()),
+            window.add_stream_with_send_order(4, 1, 7, 6).unwrap_err()
+        );
+        assert_eq!(
+            window.get_stream_for_sequence_and_send_order(FullSequence::new(5, 0), 7),
+            Some(2).as_ref()
+        );
         Ok(())
     }
 
@@ -440,7 +804,7 @@ mod test {
             test.start,
             Some(test.end),
         );
-        assert!(!window.largest_delivered().is_some());
+        assert!(window.largest_delivered().is_none());
         assert!(!window.on_object_sent(FullSequence::new(4, 1), ObjectStatus::Normal));
         assert!(window.largest_delivered().is_some());
         assert_eq!(window.largest_delivered().unwrap(), FullSequence::new(4, 1));
@@ -613,4 +977,241 @@ mod test {
         assert_eq!(windows.get_window(0), None);
         Ok(())
     }
+
+    fn subscribed_ids(windows: &SubscribeWindows, sequence: FullSequence) -> Vec<u64> {
+        let mut ids: Vec<u64> = windows
+            .sequence_is_subscribed(sequence)
+            .iter()
+            .map(|window| window.subscribe_id())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_moqt_subscribe_windows_test_coalesce_nested() -> Result<()> {
+        let windows = &mut SubscribeWindowsTest::new().windows;
+        windows.add_window(
+            0,
+            FullSequence::new(0, 0),
+            FullSequence::new(2, 0),
+            Some(FullSequence::new(4, 0)),
+        );
+        windows.add_window(
+            1,
+            FullSequence::new(0, 0),
+            FullSequence::new(1, 0),
+            Some(FullSequence::new(8, 0)),
+        );
+        windows.add_window(2, FullSequence::new(0, 0), FullSequence::new(0, 0), None);
+        windows.add_window(
+            3,
+            FullSequence::new(0, 0),
+            FullSequence::new(3, 0),
+            Some(FullSequence::new(3, 5)),
+        );
+        assert_eq!(windows.coalesced.len(), 1);
+        assert_eq!(
+            subscribed_ids(windows, FullSequence::new(3, 2)),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(subscribed_ids(windows, FullSequence::new(5, 0)), vec![1, 2]);
+        assert_eq!(subscribed_ids(windows, FullSequence::new(9, 0)), vec![2]);
+
+        // Dropping the outer window keeps the nested ones reachable.
+        windows.remove_window(2);
+        assert_eq!(windows.coalesced.len(), 1);
+        assert_eq!(
+            windows.coalesced[0].start..=windows.coalesced[0].end.unwrap(),
+            FullSequence::new(1, 0)..=FullSequence::new(8, 0)
+        );
+        assert_eq!(
+            subscribed_ids(windows, FullSequence::new(3, 2)),
+            vec![0, 1, 3]
+        );
+        assert!(subscribed_ids(windows, FullSequence::new(9, 0)).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_moqt_subscribe_windows_test_coalesce_overlapping() -> Result<()> {
+        let windows = &mut SubscribeWindowsTest::new().windows;
+        windows.add_window(
+            0,
+            FullSequence::new(0, 0),
+            FullSequence::new(1, 0),
+            Some(FullSequence::new(3, 9)),
+        );
+        windows.add_window(
+            1,
+            FullSequence::new(0, 0),
+            FullSequence::new(2, 4),
+            Some(FullSequence::new(4, 3)),
+        );
+        // Partial overlap can't be coalesced without losing precision.
+        assert_eq!(windows.coalesced.len(), 2);
+        assert_eq!(subscribed_ids(windows, FullSequence::new(1, 5)), vec![0]);
+        assert_eq!(subscribed_ids(windows, FullSequence::new(3, 0)), vec![0, 1]);
+        assert_eq!(subscribed_ids(windows, FullSequence::new(4, 0)), vec![1]);
+
+        // A window spanning both absorbs them.
+        windows.add_window(
+            2,
+            FullSequence::new(0, 0),
+            FullSequence::new(0, 0),
+            Some(FullSequence::new(5, 0)),
+        );
+        assert_eq!(windows.coalesced.len(), 1);
+        assert_eq!(
+            subscribed_ids(windows, FullSequence::new(3, 0)),
+            vec![0, 1, 2]
+        );
+        assert_eq!(subscribed_ids(windows, FullSequence::new(4, 5)), vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_moqt_subscribe_windows_test_coalesce_disjoint() -> Result<()> {
+        let windows = &mut SubscribeWindowsTest::new().windows;
+        windows.add_window(
+            0,
+            FullSequence::new(0, 0),
+            FullSequence::new(1, 0),
+            Some(FullSequence::new(2, 0)),
+        );
+        windows.add_window(1, FullSequence::new(0, 0), FullSequence::new(5, 0), None);
+        assert_eq!(windows.coalesced.len(), 2);
+        assert_eq!(subscribed_ids(windows, FullSequence::new(1, 3)), vec![0]);
+        assert!(subscribed_ids(windows, FullSequence::new(3, 0)).is_empty());
+        assert_eq!(subscribed_ids(windows, FullSequence::new(7, 0)), vec![1]);
+
+        windows.remove_window(0);
+        assert_eq!(windows.coalesced.len(), 1);
+        assert!(subscribed_ids(windows, FullSequence::new(1, 3)).is_empty());
+        Ok(())
+    }
+
+    // Deterministic windows for the index tests: (start, end) pairs starting
+    // below group |groups| and spanning up to |span| groups, every fifth one
+    // open-ended.
+    fn many_windows(
+        count: u64,
+        groups: u64,
+        span: u64,
+    ) -> Vec<(FullSequence, Option<FullSequence>)> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move |bound: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) % bound
+        };
+        (0..count)
+            .map(|i| {
+                let start = FullSequence::new(next(groups), next(8));
+                let end = (i % 5 != 0)
+                    .then(|| FullSequence::new(start.group_id + next(span), next(8)).max(start));
+                (start, end)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_moqt_subscribe_windows_test_index_matches_linear_scan() -> Result<()> {
+        let windows = &mut SubscribeWindowsTest::new().windows;
+        for (subscribe_id, (start, end)) in many_windows(500, 1000, 200).into_iter().enumerate() {
+            windows.add_window(subscribe_id as u64, FullSequence::default(), start, end);
+        }
+        for subscribe_id in (0..500).step_by(3) {
+            windows.remove_window(subscribe_id);
+        }
+
+        for pair in windows.coalesced.windows(2) {
+            assert!(pair[0].start < pair[1].start);
+            assert!(pair[0]
+                .end
+                .is_some_and(|end| pair[1].end.is_none_or(|next_end| end < next_end)));
+        }
+        for group_id in (0..1300).step_by(7) {
+            for object_id in [0, 3, 7] {
+                let sequence = FullSequence::new(group_id, object_id);
+                let mut expected: Vec<u64> = windows
+                    .windows
+                    .values()
+                    .filter(|window| window.in_window(sequence))
+                    .map(|window| window.subscribe_id())
+                    .collect();
+                expected.sort();
+                assert_eq!(
+                    subscribed_ids(windows, sequence),
+                    expected,
+                    "{:?}",
+                    sequence
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_moqt_subscribe_windows_test_update_window_regroups() -> Result<()> {
+        let windows = &mut SubscribeWindowsTest::new().windows;
+        windows.add_window(
+            1,
+            FullSequence::default(),
+            FullSequence::new(0, 0),
+            Some(FullSequence::new(10, 0)),
+        );
+        windows.add_window(
+            2,
+            FullSequence::default(),
+            FullSequence::new(2, 0),
+            Some(FullSequence::new(3, 0)),
+        );
+        assert_eq!(windows.coalesced.len(), 1);
+
+        assert!(windows.update_window(1, FullSequence::new(5, 0), Some(FullSequence::new(6, 0))));
+        assert_eq!(windows.coalesced.len(), 2);
+        assert_eq!(subscribed_ids(windows, FullSequence::new(2, 5)), vec![2]);
+        assert_eq!(subscribed_ids(windows, FullSequence::new(5, 5)), vec![1]);
+
+        // Updates can only narrow a window.
+        assert!(!windows.update_window(1, FullSequence::new(5, 0), Some(FullSequence::new(7, 0))));
+        assert!(!windows.update_window(3, FullSequence::new(5, 0), None));
+        assert_eq!(
+            subscribed_ids(windows, FullSequence::new(6, 1)),
+            Vec::<u64>::new()
+        );
+        Ok(())
+    }
+
+    // Run with `cargo test -- --ignored` to check that lookups stay cheap with
+    // many windows.
+    #[test]
+    #[ignore]
+    fn bench_moqt_subscribe_windows_sequence_is_subscribed() {
+        let windows = &mut SubscribeWindowsTest::new().windows;
+        // Windows spread over many groups, as for a relay serving many joins,
+        // with an open-ended one from the start of the track that absorbs the
+        // others into its group.
+        windows.add_window(0, FullSequence::default(), FullSequence::default(), None);
+        for (index, (start, end)) in many_windows(10_000, 100_000, 3).into_iter().enumerate() {
+            windows.add_window(index as u64 + 1, FullSequence::default(), start, end);
+        }
+        assert_eq!(windows.coalesced.len(), 1);
+        let lookups = 100_000u64;
+        let begin = std::time::Instant::now();
+        let mut hits = 0;
+        for i in 0..lookups {
+            hits += windows
+                .sequence_is_subscribed(FullSequence::new(i, i % 8))
+                .len();
+        }
+        assert!(hits as u64 >= lookups);
+        println!(
+            "{:?} per lookup over {} windows",
+            begin.elapsed() / lookups as u32,
+            windows.windows.len()
+        );
+    }
 }
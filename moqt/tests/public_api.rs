@@ -3,12 +3,13 @@ use moqt::{
     Announce, AnnounceCancel, AnnounceError, AnnounceOk, ClientSetup, Command, Connection,
     ControlMessage, EventIn, EventOut, Fetch, FetchCancel, FetchOk, FetchTarget, FilterType,
     FullSequence, FullTrackName, GoAway, MaxRequestId, MessageFramer, MessageParser,
-    MessageParserEvent, ObjectForwardingPreference, ObjectHeader, ObjectStatus, ProtocolConfig,
-    ProtocolPerspective, RemoteTrackOnObjectFragment, RequestsBlocked, Role, ServerSetup, Session,
-    SessionConfig, SessionCore, SessionDriver, SessionPerspective, SessionTransport,
-    StandaloneFetch, StreamId, StreamPurpose, Subscribe, SubscribeDone, SubscribeError,
-    SubscribeOk, SubscribeUpdate, TrackStatus, TrackStatusRequest, UnAnnounce, UnSubscribe,
-    Version, WriteOutput,
+    MessageParserEvent, ObjectEventQueue, ObjectForwardingPreference, ObjectHeader, ObjectStatus,
+    ParameterKey, Parameters, ProtocolConfig, ProtocolPerspective, RequestsBlocked, Role,
+    ServerSetup, Session, SessionConfig, SessionCore, SessionDriver, SessionErrorCode,
+    SessionPerspective, SessionTransport, StandaloneFetch, StreamId, StreamPurpose, Subscribe,
+    SubscribeDone, SubscribeError, SubscribeOk, SubscribeUpdate, TrackStatus, TrackStatusCode,
+    TrackStatusRequest, UnAnnounce, UnSubscribe, Version, WriteOutput, DEFAULT_BUFFER_POOL_SIZE,
+    DEFAULT_MAX_TRACK_ALIAS_RETRIES,
 };
 use sansio::Protocol;
 use std::time::Instant;
@@ -19,7 +20,7 @@ struct FakeTransport {
     opened_streams: Vec<(StreamPurpose, StreamId)>,
     sent_streams: Vec<(StreamId, BytesMut, bool)>,
     sent_datagrams: Vec<Bytes>,
-    closes: Vec<(u64, String)>,
+    closes: Vec<(SessionErrorCode, String)>,
 }
 
 impl FakeTransport {
@@ -49,7 +50,7 @@ impl SessionTransport for FakeTransport {
         Ok(())
     }
 
-    fn close(&mut self, code: u64, reason: String) -> moqt::Result<()> {
+    fn close(&mut self, code: SessionErrorCode, reason: String) -> moqt::Result<()> {
         self.closes.push((code, reason));
         Ok(())
     }
@@ -62,6 +63,14 @@ fn client_protocol_config() -> ProtocolConfig {
         use_web_transport: false,
         path: "/moq".to_string(),
         deliver_partial_objects: false,
+        additional_versions: vec![],
+        object_event_queue: ObjectEventQueue::Unbounded,
+        reorder_objects: false,
+        max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+        buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+        datagram_fragmentation: false,
+        length_prefixed_control: false,
+        announce_retry: None,
     }
 }
 
@@ -72,6 +81,14 @@ fn server_protocol_config() -> ProtocolConfig {
         use_web_transport: false,
         path: "/moq".to_string(),
         deliver_partial_objects: false,
+        additional_versions: vec![],
+        object_event_queue: ObjectEventQueue::Unbounded,
+        reorder_objects: false,
+        max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+        buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+        datagram_fragmentation: false,
+        length_prefixed_control: false,
+        announce_retry: None,
     }
 }
 
@@ -82,6 +99,14 @@ fn client_session_config() -> SessionConfig {
         use_web_transport: false,
         path: "/moq".to_string(),
         deliver_partial_objects: false,
+        additional_versions: vec![],
+        object_event_queue: ObjectEventQueue::Unbounded,
+        reorder_objects: false,
+        max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+        buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+        datagram_fragmentation: false,
+        length_prefixed_control: false,
+        announce_retry: None,
     }
 }
 
@@ -92,6 +117,14 @@ fn server_session_config() -> SessionConfig {
         use_web_transport: false,
         path: "/moq".to_string(),
         deliver_partial_objects: false,
+        additional_versions: vec![],
+        object_event_queue: ObjectEventQueue::Unbounded,
+        reorder_objects: false,
+        max_track_alias_retries: DEFAULT_MAX_TRACK_ALIAS_RETRIES,
+        buffer_pool_size: DEFAULT_BUFFER_POOL_SIZE,
+        datagram_fragmentation: false,
+        length_prefixed_control: false,
+        announce_retry: None,
     }
 }
 
@@ -101,6 +134,15 @@ fn encode_control(message: ControlMessage) -> moqt::Result<Bytes> {
     Ok(buf.freeze())
 }
 
+fn authorization_parameters(authorization_info: &str) -> moqt::Result<Parameters> {
+    let mut parameters = Parameters::new();
+    parameters.insert(
+        ParameterKey::AuthorizationInfo,
+        authorization_info.to_string(),
+    )?;
+    Ok(parameters)
+}
+
 #[test]
 fn public_session_core_smoke_test() -> moqt::Result<()> {
     let mut core = SessionCore::new(client_protocol_config());
@@ -147,7 +189,7 @@ fn public_session_driver_smoke_test() -> moqt::Result<()> {
         vec![(StreamPurpose::Control, 11)]
     );
     assert_eq!(driver.transport().sent_streams.len(), 1);
-    assert!(driver.transport().sent_streams[0].1.len() > 0);
+    assert!(!driver.transport().sent_streams[0].1.is_empty());
     assert!(driver.poll_event().is_none());
 
     Ok(())
@@ -164,6 +206,9 @@ fn public_session_driver_surfaces_session_established() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -201,6 +246,9 @@ fn public_session_driver_surfaces_incoming_subscribe() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -240,6 +288,9 @@ fn public_session_driver_surfaces_subscribe_accepted() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -286,6 +337,9 @@ fn public_session_driver_surfaces_subscribe_rejected() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -333,6 +387,9 @@ fn public_session_driver_surfaces_subscribe_ended() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -400,6 +457,9 @@ fn public_session_driver_surfaces_subscribe_updated() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -429,7 +489,7 @@ fn public_session_driver_surfaces_subscribe_updated() -> moqt::Result<()> {
         subscribe_id: 7,
         start_group_object: FullSequence::new(3, 1),
         end_group_object: Some(FullSequence::new(5, 9)),
-        authorization_info: Some("authz".to_string()),
+        parameters: authorization_parameters("authz")?,
     };
     driver.on_stream_data(
         0,
@@ -463,6 +523,9 @@ fn public_session_driver_surfaces_unsubscribe_received() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -506,6 +569,9 @@ fn public_session_driver_surfaces_announce_accepted() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -544,6 +610,9 @@ fn public_session_driver_surfaces_announce_rejected() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -586,6 +655,9 @@ fn public_session_driver_surfaces_announce_cancelled() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -640,6 +712,9 @@ fn public_session_driver_surfaces_incoming_announce() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -647,7 +722,7 @@ fn public_session_driver_surfaces_incoming_announce() -> moqt::Result<()> {
 
     let announce = Announce {
         track_namespace: "live".to_string(),
-        authorization_info: None,
+        parameters: Parameters::new(),
     };
     driver.on_stream_data(
         0,
@@ -674,6 +749,9 @@ fn public_session_driver_surfaces_unannounce_received() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -683,7 +761,7 @@ fn public_session_driver_surfaces_unannounce_received() -> moqt::Result<()> {
         0,
         encode_control(ControlMessage::Announce(Announce {
             track_namespace: "live".to_string(),
-            authorization_info: None,
+            parameters: Parameters::new(),
         }))?,
         false,
     )?;
@@ -721,6 +799,9 @@ fn public_session_driver_surfaces_track_status_requested() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -756,6 +837,9 @@ fn public_session_driver_surfaces_track_status_received() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -766,7 +850,7 @@ fn public_session_driver_surfaces_track_status_received() -> moqt::Result<()> {
         encode_control(ControlMessage::TrackStatus(TrackStatus {
             track_namespace: "live".to_string(),
             track_name: "camera".to_string(),
-            status_code: 200,
+            status_code: TrackStatusCode::Finished,
             last_group_object: FullSequence::new(7, 2),
         }))?,
         false,
@@ -777,7 +861,7 @@ fn public_session_driver_surfaces_track_status_received() -> moqt::Result<()> {
         Some(EventOut::TrackStatusReceived(TrackStatus {
             track_namespace: "live".to_string(),
             track_name: "camera".to_string(),
-            status_code: 200,
+            status_code: TrackStatusCode::Finished,
             last_group_object: FullSequence::new(7, 2),
         }))
     );
@@ -795,6 +879,9 @@ fn public_session_driver_surfaces_goaway_received() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -811,7 +898,7 @@ fn public_session_driver_surfaces_goaway_received() -> moqt::Result<()> {
     assert_eq!(
         driver.poll_event(),
         Some(EventOut::GoAwayReceived {
-            new_session_uri: "https://example.com/moq-next".to_string(),
+            new_session_uri: Some("https://example.com/moq-next".parse()?),
         })
     );
     Ok(())
@@ -829,6 +916,9 @@ fn public_session_driver_surfaces_incoming_fetch() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -865,6 +955,9 @@ fn public_session_driver_surfaces_fetch_accepted() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -912,6 +1005,9 @@ fn public_session_driver_surfaces_fetch_cancelled() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -956,6 +1052,9 @@ fn public_session_driver_surfaces_request_window_events() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1002,6 +1101,9 @@ fn public_session_driver_buffers_fetch_object_until_fetch_ok() -> moqt::Result<(
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1059,14 +1161,11 @@ fn public_session_driver_buffers_fetch_object_until_fetch_ok() -> moqt::Result<(
     );
     assert_eq!(
         driver.poll_event(),
-        Some(EventOut::ObjectReceived {
+        Some(EventOut::CompleteObject {
             full_track_name: FullTrackName::new("live".to_string(), "camera".to_string()),
-            fragment: RemoteTrackOnObjectFragment {
-                object_header,
-                extension_headers,
-                payload: Bytes::from_static(b"xyz"),
-                fin: true,
-            },
+            object_header,
+            extension_headers,
+            payload: Bytes::from_static(b"xyz"),
         })
     );
     Ok(())
@@ -1083,6 +1182,9 @@ fn public_session_driver_buffers_multiple_fetch_objects_until_fetch_ok() -> moqt
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1156,26 +1258,20 @@ fn public_session_driver_buffers_multiple_fetch_objects_until_fetch_ok() -> moqt
     );
     assert_eq!(
         driver.poll_event(),
-        Some(EventOut::ObjectReceived {
+        Some(EventOut::CompleteObject {
             full_track_name: FullTrackName::new("live".to_string(), "camera".to_string()),
-            fragment: RemoteTrackOnObjectFragment {
-                object_header: first,
-                extension_headers: Bytes::new(),
-                payload: Bytes::from_static(b"one"),
-                fin: true,
-            },
+            object_header: first,
+            extension_headers: Bytes::new(),
+            payload: Bytes::from_static(b"one"),
         })
     );
     assert_eq!(
         driver.poll_event(),
-        Some(EventOut::ObjectReceived {
+        Some(EventOut::CompleteObject {
             full_track_name: FullTrackName::new("live".to_string(), "camera".to_string()),
-            fragment: RemoteTrackOnObjectFragment {
-                object_header: second,
-                extension_headers: Bytes::new(),
-                payload: Bytes::from_static(b"two"),
-                fin: true,
-            },
+            object_header: second,
+            extension_headers: Bytes::new(),
+            payload: Bytes::from_static(b"two"),
         })
     );
     Ok(())
@@ -1193,6 +1289,9 @@ fn public_session_driver_publishes_object_datagram() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1268,6 +1367,9 @@ fn public_session_driver_surfaces_incoming_object_datagram() -> moqt::Result<()>
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1312,14 +1414,11 @@ fn public_session_driver_surfaces_incoming_object_datagram() -> moqt::Result<()>
 
     assert_eq!(
         driver.poll_event(),
-        Some(EventOut::ObjectReceived {
+        Some(EventOut::CompleteObject {
             full_track_name: FullTrackName::new("live".to_string(), "camera".to_string()),
-            fragment: RemoteTrackOnObjectFragment {
-                object_header,
-                extension_headers: Bytes::new(),
-                payload: Bytes::from_static(b"xyz"),
-                fin: true,
-            },
+            object_header,
+            extension_headers: Bytes::new(),
+            payload: Bytes::from_static(b"xyz"),
         })
     );
     Ok(())
@@ -1336,6 +1435,9 @@ fn public_session_driver_surfaces_incoming_object_stream() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1376,14 +1478,11 @@ fn public_session_driver_surfaces_incoming_object_stream() -> moqt::Result<()> {
 
     assert_eq!(
         driver.poll_event(),
-        Some(EventOut::ObjectReceived {
+        Some(EventOut::CompleteObject {
             full_track_name: FullTrackName::new("live".to_string(), "camera".to_string()),
-            fragment: RemoteTrackOnObjectFragment {
-                object_header,
-                extension_headers: Bytes::new(),
-                payload: Bytes::from_static(b"xyz"),
-                fin: true,
-            },
+            object_header,
+            extension_headers: Bytes::new(),
+            payload: Bytes::from_static(b"xyz"),
         })
     );
     Ok(())
@@ -1408,7 +1507,7 @@ fn public_session_driver_surfaces_protocol_close_to_transport() -> moqt::Result<
     assert_eq!(
         driver.transport().closes,
         vec![(
-            1,
+            SessionErrorCode::ProtocolViolation,
             "received SUBSCRIBE_DONE before session setup".to_string()
         )]
     );
@@ -1445,6 +1544,9 @@ fn public_session_external_establishment_round_trip() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1479,6 +1581,9 @@ fn public_session_external_subscribe_round_trip() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1524,6 +1629,9 @@ fn public_session_external_subscribe_rejection_round_trip() -> moqt::Result<()>
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1570,6 +1678,9 @@ fn public_session_external_subscribe_done_round_trip() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1628,6 +1739,9 @@ fn public_session_external_goaway_round_trip() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1644,7 +1758,7 @@ fn public_session_external_goaway_round_trip() -> moqt::Result<()> {
     assert_eq!(
         session.poll_event(),
         Some(EventOut::GoAwayReceived {
-            new_session_uri: "https://example.com/moq-next".to_string(),
+            new_session_uri: Some("https://example.com/moq-next".parse()?),
         })
     );
     Ok(())
@@ -1660,6 +1774,9 @@ fn public_session_external_track_status_round_trip() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1670,7 +1787,7 @@ fn public_session_external_track_status_round_trip() -> moqt::Result<()> {
         encode_control(ControlMessage::TrackStatus(TrackStatus {
             track_namespace: "live".to_string(),
             track_name: "camera".to_string(),
-            status_code: 200,
+            status_code: TrackStatusCode::Finished,
             last_group_object: FullSequence::new(7, 2),
         }))?,
         false,
@@ -1681,7 +1798,7 @@ fn public_session_external_track_status_round_trip() -> moqt::Result<()> {
         Some(EventOut::TrackStatusReceived(TrackStatus {
             track_namespace: "live".to_string(),
             track_name: "camera".to_string(),
-            status_code: 200,
+            status_code: TrackStatusCode::Finished,
             last_group_object: FullSequence::new(7, 2),
         }))
     );
@@ -1698,6 +1815,9 @@ fn public_session_external_track_status_request_round_trip() -> moqt::Result<()>
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1732,6 +1852,9 @@ fn public_session_external_announce_acceptance_round_trip() -> moqt::Result<()>
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1769,6 +1892,9 @@ fn public_session_external_announce_rejection_round_trip() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1810,6 +1936,9 @@ fn public_session_external_announce_cancel_round_trip() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1863,6 +1992,9 @@ fn public_session_external_server_receives_announce() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1870,7 +2002,7 @@ fn public_session_external_server_receives_announce() -> moqt::Result<()> {
 
     let announce = Announce {
         track_namespace: "live".to_string(),
-        authorization_info: None,
+        parameters: Parameters::new(),
     };
     session.on_stream_data(
         0,
@@ -1903,6 +2035,9 @@ fn public_session_external_server_receives_subscribe() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1947,6 +2082,9 @@ fn public_session_external_server_receives_unsubscribe() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -1997,6 +2135,9 @@ fn public_session_external_server_receives_subscribe_update() -> moqt::Result<()
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -2026,7 +2167,7 @@ fn public_session_external_server_receives_subscribe_update() -> moqt::Result<()
         subscribe_id: 7,
         start_group_object: FullSequence::new(3, 1),
         end_group_object: Some(FullSequence::new(5, 9)),
-        authorization_info: Some("authz".to_string()),
+        parameters: authorization_parameters("authz")?,
     };
     session.on_stream_data(
         0,
@@ -2052,6 +2193,9 @@ fn public_session_external_server_receives_unannounce() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -2061,7 +2205,7 @@ fn public_session_external_server_receives_unannounce() -> moqt::Result<()> {
         0,
         encode_control(ControlMessage::Announce(Announce {
             track_namespace: "live".to_string(),
-            authorization_info: None,
+            parameters: Parameters::new(),
         }))?,
         false,
     )?;
@@ -2098,6 +2242,9 @@ fn public_session_external_receives_object_datagram() -> moqt::Result<()> {
         encode_control(ControlMessage::ServerSetup(ServerSetup {
             supported_version: Version::Draft04,
             role: Some(Role::PubSub),
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }))?,
         false,
     )?;
@@ -2142,14 +2289,11 @@ fn public_session_external_receives_object_datagram() -> moqt::Result<()> {
 
     assert_eq!(
         session.poll_event(),
-        Some(EventOut::ObjectReceived {
+        Some(EventOut::CompleteObject {
             full_track_name: FullTrackName::new("live".to_string(), "camera".to_string()),
-            fragment: RemoteTrackOnObjectFragment {
-                object_header,
-                extension_headers: Bytes::new(),
-                payload: Bytes::from_static(b"xyz"),
-                fin: true,
-            },
+            object_header,
+            extension_headers: Bytes::new(),
+            payload: Bytes::from_static(b"xyz"),
         })
     );
     Ok(())
@@ -2288,6 +2432,9 @@ fn public_wire_helpers_round_trip_control_message() -> moqt::Result<()> {
             role: Some(Role::PubSub),
             path: Some("/moq".to_string()),
             uses_web_transport: false,
+            partial_object_delivery: false,
+            datagram_fragmentation: false,
+            length_prefixed_control: false,
         }),
         &mut bytes,
     )?;
@@ -2316,7 +2463,7 @@ fn public_wire_helpers_round_trip_subscribe_update_without_auth() -> moqt::Resul
             subscribe_id: 7,
             start_group_object: FullSequence::new(3, 1),
             end_group_object: Some(FullSequence::new(5, 9)),
-            authorization_info: None,
+            parameters: Parameters::new(),
         }),
         &mut bytes,
     )?;
@@ -2329,7 +2476,7 @@ fn public_wire_helpers_round_trip_subscribe_update_without_auth() -> moqt::Resul
             assert_eq!(update.subscribe_id, 7);
             assert_eq!(update.start_group_object, FullSequence::new(3, 1));
             assert_eq!(update.end_group_object, Some(FullSequence::new(5, 9)));
-            assert_eq!(update.authorization_info, None);
+            assert_eq!(update.authorization_info()?, None);
         }
         other => panic!("unexpected parser event: {other:?}"),
     }
@@ -2343,7 +2490,7 @@ fn public_wire_helpers_round_trip_announce_without_auth() -> moqt::Result<()> {
     MessageFramer::serialize_control_message(
         ControlMessage::Announce(Announce {
             track_namespace: "live".to_string(),
-            authorization_info: None,
+            parameters: Parameters::new(),
         }),
         &mut bytes,
     )?;
@@ -2354,7 +2501,7 @@ fn public_wire_helpers_round_trip_announce_without_auth() -> moqt::Result<()> {
     match parser.poll_event() {
         Some(MessageParserEvent::ControlMessage(ControlMessage::Announce(announce))) => {
             assert_eq!(announce.track_namespace, "live".to_string());
-            assert_eq!(announce.authorization_info, None);
+            assert_eq!(announce.authorization_info().unwrap(), None);
         }
         other => panic!("unexpected parser event: {other:?}"),
     }